@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use ktx2::Format;
+
+use crate::texture::ColorSpace;
+
+/// Families of GPU block compression. Devices support each family as a
+/// whole, so assets are usually shipped once per family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    None,
+    /// BC1-7, desktop GPUs.
+    Bc,
+    /// ETC2/EAC, most mobile GPUs and GLES 3.
+    Etc2,
+    /// ASTC LDR, recent mobile GPUs.
+    Astc,
+}
+
+impl Compression {
+    /// Suffix of the file holding this family's variant of an asset, e.g.
+    /// `rock.bc.ktx2` for `rock.ktx2`.
+    pub fn suffix(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Bc => Some("bc"),
+            Compression::Etc2 => Some("etc2"),
+            Compression::Astc => Some("astc"),
+        }
+    }
+}
+
+/// Texel formats understood in KTX2 files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockFormat {
+    Rgba8,
+    Rgba16Float,
+    Rgba32Float,
+    /// RGB with 1-bit alpha.
+    Bc1,
+    Bc3,
+    /// One channel.
+    Bc4,
+    /// Two channels, usually a normal map's XY.
+    Bc5,
+    Bc6hUfloat,
+    Bc7,
+    Etc2Rgb8,
+    Etc2Rgb8A1,
+    Etc2Rgba8,
+    EacR11,
+    EacRg11,
+    Astc { block_width: u8, block_height: u8 },
+}
+
+impl BlockFormat {
+    pub fn compression(self) -> Compression {
+        match self {
+            BlockFormat::Rgba8 | BlockFormat::Rgba16Float | BlockFormat::Rgba32Float => Compression::None,
+            BlockFormat::Bc1
+            | BlockFormat::Bc3
+            | BlockFormat::Bc4
+            | BlockFormat::Bc5
+            | BlockFormat::Bc6hUfloat
+            | BlockFormat::Bc7 => Compression::Bc,
+            BlockFormat::Etc2Rgb8
+            | BlockFormat::Etc2Rgb8A1
+            | BlockFormat::Etc2Rgba8
+            | BlockFormat::EacR11
+            | BlockFormat::EacRg11 => Compression::Etc2,
+            BlockFormat::Astc { .. } => Compression::Astc,
+        }
+    }
+
+    /// The format and color space of a Vulkan format as stored in KTX2.
+    fn from_vk(format: Format) -> Option<(Self, ColorSpace)> {
+        use ColorSpace::{Linear, Srgb};
+        let astc = |w, h| BlockFormat::Astc { block_width: w, block_height: h };
+        Some(match format {
+            Format::R8G8B8A8_UNORM => (BlockFormat::Rgba8, Linear),
+            Format::R8G8B8A8_SRGB => (BlockFormat::Rgba8, Srgb),
+            Format::R16G16B16A16_SFLOAT => (BlockFormat::Rgba16Float, Linear),
+            Format::R32G32B32A32_SFLOAT => (BlockFormat::Rgba32Float, Linear),
+            Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => (BlockFormat::Bc1, Linear),
+            Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => (BlockFormat::Bc1, Srgb),
+            Format::BC3_UNORM_BLOCK => (BlockFormat::Bc3, Linear),
+            Format::BC3_SRGB_BLOCK => (BlockFormat::Bc3, Srgb),
+            Format::BC4_UNORM_BLOCK => (BlockFormat::Bc4, Linear),
+            Format::BC5_UNORM_BLOCK => (BlockFormat::Bc5, Linear),
+            Format::BC6H_UFLOAT_BLOCK => (BlockFormat::Bc6hUfloat, Linear),
+            Format::BC7_UNORM_BLOCK => (BlockFormat::Bc7, Linear),
+            Format::BC7_SRGB_BLOCK => (BlockFormat::Bc7, Srgb),
+            Format::ETC2_R8G8B8_UNORM_BLOCK => (BlockFormat::Etc2Rgb8, Linear),
+            Format::ETC2_R8G8B8_SRGB_BLOCK => (BlockFormat::Etc2Rgb8, Srgb),
+            Format::ETC2_R8G8B8A1_UNORM_BLOCK => (BlockFormat::Etc2Rgb8A1, Linear),
+            Format::ETC2_R8G8B8A1_SRGB_BLOCK => (BlockFormat::Etc2Rgb8A1, Srgb),
+            Format::ETC2_R8G8B8A8_UNORM_BLOCK => (BlockFormat::Etc2Rgba8, Linear),
+            Format::ETC2_R8G8B8A8_SRGB_BLOCK => (BlockFormat::Etc2Rgba8, Srgb),
+            Format::EAC_R11_UNORM_BLOCK => (BlockFormat::EacR11, Linear),
+            Format::EAC_R11G11_UNORM_BLOCK => (BlockFormat::EacRg11, Linear),
+            Format::ASTC_4x4_UNORM_BLOCK => (astc(4, 4), Linear),
+            Format::ASTC_4x4_SRGB_BLOCK => (astc(4, 4), Srgb),
+            Format::ASTC_5x5_UNORM_BLOCK => (astc(5, 5), Linear),
+            Format::ASTC_5x5_SRGB_BLOCK => (astc(5, 5), Srgb),
+            Format::ASTC_6x6_UNORM_BLOCK => (astc(6, 6), Linear),
+            Format::ASTC_6x6_SRGB_BLOCK => (astc(6, 6), Srgb),
+            Format::ASTC_8x8_UNORM_BLOCK => (astc(8, 8), Linear),
+            Format::ASTC_8x8_SRGB_BLOCK => (astc(8, 8), Srgb),
+            Format::ASTC_10x10_UNORM_BLOCK => (astc(10, 10), Linear),
+            Format::ASTC_10x10_SRGB_BLOCK => (astc(10, 10), Srgb),
+            Format::ASTC_12x12_UNORM_BLOCK => (astc(12, 12), Linear),
+            Format::ASTC_12x12_SRGB_BLOCK => (astc(12, 12), Srgb),
+            _ => return None,
+        })
+    }
+}
+
+/// A texture loaded from a KTX2 container, with its mip chain as stored.
+#[derive(Clone, Debug)]
+pub struct CompressedTexture {
+    pub width: u32,
+    pub height: u32,
+    /// Array layers times faces; faces of a layer are consecutive.
+    pub layers: u32,
+    pub cube: bool,
+    pub format: BlockFormat,
+    pub color_space: ColorSpace,
+    /// Largest level first. Each level holds all layers back to back.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedTexture {
+    /// Parses a KTX2 file. Supercompressed (zstd, BasisLZ) and 3D textures
+    /// are not supported.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow::anyhow!("invalid KTX2: {e}"))?;
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme {
+            bail!("supercompressed KTX2 ({scheme:?}) is not supported");
+        }
+        ensure!(header.pixel_depth <= 1, "3D KTX2 textures are not supported");
+        ensure!(header.pixel_height > 0, "1D KTX2 textures are not supported");
+        let vk = header.format.context("KTX2 without a format (Basis Universal) is not supported")?;
+        let (format, color_space) = BlockFormat::from_vk(vk).with_context(|| format!("unsupported KTX2 format {vk:?}"))?;
+        ensure!(header.face_count == 1 || header.face_count == 6, "unexpected face count {}", header.face_count);
+
+        Ok(Self {
+            width: header.pixel_width,
+            height: header.pixel_height,
+            layers: header.layer_count.max(1) * header.face_count,
+            cube: header.face_count == 6,
+            format,
+            color_space,
+            levels: reader.levels().map(|level| level.data.to_vec()).collect(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("parse {}", path.display()))
+    }
+
+    /// The file to load for `path` given the families a device supports, best
+    /// first: the first existing `<stem>.<suffix>.ktx2` sibling, else `path`
+    /// itself.
+    pub fn variant_path(path: &Path, supported: &[Compression]) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        supported
+            .iter()
+            .filter_map(|c| c.suffix())
+            .map(|suffix| path.with_file_name(format!("{stem}.{suffix}.ktx2")))
+            .find(|candidate| candidate.is_file())
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ktx2::{Header, Index, SupercompressionScheme};
+
+    use super::*;
+
+    /// A 2x2 sRGB RGBA8 KTX2 file with one level, after `edit` changed its
+    /// header.
+    fn ktx2(edit: impl FnOnce(&mut Header)) -> Vec<u8> {
+        // Header, one level index entry, a data format descriptor holding
+        // only its length, then the texels.
+        let (dfd, data) = (80 + 24, 80 + 24 + 4);
+        let mut header = Header {
+            format: Some(Format::R8G8B8A8_SRGB),
+            type_size: 1,
+            pixel_width: 2,
+            pixel_height: 2,
+            pixel_depth: 0,
+            layer_count: 0,
+            face_count: 1,
+            level_count: 1,
+            supercompression_scheme: None,
+            index: Index { dfd_byte_offset: dfd, dfd_byte_length: 4, kvd_byte_offset: 0, kvd_byte_length: 0, sgd_byte_offset: 0, sgd_byte_length: 0 },
+        };
+        edit(&mut header);
+        let mut bytes = header.as_bytes().to_vec();
+        for value in [data as u64, 16, 16] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend(0..16u8);
+        bytes
+    }
+
+    #[test]
+    fn parses_an_uncompressed_texture() {
+        let tex = CompressedTexture::parse(&ktx2(|_| {})).unwrap();
+        assert_eq!((tex.width, tex.height, tex.layers, tex.cube), (2, 2, 1, false));
+        assert_eq!((tex.format, tex.color_space), (BlockFormat::Rgba8, ColorSpace::Srgb));
+        assert_eq!(tex.levels, [(0..16).collect::<Vec<u8>>()]);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let error = |edit: fn(&mut Header)| CompressedTexture::parse(&ktx2(edit)).unwrap_err().to_string();
+        assert!(error(|h| h.supercompression_scheme = Some(SupercompressionScheme::Zstandard)).contains("supercompressed"));
+        assert!(error(|h| h.pixel_depth = 2).contains("3D"));
+        assert!(error(|h| h.pixel_height = 0).contains("1D"));
+        assert!(error(|h| h.format = None).contains("Basis"));
+        assert!(error(|h| h.format = Some(Format::R8_UNORM)).contains("unsupported"));
+        assert!(error(|h| h.face_count = 2).contains("face count"));
+        assert!(CompressedTexture::parse(b"not a KTX2 file").is_err());
+    }
+
+    #[test]
+    fn variant_path_prefers_the_first_supported_variant() {
+        let dir = std::env::temp_dir().join(format!("mars-asset-variants-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["rock.etc2.ktx2", "rock.astc.ktx2"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        let path = dir.join("rock.ktx2");
+        let variant = |supported: &[Compression]| CompressedTexture::variant_path(&path, supported);
+
+        assert_eq!(variant(&[Compression::Bc, Compression::Astc, Compression::Etc2]), dir.join("rock.astc.ktx2"));
+        assert_eq!(variant(&[Compression::None, Compression::Etc2, Compression::Astc]), dir.join("rock.etc2.ktx2"));
+        assert_eq!(variant(&[Compression::Bc]), path);
+        assert_eq!(variant(&[]), path);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
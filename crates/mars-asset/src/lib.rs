@@ -1 +1,5 @@
-pub fn hello() { println!("Hello from mars-core!"); }
\ No newline at end of file
+pub mod compressed;
+pub mod lut;
+pub mod texture;
+pub mod video;
+pub mod watch;
@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// A 3D color lookup table as exported by grading tools in the Adobe/Resolve
+/// `.cube` format. Entries are RGB with red varying fastest, then green, then
+/// blue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    /// Entries per axis.
+    pub size: u32,
+    /// Input range mapped onto the table; usually 0 to 1.
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    /// A table that maps every color onto itself.
+    pub fn identity(size: u32) -> Self {
+        let size = size.max(2);
+        let scale = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                }
+            }
+        }
+        Self { title: None, size, domain_min: [0.0; 3], domain_max: [1.0; 3], data }
+    }
+
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || format!("line {}: `{line}`", n + 1);
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            match keyword {
+                "TITLE" => title = Some(line["TITLE".len()..].trim().trim_matches('"').to_owned()),
+                "LUT_3D_SIZE" => {
+                    let n: u32 = words.next().unwrap_or_default().parse().with_context(err)?;
+                    ensure!((2..=256).contains(&n), "LUT_3D_SIZE {n} out of range (2..=256)");
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+                "DOMAIN_MIN" => domain_min = parse_triple(words).with_context(err)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(words).with_context(err)?,
+                // Resolve's variant of the domain, the same for all channels.
+                "LUT_3D_INPUT_RANGE" => {
+                    let [lo, hi] = [words.next(), words.next()].map(|w| w.unwrap_or_default().parse::<f32>());
+                    let (lo, hi) = (lo.with_context(err)?, hi.with_context(err)?);
+                    domain_min = [lo; 3];
+                    domain_max = [hi; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    tracing::debug!("ignoring .cube keyword {keyword}");
+                }
+                _ => data.push(parse_triple(line.split_whitespace()).with_context(err)?),
+            }
+        }
+
+        let size = size.context("missing LUT_3D_SIZE")?;
+        let expected = (size * size * size) as usize;
+        ensure!(data.len() == expected, "expected {expected} entries for size {size}, found {}", data.len());
+        ensure!((0..3).all(|i| domain_max[i] > domain_min[i]), "empty domain {domain_min:?}..{domain_max:?}");
+        Ok(Self { title, size, domain_min, domain_max, data })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        Self::parse(&src).with_context(|| format!("parse {}", path.display()))
+    }
+
+    /// Looks up `rgb` with trilinear interpolation, clamping to the domain.
+    /// Matches what the GPU grading pass computes.
+    pub fn sample(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut next = [0usize; 3];
+        let mut frac = [0.0; 3];
+        for i in 0..3 {
+            let t = ((rgb[i] - self.domain_min[i]) / (self.domain_max[i] - self.domain_min[i])).clamp(0.0, 1.0) * max;
+            base[i] = t.floor() as usize;
+            next[i] = (base[i] + 1).min(self.size as usize - 1);
+            frac[i] = t - t.floor();
+        }
+        let n = self.size as usize;
+        let at = |r: usize, g: usize, b: usize| self.data[r + g * n + b * n * n];
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t);
+
+        let [r0, g0, b0] = base;
+        let [r1, g1, b1] = next;
+        let c00 = lerp(at(r0, g0, b0), at(r1, g0, b0), frac[0]);
+        let c10 = lerp(at(r0, g1, b0), at(r1, g1, b0), frac[0]);
+        let c01 = lerp(at(r0, g0, b1), at(r1, g0, b1), frac[0]);
+        let c11 = lerp(at(r0, g1, b1), at(r1, g1, b1), frac[0]);
+        lerp(lerp(c00, c10, frac[1]), lerp(c01, c11, frac[1]), frac[2])
+    }
+}
+
+fn parse_triple<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<[f32; 3]> {
+    let mut out = [0.0; 3];
+    for v in &mut out {
+        *v = words.next().context("expected three numbers")?.parse()?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE_2: &str = "0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+
+    #[test]
+    fn parses_header_keywords() {
+        let src = format!("# comment\nTITLE \"Warm look\"\nLUT_3D_SIZE 2\nLUT_1D_INPUT_RANGE 0 1\n\n{SIZE_2}");
+        let lut = CubeLut::parse(&src).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Warm look"));
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [1.0; 3]);
+        assert_eq!(lut, CubeLut { title: lut.title.clone(), ..CubeLut::identity(2) });
+    }
+
+    #[test]
+    fn parses_domain() {
+        let src = format!("LUT_3D_SIZE 2\nDOMAIN_MIN 0 -0.5 0\nDOMAIN_MAX 1 2 4\n{SIZE_2}");
+        let lut = CubeLut::parse(&src).unwrap();
+        assert_eq!(lut.domain_min, [0.0, -0.5, 0.0]);
+        assert_eq!(lut.domain_max, [1.0, 2.0, 4.0]);
+
+        let src = format!("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -1 3\n{SIZE_2}");
+        let lut = CubeLut::parse(&src).unwrap();
+        assert_eq!(lut.domain_min, [-1.0; 3]);
+        assert_eq!(lut.domain_max, [3.0; 3]);
+
+        let src = format!("LUT_3D_SIZE 2\nDOMAIN_MIN 1 1 1\nDOMAIN_MAX 1 1 1\n{SIZE_2}");
+        assert!(CubeLut::parse(&src).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let err = CubeLut::parse(&format!("LUT_3D_SIZE 3\n{SIZE_2}")).unwrap_err();
+        assert!(err.to_string().contains("expected 27 entries"), "{err}");
+        assert!(CubeLut::parse(SIZE_2).is_err());
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(CubeLut::parse("LUT_3D_SIZE 1\n0 0 0\n").is_err());
+        assert!(CubeLut::parse(&format!("LUT_3D_SIZE 2\n0 0\n{SIZE_2}")).is_err());
+    }
+
+    #[test]
+    fn identity_samples_to_input() {
+        let lut = CubeLut::identity(17);
+        for rgb in [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.25, 0.5, 0.75], [0.1, 0.9, 0.33]] {
+            let out = lut.sample(rgb);
+            for i in 0..3 {
+                assert!((out[i] - rgb[i]).abs() < 1e-5, "{rgb:?} -> {out:?}");
+            }
+        }
+        // Outside the domain clamps to the edge.
+        assert_eq!(lut.sample([-1.0, 2.0, 0.0]), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn samples_between_entries() {
+        let mut lut = CubeLut::identity(2);
+        for entry in &mut lut.data {
+            *entry = entry.map(|c| 1.0 - c);
+        }
+        let out = lut.sample([0.25, 0.5, 1.0]);
+        assert_eq!(out, [0.75, 0.5, 0.0]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TexelFormat {
+    Rgba8,
+    Rgba32Float,
+}
+
+impl TexelFormat {
+    pub fn bytes_per_texel(self) -> u32 {
+        match self {
+            TexelFormat::Rgba8 => 4,
+            TexelFormat::Rgba32Float => 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// What a texture's texels mean, which decides how 8-bit data is decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureUsage {
+    /// Albedo, emissive, UI: authored in sRGB and decoded to linear when
+    /// sampled.
+    Color,
+    /// Normals, roughness/metalness, masks: raw values.
+    Data,
+}
+
+impl TextureUsage {
+    pub fn color_space(self) -> ColorSpace {
+        match self {
+            TextureUsage::Color => ColorSpace::Srgb,
+            TextureUsage::Data => ColorSpace::Linear,
+        }
+    }
+}
+
+/// Decoded, uncompressed image ready for upload. Rows are tightly packed.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub format: TexelFormat,
+    pub color_space: ColorSpace,
+    pub data: Vec<u8>,
+}
+
+impl TextureData {
+    pub fn bytes_per_row(&self) -> u32 {
+        self.width * self.format.bytes_per_texel()
+    }
+
+    /// Decodes PNG, JPEG or Radiance HDR, recognized by their contents. HDR
+    /// images stay in linear float, everything else is 8-bit sRGB.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_image(image::load_from_memory(bytes).context("decode image")?))
+    }
+
+    /// Decodes TGA, which has no signature to be recognized by.
+    pub fn decode_tga(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_image(image::load_from_memory_with_format(bytes, image::ImageFormat::Tga).context("decode TGA")?))
+    }
+
+    fn from_image(img: image::DynamicImage) -> Self {
+        let (width, height) = (img.width(), img.height());
+
+        let is_float = matches!(img.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+        if is_float {
+            let data = img.into_rgba32f().into_raw().into_iter().flat_map(f32::to_ne_bytes).collect();
+            Self { width, height, format: TexelFormat::Rgba32Float, color_space: ColorSpace::Linear, data }
+        } else {
+            let data = img.into_rgba8().into_raw();
+            Self { width, height, format: TexelFormat::Rgba8, color_space: ColorSpace::Srgb, data }
+        }
+    }
+
+    /// Interprets 8-bit data as `usage` requires; float data is always
+    /// linear.
+    pub fn with_usage(mut self, usage: TextureUsage) -> Self {
+        if self.format == TexelFormat::Rgba8 {
+            self.color_space = usage.color_space();
+        }
+        self
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        let is_tga = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tga"));
+        let data = if is_tga { Self::decode_tga(&bytes) } else { Self::decode(&bytes) };
+        data.with_context(|| format!("decode {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat, Rgb32FImage, RgbaImage};
+
+    use super::*;
+
+    const TEXELS: [u8; 8] = [255, 0, 0, 255, 10, 20, 30, 128];
+
+    fn encode(img: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        img.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    fn rgba8() -> DynamicImage {
+        RgbaImage::from_raw(2, 1, TEXELS.to_vec()).unwrap().into()
+    }
+
+    #[test]
+    fn decodes_png_as_srgb_bytes() {
+        let tex = TextureData::decode(&encode(rgba8(), ImageFormat::Png)).unwrap();
+        assert_eq!((tex.width, tex.height, tex.format, tex.color_space), (2, 1, TexelFormat::Rgba8, ColorSpace::Srgb));
+        assert_eq!(tex.bytes_per_row(), 8);
+        assert_eq!(tex.data, TEXELS);
+    }
+
+    #[test]
+    fn decodes_tga_only_when_asked() {
+        let bytes = encode(rgba8(), ImageFormat::Tga);
+        let tex = TextureData::decode_tga(&bytes).unwrap();
+        assert_eq!((tex.width, tex.height, tex.format), (2, 1, TexelFormat::Rgba8));
+        assert_eq!(tex.data, TEXELS);
+        assert!(TextureData::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn decodes_hdr_as_linear_floats() {
+        let img = Rgb32FImage::from_raw(1, 1, vec![0.5, 2.0, 4.0]).unwrap();
+        let tex = TextureData::decode(&encode(img.into(), ImageFormat::Hdr)).unwrap();
+        assert_eq!((tex.format, tex.color_space), (TexelFormat::Rgba32Float, ColorSpace::Linear));
+        assert_eq!(tex.bytes_per_row(), 16);
+        let texel: Vec<f32> = tex.data.chunks(4).map(|c| f32::from_ne_bytes(c.try_into().unwrap())).collect();
+        for (value, expected) in texel.iter().zip([0.5, 2.0, 4.0, 1.0]) {
+            assert!((value - expected).abs() <= expected * 0.01, "{texel:?}");
+        }
+    }
+
+    #[test]
+    fn usage_picks_the_color_space_of_8_bit_data() {
+        let tex = TextureData::decode(&encode(rgba8(), ImageFormat::Png)).unwrap();
+        assert_eq!(tex.clone().with_usage(TextureUsage::Data).color_space, ColorSpace::Linear);
+        assert_eq!(tex.with_usage(TextureUsage::Color).color_space, ColorSpace::Srgb);
+
+        let hdr = TextureData { width: 1, height: 1, format: TexelFormat::Rgba32Float, color_space: ColorSpace::Linear, data: vec![0; 16] };
+        assert_eq!(hdr.with_usage(TextureUsage::Color).color_space, ColorSpace::Linear);
+    }
+}
@@ -0,0 +1,194 @@
+//! Writers for recorded frames: numbered PNG files or a raw Y4M stream.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+
+/// Destination of a sequence of RGBA8 frames with sRGB-encoded color.
+pub trait FrameSink {
+    /// Appends a frame of `width` x `height` tightly packed texels.
+    fn write_frame(&mut self, width: u32, height: u32, rgba: &[u8]) -> Result<()>;
+
+    /// Sets the playback rate to `num / den` frames per second, before the
+    /// first frame. Sinks without timing ignore it.
+    fn set_frame_rate(&mut self, _num: u32, _den: u32) {}
+
+    /// Flushes buffered output. Writing after this is an error.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every frame to `dir/<prefix>_000000.png`, `..._000001.png` and so
+/// on, creating the directory if needed.
+pub struct PngSequence {
+    dir: PathBuf,
+    prefix: String,
+    next: u32,
+}
+
+impl PngSequence {
+    pub fn new(dir: impl AsRef<Path>, prefix: &str) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        Ok(Self { dir, prefix: prefix.to_owned(), next: 0 })
+    }
+
+    /// Path of the `index`th frame.
+    pub fn frame_path(&self, index: u32) -> PathBuf {
+        self.dir.join(format!("{}_{index:06}.png", self.prefix))
+    }
+}
+
+impl FrameSink for PngSequence {
+    fn write_frame(&mut self, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+        let path = self.frame_path(self.next);
+        image::save_buffer(&path, rgba, width, height, image::ExtendedColorType::Rgba8)
+            .with_context(|| format!("write {}", path.display()))?;
+        self.next += 1;
+        Ok(())
+    }
+}
+
+/// Writes a YUV4MPEG2 stream, as read by ffmpeg and most players:
+/// 4:4:4 BT.709 limited-range Y'CbCr at a constant frame rate. Alpha is
+/// dropped. The size and frame rate are fixed by the first frame.
+pub struct Y4mWriter<W: Write> {
+    out: Option<W>,
+    /// Frames per second as a reduced fraction.
+    rate: (u32, u32),
+    size: Option<(u32, u32)>,
+    plane: Vec<u8>,
+}
+
+impl Y4mWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, fps: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file), fps))
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(out: W, fps: u32) -> Self {
+        let mut writer = Self { out: Some(out), rate: (1, 1), size: None, plane: Vec::new() };
+        writer.set_frame_rate(fps, 1);
+        writer
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// BT.709 limited-range Y', Cb and Cr of a gamma-encoded RGB texel.
+fn ycbcr(px: &[u8]) -> [u8; 3] {
+    let (r, g, b) = (f32::from(px[0]) / 255.0, f32::from(px[1]) / 255.0, f32::from(px[2]) / 255.0);
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let cb = (b - y) / 1.8556;
+    let cr = (r - y) / 1.5748;
+    let quantize = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    [quantize(16.0 + 219.0 * y), quantize(128.0 + 224.0 * cb), quantize(128.0 + 224.0 * cr)]
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn write_frame(&mut self, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+        let out = self.out.as_mut().context("Y4M stream already finished")?;
+        ensure!(rgba.len() == (width * height * 4) as usize, "frame of {width}x{height} has {} bytes", rgba.len());
+        match self.size {
+            None => {
+                let (num, den) = self.rate;
+                writeln!(out, "YUV4MPEG2 W{width} H{height} F{num}:{den} Ip A1:1 C444 XCOLORRANGE=LIMITED")?;
+                self.size = Some((width, height));
+            }
+            Some(size) => ensure!(size == (width, height), "Y4M frame size changed from {size:?} to {:?}", (width, height)),
+        }
+
+        let texels = (width * height) as usize;
+        self.plane.resize(texels * 3, 0);
+        for (i, px) in rgba.chunks_exact(4).enumerate() {
+            let [y, cb, cr] = ycbcr(px);
+            self.plane[i] = y;
+            self.plane[texels + i] = cb;
+            self.plane[2 * texels + i] = cr;
+        }
+        out.write_all(b"FRAME\n")?;
+        out.write_all(&self.plane)?;
+        Ok(())
+    }
+
+    fn set_frame_rate(&mut self, num: u32, den: u32) {
+        if self.size.is_some() {
+            tracing::warn!("ignoring Y4M frame rate change after the first frame");
+            return;
+        }
+        let (num, den) = (num.max(1), den.max(1));
+        let divisor = gcd(num, den);
+        self.rate = (num / divisor, den / divisor);
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut out) = self.out.take() {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ycbcr_matches_bt709_limited_range() {
+        assert_eq!(ycbcr(&[0, 0, 0, 255]), [16, 128, 128]);
+        assert_eq!(ycbcr(&[255, 255, 255, 255]), [235, 128, 128]);
+        assert_eq!(ycbcr(&[255, 0, 0, 255]), [63, 102, 240]);
+        assert_eq!(ycbcr(&[0, 255, 0, 255]), [173, 42, 26]);
+        assert_eq!(ycbcr(&[0, 0, 255, 255]), [32, 240, 118]);
+    }
+
+    #[test]
+    fn writes_header_once_and_planar_frames() {
+        let mut writer = Y4mWriter::new(Vec::new(), 30);
+        // Black then white, twice over.
+        let rgba = [0, 0, 0, 255, 255, 255, 255, 0];
+        writer.write_frame(2, 1, &rgba).unwrap();
+        writer.write_frame(2, 1, &rgba).unwrap();
+        let frame = [b"FRAME\n".as_slice(), &[16, 235, 128, 128, 128, 128]].concat();
+        let expected = [b"YUV4MPEG2 W2 H1 F30:1 Ip A1:1 C444 XCOLORRANGE=LIMITED\n".as_slice(), &frame, &frame].concat();
+        assert_eq!(writer.out.as_ref().unwrap(), &expected);
+
+        writer.finish().unwrap();
+        assert!(writer.write_frame(2, 1, &rgba).is_err());
+    }
+
+    #[test]
+    fn frame_rate_is_reduced_and_fixed_by_the_first_frame() {
+        let header = |writer: &Y4mWriter<Vec<u8>>| {
+            let out = writer.out.as_ref().unwrap();
+            String::from_utf8_lossy(&out[..out.iter().position(|&b| b == b'\n').unwrap()]).into_owned()
+        };
+        let mut writer = Y4mWriter::new(Vec::new(), 60);
+        writer.set_frame_rate(60, 7);
+        writer.write_frame(1, 1, &[0; 4]).unwrap();
+        writer.set_frame_rate(24, 1);
+        assert!(header(&writer).contains(" F60:7 "));
+
+        let mut writer = Y4mWriter::new(Vec::new(), 60);
+        writer.set_frame_rate(60, 4);
+        writer.write_frame(1, 1, &[0; 4]).unwrap();
+        assert!(header(&writer).contains(" F15:1 "));
+    }
+
+    #[test]
+    fn frame_size_is_fixed_by_the_first_frame() {
+        let mut writer = Y4mWriter::new(Vec::new(), 30);
+        writer.write_frame(2, 1, &[0; 8]).unwrap();
+        let err = writer.write_frame(1, 2, &[0; 8]).unwrap_err();
+        assert!(err.to_string().contains("size changed"), "{err}");
+        assert!(writer.write_frame(2, 1, &[0; 4]).is_err());
+    }
+}
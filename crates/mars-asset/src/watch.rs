@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// Reports changes to a set of files, for hot reloading.
+///
+/// Each file's directory is watched rather than the file itself, so edits
+/// that replace the file (as most editors do) are still seen.
+pub struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .context("create file watcher")?;
+        Ok(Self { watcher, events, files: HashSet::new(), dirs: HashSet::new() })
+    }
+
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let path = path.canonicalize().with_context(|| format!("watch {}", path.display()))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| path.clone());
+        if self.dirs.insert(dir.clone()) {
+            self.watcher.watch(&dir, RecursiveMode::NonRecursive).with_context(|| format!("watch {}", dir.display()))?;
+        }
+        self.files.insert(path);
+        Ok(())
+    }
+
+    pub fn is_watched(&self, path: &Path) -> bool {
+        path.canonicalize().is_ok_and(|p| self.files.contains(&p))
+    }
+
+    /// Watched files created or modified since the last call, each once.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!("file watcher: {e}");
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if self.files.contains(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,186 @@
+use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Inverted box that any `union` or `grow` replaces.
+    pub const EMPTY: Self = Self { min: Vec3::splat(f32::INFINITY), max: Vec3::splat(f32::NEG_INFINITY) };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center_half_extents(center: Vec3, half: Vec3) -> Self {
+        Self { min: center - half, max: center + half }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |b, p| b.grow(p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn grow(&self, p: Vec3) -> Self {
+        Self { min: self.min.min(p), max: self.max.max(p) }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    pub fn expand(&self, margin: f32) -> Self {
+        Self { min: self.min - Vec3::splat(margin), max: self.max + Vec3::splat(margin) }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    pub fn contains(&self, other: &Self) -> bool {
+        self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        self.min.cmple(p).all() && self.max.cmpge(p).all()
+    }
+
+    /// Bounds of this box after an affine transform (Arvo's method).
+    pub fn transform(&self, m: &Mat4) -> Self {
+        let center = m.transform_point3(self.center());
+        let h = self.half_extents();
+        let half = m.x_axis.truncate().abs() * h.x + m.y_axis.truncate().abs() * h.y + m.z_axis.truncate().abs() * h.z;
+        Self::from_center_half_extents(center, half)
+    }
+
+    /// Entry distance along `ray`, clamped to 0 when the origin is inside.
+    pub fn ray_intersect(&self, ray: &Ray) -> Option<f32> {
+        let inv = ray.direction.recip();
+        let t0 = (self.min - ray.origin) * inv;
+        let t1 = (self.max - ray.origin) * inv;
+        let t_near = t0.min(t1).max_element().max(0.0);
+        let t_far = t0.max(t1).min_element();
+        (t_far >= t_near).then_some(t_near)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        Self { center: aabb.center(), radius: aabb.half_extents().length() }
+    }
+
+    /// Conservative bounds under `m`; the radius scales by the largest axis scale.
+    pub fn transform(&self, m: &Mat4) -> Self {
+        let scale = m.x_axis.truncate().length_squared()
+            .max(m.y_axis.truncate().length_squared())
+            .max(m.z_axis.truncate().length_squared())
+            .sqrt();
+        Self { center: m.transform_point3(self.center), radius: self.radius * scale }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_center_half_extents(self.center, Vec3::splat(self.radius))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Not required to be normalized; distances are in units of its length.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// Plane `normal · p + d = 0`; points with a positive distance are in front.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn from_vec4(v: Vec4) -> Self {
+        let len = v.truncate().length();
+        Self { normal: v.truncate() / len, d: v.w / len }
+    }
+
+    pub fn distance(&self, p: Vec3) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near, far; normals point inward.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a `[0, 1]` depth-range view-projection matrix.
+    pub fn from_view_proj(m: &Mat4) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        Self {
+            planes: [
+                Plane::from_vec4(r3 + r0),
+                Plane::from_vec4(r3 - r0),
+                Plane::from_vec4(r3 + r1),
+                Plane::from_vec4(r3 - r1),
+                Plane::from_vec4(r2),
+                Plane::from_vec4(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, s: &Sphere) -> bool {
+        self.planes.iter().all(|p| p.distance(s.center) >= -s.radius)
+    }
+
+    pub fn intersects_aabb(&self, b: &Aabb) -> bool {
+        self.planes.iter().all(|p| {
+            let positive = Vec3::select(p.normal.cmpge(Vec3::ZERO), b.max, b.min);
+            p.distance(positive) >= 0.0
+        })
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|p| p.distance(point) >= 0.0)
+    }
+}
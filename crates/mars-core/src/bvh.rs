@@ -0,0 +1,473 @@
+//! Dynamic AABB tree for moving objects.
+//!
+//! Leaves store a "fat" box (the real bounds grown by a margin), so small
+//! movements only touch the tree when an object leaves its fat box. Inserts
+//! pick a sibling by surface area cost and the tree is kept balanced with
+//! AVL-style rotations.
+
+use crate::bounds::{Aabb, Frustum, Ray};
+
+const NULL: u32 = u32::MAX;
+
+/// Handle to an object in a [`Bvh`]. The generation tells a removed object
+/// apart from a later one reusing its node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BvhProxy {
+    index: u32,
+    generation: u32,
+}
+
+struct Node<T> {
+    aabb: Aabb,
+    /// Parent link, or the next free node while on the free list.
+    parent: u32,
+    left: u32,
+    right: u32,
+    /// 0 for leaves, -1 for free nodes.
+    height: i32,
+    /// Bumped each time the node is freed.
+    generation: u32,
+    data: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn is_leaf(&self) -> bool {
+        self.left == NULL
+    }
+}
+
+pub struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    root: u32,
+    free: u32,
+    margin: f32,
+    len: usize,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl<T> Bvh<T> {
+    pub fn new(margin: f32) -> Self {
+        Self { nodes: Vec::new(), root: NULL, free: NULL, margin, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn height(&self) -> i32 {
+        if self.root == NULL {
+            0
+        } else {
+            self.nodes[self.root as usize].height
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NULL;
+        self.free = NULL;
+        self.len = 0;
+    }
+
+    pub fn insert(&mut self, aabb: Aabb, data: T) -> BvhProxy {
+        let leaf = self.allocate();
+        let node = &mut self.nodes[leaf as usize];
+        node.aabb = aabb.expand(self.margin);
+        node.height = 0;
+        node.data = Some(data);
+        self.insert_leaf(leaf);
+        self.len += 1;
+        self.proxy(leaf)
+    }
+
+    pub fn remove(&mut self, proxy: BvhProxy) -> Option<T> {
+        let leaf = self.leaf(proxy)?;
+        self.remove_leaf(leaf);
+        let data = self.nodes[leaf as usize].data.take();
+        self.release(leaf);
+        self.len -= 1;
+        data
+    }
+
+    /// Refits a moved object. Returns `true` if it left its fat box and was
+    /// reinserted, `false` if the tree was untouched.
+    pub fn update(&mut self, proxy: BvhProxy, aabb: Aabb) -> bool {
+        let Some(leaf) = self.leaf(proxy) else { return false };
+        if self.nodes[leaf as usize].aabb.contains(&aabb) {
+            return false;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf as usize].aabb = aabb.expand(self.margin);
+        self.insert_leaf(leaf);
+        true
+    }
+
+    pub fn get(&self, proxy: BvhProxy) -> Option<&T> {
+        let leaf = self.leaf(proxy)?;
+        self.nodes[leaf as usize].data.as_ref()
+    }
+
+    pub fn get_mut(&mut self, proxy: BvhProxy) -> Option<&mut T> {
+        let leaf = self.leaf(proxy)?;
+        self.nodes[leaf as usize].data.as_mut()
+    }
+
+    pub fn contains(&self, proxy: BvhProxy) -> bool {
+        self.leaf(proxy).is_some()
+    }
+
+    pub fn fat_aabb(&self, proxy: BvhProxy) -> Option<Aabb> {
+        self.leaf(proxy).map(|leaf| self.nodes[leaf as usize].aabb)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BvhProxy, &T)> {
+        self.nodes.iter().enumerate().filter_map(|(i, n)| n.data.as_ref().map(|d| (self.proxy(i as u32), d)))
+    }
+
+    /// Calls `f` for every leaf whose fat box overlaps `aabb`; return `false`
+    /// from `f` to stop early.
+    pub fn query_aabb(&self, aabb: &Aabb, mut f: impl FnMut(BvhProxy, &T) -> bool) {
+        self.traverse(|b| b.intersects(aabb), |p, d| f(p, d));
+    }
+
+    pub fn query_frustum(&self, frustum: &Frustum, mut f: impl FnMut(BvhProxy, &T) -> bool) {
+        self.traverse(|b| frustum.intersects_aabb(b), |p, d| f(p, d));
+    }
+
+    /// Closest hit along `ray` within `max_t`. `hit` performs the exact test
+    /// for a candidate leaf and returns its distance, if any.
+    pub fn cast_ray(
+        &self,
+        ray: &Ray,
+        max_t: f32,
+        mut hit: impl FnMut(BvhProxy, &T) -> Option<f32>,
+    ) -> Option<(BvhProxy, f32)> {
+        let mut best = None;
+        let mut max_t = max_t;
+        let mut stack = Vec::with_capacity(32);
+        if self.root != NULL {
+            stack.push(self.root);
+        }
+
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i as usize];
+            match node.aabb.ray_intersect(ray) {
+                Some(t) if t <= max_t => {}
+                _ => continue,
+            }
+            if node.is_leaf() {
+                if let Some(t) = hit(self.proxy(i), node.data.as_ref().unwrap()) {
+                    if t <= max_t {
+                        max_t = t;
+                        best = Some((self.proxy(i), t));
+                    }
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+        best
+    }
+
+    fn traverse(&self, mut overlaps: impl FnMut(&Aabb) -> bool, mut f: impl FnMut(BvhProxy, &T) -> bool) {
+        let mut stack = Vec::with_capacity(32);
+        if self.root != NULL {
+            stack.push(self.root);
+        }
+
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i as usize];
+            if !overlaps(&node.aabb) {
+                continue;
+            }
+            if node.is_leaf() {
+                if !f(self.proxy(i), node.data.as_ref().unwrap()) {
+                    return;
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+    }
+
+    fn proxy(&self, leaf: u32) -> BvhProxy {
+        BvhProxy { index: leaf, generation: self.nodes[leaf as usize].generation }
+    }
+
+    /// Node index of a live leaf, or `None` if `proxy` is stale.
+    fn leaf(&self, proxy: BvhProxy) -> Option<u32> {
+        let node = self.nodes.get(proxy.index as usize)?;
+        let live = node.generation == proxy.generation && node.height == 0 && node.data.is_some();
+        live.then_some(proxy.index)
+    }
+
+    fn allocate(&mut self) -> u32 {
+        let mut fresh = Node { aabb: Aabb::EMPTY, parent: NULL, left: NULL, right: NULL, height: 0, generation: 0, data: None };
+        if self.free == NULL {
+            self.nodes.push(fresh);
+            return self.nodes.len() as u32 - 1;
+        }
+        let i = self.free;
+        self.free = self.nodes[i as usize].parent;
+        fresh.generation = self.nodes[i as usize].generation;
+        self.nodes[i as usize] = fresh;
+        i
+    }
+
+    fn release(&mut self, i: u32) {
+        let node = &mut self.nodes[i as usize];
+        node.parent = self.free;
+        node.left = NULL;
+        node.right = NULL;
+        node.height = -1;
+        node.generation = node.generation.wrapping_add(1);
+        node.data = None;
+        self.free = i;
+    }
+
+    fn insert_leaf(&mut self, leaf: u32) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf as usize].parent = NULL;
+            return;
+        }
+
+        // Descend towards the sibling with the lowest surface area cost.
+        let leaf_aabb = self.nodes[leaf as usize].aabb;
+        let mut index = self.root;
+        while !self.nodes[index as usize].is_leaf() {
+            let node = &self.nodes[index as usize];
+            let area = node.aabb.surface_area();
+            let combined_area = node.aabb.union(&leaf_aabb).surface_area();
+            let cost = 2.0 * combined_area;
+            let inheritance = 2.0 * (combined_area - area);
+
+            let child_cost = |c: u32| {
+                let child = &self.nodes[c as usize];
+                let merged = leaf_aabb.union(&child.aabb).surface_area();
+                if child.is_leaf() { merged + inheritance } else { merged - child.aabb.surface_area() + inheritance }
+            };
+            let (cost_left, cost_right) = (child_cost(node.left), child_cost(node.right));
+
+            if cost < cost_left && cost < cost_right {
+                break;
+            }
+            index = if cost_left < cost_right { node.left } else { node.right };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling as usize].parent;
+        let new_parent = self.allocate();
+        {
+            let sibling_node = &self.nodes[sibling as usize];
+            let aabb = leaf_aabb.union(&sibling_node.aabb);
+            let height = sibling_node.height + 1;
+            let node = &mut self.nodes[new_parent as usize];
+            node.parent = old_parent;
+            node.aabb = aabb;
+            node.height = height;
+            node.left = sibling;
+            node.right = leaf;
+        }
+        self.nodes[sibling as usize].parent = new_parent;
+        self.nodes[leaf as usize].parent = new_parent;
+        self.replace_child(old_parent, sibling, new_parent);
+
+        self.fix_upwards(self.nodes[leaf as usize].parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: u32) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+        let parent = self.nodes[leaf as usize].parent;
+        let grand = self.nodes[parent as usize].parent;
+        let p = &self.nodes[parent as usize];
+        let sibling = if p.left == leaf { p.right } else { p.left };
+
+        self.nodes[sibling as usize].parent = grand;
+        self.replace_child(grand, parent, sibling);
+        self.release(parent);
+        self.fix_upwards(grand);
+    }
+
+    /// Points `parent`'s link to `old` at `new`, or makes `new` the root.
+    fn replace_child(&mut self, parent: u32, old: u32, new: u32) {
+        if parent == NULL {
+            self.root = new;
+            return;
+        }
+        let p = &mut self.nodes[parent as usize];
+        if p.left == old {
+            p.left = new;
+        } else {
+            p.right = new;
+        }
+    }
+
+    fn fix_upwards(&mut self, mut index: u32) {
+        while index != NULL {
+            index = self.balance(index);
+            let (l, r) = (self.nodes[index as usize].left, self.nodes[index as usize].right);
+            let aabb = self.nodes[l as usize].aabb.union(&self.nodes[r as usize].aabb);
+            let height = 1 + self.nodes[l as usize].height.max(self.nodes[r as usize].height);
+            let node = &mut self.nodes[index as usize];
+            node.aabb = aabb;
+            node.height = height;
+            index = node.parent;
+        }
+    }
+
+    /// Rotates the taller child of `a` up if the subtree is imbalanced and
+    /// returns the new subtree root.
+    fn balance(&mut self, a: u32) -> u32 {
+        let (b, c) = {
+            let n = &self.nodes[a as usize];
+            if n.is_leaf() || n.height < 2 {
+                return a;
+            }
+            (n.left, n.right)
+        };
+        let diff = self.nodes[c as usize].height - self.nodes[b as usize].height;
+        if diff > 1 {
+            self.rotate_up(a, c, b, false)
+        } else if diff < -1 {
+            self.rotate_up(a, b, c, true)
+        } else {
+            a
+        }
+    }
+
+    /// Promotes `up` (a child of `a`) above `a`. `other` is `a`'s remaining
+    /// child; `up_is_left` tells which side of `a` `up` came from.
+    fn rotate_up(&mut self, a: u32, up: u32, other: u32, up_is_left: bool) -> u32 {
+        let (f, g) = (self.nodes[up as usize].left, self.nodes[up as usize].right);
+        let a_parent = self.nodes[a as usize].parent;
+
+        self.nodes[up as usize].left = a;
+        self.nodes[up as usize].parent = a_parent;
+        self.nodes[a as usize].parent = up;
+        self.replace_child(a_parent, a, up);
+
+        // Keep the taller grandchild under `up`, hand the shorter one to `a`.
+        let (keep, give) = if self.nodes[f as usize].height > self.nodes[g as usize].height { (f, g) } else { (g, f) };
+        self.nodes[up as usize].right = keep;
+        if up_is_left {
+            self.nodes[a as usize].left = give;
+        } else {
+            self.nodes[a as usize].right = give;
+        }
+        self.nodes[give as usize].parent = a;
+
+        let a_aabb = self.nodes[other as usize].aabb.union(&self.nodes[give as usize].aabb);
+        let a_height = 1 + self.nodes[other as usize].height.max(self.nodes[give as usize].height);
+        self.nodes[a as usize].aabb = a_aabb;
+        self.nodes[a as usize].height = a_height;
+
+        self.nodes[up as usize].aabb = a_aabb.union(&self.nodes[keep as usize].aabb);
+        self.nodes[up as usize].height = 1 + a_height.max(self.nodes[keep as usize].height);
+        up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn cube(center: Vec3) -> Aabb {
+        Aabb::from_center_half_extents(center, Vec3::splat(0.5))
+    }
+
+    fn overlapping(bvh: &Bvh<u32>, aabb: Aabb) -> Vec<u32> {
+        let mut hits = Vec::new();
+        bvh.query_aabb(&aabb, |_, &d| {
+            hits.push(d);
+            true
+        });
+        hits.sort();
+        hits
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut bvh = Bvh::new(0.1);
+        let proxies: Vec<_> = (0..100).map(|i| bvh.insert(cube(Vec3::new(i as f32 * 2.0, 0.0, 0.0)), i)).collect();
+        assert_eq!(bvh.len(), 100);
+        assert!(bvh.height() <= 10, "unbalanced: height {}", bvh.height());
+        for (i, &p) in proxies.iter().enumerate() {
+            assert_eq!(bvh.get(p), Some(&(i as u32)));
+        }
+
+        assert_eq!(bvh.remove(proxies[7]), Some(7));
+        assert_eq!(bvh.remove(proxies[7]), None);
+        assert_eq!(bvh.len(), 99);
+        assert_eq!(bvh.iter().count(), 99);
+        assert!(overlapping(&bvh, cube(Vec3::new(14.0, 0.0, 0.0))).is_empty());
+    }
+
+    #[test]
+    fn stale_proxy_after_reuse() {
+        let mut bvh = Bvh::new(0.1);
+        let a = bvh.insert(cube(Vec3::ZERO), 1);
+        bvh.remove(a);
+        let b = bvh.insert(cube(Vec3::ZERO), 2);
+        assert_ne!(a, b);
+        assert_eq!(bvh.get(a), None);
+        assert_eq!(bvh.get_mut(a), None);
+        assert!(!bvh.update(a, cube(Vec3::X * 10.0)));
+        assert_eq!(bvh.remove(a), None);
+        assert_eq!(bvh.get(b), Some(&2));
+    }
+
+    #[test]
+    fn refit_only_when_leaving_fat_box() {
+        let mut bvh = Bvh::new(0.5);
+        let p = bvh.insert(cube(Vec3::ZERO), 0);
+        bvh.insert(cube(Vec3::X * 5.0), 1);
+        assert!(!bvh.update(p, cube(Vec3::X * 0.2)));
+        assert!(bvh.update(p, cube(Vec3::Y * 20.0)));
+        assert_eq!(overlapping(&bvh, cube(Vec3::Y * 20.0)), vec![0]);
+        assert!(overlapping(&bvh, cube(Vec3::ZERO)).is_empty());
+    }
+
+    #[test]
+    fn aabb_query_matches_brute_force() {
+        let mut bvh = Bvh::new(0.0);
+        let boxes: Vec<_> = (0..64).map(|i| cube(Vec3::new((i % 8) as f32 * 1.5, (i / 8) as f32 * 1.5, 0.0))).collect();
+        for (i, b) in boxes.iter().enumerate() {
+            bvh.insert(*b, i as u32);
+        }
+        let query = Aabb::new(Vec3::new(2.0, 2.0, -1.0), Vec3::new(5.0, 4.0, 1.0));
+        let expected: Vec<u32> = (0..64).filter(|&i| boxes[i as usize].intersects(&query)).collect();
+        assert_eq!(overlapping(&bvh, query), expected);
+    }
+
+    #[test]
+    fn ray_returns_closest_hit() {
+        let mut bvh = Bvh::new(0.0);
+        for i in 0..10 {
+            bvh.insert(cube(Vec3::new(0.0, 0.0, -(i as f32) * 3.0 - 5.0)), i);
+        }
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        let hit = bvh.cast_ray(&ray, f32::MAX, |p, _| bvh.fat_aabb(p)?.ray_intersect(&ray));
+        let (proxy, t) = hit.expect("hit");
+        assert_eq!(bvh.get(proxy), Some(&0));
+        assert!((t - 4.5).abs() < 1e-4, "t = {t}");
+        assert!(bvh.cast_ray(&ray, 4.0, |p, _| bvh.fat_aabb(p)?.ray_intersect(&ray)).is_none());
+        assert!(bvh.cast_ray(&Ray::new(Vec3::ZERO, Vec3::Z), f32::MAX, |_, _| Some(0.0)).is_none());
+    }
+}
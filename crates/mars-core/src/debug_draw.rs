@@ -0,0 +1,407 @@
+//! Immediate-mode debug shapes that any system can queue from any thread.
+//!
+//! Shapes go into the [`DebugDraw::global`] queue, renderers draw a
+//! [snapshot](DebugDraw::snapshot) of it and whoever drives the frames
+//! [ticks](DebugDraw::tick) it once per frame. An item lives for one frame
+//! unless its [`DebugStyle::duration`] is set. World-space shapes use world
+//! units; screen-space shapes use pixels of the render target from its
+//! top-left corner.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use parking_lot::Mutex;
+
+use crate::bounds::Aabb;
+
+/// Segments per full circle of a sphere or circle outline.
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugStyle {
+    /// Linear RGBA.
+    pub color: Vec4,
+    /// Hidden behind scene geometry when the renderer has a depth buffer.
+    pub depth_test: bool,
+    /// Seconds to keep drawing the item; 0 draws it for one frame.
+    pub duration: f32,
+}
+
+impl DebugStyle {
+    pub fn new(color: Vec4) -> Self {
+        Self { color, depth_test: true, duration: 0.0 }
+    }
+
+    /// Draws on top of the scene.
+    pub fn without_depth_test(mut self) -> Self {
+        self.depth_test = false;
+        self
+    }
+
+    pub fn lasting(mut self, seconds: f32) -> Self {
+        self.duration = seconds;
+        self
+    }
+}
+
+impl From<Vec4> for DebugStyle {
+    fn from(color: Vec4) -> Self {
+        Self::new(color)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugSpace {
+    World,
+    /// Pixels from the top-left corner; z is ignored.
+    Screen,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugShape {
+    Line { a: Vec3, b: Vec3 },
+    Arrow { from: Vec3, to: Vec3 },
+    /// The cube from -1 to 1 under `transform`.
+    Box { transform: Mat4 },
+    Sphere { center: Vec3, radius: f32 },
+    /// The `[0, 1]` depth clip volume under `inv_view_proj`.
+    Frustum { inv_view_proj: Mat4 },
+    /// `cells` by `cells` squares spanned by `u` and `v`, centered on `center`.
+    Grid { center: Vec3, u: Vec3, v: Vec3, cells: u32 },
+    /// Text whose top-left corner is at `position` (projected for world
+    /// space), `size` pixels tall.
+    Text { position: Vec3, text: String, size: f32 },
+}
+
+impl DebugShape {
+    /// Appends the line segments outlining the shape. Text has none.
+    pub fn segments(&self, space: DebugSpace, out: &mut Vec<[Vec3; 2]>) {
+        match *self {
+            DebugShape::Line { a, b } => out.push([a, b]),
+            DebugShape::Arrow { from, to } => {
+                out.push([from, to]);
+                let dir = to - from;
+                let len = dir.length();
+                if len <= f32::EPSILON {
+                    return;
+                }
+                let head = len.min(1.0) * 0.2;
+                let back = to - dir / len * head;
+                let sides = match space {
+                    DebugSpace::Screen => vec![Vec3::new(-dir.y, dir.x, 0.0) / len],
+                    DebugSpace::World => {
+                        let (a, b) = (dir / len).any_orthonormal_pair();
+                        vec![a, b]
+                    }
+                };
+                for side in sides {
+                    out.push([to, back + side * head * 0.5]);
+                    out.push([to, back - side * head * 0.5]);
+                }
+            }
+            DebugShape::Box { transform } => {
+                let corners: [Vec3; 8] = std::array::from_fn(|i| {
+                    let c = Vec3::new(
+                        if i & 1 == 0 { -1.0 } else { 1.0 },
+                        if i & 2 == 0 { -1.0 } else { 1.0 },
+                        if i & 4 == 0 { -1.0 } else { 1.0 },
+                    );
+                    transform.transform_point3(c)
+                });
+                push_cube_edges(&corners, space == DebugSpace::Screen, out);
+            }
+            DebugShape::Frustum { inv_view_proj } => {
+                let corners: [Vec3; 8] = std::array::from_fn(|i| {
+                    let ndc = Vec3::new(
+                        if i & 1 == 0 { -1.0 } else { 1.0 },
+                        if i & 2 == 0 { -1.0 } else { 1.0 },
+                        if i & 4 == 0 { 0.0 } else { 1.0 },
+                    );
+                    inv_view_proj.project_point3(ndc)
+                });
+                push_cube_edges(&corners, false, out);
+            }
+            DebugShape::Sphere { center, radius } => {
+                let axes = match space {
+                    DebugSpace::Screen => &[(Vec3::X, Vec3::Y)][..],
+                    DebugSpace::World => &[(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)][..],
+                };
+                for &(u, v) in axes {
+                    let point = |i: usize| {
+                        let a = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                        center + (u * a.cos() + v * a.sin()) * radius
+                    };
+                    out.extend((0..CIRCLE_SEGMENTS).map(|i| [point(i), point(i + 1)]));
+                }
+            }
+            DebugShape::Grid { center, u, v, cells } => {
+                let half = cells as f32 * 0.5;
+                for i in 0..=cells {
+                    let t = i as f32 - half;
+                    out.push([center + u * t - v * half, center + u * t + v * half]);
+                    out.push([center + v * t - u * half, center + v * t + u * half]);
+                }
+            }
+            DebugShape::Text { .. } => {}
+        }
+    }
+}
+
+/// Edges of a box whose corner `i` has bit 0 set for +x, bit 1 for +y and
+/// bit 2 for +z. A `flat` box only has its z = -1 face.
+fn push_cube_edges(corners: &[Vec3; 8], flat: bool, out: &mut Vec<[Vec3; 2]>) {
+    let (count, bits) = if flat { (4, &[1, 2][..]) } else { (8, &[1, 2, 4][..]) };
+    for i in 0..count {
+        for &bit in bits {
+            if i & bit == 0 {
+                out.push([corners[i], corners[i | bit]]);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugItem {
+    pub shape: DebugShape,
+    pub space: DebugSpace,
+    pub style: DebugStyle,
+    /// Seconds left; the item is dropped once this reaches 0.
+    pub remaining: f32,
+}
+
+/// Thread-safe queue of debug shapes.
+pub struct DebugDraw {
+    items: Mutex<Vec<DebugItem>>,
+    enabled: AtomicBool,
+}
+
+static GLOBAL: DebugDraw = DebugDraw::new();
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugDraw {
+    pub const fn new() -> Self {
+        Self { items: Mutex::new(Vec::new()), enabled: AtomicBool::new(true) }
+    }
+
+    /// The process-wide queue drawn by the renderer.
+    pub fn global() -> &'static DebugDraw {
+        &GLOBAL
+    }
+
+    /// While disabled, new items are ignored; queued ones still expire.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn push(&self, shape: DebugShape, space: DebugSpace, style: impl Into<DebugStyle>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let style = style.into();
+        self.items.lock().push(DebugItem { shape, space, style, remaining: style.duration });
+    }
+
+    pub fn line(&self, a: Vec3, b: Vec3, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Line { a, b }, DebugSpace::World, style);
+    }
+
+    pub fn arrow(&self, from: Vec3, to: Vec3, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Arrow { from, to }, DebugSpace::World, style);
+    }
+
+    pub fn aabb(&self, aabb: &Aabb, style: impl Into<DebugStyle>) {
+        let transform = Mat4::from_translation(aabb.center()) * Mat4::from_scale(aabb.half_extents());
+        self.push(DebugShape::Box { transform }, DebugSpace::World, style);
+    }
+
+    /// A box with half extents `half` under `transform`, for oriented boxes.
+    pub fn cuboid(&self, transform: Mat4, half: Vec3, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Box { transform: transform * Mat4::from_scale(half) }, DebugSpace::World, style);
+    }
+
+    pub fn sphere(&self, center: Vec3, radius: f32, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Sphere { center, radius }, DebugSpace::World, style);
+    }
+
+    /// The volume seen through `view_proj`, e.g. another camera's.
+    pub fn frustum(&self, view_proj: &Mat4, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Frustum { inv_view_proj: view_proj.inverse() }, DebugSpace::World, style);
+    }
+
+    /// A grid on the plane through `center` spanned by the cell edges `u`
+    /// and `v`.
+    pub fn grid(&self, center: Vec3, u: Vec3, v: Vec3, cells: u32, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Grid { center, u, v, cells }, DebugSpace::World, style);
+    }
+
+    /// A label at a world position, `size` pixels tall.
+    pub fn text(&self, position: Vec3, text: impl Into<String>, size: f32, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Text { position, text: text.into(), size }, DebugSpace::World, style);
+    }
+
+    pub fn line_2d(&self, a: Vec2, b: Vec2, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Line { a: a.extend(0.0), b: b.extend(0.0) }, DebugSpace::Screen, style);
+    }
+
+    pub fn arrow_2d(&self, from: Vec2, to: Vec2, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Arrow { from: from.extend(0.0), to: to.extend(0.0) }, DebugSpace::Screen, style);
+    }
+
+    pub fn rect_2d(&self, min: Vec2, max: Vec2, style: impl Into<DebugStyle>) {
+        let center = ((min + max) * 0.5).extend(0.0);
+        let half = ((max - min) * 0.5).extend(0.0);
+        let transform = Mat4::from_translation(center) * Mat4::from_scale(half);
+        self.push(DebugShape::Box { transform }, DebugSpace::Screen, style);
+    }
+
+    pub fn circle_2d(&self, center: Vec2, radius: f32, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Sphere { center: center.extend(0.0), radius }, DebugSpace::Screen, style);
+    }
+
+    pub fn text_2d(&self, position: Vec2, text: impl Into<String>, size: f32, style: impl Into<DebugStyle>) {
+        self.push(DebugShape::Text { position: position.extend(0.0), text: text.into(), size }, DebugSpace::Screen, style);
+    }
+
+    pub fn clear(&self) {
+        self.items.lock().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.lock().is_empty()
+    }
+
+    /// Copies the items to draw this frame into `out`. Leaves the queue
+    /// as is, so any number of renderers can draw the same frame.
+    pub fn snapshot(&self, out: &mut Vec<DebugItem>) {
+        out.extend(self.items.lock().iter().cloned());
+    }
+
+    /// Ages the items by `dt` seconds and drops those with no time left.
+    /// Call once per frame after drawing it, so every item is drawn at least
+    /// once.
+    pub fn tick(&self, dt: f32) {
+        self.items.lock().retain_mut(|item| {
+            item.remaining -= dt;
+            item.remaining > 0.0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, vec3};
+
+    use super::*;
+
+    fn segments(shape: DebugShape, space: DebugSpace) -> Vec<[Vec3; 2]> {
+        let mut out = Vec::new();
+        shape.segments(space, &mut out);
+        out
+    }
+
+    #[test]
+    fn arrow_heads_point_at_the_tip() {
+        let (from, to) = (Vec3::ZERO, vec3(0.0, 0.0, 4.0));
+        let world = segments(DebugShape::Arrow { from, to }, DebugSpace::World);
+        assert_eq!(world.len(), 5);
+        assert_eq!(world[0], [from, to]);
+        for &[tip, barb] in &world[1..] {
+            assert_eq!(tip, to);
+            // Heads are 0.2 long, capped at arrows of length 1.
+            assert!((barb.z - 3.8).abs() < 1e-5, "{barb}");
+            assert!((barb.truncate().length() - 0.1).abs() < 1e-5, "{barb}");
+        }
+
+        let screen = segments(DebugShape::Arrow { from: Vec3::ZERO, to: vec3(0.5, 0.0, 0.0) }, DebugSpace::Screen);
+        assert_eq!(screen.len(), 3);
+        assert!(screen.iter().all(|s| s[0].z == 0.0 && s[1].z == 0.0));
+        assert!((screen[1][1] - vec3(0.4, 0.05, 0.0)).length() < 1e-5, "{}", screen[1][1]);
+
+        assert_eq!(segments(DebugShape::Arrow { from: to, to }, DebugSpace::World), [[to, to]]);
+    }
+
+    #[test]
+    fn boxes_have_twelve_edges_and_screen_boxes_four() {
+        let transform = Mat4::from_scale(Vec3::splat(2.0));
+        let edges = segments(DebugShape::Box { transform }, DebugSpace::World);
+        assert_eq!(edges.len(), 12);
+        for [a, b] in edges {
+            assert_eq!((b - a).length(), 4.0);
+        }
+
+        let draw = DebugDraw::new();
+        draw.rect_2d(vec2(10.0, 20.0), vec2(30.0, 60.0), Vec4::ONE);
+        let mut items = Vec::new();
+        draw.snapshot(&mut items);
+        let edges = segments(items[0].shape.clone(), DebugSpace::Screen);
+        let expected = [
+            [vec3(10.0, 20.0, 0.0), vec3(30.0, 20.0, 0.0)],
+            [vec3(10.0, 20.0, 0.0), vec3(10.0, 60.0, 0.0)],
+            [vec3(30.0, 20.0, 0.0), vec3(30.0, 60.0, 0.0)],
+            [vec3(10.0, 60.0, 0.0), vec3(30.0, 60.0, 0.0)],
+        ];
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn grids_and_circles() {
+        let grid = DebugShape::Grid { center: vec3(0.0, 1.0, 0.0), u: Vec3::X, v: Vec3::Z, cells: 2 };
+        let lines = segments(grid, DebugSpace::World);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], [vec3(-1.0, 1.0, -1.0), vec3(-1.0, 1.0, 1.0)]);
+        assert_eq!(lines[1], [vec3(-1.0, 1.0, -1.0), vec3(1.0, 1.0, -1.0)]);
+        assert_eq!(lines[5], [vec3(-1.0, 1.0, 1.0), vec3(1.0, 1.0, 1.0)]);
+
+        let sphere = DebugShape::Sphere { center: Vec3::ZERO, radius: 2.0 };
+        assert_eq!(segments(sphere.clone(), DebugSpace::World).len(), 3 * CIRCLE_SEGMENTS);
+        let circle = segments(sphere, DebugSpace::Screen);
+        assert_eq!(circle.len(), CIRCLE_SEGMENTS);
+        assert!(circle.iter().flatten().all(|p| (p.length() - 2.0).abs() < 1e-5 && p.z == 0.0));
+
+        let text = DebugShape::Text { position: Vec3::ZERO, text: "hi".into(), size: 12.0 };
+        assert!(segments(text, DebugSpace::World).is_empty());
+    }
+
+    #[test]
+    fn items_are_drawn_at_least_once_and_dropped_at_zero() {
+        let draw = DebugDraw::new();
+        draw.line(Vec3::ZERO, Vec3::X, Vec4::ONE);
+        draw.line(Vec3::ZERO, Vec3::Y, DebugStyle::new(Vec4::ONE).lasting(0.5));
+
+        let mut drawn = Vec::new();
+        for _ in 0..4 {
+            let mut items = Vec::new();
+            // Several renderers may read the same frame.
+            draw.snapshot(&mut items);
+            draw.snapshot(&mut items);
+            drawn.push(items.len() / 2);
+            draw.tick(0.25);
+        }
+        assert_eq!(drawn, [2, 1, 0, 0]);
+
+        // A frame longer than the item's duration still draws it once.
+        draw.line(Vec3::ZERO, Vec3::Z, DebugStyle::new(Vec4::ONE).lasting(0.1));
+        let mut items = Vec::new();
+        draw.snapshot(&mut items);
+        assert_eq!(items.len(), 1);
+        draw.tick(1.0);
+        assert!(draw.is_empty());
+
+        draw.set_enabled(false);
+        draw.line(Vec3::ZERO, Vec3::X, Vec4::ONE);
+        assert!(draw.is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+
+use hashbrown::HashMap;
+
+use super::borrow::{BorrowFlag, Ref, RefMut};
+use super::entity::Entity;
+use super::{Component, ComponentInfo};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArchetypeId(pub(crate) u32);
+
+/// Type-erased `Vec<T>` of one component type.
+pub(crate) trait ColumnStorage: Any + Send + Sync {
+    /// Drops the value at `row`, moving the last one into its place.
+    fn swap_remove(&mut self, row: usize);
+    /// Moves the value at `row` to the end of `other`, a column of the same
+    /// type, moving the last one into its place.
+    fn move_to(&mut self, row: usize, other: &mut dyn ColumnStorage);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> ColumnStorage for Vec<T> {
+    fn swap_remove(&mut self, row: usize) {
+        Vec::swap_remove(self, row);
+    }
+
+    fn move_to(&mut self, row: usize, other: &mut dyn ColumnStorage) {
+        let value = Vec::swap_remove(self, row);
+        other.as_any_mut().downcast_mut::<Vec<T>>().expect("columns of the same type").push(value);
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub(crate) struct Column {
+    pub info: ComponentInfo,
+    pub borrow: BorrowFlag,
+    storage: UnsafeCell<Box<dyn ColumnStorage>>,
+}
+
+// Access to `storage` through `&Column` is guarded by `borrow`.
+unsafe impl Sync for Column {}
+
+impl Column {
+    fn new(info: ComponentInfo) -> Self {
+        Self { info, borrow: BorrowFlag::default(), storage: UnsafeCell::new((info.new_column)()) }
+    }
+
+    pub fn storage_mut(&mut self) -> &mut dyn ColumnStorage {
+        &mut **self.storage.get_mut()
+    }
+
+    pub fn vec_mut<T: Component>(&mut self) -> &mut Vec<T> {
+        self.storage.get_mut().as_any_mut().downcast_mut::<Vec<T>>().expect("column of T")
+    }
+
+    /// Pointer to the first value, for reading.
+    ///
+    /// # Safety
+    /// The caller must hold a shared borrow of the column.
+    pub unsafe fn data<T: Component>(&self) -> *const T {
+        let storage = unsafe { &**self.storage.get() };
+        storage.as_any().downcast_ref::<Vec<T>>().expect("column of T").as_ptr()
+    }
+
+    /// Pointer to the first value, for reading and writing.
+    ///
+    /// # Safety
+    /// The caller must hold the unique borrow of the column.
+    pub unsafe fn data_mut<T: Component>(&self) -> *mut T {
+        let storage = unsafe { &mut **self.storage.get() };
+        storage.as_any_mut().downcast_mut::<Vec<T>>().expect("column of T").as_mut_ptr()
+    }
+
+    pub fn borrow(&self) {
+        if !self.borrow.try_borrow() {
+            panic!("{} is already borrowed mutably", self.info.name);
+        }
+    }
+
+    pub fn borrow_mut(&self) {
+        if !self.borrow.try_borrow_mut() {
+            panic!("{} is already borrowed", self.info.name);
+        }
+    }
+}
+
+/// The entities with one particular set of component types, and their
+/// components in one column per type. Rows line up across columns.
+pub struct Archetype {
+    /// Sorted.
+    types: Vec<TypeId>,
+    columns: Vec<Column>,
+    entities: Vec<Entity>,
+    /// Archetypes reached by inserting a bundle type or removing a component
+    /// type, filled lazily.
+    pub(crate) add_edges: HashMap<TypeId, ArchetypeId>,
+    pub(crate) remove_edges: HashMap<TypeId, ArchetypeId>,
+}
+
+impl Archetype {
+    /// `infos` must be sorted by type id and free of duplicates.
+    pub(crate) fn new(infos: &[ComponentInfo]) -> Self {
+        Self {
+            types: infos.iter().map(|i| i.id).collect(),
+            columns: infos.iter().map(|&i| Column::new(i)).collect(),
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// The component types, sorted by id.
+    pub fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    pub fn component_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.columns.iter().map(|c| c.info.name)
+    }
+
+    pub fn has<T: Component>(&self) -> bool {
+        self.has_type(TypeId::of::<T>())
+    }
+
+    pub fn has_type(&self, id: TypeId) -> bool {
+        self.types.binary_search(&id).is_ok()
+    }
+
+    pub(crate) fn infos(&self) -> impl Iterator<Item = ComponentInfo> + '_ {
+        self.columns.iter().map(|c| c.info)
+    }
+
+    pub(crate) fn column(&self, id: TypeId) -> Option<&Column> {
+        self.types.binary_search(&id).ok().map(|i| &self.columns[i])
+    }
+
+    pub(crate) fn column_mut(&mut self, id: TypeId) -> Option<&mut Column> {
+        self.types.binary_search(&id).ok().map(|i| &mut self.columns[i])
+    }
+
+    pub(crate) fn columns_mut(&mut self) -> &mut [Column] {
+        &mut self.columns
+    }
+
+    pub(crate) fn entities_mut(&mut self) -> &mut Vec<Entity> {
+        &mut self.entities
+    }
+
+    pub(crate) fn get<T: Component>(&self, row: usize) -> Option<Ref<'_, T>> {
+        let column = self.column(TypeId::of::<T>())?;
+        column.borrow();
+        // The shared borrow is held by the returned guard.
+        let value = unsafe { &*column.data::<T>().add(row) };
+        Some(Ref { flag: &column.borrow, value })
+    }
+
+    pub(crate) fn get_mut<T: Component>(&self, row: usize) -> Option<RefMut<'_, T>> {
+        let column = self.column(TypeId::of::<T>())?;
+        column.borrow_mut();
+        // The unique borrow is held by the returned guard.
+        let value = unsafe { &mut *column.data_mut::<T>().add(row) };
+        Some(RefMut { flag: &column.borrow, value })
+    }
+
+    /// Stores `value` at `row`, dropping the previous value, or appends it
+    /// when `row` is one past the end of its column.
+    pub(crate) fn write<T: Component>(&mut self, row: usize, value: T) {
+        let column = self.column_mut(TypeId::of::<T>()).expect("archetype has the bundle's types").vec_mut::<T>();
+        if row < column.len() {
+            column[row] = value;
+        } else {
+            debug_assert_eq!(row, column.len());
+            column.push(value);
+        }
+    }
+
+    /// Drops every row.
+    pub(crate) fn clear(&mut self) {
+        for column in &mut self.columns {
+            column.storage_mut().clear();
+        }
+        self.entities.clear();
+    }
+}
@@ -0,0 +1,80 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const UNIQUE: usize = 1 << (usize::BITS - 1);
+
+/// Shared/unique borrow state of a column: a reader count, or the high bit
+/// while borrowed mutably.
+#[derive(Default)]
+pub(crate) struct BorrowFlag(AtomicUsize);
+
+impl BorrowFlag {
+    pub fn try_borrow(&self) -> bool {
+        let previous = self.0.fetch_add(1, Ordering::Acquire);
+        if previous & UNIQUE != 0 {
+            self.0.fetch_sub(1, Ordering::Release);
+            return false;
+        }
+        true
+    }
+
+    pub fn release(&self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+
+    pub fn try_borrow_mut(&self) -> bool {
+        self.0.compare_exchange(0, UNIQUE, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    pub fn release_mut(&self) {
+        // A subtraction, not a store: failed shared attempts may briefly
+        // count on top of the unique bit.
+        self.0.fetch_sub(UNIQUE, Ordering::Release);
+    }
+}
+
+/// Shared borrow of one component, released on drop.
+pub struct Ref<'a, T> {
+    pub(crate) flag: &'a BorrowFlag,
+    pub(crate) value: &'a T,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> Drop for Ref<'_, T> {
+    fn drop(&mut self) {
+        self.flag.release();
+    }
+}
+
+/// Unique borrow of one component, released on drop.
+pub struct RefMut<'a, T> {
+    pub(crate) flag: &'a BorrowFlag,
+    pub(crate) value: &'a mut T,
+}
+
+impl<T> Deref for RefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for RefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Drop for RefMut<'_, T> {
+    fn drop(&mut self) {
+        self.flag.release_mut();
+    }
+}
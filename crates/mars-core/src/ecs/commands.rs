@@ -0,0 +1,68 @@
+use super::entity::Entity;
+use super::world::{Bundle, World};
+use super::Component;
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Structural changes recorded while the world is shared, applied later in
+/// the order they were recorded.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Records an arbitrary change.
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) {
+        self.add(move |world| {
+            world.spawn(bundle);
+        });
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert<B: Bundle>(&mut self, entity: Entity, bundle: B) {
+        self.add(move |world| {
+            world.insert(entity, bundle);
+        });
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    pub fn insert_resource<R: Component>(&mut self, value: R) {
+        self.add(move |world| {
+            world.insert_resource(value);
+        });
+    }
+
+    /// Applies and drops every recorded change.
+    pub fn apply(&mut self, world: &mut World) {
+        for command in self.queue.drain(..) {
+            command(world);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// Handle to an entity. The generation tells a despawned entity apart from a
+/// later one reusing its index.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the entity into 64 bits, e.g. for hashing or GPU picking.
+    pub fn to_bits(self) -> u64 {
+        u64::from(self.generation) << 32 | u64::from(self.index)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self { index: bits as u32, generation: (bits >> 32) as u32 }
+    }
+}
+
+impl fmt::Debug for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// Where an entity's components live.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Location {
+    pub archetype: u32,
+    pub row: u32,
+}
+
+struct Meta {
+    generation: u32,
+    location: Option<Location>,
+}
+
+/// Allocates entity ids and tracks their locations.
+#[derive(Default)]
+pub(crate) struct Entities {
+    meta: Vec<Meta>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Entities {
+    pub fn alloc(&mut self, location: Location) -> Entity {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let meta = &mut self.meta[index as usize];
+            meta.location = Some(location);
+            return Entity { index, generation: meta.generation };
+        }
+        self.meta.push(Meta { generation: 0, location: Some(location) });
+        Entity { index: self.meta.len() as u32 - 1, generation: 0 }
+    }
+
+    /// Frees `entity`, returning where it was.
+    pub fn free(&mut self, entity: Entity) -> Option<Location> {
+        let meta = self.meta.get_mut(entity.index as usize).filter(|m| m.generation == entity.generation)?;
+        let location = meta.location.take()?;
+        meta.generation = meta.generation.wrapping_add(1);
+        self.free.push(entity.index);
+        self.len -= 1;
+        Some(location)
+    }
+
+    pub fn location(&self, entity: Entity) -> Option<Location> {
+        self.meta.get(entity.index as usize).filter(|m| m.generation == entity.generation)?.location
+    }
+
+    /// Updates the location of a live entity, found by index.
+    pub fn set_location(&mut self, index: u32, location: Location) {
+        self.meta[index as usize].location = Some(location);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn clear(&mut self) {
+        for (index, meta) in self.meta.iter_mut().enumerate() {
+            if meta.location.take().is_some() {
+                meta.generation = meta.generation.wrapping_add(1);
+                self.free.push(index as u32);
+            }
+        }
+        self.len = 0;
+    }
+}
@@ -0,0 +1,55 @@
+//! Archetype-based entity-component-system.
+//!
+//! Entities with the same set of component types share an [`Archetype`],
+//! which stores each type in its own column, so queries walk dense arrays.
+//! Adding or removing a component moves the entity's row to another
+//! archetype.
+//!
+//! Queries and [`World::get`] borrow columns through runtime-checked flags
+//! and only need `&World`, so queries over disjoint components can run on
+//! several threads at once; conflicting borrows panic. Structural changes
+//! (spawning, despawning, inserting, removing) need `&mut World`, or go
+//! through [`Commands`] to be applied later.
+//!
+//! A [`Schedule`] runs [`System`]s stage by stage, in parallel where their
+//! declared access allows.
+
+mod archetype;
+mod borrow;
+mod commands;
+mod entity;
+mod query;
+mod resource;
+mod schedule;
+mod system;
+mod world;
+
+pub use archetype::{Archetype, ArchetypeId};
+pub use borrow::{Ref, RefMut};
+pub use commands::Commands;
+pub use entity::Entity;
+pub use query::{QueryBorrow, QueryData, QueryFilter, QueryIter, With, Without};
+pub use schedule::{Ambiguity, Schedule, Stage};
+pub use system::{resource_exists, Access, System};
+pub use world::{Bundle, World};
+
+use std::any::TypeId;
+
+/// Anything that can be stored on an entity.
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+/// Type information needed to create a column for a component.
+#[derive(Clone, Copy, Debug)]
+pub struct ComponentInfo {
+    pub id: TypeId,
+    pub name: &'static str,
+    new_column: fn() -> Box<dyn archetype::ColumnStorage>,
+}
+
+impl ComponentInfo {
+    pub fn of<T: Component>() -> Self {
+        Self { id: TypeId::of::<T>(), name: std::any::type_name::<T>(), new_column: || Box::new(Vec::<T>::new()) }
+    }
+}
@@ -0,0 +1,410 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use super::archetype::Archetype;
+use super::entity::Entity;
+use super::system::Access;
+use super::Component;
+
+/// What a query yields per entity: `&T`, `&mut T`, `Option<Q>`, [`Entity`],
+/// or tuples of these.
+///
+/// # Safety
+/// `borrow` must take every column borrow that `get` relies on, taking none
+/// if it fails, and `release` must undo exactly those.
+pub unsafe trait QueryData {
+    type Item<'a>;
+    #[doc(hidden)]
+    type Fetch: Copy;
+
+    fn matches(archetype: &Archetype) -> bool;
+    /// Adds the components this reads and writes.
+    fn access(access: &mut Access);
+    /// Returns the name of the component that is already borrowed in a
+    /// conflicting way.
+    #[doc(hidden)]
+    fn borrow(archetype: &Archetype) -> Result<(), &'static str>;
+    #[doc(hidden)]
+    fn release(archetype: &Archetype);
+    #[doc(hidden)]
+    fn fetch(archetype: &Archetype) -> Self::Fetch;
+    /// # Safety
+    /// `fetch` must come from a matching, borrowed archetype and `row` must
+    /// be in bounds.
+    #[doc(hidden)]
+    unsafe fn get<'a>(fetch: Self::Fetch, row: usize) -> Self::Item<'a>;
+}
+
+unsafe impl<T: Component> QueryData for &T {
+    type Item<'a> = &'a T;
+    type Fetch = *const T;
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has::<T>()
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>();
+    }
+
+    fn borrow(archetype: &Archetype) -> Result<(), &'static str> {
+        let column = archetype.column(TypeId::of::<T>()).expect("matching archetype");
+        column.borrow.try_borrow().then_some(()).ok_or(column.info.name)
+    }
+
+    fn release(archetype: &Archetype) {
+        archetype.column(TypeId::of::<T>()).expect("matching archetype").borrow.release();
+    }
+
+    fn fetch(archetype: &Archetype) -> *const T {
+        unsafe { archetype.column(TypeId::of::<T>()).expect("matching archetype").data::<T>() }
+    }
+
+    unsafe fn get<'a>(fetch: *const T, row: usize) -> &'a T {
+        unsafe { &*fetch.add(row) }
+    }
+}
+
+unsafe impl<T: Component> QueryData for &mut T {
+    type Item<'a> = &'a mut T;
+    type Fetch = *mut T;
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has::<T>()
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<T>();
+    }
+
+    fn borrow(archetype: &Archetype) -> Result<(), &'static str> {
+        let column = archetype.column(TypeId::of::<T>()).expect("matching archetype");
+        column.borrow.try_borrow_mut().then_some(()).ok_or(column.info.name)
+    }
+
+    fn release(archetype: &Archetype) {
+        archetype.column(TypeId::of::<T>()).expect("matching archetype").borrow.release_mut();
+    }
+
+    fn fetch(archetype: &Archetype) -> *mut T {
+        unsafe { archetype.column(TypeId::of::<T>()).expect("matching archetype").data_mut::<T>() }
+    }
+
+    unsafe fn get<'a>(fetch: *mut T, row: usize) -> &'a mut T {
+        unsafe { &mut *fetch.add(row) }
+    }
+}
+
+unsafe impl QueryData for Entity {
+    type Item<'a> = Entity;
+    type Fetch = *const Entity;
+
+    fn matches(_: &Archetype) -> bool {
+        true
+    }
+
+    fn access(_: &mut Access) {}
+
+    fn borrow(_: &Archetype) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn release(_: &Archetype) {}
+
+    fn fetch(archetype: &Archetype) -> *const Entity {
+        archetype.entities().as_ptr()
+    }
+
+    unsafe fn get<'a>(fetch: *const Entity, row: usize) -> Self::Item<'a> {
+        unsafe { *fetch.add(row) }
+    }
+}
+
+/// Matches every archetype, yielding `None` where `Q` doesn't match.
+unsafe impl<Q: QueryData> QueryData for Option<Q> {
+    type Item<'a> = Option<Q::Item<'a>>;
+    type Fetch = Option<Q::Fetch>;
+
+    fn matches(_: &Archetype) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        Q::access(access);
+    }
+
+    fn borrow(archetype: &Archetype) -> Result<(), &'static str> {
+        if Q::matches(archetype) { Q::borrow(archetype) } else { Ok(()) }
+    }
+
+    fn release(archetype: &Archetype) {
+        if Q::matches(archetype) {
+            Q::release(archetype);
+        }
+    }
+
+    fn fetch(archetype: &Archetype) -> Self::Fetch {
+        Q::matches(archetype).then(|| Q::fetch(archetype))
+    }
+
+    unsafe fn get<'a>(fetch: Self::Fetch, row: usize) -> Self::Item<'a> {
+        fetch.map(|f| unsafe { Q::get(f, row) })
+    }
+}
+
+/// Narrows a query by component presence without borrowing anything.
+pub trait QueryFilter {
+    fn matches(archetype: &Archetype) -> bool;
+}
+
+/// Only entities that have a `T`.
+pub struct With<T>(PhantomData<T>);
+
+/// Only entities that don't have a `T`.
+pub struct Without<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for With<T> {
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has::<T>()
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    fn matches(archetype: &Archetype) -> bool {
+        !archetype.has::<T>()
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case, unused_labels, clippy::unused_unit)]
+        unsafe impl<$($name: QueryData),*> QueryData for ($($name,)*) {
+            type Item<'a> = ($($name::Item<'a>,)*);
+            type Fetch = ($($name::Fetch,)*);
+
+            fn matches(_archetype: &Archetype) -> bool {
+                true $(&& $name::matches(_archetype))*
+            }
+
+            fn access(_access: &mut Access) {
+                $($name::access(_access);)*
+            }
+
+            fn borrow(_archetype: &Archetype) -> Result<(), &'static str> {
+                // On failure, release what the earlier members took.
+                let mut _taken = 0;
+                let result = 'borrow: {
+                    $(
+                        if let Err(name) = $name::borrow(_archetype) {
+                            break 'borrow Err(name);
+                        }
+                        _taken += 1;
+                    )*
+                    Ok(())
+                };
+                if result.is_err() {
+                    let mut _index = 0;
+                    $(if _index < _taken { $name::release(_archetype); } _index += 1;)*
+                }
+                result
+            }
+
+            fn release(_archetype: &Archetype) {
+                $($name::release(_archetype);)*
+            }
+
+            fn fetch(_archetype: &Archetype) -> Self::Fetch {
+                ($($name::fetch(_archetype),)*)
+            }
+
+            unsafe fn get<'a>(fetch: Self::Fetch, _row: usize) -> Self::Item<'a> {
+                let ($($name,)*) = fetch;
+                ($(unsafe { $name::get($name, _row) },)*)
+            }
+        }
+
+        impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            fn matches(_archetype: &Archetype) -> bool {
+                true $(&& $name::matches(_archetype))*
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+
+/// A query's column borrows, held until dropped. Iterate it with
+/// [`iter`](Self::iter) or a `for` loop over `&mut`.
+pub struct QueryBorrow<'w, Q: QueryData, F: QueryFilter = ()> {
+    archetypes: &'w [Archetype],
+    /// Indices of the matching archetypes that have been borrowed.
+    matched: Vec<usize>,
+    _marker: PhantomData<fn() -> (Q, F)>,
+}
+
+impl<'w, Q: QueryData, F: QueryFilter> QueryBorrow<'w, Q, F> {
+    pub(crate) fn new(archetypes: &'w [Archetype]) -> Self {
+        let mut borrow = Self { archetypes, matched: Vec::new(), _marker: PhantomData };
+        for (index, archetype) in archetypes.iter().enumerate() {
+            if Q::matches(archetype) && F::matches(archetype) && !archetype.is_empty() {
+                // Unwinding drops `borrow`, releasing the earlier archetypes.
+                if let Err(name) = Q::borrow(archetype) {
+                    panic!("{name} is already borrowed in a way that conflicts with {}", std::any::type_name::<Q>());
+                }
+                borrow.matched.push(index);
+            }
+        }
+        borrow
+    }
+
+    pub fn iter(&mut self) -> QueryIter<'_, Q> {
+        QueryIter { archetypes: self.archetypes, matched: self.matched.iter(), current: None, row: 0 }
+    }
+
+    /// Number of matching entities.
+    pub fn len(&self) -> usize {
+        self.matched.iter().map(|&i| self.archetypes[i].len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+}
+
+impl<Q: QueryData, F: QueryFilter> Drop for QueryBorrow<'_, Q, F> {
+    fn drop(&mut self) {
+        for &index in &self.matched {
+            Q::release(&self.archetypes[index]);
+        }
+    }
+}
+
+impl<'q, Q: QueryData, F: QueryFilter> IntoIterator for &'q mut QueryBorrow<'_, Q, F> {
+    type Item = Q::Item<'q>;
+    type IntoIter = QueryIter<'q, Q>;
+
+    fn into_iter(self) -> QueryIter<'q, Q> {
+        self.iter()
+    }
+}
+
+pub struct QueryIter<'q, Q: QueryData> {
+    archetypes: &'q [Archetype],
+    matched: std::slice::Iter<'q, usize>,
+    /// Fetch and length of the archetype being walked.
+    current: Option<(Q::Fetch, usize)>,
+    row: usize,
+}
+
+impl<'q, Q: QueryData> Iterator for QueryIter<'q, Q> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Q::Item<'q>> {
+        loop {
+            if let Some((fetch, len)) = self.current {
+                if self.row < len {
+                    let row = self.row;
+                    self.row += 1;
+                    // The borrows are held by the `QueryBorrow` this iterator
+                    // borrows from, and each row is yielded once.
+                    return Some(unsafe { Q::get(fetch, row) });
+                }
+            }
+            let archetype = &self.archetypes[*self.matched.next()?];
+            self.current = Some((Q::fetch(archetype), archetype.len()));
+            self.row = 0;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let current = self.current.map_or(0, |(_, len)| len - self.row);
+        let rest: usize = self.matched.clone().map(|&i| self.archetypes[i].len()).sum();
+        (current + rest, Some(current + rest))
+    }
+}
+
+impl<Q: QueryData> ExactSizeIterator for QueryIter<'_, Q> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::World;
+    use super::*;
+
+    struct Position(i32);
+    struct Velocity(i32);
+    struct Frozen;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.spawn((Position(1), Velocity(1)));
+        world.spawn((Position(2),));
+        world.spawn((Position(3), Velocity(3), Frozen));
+        world
+    }
+
+    fn sorted(mut values: Vec<i32>) -> Vec<i32> {
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn filters() {
+        let world = world();
+        let with: Vec<_> = world.query_filtered::<&Position, With<Velocity>>().iter().map(|p| p.0).collect();
+        assert_eq!(sorted(with), [1, 3]);
+        let without: Vec<_> = world.query_filtered::<&Position, Without<Frozen>>().iter().map(|p| p.0).collect();
+        assert_eq!(sorted(without), [1, 2]);
+        let both: Vec<_> =
+            world.query_filtered::<&Position, (With<Velocity>, Without<Frozen>)>().iter().map(|p| p.0).collect();
+        assert_eq!(both, [1]);
+    }
+
+    #[test]
+    fn optional_components() {
+        let world = world();
+        let mut pairs: Vec<_> =
+            world.query::<(&Position, Option<&Velocity>)>().iter().map(|(p, v)| (p.0, v.map(|v| v.0))).collect();
+        pairs.sort();
+        assert_eq!(pairs, [(1, Some(1)), (2, None), (3, Some(3))]);
+    }
+
+    #[test]
+    fn writes_are_visible() {
+        let world = world();
+        for (position, velocity) in &mut world.query::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+        let positions: Vec<_> = world.query::<&Position>().iter().map(|p| p.0).collect();
+        assert_eq!(sorted(positions), [2, 2, 6]);
+    }
+
+    #[test]
+    fn shared_borrows_coexist() {
+        let world = world();
+        let _a = world.query::<&Position>();
+        let _b = world.query::<(&Position, &Velocity)>();
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn conflicting_borrow_panics() {
+        let world = world();
+        let _read = world.query::<&Position>();
+        let _write = world.query::<&mut Position>();
+    }
+
+    #[test]
+    fn borrows_are_released_on_drop() {
+        let world = world();
+        drop(world.query::<&mut Position>());
+        assert_eq!(world.query::<&mut Position>().len(), 3);
+    }
+}
@@ -0,0 +1,59 @@
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+
+use hashbrown::HashMap;
+
+use super::borrow::{BorrowFlag, Ref, RefMut};
+use super::Component;
+
+struct Cell {
+    name: &'static str,
+    borrow: BorrowFlag,
+    value: UnsafeCell<Box<dyn Any + Send + Sync>>,
+}
+
+/// Singleton values stored on the world, one per type.
+#[derive(Default)]
+pub(crate) struct Resources {
+    cells: HashMap<TypeId, Cell>,
+}
+
+// Access to a cell's value through `&Resources` is guarded by its flag.
+unsafe impl Sync for Resources {}
+
+impl Resources {
+    pub fn insert<R: Component>(&mut self, value: R) -> Option<R> {
+        let cell = Cell { name: std::any::type_name::<R>(), borrow: BorrowFlag::default(), value: UnsafeCell::new(Box::new(value)) };
+        let old = self.cells.insert(TypeId::of::<R>(), cell)?;
+        old.value.into_inner().downcast().ok().map(|b| *b)
+    }
+
+    pub fn remove<R: Component>(&mut self) -> Option<R> {
+        let cell = self.cells.remove(&TypeId::of::<R>())?;
+        cell.value.into_inner().downcast().ok().map(|b| *b)
+    }
+
+    pub fn contains<R: Component>(&self) -> bool {
+        self.cells.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get<R: Component>(&self) -> Option<Ref<'_, R>> {
+        let cell = self.cells.get(&TypeId::of::<R>())?;
+        if !cell.borrow.try_borrow() {
+            panic!("{} is already borrowed mutably", cell.name);
+        }
+        // The shared borrow is held by the returned guard.
+        let value = unsafe { &**cell.value.get() };
+        Some(Ref { flag: &cell.borrow, value: value.downcast_ref().expect("resource of R") })
+    }
+
+    pub fn get_mut<R: Component>(&self) -> Option<RefMut<'_, R>> {
+        let cell = self.cells.get(&TypeId::of::<R>())?;
+        if !cell.borrow.try_borrow_mut() {
+            panic!("{} is already borrowed", cell.name);
+        }
+        // The unique borrow is held by the returned guard.
+        let value = unsafe { &mut **cell.value.get() };
+        Some(RefMut { flag: &cell.borrow, value: value.downcast_mut().expect("resource of R") })
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{bail, Context, Result};
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use smallvec::SmallVec;
+
+use super::commands::Commands;
+use super::system::{System, SystemFn};
+use super::world::World;
+use crate::time::{FixedTime, Time};
+
+/// When a system runs. [`Schedule::run`] runs startup once, then the
+/// per-frame stages in declaration order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Startup,
+    PreUpdate,
+    FixedUpdate,
+    Update,
+    PostUpdate,
+    /// Copies what the renderer needs out of the world.
+    RenderExtract,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] =
+        [Stage::Startup, Stage::PreUpdate, Stage::FixedUpdate, Stage::Update, Stage::PostUpdate, Stage::RenderExtract];
+}
+
+/// Two systems in a stage whose access conflicts but whose order isn't
+/// constrained, so which one sees the other's writes can change between
+/// runs.
+#[derive(Clone, Debug)]
+pub struct Ambiguity {
+    pub stage: Stage,
+    pub first: String,
+    pub second: String,
+    /// The types both touch, at least one of them writing.
+    pub types: Vec<&'static str>,
+}
+
+struct Entry {
+    system: System,
+    commands: Commands,
+}
+
+impl Entry {
+    fn run_shared(&mut self, world: &World) {
+        match &mut self.system.run {
+            SystemFn::Shared(run) => run(world, &mut self.commands),
+            SystemFn::Exclusive(_) => unreachable!("exclusive systems run alone"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StageSystems {
+    entries: Vec<Entry>,
+    /// Groups of systems that may run in parallel, in order. `None` when
+    /// systems changed since the last build.
+    waves: Option<Vec<SmallVec<[usize; 4]>>>,
+    ambiguities: Vec<Ambiguity>,
+}
+
+/// Systems grouped into stages.
+///
+/// Within a stage, systems run in waves: each wave holds systems whose
+/// `before`/`after` constraints are met and whose access doesn't conflict,
+/// and its systems run in parallel on the rayon pool. Commands are applied
+/// at the end of the stage, and before each exclusive system.
+pub struct Schedule {
+    stages: [StageSystems; 6],
+    started: bool,
+    parallel: bool,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self { stages: Default::default(), started: false, parallel: true }
+    }
+
+    pub fn add_system(&mut self, stage: Stage, system: System) -> &mut Self {
+        let systems = &mut self.stages[stage as usize];
+        systems.entries.push(Entry { system, commands: Commands::new() });
+        systems.waves = None;
+        self
+    }
+
+    /// Runs every wave on the calling thread, e.g. to rule out threading
+    /// when debugging. Order within a wave stays the same.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Names of a stage's systems, in the order they were added.
+    pub fn system_names(&self, stage: Stage) -> impl Iterator<Item = &str> + '_ {
+        self.stages[stage as usize].entries.iter().map(|e| e.system.name())
+    }
+
+    /// Resolves ordering constraints and plans the waves of stages whose
+    /// systems changed, logging new ambiguities. Running builds as needed;
+    /// call this to surface errors early.
+    pub fn build(&mut self) -> Result<()> {
+        for stage in Stage::ALL {
+            let systems = &mut self.stages[stage as usize];
+            if systems.waves.is_none() {
+                let (waves, ambiguities) = plan(stage, &systems.entries)?;
+                for a in &ambiguities {
+                    tracing::warn!(
+                        "{:?}: `{}` and `{}` both access {} with no order between them",
+                        a.stage,
+                        a.first,
+                        a.second,
+                        a.types.join(", ")
+                    );
+                }
+                systems.waves = Some(waves);
+                systems.ambiguities = ambiguities;
+            }
+        }
+        Ok(())
+    }
+
+    /// Conflicting, unordered system pairs found by the last build.
+    pub fn ambiguities(&self) -> impl Iterator<Item = &Ambiguity> + '_ {
+        self.stages.iter().flat_map(|s| &s.ambiguities)
+    }
+
+    /// Runs startup on the first call, then one pass of each per-frame
+    /// stage.
+    ///
+    /// A [`Time`] resource is updated before anything runs. Fixed-update runs
+    /// as many times as a [`FixedTime`] resource owes for this frame's
+    /// virtual delta, or once without one.
+    pub fn run(&mut self, world: &mut World) -> Result<()> {
+        if let Some(mut time) = world.get_resource_mut::<Time>() {
+            time.update();
+        }
+        if !self.started {
+            self.run_stage(Stage::Startup, world)?;
+            self.started = true;
+        }
+        self.run_stage(Stage::PreUpdate, world)?;
+        let delta = world.get_resource::<Time>().map(|t| t.delta());
+        let steps = match (world.get_resource_mut::<FixedTime>(), delta) {
+            (Some(mut fixed), Some(delta)) => fixed.accumulate(delta),
+            _ => 1,
+        };
+        for _ in 0..steps {
+            self.run_stage(Stage::FixedUpdate, world)?;
+        }
+        for stage in [Stage::Update, Stage::PostUpdate, Stage::RenderExtract] {
+            self.run_stage(stage, world)?;
+        }
+        Ok(())
+    }
+
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World) -> Result<()> {
+        self.build()?;
+        let parallel = self.parallel;
+        let StageSystems { entries, waves, .. } = &mut self.stages[stage as usize];
+        for wave in waves.as_ref().expect("built") {
+            let active: SmallVec<[usize; 4]> = wave
+                .iter()
+                .copied()
+                .filter(|&i| entries[i].system.conditions.iter_mut().all(|condition| condition(world)))
+                .collect();
+            match active[..] {
+                [] => {}
+                [i] if matches!(entries[i].system.run, SystemFn::Exclusive(_)) => {
+                    for entry in entries.iter_mut() {
+                        entry.commands.apply(world);
+                    }
+                    if let SystemFn::Exclusive(run) = &mut entries[i].system.run {
+                        run(world);
+                    }
+                }
+                [i] => entries[i].run_shared(world),
+                _ if !parallel => {
+                    for &i in &active {
+                        entries[i].run_shared(world);
+                    }
+                }
+                _ => {
+                    let world: &World = world;
+                    let mut batch: Vec<&mut Entry> =
+                        entries.iter_mut().enumerate().filter(|(i, _)| active.contains(i)).map(|(_, e)| e).collect();
+                    batch.par_iter_mut().for_each(|entry| entry.run_shared(world));
+                }
+            }
+        }
+        for entry in entries.iter_mut() {
+            entry.commands.apply(world);
+        }
+        Ok(())
+    }
+}
+
+type Waves = Vec<SmallVec<[usize; 4]>>;
+
+/// Orders a stage's systems into waves and finds its ambiguities.
+fn plan(stage: Stage, entries: &[Entry]) -> Result<(Waves, Vec<Ambiguity>)> {
+    let systems: Vec<&System> = entries.iter().map(|e| &e.system).collect();
+    let mut index = HashMap::new();
+    for (i, system) in systems.iter().enumerate() {
+        if index.insert(system.name(), i).is_some() {
+            bail!("{stage:?}: more than one system named `{}`", system.name());
+        }
+    }
+    let lookup = |from: &System, name: &str| {
+        index.get(name).copied().with_context(|| format!("{stage:?}: `{}` is ordered against unknown system `{name}`", from.name()))
+    };
+
+    // deps[i]: systems that must finish before system i starts.
+    let mut deps = vec![SmallVec::<[usize; 4]>::new(); systems.len()];
+    for (i, system) in systems.iter().enumerate() {
+        for name in &system.after {
+            deps[i].push(lookup(system, name)?);
+        }
+        for name in &system.before {
+            deps[lookup(system, name)?].push(i);
+        }
+    }
+
+    let mut waves = Vec::new();
+    let mut done = vec![false; systems.len()];
+    let mut remaining = systems.len();
+    while remaining > 0 {
+        let mut wave = SmallVec::<[usize; 4]>::new();
+        for i in (0..systems.len()).filter(|&i| !done[i] && deps[i].iter().all(|&d| done[d])) {
+            let exclusive = systems[i].access.is_exclusive();
+            if exclusive || wave.iter().any(|&w: &usize| systems[w].access.is_exclusive()) {
+                if wave.is_empty() {
+                    wave.push(i);
+                }
+                break;
+            }
+            if wave.iter().all(|&w| systems[w].access.is_compatible(&systems[i].access)) {
+                wave.push(i);
+            }
+        }
+        if wave.is_empty() {
+            let stuck: Vec<_> = (0..systems.len()).filter(|&i| !done[i]).map(|i| systems[i].name()).collect();
+            bail!("{stage:?}: ordering cycle among {}", stuck.join(", "));
+        }
+        for &i in &wave {
+            done[i] = true;
+        }
+        remaining -= wave.len();
+        waves.push(wave);
+    }
+
+    // before[i][j]: system j is guaranteed to finish before system i starts.
+    let mut before = vec![vec![false; systems.len()]; systems.len()];
+    for &i in waves.iter().flatten() {
+        for &d in &deps[i] {
+            let (of_d, of_i) = if d < i {
+                let (left, right) = before.split_at_mut(i);
+                (&left[d], &mut right[0])
+            } else {
+                let (left, right) = before.split_at_mut(d);
+                (&right[0], &mut left[i])
+            };
+            for (reach, &through) in of_i.iter_mut().zip(of_d) {
+                *reach |= through;
+            }
+            of_i[d] = true;
+        }
+    }
+
+    let mut ambiguities = Vec::new();
+    for i in 0..systems.len() {
+        for j in i + 1..systems.len() {
+            let (a, b) = (systems[i], systems[j]);
+            if before[i][j] || before[j][i] || a.access.is_exclusive() || b.access.is_exclusive() {
+                continue;
+            }
+            if a.ambiguous_with.iter().any(|n| n == b.name()) || b.ambiguous_with.iter().any(|n| n == a.name()) {
+                continue;
+            }
+            let types = a.access.conflicts(&b.access);
+            if !types.is_empty() {
+                ambiguities.push(Ambiguity {
+                    stage,
+                    first: a.name().to_string(),
+                    second: b.name().to_string(),
+                    types: types.to_vec(),
+                });
+            }
+        }
+    }
+    Ok((waves, ambiguities))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::ecs::resource_exists;
+
+    struct A;
+    struct B;
+    struct Log(Vec<&'static str>);
+
+    fn waves(schedule: &mut Schedule, stage: Stage) -> Vec<Vec<String>> {
+        schedule.build().unwrap();
+        let systems = &schedule.stages[stage as usize];
+        let name = |i: usize| systems.entries[i].system.name().to_string();
+        systems.waves.as_ref().unwrap().iter().map(|wave| wave.iter().map(|&i| name(i)).collect()).collect()
+    }
+
+    /// A system that appends its name to the [`Log`] resource.
+    fn logging(name: &'static str) -> System {
+        System::new(name, move |world, _| world.resource_mut::<Log>().0.push(name)).writes::<Log>()
+    }
+
+    #[test]
+    fn conflicting_and_ordered_systems_split_into_waves() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, System::new("write_a", |_, _| {}).writes::<A>())
+            .add_system(Stage::Update, System::new("read_a", |_, _| {}).reads::<A>())
+            .add_system(Stage::Update, System::new("read_b", |_, _| {}).reads::<B>())
+            .add_system(Stage::Update, System::new("after_read_b", |_, _| {}).after("read_b"))
+            .add_system(Stage::Update, System::exclusive("exclusive", |_| {}));
+        assert_eq!(
+            waves(&mut schedule, Stage::Update),
+            [vec!["write_a", "read_b"], vec!["read_a", "after_read_b"], vec!["exclusive"]]
+        );
+    }
+
+    #[test]
+    fn ordering_constraints_set_execution_order() {
+        let mut world = World::new();
+        world.insert_resource(Log(Vec::new()));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, logging("third").after("second"))
+            .add_system(Stage::Update, logging("second"))
+            .add_system(Stage::Update, logging("first").before("second"));
+        schedule.run_stage(Stage::Update, &mut world).unwrap();
+        assert_eq!(world.resource::<Log>().0, ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn cycles_and_unknown_names_fail_to_build() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, System::new("a", |_, _| {}).after("b"))
+            .add_system(Stage::Update, System::new("b", |_, _| {}).after("c"))
+            .add_system(Stage::Update, System::new("c", |_, _| {}).after("a"));
+        let error = schedule.build().unwrap_err().to_string();
+        assert!(error.contains("ordering cycle"), "{error}");
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, System::new("a", |_, _| {}).after("missing"));
+        let error = schedule.build().unwrap_err().to_string();
+        assert!(error.contains("unknown system `missing`"), "{error}");
+    }
+
+    #[test]
+    fn run_conditions_skip_systems() {
+        let mut world = World::new();
+        world.insert_resource(Log(Vec::new()));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, logging("gated").run_if(resource_exists::<A>()))
+            .add_system(Stage::Update, logging("always").after("gated"));
+        schedule.run_stage(Stage::Update, &mut world).unwrap();
+        world.insert_resource(A);
+        schedule.run_stage(Stage::Update, &mut world).unwrap();
+        assert_eq!(world.resource::<Log>().0, ["always", "gated", "always"]);
+    }
+
+    #[test]
+    fn commands_apply_at_stage_end_and_before_exclusive_systems() {
+        let mut world = World::new();
+        world.insert_resource(Log(Vec::new()));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut schedule = Schedule::new();
+        let record = |name: &'static str, seen: &Arc<Mutex<Vec<usize>>>| {
+            let seen = seen.clone();
+            System::new(name, move |world, commands| {
+                seen.lock().push(world.len());
+                commands.spawn((A,));
+                commands.add(move |world| world.resource_mut::<Log>().0.push(name));
+            })
+        };
+        schedule
+            .add_system(Stage::Update, record("first", &seen))
+            .add_system(Stage::Update, record("second", &seen).after("first"))
+            .add_system(Stage::Update, {
+                let seen = seen.clone();
+                System::exclusive("exclusive", move |world| seen.lock().push(world.len())).after("second")
+            })
+            .add_system(Stage::PostUpdate, {
+                let seen = seen.clone();
+                System::new("later_stage", move |world, _| seen.lock().push(world.len()))
+            });
+        schedule.run_stage(Stage::Update, &mut world).unwrap();
+        schedule.run_stage(Stage::PostUpdate, &mut world).unwrap();
+        // Shared systems don't see each other's commands; the exclusive
+        // system and the next stage see both, applied in system order.
+        assert_eq!(*seen.lock(), [0, 0, 2, 2]);
+        assert_eq!(world.resource::<Log>().0, ["first", "second"]);
+    }
+
+    #[test]
+    fn unordered_conflicts_are_reported() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, System::new("write", |_, _| {}).writes::<A>().reads::<B>())
+            .add_system(Stage::Update, System::new("read", |_, _| {}).reads::<A>().reads::<B>())
+            .add_system(Stage::Update, System::new("ordered", |_, _| {}).writes::<A>().after("read"))
+            .add_system(Stage::Update, System::new("allowed", |_, _| {}).writes::<B>().ambiguous_with("write"));
+        schedule.build().unwrap();
+        let found: Vec<_> = schedule.ambiguities().map(|a| (a.first.as_str(), a.second.as_str())).collect();
+        // `ordered` runs after `read`, which runs after `write` as they
+        // conflict, but nothing in the declarations says so.
+        assert_eq!(found, [("write", "read"), ("write", "ordered"), ("read", "allowed")]);
+        let first = schedule.ambiguities().next().unwrap();
+        assert_eq!(first.stage, Stage::Update);
+        assert_eq!(first.types, [std::any::type_name::<A>()]);
+    }
+}
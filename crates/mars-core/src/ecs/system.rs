@@ -0,0 +1,174 @@
+use std::any::TypeId;
+
+use smallvec::SmallVec;
+
+use super::commands::Commands;
+use super::query::QueryData;
+use super::world::World;
+use super::Component;
+
+type Types = SmallVec<[(TypeId, &'static str); 4]>;
+
+/// The component and resource types a system reads and writes. The
+/// scheduler runs systems in parallel only when their access doesn't
+/// conflict.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Types,
+    writes: Types,
+    exclusive: bool,
+}
+
+impl Access {
+    pub fn read<T: Component>(&mut self) {
+        Self::add(&mut self.reads, TypeId::of::<T>(), std::any::type_name::<T>());
+    }
+
+    pub fn write<T: Component>(&mut self) {
+        Self::add(&mut self.writes, TypeId::of::<T>(), std::any::type_name::<T>());
+    }
+
+    fn add(types: &mut Types, id: TypeId, name: &'static str) {
+        if !types.iter().any(|&(t, _)| t == id) {
+            types.push((id, name));
+        }
+    }
+
+    /// Access to the whole world, conflicting with everything.
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn reads(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.reads.iter().map(|&(_, name)| name)
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.writes.iter().map(|&(_, name)| name)
+    }
+
+    /// Names of the types one side writes and the other reads or writes.
+    pub fn conflicts(&self, other: &Access) -> SmallVec<[&'static str; 2]> {
+        let mut names = SmallVec::new();
+        let mut check = |writes: &Types, theirs: &Access| {
+            for &(id, name) in writes {
+                let touched = theirs.writes.iter().chain(&theirs.reads).any(|&(t, _)| t == id);
+                if touched && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        };
+        check(&self.writes, other);
+        check(&other.writes, self);
+        names
+    }
+
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        !self.exclusive && !other.exclusive && self.conflicts(other).is_empty()
+    }
+}
+
+type SharedFn = Box<dyn FnMut(&World, &mut Commands) + Send>;
+type ExclusiveFn = Box<dyn FnMut(&mut World) + Send>;
+type Condition = Box<dyn FnMut(&World) -> bool + Send>;
+
+pub(crate) enum SystemFn {
+    Shared(SharedFn),
+    Exclusive(ExclusiveFn),
+}
+
+/// A named function run by a [`Schedule`](super::Schedule), with the data it
+/// touches declared up front.
+///
+/// A shared system gets `&World` and a [`Commands`] buffer for structural
+/// changes; its borrows are still checked at runtime, so an undeclared
+/// access that collides with a system running in parallel panics rather
+/// than racing. An exclusive system gets `&mut World` and runs alone.
+pub struct System {
+    pub(crate) name: String,
+    pub(crate) access: Access,
+    pub(crate) before: Vec<String>,
+    pub(crate) after: Vec<String>,
+    pub(crate) ambiguous_with: Vec<String>,
+    pub(crate) conditions: Vec<Condition>,
+    pub(crate) run: SystemFn,
+}
+
+impl System {
+    pub fn new(name: impl Into<String>, run: impl FnMut(&World, &mut Commands) + Send + 'static) -> Self {
+        Self::with_fn(name.into(), SystemFn::Shared(Box::new(run)), Access::default())
+    }
+
+    pub fn exclusive(name: impl Into<String>, run: impl FnMut(&mut World) + Send + 'static) -> Self {
+        let access = Access { exclusive: true, ..Access::default() };
+        Self::with_fn(name.into(), SystemFn::Exclusive(Box::new(run)), access)
+    }
+
+    fn with_fn(name: String, run: SystemFn, access: Access) -> Self {
+        Self {
+            name,
+            access,
+            before: Vec::new(),
+            after: Vec::new(),
+            ambiguous_with: Vec::new(),
+            conditions: Vec::new(),
+            run,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Declares a read of a component or resource type.
+    pub fn reads<T: Component>(mut self) -> Self {
+        self.access.read::<T>();
+        self
+    }
+
+    /// Declares a write of a component or resource type.
+    pub fn writes<T: Component>(mut self) -> Self {
+        self.access.write::<T>();
+        self
+    }
+
+    /// Declares the access of a query the system runs.
+    pub fn query<Q: QueryData>(mut self) -> Self {
+        Q::access(&mut self.access);
+        self
+    }
+
+    /// Runs this system before the named one in the same stage.
+    pub fn before(mut self, name: impl Into<String>) -> Self {
+        self.before.push(name.into());
+        self
+    }
+
+    /// Runs this system after the named one in the same stage.
+    pub fn after(mut self, name: impl Into<String>) -> Self {
+        self.after.push(name.into());
+        self
+    }
+
+    /// Silences the ambiguity warning between this system and the named one.
+    pub fn ambiguous_with(mut self, name: impl Into<String>) -> Self {
+        self.ambiguous_with.push(name.into());
+        self
+    }
+
+    /// Skips the system on a run where `condition` returns false. Several
+    /// conditions must all pass.
+    pub fn run_if(mut self, condition: impl FnMut(&World) -> bool + Send + 'static) -> Self {
+        self.conditions.push(Box::new(condition));
+        self
+    }
+}
+
+/// Run condition that passes while the `R` resource exists.
+pub fn resource_exists<R: Component>() -> impl FnMut(&World) -> bool + Send + 'static {
+    |world| world.contains_resource::<R>()
+}
@@ -0,0 +1,382 @@
+use std::any::TypeId;
+
+use hashbrown::HashMap;
+
+use super::archetype::{Archetype, ArchetypeId};
+use super::borrow::{Ref, RefMut};
+use super::entity::{Entities, Entity, Location};
+use super::query::{QueryBorrow, QueryData, QueryFilter};
+use super::resource::Resources;
+use super::{Component, ComponentInfo};
+
+/// A set of components spawned or inserted together: a tuple of up to eight
+/// distinct component types.
+///
+/// # Safety
+/// `write` must store exactly the types `infos` lists.
+pub unsafe trait Bundle: Send + Sync + 'static {
+    #[doc(hidden)]
+    fn infos(out: &mut Vec<ComponentInfo>);
+    /// Stores each component at `row`, replacing or appending.
+    #[doc(hidden)]
+    fn write(self, archetype: &mut Archetype, row: usize);
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: Component),*> Bundle for ($($name,)*) {
+            fn infos(_out: &mut Vec<ComponentInfo>) {
+                $(_out.push(ComponentInfo::of::<$name>());)*
+            }
+
+            fn write(self, _archetype: &mut Archetype, _row: usize) {
+                let ($($name,)*) = self;
+                $(_archetype.write(_row, $name);)*
+            }
+        }
+    };
+}
+
+impl_bundle!();
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+/// Sorted infos of a bundle type.
+fn bundle_infos<B: Bundle>() -> Vec<ComponentInfo> {
+    let mut infos = Vec::new();
+    B::infos(&mut infos);
+    infos.sort_by_key(|i| i.id);
+    if let Some(pair) = infos.windows(2).find(|p| p[0].id == p[1].id) {
+        panic!("bundle {} has {} more than once", std::any::type_name::<B>(), pair[0].name);
+    }
+    infos
+}
+
+/// Mutable references to two different archetypes.
+fn pair_mut(archetypes: &mut [Archetype], a: usize, b: usize) -> (&mut Archetype, &mut Archetype) {
+    assert_ne!(a, b);
+    if a < b {
+        let (left, right) = archetypes.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = archetypes.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+/// Entities and their components, grouped into archetypes, plus resources:
+/// singleton values looked up by type.
+pub struct World {
+    entities: Entities,
+    /// Index 0 is the archetype without components.
+    archetypes: Vec<Archetype>,
+    index: HashMap<Vec<TypeId>, ArchetypeId>,
+    /// Archetype each bundle type spawns into.
+    bundles: HashMap<TypeId, ArchetypeId>,
+    resources: Resources,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        let mut index = HashMap::new();
+        index.insert(Vec::new(), ArchetypeId(0));
+        Self {
+            entities: Entities::default(),
+            archetypes: vec![Archetype::new(&[])],
+            index,
+            bundles: HashMap::new(),
+            resources: Resources::default(),
+        }
+    }
+
+    /// Number of live entities.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.len() == 0
+    }
+
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
+    }
+
+    /// All live entities, archetype by archetype.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.archetypes.iter().flat_map(|a| a.entities().iter().copied())
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.location(entity).is_some()
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.entities.location(entity).is_some_and(|l| self.archetypes[l.archetype as usize].has::<T>())
+    }
+
+    /// The archetype `entity` lives in.
+    pub fn archetype_of(&self, entity: Entity) -> Option<&Archetype> {
+        self.entities.location(entity).map(|l| &self.archetypes[l.archetype as usize])
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let id = match self.bundles.get(&TypeId::of::<B>()) {
+            Some(&id) => id,
+            None => {
+                let id = self.archetype_for(&bundle_infos::<B>());
+                self.bundles.insert(TypeId::of::<B>(), id);
+                id
+            }
+        };
+        let archetype = &mut self.archetypes[id.0 as usize];
+        let row = archetype.len();
+        bundle.write(archetype, row);
+        let entity = self.entities.alloc(Location { archetype: id.0, row: row as u32 });
+        archetype.entities_mut().push(entity);
+        entity
+    }
+
+    /// Despawns `entity`, dropping its components. Returns false if it was
+    /// already gone.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        let Some(location) = self.entities.free(entity) else { return false };
+        let archetype = &mut self.archetypes[location.archetype as usize];
+        let row = location.row as usize;
+        for column in archetype.columns_mut() {
+            column.storage_mut().swap_remove(row);
+        }
+        self.remove_entity_row(location);
+        true
+    }
+
+    /// Adds the bundle's components to `entity`, replacing any it already
+    /// has. Returns false if the entity is gone.
+    pub fn insert<B: Bundle>(&mut self, entity: Entity, bundle: B) -> bool {
+        let Some(location) = self.entities.location(entity) else { return false };
+        let source = ArchetypeId(location.archetype);
+        let key = TypeId::of::<B>();
+        let target = match self.archetypes[source.0 as usize].add_edges.get(&key) {
+            Some(&target) => target,
+            None => {
+                let mut infos: Vec<_> = self.archetypes[source.0 as usize].infos().collect();
+                for info in bundle_infos::<B>() {
+                    if !infos.iter().any(|i| i.id == info.id) {
+                        infos.push(info);
+                    }
+                }
+                infos.sort_by_key(|i| i.id);
+                let target = self.archetype_for(&infos);
+                self.archetypes[source.0 as usize].add_edges.insert(key, target);
+                target
+            }
+        };
+        if target == source {
+            bundle.write(&mut self.archetypes[source.0 as usize], location.row as usize);
+        } else {
+            let row = self.move_entity(entity, location, target, None);
+            bundle.write(&mut self.archetypes[target.0 as usize], row);
+        }
+        true
+    }
+
+    /// Takes the `T` off `entity`, if it is alive and has one.
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let location = self.entities.location(entity)?;
+        let source = ArchetypeId(location.archetype);
+        let key = TypeId::of::<T>();
+        if !self.archetypes[source.0 as usize].has_type(key) {
+            return None;
+        }
+        let target = match self.archetypes[source.0 as usize].remove_edges.get(&key) {
+            Some(&target) => target,
+            None => {
+                let infos: Vec<_> = self.archetypes[source.0 as usize].infos().filter(|i| i.id != key).collect();
+                let target = self.archetype_for(&infos);
+                self.archetypes[source.0 as usize].remove_edges.insert(key, target);
+                target
+            }
+        };
+        let value = self.archetypes[source.0 as usize]
+            .column_mut(key)
+            .expect("archetype has T")
+            .vec_mut::<T>()
+            .swap_remove(location.row as usize);
+        self.move_entity(entity, location, target, Some(key));
+        Some(value)
+    }
+
+    /// Borrows `entity`'s `T`. Panics if it is borrowed mutably elsewhere.
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let location = self.entities.location(entity)?;
+        self.archetypes[location.archetype as usize].get::<T>(location.row as usize)
+    }
+
+    /// Borrows `entity`'s `T` mutably. Panics if it is borrowed elsewhere.
+    pub fn get_mut<T: Component>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        let location = self.entities.location(entity)?;
+        self.archetypes[location.archetype as usize].get_mut::<T>(location.row as usize)
+    }
+
+    /// Borrows the columns `Q` reads and writes in every matching archetype.
+    /// Panics if one is already borrowed in a conflicting way.
+    pub fn query<Q: QueryData>(&self) -> QueryBorrow<'_, Q> {
+        QueryBorrow::new(&self.archetypes)
+    }
+
+    pub fn query_filtered<Q: QueryData, F: QueryFilter>(&self) -> QueryBorrow<'_, Q, F> {
+        QueryBorrow::new(&self.archetypes)
+    }
+
+    /// Adds a resource, returning the one of the same type it replaces.
+    pub fn insert_resource<R: Component>(&mut self, value: R) -> Option<R> {
+        self.resources.insert(value)
+    }
+
+    pub fn remove_resource<R: Component>(&mut self) -> Option<R> {
+        self.resources.remove()
+    }
+
+    pub fn contains_resource<R: Component>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    /// Borrows the `R` resource. Panics if it is borrowed mutably elsewhere.
+    pub fn get_resource<R: Component>(&self) -> Option<Ref<'_, R>> {
+        self.resources.get()
+    }
+
+    /// Borrows the `R` resource mutably. Panics if it is borrowed elsewhere.
+    pub fn get_resource_mut<R: Component>(&self) -> Option<RefMut<'_, R>> {
+        self.resources.get_mut()
+    }
+
+    /// Like [`get_resource`](Self::get_resource), panicking if there is no `R`.
+    pub fn resource<R: Component>(&self) -> Ref<'_, R> {
+        self.get_resource().unwrap_or_else(|| panic!("no {} resource", std::any::type_name::<R>()))
+    }
+
+    /// Like [`get_resource_mut`](Self::get_resource_mut), panicking if there
+    /// is no `R`.
+    pub fn resource_mut<R: Component>(&self) -> RefMut<'_, R> {
+        self.get_resource_mut().unwrap_or_else(|| panic!("no {} resource", std::any::type_name::<R>()))
+    }
+
+    /// Despawns every entity. Archetypes and resources are kept.
+    pub fn clear(&mut self) {
+        for archetype in &mut self.archetypes {
+            archetype.clear();
+        }
+        self.entities.clear();
+    }
+
+    fn archetype_for(&mut self, infos: &[ComponentInfo]) -> ArchetypeId {
+        let types: Vec<_> = infos.iter().map(|i| i.id).collect();
+        if let Some(&id) = self.index.get(&types) {
+            return id;
+        }
+        let id = ArchetypeId(self.archetypes.len() as u32);
+        self.archetypes.push(Archetype::new(infos));
+        self.index.insert(types, id);
+        id
+    }
+
+    /// Moves `entity`'s components to `target`, dropping those it lacks
+    /// except `taken`, which the caller has already removed. Returns the new
+    /// row; columns `target` has that the source lacks are left one short.
+    fn move_entity(&mut self, entity: Entity, location: Location, target: ArchetypeId, taken: Option<TypeId>) -> usize {
+        let row = location.row as usize;
+        let (source, dest) = pair_mut(&mut self.archetypes, location.archetype as usize, target.0 as usize);
+        for column in source.columns_mut() {
+            let id = column.info.id;
+            if Some(id) == taken {
+                continue;
+            }
+            match dest.column_mut(id) {
+                Some(dest_column) => column.storage_mut().move_to(row, dest_column.storage_mut()),
+                None => column.storage_mut().swap_remove(row),
+            }
+        }
+        let new_row = dest.len();
+        dest.entities_mut().push(entity);
+        self.remove_entity_row(location);
+        self.entities.set_location(entity.index(), Location { archetype: target.0, row: new_row as u32 });
+        new_row
+    }
+
+    /// Removes the row's entity after its components are gone, updating the
+    /// entity swapped into its place.
+    fn remove_entity_row(&mut self, location: Location) {
+        let entities = self.archetypes[location.archetype as usize].entities_mut();
+        let row = location.row as usize;
+        entities.swap_remove(row);
+        if let Some(&moved) = entities.get(row) {
+            self.entities.set_location(moved.index(), location);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[test]
+    fn despawned_index_is_reused_with_new_generation() {
+        let mut world = World::new();
+        let a = world.spawn((Position(1),));
+        let b = world.spawn((Position(2),));
+        assert!(world.despawn(a));
+        assert!(!world.despawn(a));
+        assert!(!world.contains(a));
+
+        let c = world.spawn((Position(3),));
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c.generation(), a.generation());
+        assert!(world.get::<Position>(a).is_none());
+        assert_eq!(*world.get::<Position>(c).unwrap(), Position(3));
+        assert_eq!(*world.get::<Position>(b).unwrap(), Position(2));
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn insert_and_remove_move_between_archetypes() {
+        let mut world = World::new();
+        let a = world.spawn((Position(1),));
+        let b = world.spawn((Position(2),));
+        let alone = world.archetype_of(a).unwrap().types().to_vec();
+
+        assert!(world.insert(a, (Velocity(10),)));
+        assert!(world.has::<Velocity>(a));
+        assert_ne!(world.archetype_of(a).unwrap().types(), &alone[..]);
+        // The entity left behind was swapped into the freed row.
+        assert_eq!(*world.get::<Position>(b).unwrap(), Position(2));
+        assert_eq!(*world.get::<Position>(a).unwrap(), Position(1));
+
+        // Inserting a type it already has replaces it in place.
+        world.insert(a, (Velocity(20),));
+        assert_eq!(*world.get::<Velocity>(a).unwrap(), Velocity(20));
+
+        assert_eq!(world.remove::<Velocity>(a), Some(Velocity(20)));
+        assert_eq!(world.remove::<Velocity>(a), None);
+        assert_eq!(world.archetype_of(a).unwrap().types(), &alone[..]);
+        assert_eq!(*world.get::<Position>(a).unwrap(), Position(1));
+    }
+}
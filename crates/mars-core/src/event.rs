@@ -0,0 +1,243 @@
+//! Typed event channels.
+//!
+//! Writers [`send`](Events::send) into an [`Events<T>`]; each reader keeps
+//! its own [`EventReader`] cursor and sees every event once, in order. Events
+//! are double-buffered: [`Events::update`], called once per frame, drops the
+//! events sent before the previous update. A reader that reads at least once
+//! per update therefore never misses one, whether it runs before or after
+//! the writer in the frame.
+//!
+//! As a world resource, pair each `Events<T>` with
+//! [`events_update_system`] in the pre-update stage.
+
+use std::marker::PhantomData;
+
+use crate::ecs::{Component, System};
+
+pub struct Events<T> {
+    /// Sent before the last update; dropped on the next.
+    older: Vec<T>,
+    /// Id of `older[0]`.
+    older_start: usize,
+    /// Sent since the last update.
+    newer: Vec<T>,
+    newer_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self { older: Vec::new(), older_start: 0, newer: Vec::new(), newer_start: 0 }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.newer.push(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.newer.extend(events);
+    }
+
+    /// Drops the events sent before the previous update and starts a new
+    /// buffer.
+    pub fn update(&mut self) {
+        std::mem::swap(&mut self.older, &mut self.newer);
+        self.newer.clear();
+        self.older_start = self.newer_start;
+        self.newer_start += self.older.len();
+    }
+
+    /// Events still buffered.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.older.is_empty() && self.newer.is_empty()
+    }
+
+    /// Drops every buffered event. Readers skip them.
+    pub fn clear(&mut self) {
+        self.newer_start = self.end();
+        self.older_start = self.newer_start;
+        self.older.clear();
+        self.newer.clear();
+    }
+
+    /// Takes every buffered event, oldest first, without going through a
+    /// reader.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.newer_start = self.end();
+        self.older_start = self.newer_start;
+        self.older.drain(..).chain(self.newer.drain(..))
+    }
+
+    /// A reader that skips the events already buffered.
+    pub fn reader_at_end(&self) -> EventReader<T> {
+        EventReader { cursor: self.end(), _marker: PhantomData }
+    }
+
+    /// Events `reader` hasn't seen, oldest first. The cursor advances as the
+    /// iterator does, so events left unconsumed are read next time.
+    pub fn read<'a>(&'a self, reader: &'a mut EventReader<T>) -> EventIter<'a, T> {
+        if reader.cursor < self.older_start {
+            tracing::warn!(
+                "{}: reader missed {} events; read at least once per update",
+                std::any::type_name::<T>(),
+                self.older_start - reader.cursor
+            );
+            reader.cursor = self.older_start;
+        }
+        let skip_older = (reader.cursor - self.older_start).min(self.older.len());
+        let skip_newer = reader.cursor.saturating_sub(self.newer_start).min(self.newer.len());
+        EventIter {
+            older: self.older[skip_older..].iter(),
+            newer: self.newer[skip_newer..].iter(),
+            cursor: &mut reader.cursor,
+        }
+    }
+
+    /// Id the next event will get.
+    fn end(&self) -> usize {
+        self.newer_start + self.newer.len()
+    }
+}
+
+/// One reader's position in an [`Events<T>`]. A new reader sees every event
+/// still buffered.
+pub struct EventReader<T> {
+    /// Id of the next event to read.
+    cursor: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self { cursor: 0, _marker: PhantomData }
+    }
+}
+
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of events [`Events::read`] would yield.
+    pub fn unread(&self, events: &Events<T>) -> usize {
+        events.end() - self.cursor.clamp(events.older_start, events.end())
+    }
+}
+
+pub struct EventIter<'a, T> {
+    older: std::slice::Iter<'a, T>,
+    newer: std::slice::Iter<'a, T>,
+    cursor: &'a mut usize,
+}
+
+impl<'a, T> Iterator for EventIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let event = self.older.next().or_else(|| self.newer.next())?;
+        *self.cursor += 1;
+        Some(event)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.older.len() + self.newer.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for EventIter<'_, T> {}
+
+/// Updates the `Events<T>` resource, meant for the pre-update stage.
+pub fn events_update_system<T: Component>() -> System {
+    System::new(format!("update_events<{}>", std::any::type_name::<T>()), |world, _| {
+        if let Some(mut events) = world.get_resource_mut::<Events<T>>() {
+            events.update();
+        }
+    })
+    .writes::<Events<T>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(events: &Events<u32>, reader: &mut EventReader<u32>) -> Vec<u32> {
+        events.read(reader).copied().collect()
+    }
+
+    #[test]
+    fn events_survive_one_update() {
+        let mut events = Events::new();
+        let mut reader = EventReader::new();
+        events.send(1);
+        assert_eq!(reader.unread(&events), 1);
+        events.update();
+        events.send(2);
+        assert_eq!(read(&events, &mut reader), [1, 2]);
+        assert!(read(&events, &mut reader).is_empty());
+    }
+
+    #[test]
+    fn events_are_dropped_after_two_updates() {
+        let mut events = Events::new();
+        let mut reader = EventReader::new();
+        events.send_batch([1, 2]);
+        events.update();
+        events.update();
+        assert!(events.is_empty());
+        assert_eq!(reader.unread(&events), 0);
+        events.send(3);
+        assert_eq!(read(&events, &mut reader), [3]);
+    }
+
+    #[test]
+    fn readers_keep_separate_cursors() {
+        let mut events = Events::new();
+        let mut fast = EventReader::new();
+        let mut slow = EventReader::new();
+        events.send(1);
+        assert_eq!(read(&events, &mut fast), [1]);
+        events.update();
+        events.send(2);
+        assert_eq!(read(&events, &mut fast), [2]);
+        events.update();
+        events.send(3);
+        // `slow` never read before event 1 was dropped.
+        assert_eq!(read(&events, &mut slow), [2, 3]);
+        assert_eq!(read(&events, &mut fast), [3]);
+    }
+
+    #[test]
+    fn partial_reads_resume() {
+        let mut events = Events::new();
+        let mut reader = events.reader_at_end();
+        events.send_batch([1, 2, 3]);
+        assert_eq!(events.read(&mut reader).next(), Some(&1));
+        events.update();
+        assert_eq!(read(&events, &mut reader), [2, 3]);
+    }
+
+    #[test]
+    fn clear_and_drain_skip_readers_ahead() {
+        let mut events = Events::new();
+        let mut reader = EventReader::new();
+        events.send_batch([1, 2]);
+        events.clear();
+        assert!(read(&events, &mut reader).is_empty());
+        events.send(3);
+        events.update();
+        events.send(4);
+        assert_eq!(events.drain().collect::<Vec<_>>(), [3, 4]);
+        events.send(5);
+        assert_eq!(read(&events, &mut reader), [5]);
+    }
+}
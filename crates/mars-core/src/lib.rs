@@ -1 +1,7 @@
-pub fn hello() { println!("Hello from mars-core!"); }
\ No newline at end of file
+pub mod bounds;
+pub mod bvh;
+pub mod debug_draw;
+pub mod ecs;
+pub mod event;
+pub mod time;
+pub mod transform;
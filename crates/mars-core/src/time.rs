@@ -0,0 +1,423 @@
+//! Frame timing and fixed-timestep accumulation.
+//!
+//! [`Time`] turns readings of a [`Clock`] into per-frame deltas. The real
+//! clock follows the wall clock; the virtual clock, which gameplay should
+//! use, is clamped, scaled and can be paused. [`FixedTime`] converts virtual
+//! time into a whole number of fixed steps per frame plus an interpolation
+//! alpha for rendering between the last two steps.
+//!
+//! Both are plain structs that also work as world resources: when the world
+//! has them, [`Schedule::run`](crate::ecs::Schedule::run) updates `Time` and
+//! runs the fixed-update stage once per step.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Default cap on the virtual delta of one frame.
+pub const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(250);
+
+/// Default cap on fixed steps run in one frame.
+pub const DEFAULT_MAX_STEPS: u32 = 8;
+
+/// Largest accepted [`Time::set_scale`].
+pub const MAX_TIME_SCALE: f64 = 1000.0;
+
+/// Source of monotonic time, measured from an arbitrary epoch.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+/// The wall clock, from when it was created.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic stepping. Clones
+/// share the same time, so one can drive a [`Time`] that owns another.
+#[derive(Clone, Default)]
+pub struct ManualClock(Arc<Mutex<Duration>>);
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock() += by;
+    }
+
+    pub fn set(&self, now: Duration) {
+        *self.0.lock() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.0.lock()
+    }
+}
+
+/// Per-frame timing. Call [`update`](Self::update) once at the start of each
+/// frame; the first update has a zero delta.
+pub struct Time {
+    clock: Box<dyn Clock>,
+    last: Option<Duration>,
+    real_delta: Duration,
+    real_elapsed: Duration,
+    delta: Duration,
+    elapsed: Duration,
+    frame: u64,
+    scale: f64,
+    paused: bool,
+    max_delta: Duration,
+    fps: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::default())
+    }
+
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            last: None,
+            real_delta: Duration::ZERO,
+            real_elapsed: Duration::ZERO,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            frame: 0,
+            scale: 1.0,
+            paused: false,
+            max_delta: DEFAULT_MAX_DELTA,
+            fps: 0.0,
+        }
+    }
+
+    /// Reads the clock and advances both clocks by the time since the last
+    /// update.
+    pub fn update(&mut self) {
+        let now = self.clock.now();
+        self.real_delta = self.last.map_or(Duration::ZERO, |last| now.saturating_sub(last));
+        self.last = Some(now);
+        self.real_elapsed += self.real_delta;
+        self.delta = if self.paused {
+            Duration::ZERO
+        } else {
+            // Saturates rather than panicking when a huge max delta is scaled up.
+            Duration::try_from_secs_f64(self.real_delta.min(self.max_delta).as_secs_f64() * self.scale).unwrap_or(Duration::MAX)
+        };
+        self.elapsed = self.elapsed.saturating_add(self.delta);
+        self.frame += 1;
+
+        let dt = self.real_delta.as_secs_f32();
+        if dt > 0.0 {
+            self.fps = if self.fps == 0.0 { 1.0 / dt } else { 0.9 * self.fps + 0.1 / dt };
+        }
+    }
+
+    /// Virtual time since the last update: clamped to
+    /// [`max_delta`](Self::set_max_delta), scaled, and zero while paused.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Virtual time since the first update.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_secs(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    /// Wall-clock time since the last update.
+    pub fn real_delta(&self) -> Duration {
+        self.real_delta
+    }
+
+    pub fn real_delta_secs(&self) -> f32 {
+        self.real_delta.as_secs_f32()
+    }
+
+    /// Wall-clock time since the first update.
+    pub fn real_elapsed(&self) -> Duration {
+        self.real_elapsed
+    }
+
+    /// Number of updates so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    /// Smoothed frames per second of real time.
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Speeds up (> 1) or slows down (< 1) virtual time. Scales are clamped
+    /// to `0..=`[`MAX_TIME_SCALE`]; NaN and infinity are ignored.
+    pub fn set_scale(&mut self, scale: f64) {
+        if !scale.is_finite() {
+            tracing::warn!("ignoring time scale {scale}");
+            return;
+        }
+        self.scale = scale.clamp(0.0, MAX_TIME_SCALE);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops virtual time; real time keeps running.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn max_delta(&self) -> Duration {
+        self.max_delta
+    }
+
+    /// Caps the virtual delta of one frame, so a long stall (a breakpoint, a
+    /// dragged window) doesn't turn into one huge step.
+    pub fn set_max_delta(&mut self, max_delta: Duration) {
+        self.max_delta = max_delta;
+    }
+}
+
+/// Fixed-timestep accumulator.
+pub struct FixedTime {
+    step: Duration,
+    accumulator: Duration,
+    max_steps: u32,
+    steps: u32,
+    elapsed: Duration,
+    dropped: Duration,
+}
+
+impl FixedTime {
+    pub fn new(step: Duration) -> Self {
+        assert!(!step.is_zero(), "fixed step must be positive");
+        Self {
+            step,
+            accumulator: Duration::ZERO,
+            max_steps: DEFAULT_MAX_STEPS,
+            steps: 0,
+            elapsed: Duration::ZERO,
+            dropped: Duration::ZERO,
+        }
+    }
+
+    /// Steps `hz` times per second of virtual time.
+    pub fn from_hz(hz: f64) -> Self {
+        assert!(hz > 0.0 && hz.is_finite(), "fixed update rate must be positive and finite, got {hz}");
+        Self::new(Duration::from_secs_f64(1.0 / hz))
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn step_secs(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    /// Caps the steps run per frame. When a frame owes more, the excess
+    /// whole steps are dropped rather than carried over, so a slow frame
+    /// can't make the next one slower still.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1);
+    }
+
+    /// Adds a frame's virtual delta and returns how many steps to run.
+    pub fn accumulate(&mut self, delta: Duration) -> u32 {
+        self.accumulator = self.accumulator.saturating_add(delta);
+        let owed = (self.accumulator.as_nanos() / self.step.as_nanos()) as u64;
+        let steps = owed.min(u64::from(self.max_steps)) as u32;
+        self.accumulator -= self.step * steps;
+        if owed > u64::from(steps) {
+            let dropped = self.step * (owed - u64::from(steps)) as u32;
+            self.accumulator -= dropped;
+            self.dropped += dropped;
+            tracing::debug!("fixed step: dropped {dropped:?} behind");
+        }
+        self.steps = steps;
+        self.elapsed += self.step * steps;
+        steps
+    }
+
+    /// Steps returned by the last [`accumulate`](Self::accumulate).
+    pub fn steps_this_frame(&self) -> u32 {
+        self.steps
+    }
+
+    /// How far time is past the last step, in steps: 0 right on a step,
+    /// approaching 1 just before the next. Render state interpolated between
+    /// the last two steps by this lags real time by under one step but moves
+    /// smoothly.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.step.as_secs_f64()) as f32
+    }
+
+    /// Time covered by the steps run so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Total time skipped by the max-steps guard.
+    pub fn dropped(&self) -> Duration {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn manual_time() -> (Time, ManualClock) {
+        let clock = ManualClock::new();
+        let mut time = Time::with_clock(clock.clone());
+        time.update();
+        (time, clock)
+    }
+
+    #[test]
+    fn first_update_has_zero_delta() {
+        let (time, _) = manual_time();
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.frame_count(), 1);
+    }
+
+    #[test]
+    fn pause_and_scale_affect_virtual_time_only() {
+        let (mut time, clock) = manual_time();
+        time.set_scale(2.0);
+        clock.advance(10 * MS);
+        time.update();
+        assert_eq!(time.delta(), 20 * MS);
+        assert_eq!(time.real_delta(), 10 * MS);
+
+        time.set_paused(true);
+        clock.advance(10 * MS);
+        time.update();
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.real_delta(), 10 * MS);
+        assert_eq!(time.elapsed(), 20 * MS);
+        assert_eq!(time.real_elapsed(), 20 * MS);
+    }
+
+    #[test]
+    fn delta_is_clamped_before_scaling() {
+        let (mut time, clock) = manual_time();
+        time.set_max_delta(50 * MS);
+        time.set_scale(0.5);
+        clock.advance(Duration::from_secs(1));
+        time.update();
+        assert_eq!(time.delta(), 25 * MS);
+    }
+
+    #[test]
+    fn invalid_scales_are_rejected() {
+        let (mut time, _) = manual_time();
+        time.set_scale(3.0);
+        time.set_scale(f64::NAN);
+        time.set_scale(f64::INFINITY);
+        assert_eq!(time.scale(), 3.0);
+        time.set_scale(-1.0);
+        assert_eq!(time.scale(), 0.0);
+    }
+
+    #[test]
+    fn huge_scales_are_clamped() {
+        let (mut time, clock) = manual_time();
+        time.set_scale(1e300);
+        assert_eq!(time.scale(), MAX_TIME_SCALE);
+        clock.advance(10 * MS);
+        time.update();
+        assert_eq!(time.delta(), 10 * MS * MAX_TIME_SCALE as u32);
+
+        // Scaling a delta past what a Duration holds saturates.
+        time.set_max_delta(Duration::MAX);
+        clock.advance(Duration::from_secs(u64::MAX / 100));
+        time.update();
+        assert_eq!(time.delta(), Duration::MAX);
+        assert_eq!(time.elapsed(), Duration::MAX);
+    }
+
+    #[test]
+    fn accumulate_carries_the_remainder() {
+        let mut fixed = FixedTime::new(10 * MS);
+        assert_eq!(fixed.accumulate(25 * MS), 2);
+        assert_eq!(fixed.steps_this_frame(), 2);
+        assert!((fixed.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(fixed.accumulate(4 * MS), 0);
+        assert!((fixed.alpha() - 0.9).abs() < 1e-6);
+        assert_eq!(fixed.accumulate(MS), 1);
+        assert_eq!(fixed.alpha(), 0.0);
+        assert_eq!(fixed.elapsed(), 30 * MS);
+        assert_eq!(fixed.dropped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn max_steps_drops_whole_steps() {
+        let mut fixed = FixedTime::new(10 * MS);
+        fixed.set_max_steps(3);
+        assert_eq!(fixed.accumulate(55 * MS), 3);
+        // Two whole steps are dropped; the partial one is kept.
+        assert_eq!(fixed.dropped(), 20 * MS);
+        assert!((fixed.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(fixed.elapsed(), 30 * MS);
+        assert_eq!(fixed.accumulate(5 * MS), 1);
+    }
+
+    #[test]
+    fn from_hz_sets_the_step() {
+        assert_eq!(FixedTime::from_hz(50.0).step(), 20 * MS);
+    }
+
+    #[test]
+    #[should_panic(expected = "fixed update rate must be positive and finite")]
+    fn from_hz_rejects_zero() {
+        FixedTime::from_hz(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "fixed update rate must be positive and finite")]
+    fn from_hz_rejects_nan() {
+        FixedTime::from_hz(f64::NAN);
+    }
+}
@@ -0,0 +1,362 @@
+//! Local and world transforms with parent/child relationships.
+//!
+//! An entity's [`Transform`] is relative to its [`Parent`], or to the world
+//! without one. [`propagate_transforms`] writes each entity's world matrix
+//! into its [`GlobalTransform`], which everything downstream (scenes,
+//! renderers, culling) should read rather than composing matrices itself.
+//! Entities need both components to take part; spawn them together.
+//!
+//! Parents and children are linked with [`World::set_parent`] and friends,
+//! which keep [`Parent`] and [`Children`] consistent. Despawn a parent with
+//! [`World::despawn_recursive`]; children left behind by a plain
+//! [`World::despawn`] stop being updated.
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::ecs::{Bundle, Entity, System, Without, World};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self { translation: Vec3::ZERO, rotation: Quat::IDENTITY, scale: Vec3::ONE };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self { rotation, ..Self::IDENTITY }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self { scale, ..Self::IDENTITY }
+    }
+
+    /// Decomposes an affine matrix. Shear, which a transform can't hold, is
+    /// lost.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self { translation, rotation, scale }
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Rotates so that -Z points at `target`.
+    pub fn looking_at(mut self, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - self.translation).normalize();
+        let right = up.cross(-forward).normalize();
+        let up = (-forward).cross(right);
+        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, -forward));
+        self
+    }
+}
+
+/// World matrix of an entity, written by [`propagate_transforms`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobalTransform {
+    matrix: Mat4,
+    /// The local transform `matrix` was computed from; `None` forces a
+    /// recompute.
+    source: Option<Transform>,
+}
+
+impl GlobalTransform {
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.matrix.w_axis.truncate()
+    }
+
+    pub fn to_transform(&self) -> Transform {
+        Transform::from_matrix(self.matrix)
+    }
+}
+
+/// The entity this one's transform is relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Entities whose [`Parent`] is this one, in the order they were attached.
+#[derive(Clone, Debug, Default)]
+pub struct Children(SmallVec<[Entity; 4]>);
+
+impl std::ops::Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+/// Updates every [`GlobalTransform`] whose local transform, or an
+/// ancestor's, changed since the last call, walking down from the roots.
+/// Unchanged subtrees are visited but not recomputed.
+pub fn propagate_transforms(world: &World) {
+    let mut roots = world.query_filtered::<(Entity, &Transform, &mut GlobalTransform), Without<Parent>>();
+    for (entity, local, global) in &mut roots {
+        let dirty = global.source != Some(*local);
+        if dirty {
+            global.matrix = local.matrix();
+            global.source = Some(*local);
+        }
+        propagate_children(world, entity, global.matrix, dirty);
+    }
+}
+
+fn propagate_children(world: &World, entity: Entity, parent: Mat4, parent_dirty: bool) {
+    let Some(children) = world.get::<Children>(entity) else { return };
+    for &child in children.iter() {
+        let (Some(local), Some(mut global)) = (world.get::<Transform>(child), world.get_mut::<GlobalTransform>(child))
+        else {
+            continue;
+        };
+        let dirty = parent_dirty || global.source != Some(*local);
+        if dirty {
+            global.matrix = parent * local.matrix();
+            global.source = Some(*local);
+        }
+        let matrix = global.matrix;
+        drop((local, global));
+        propagate_children(world, child, matrix, dirty);
+    }
+}
+
+/// [`propagate_transforms`] as a system, meant for the post-update stage.
+pub fn transform_propagate_system() -> System {
+    System::new("propagate_transforms", |world, _| propagate_transforms(world))
+        .reads::<Transform>()
+        .reads::<Parent>()
+        .reads::<Children>()
+        .writes::<GlobalTransform>()
+}
+
+impl World {
+    /// Attaches `child` to `parent`, or detaches it with `None`, keeping its
+    /// local transform, so it moves with its new parent. Returns false if
+    /// either entity is gone or `parent` is `child` or one of its
+    /// descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Option<Entity>) -> bool {
+        self.reparent(child, parent, false)
+    }
+
+    /// Like [`set_parent`](Self::set_parent), but rewrites the local
+    /// transform so the child stays where it is in the world.
+    pub fn set_parent_in_place(&mut self, child: Entity, parent: Option<Entity>) -> bool {
+        self.reparent(child, parent, true)
+    }
+
+    /// Spawns `bundle` as the last child of `parent`. Returns `None`, and
+    /// spawns nothing, if `parent` is gone.
+    pub fn spawn_child<B: Bundle>(&mut self, parent: Entity, bundle: B) -> Option<Entity> {
+        if !self.contains(parent) {
+            return None;
+        }
+        let child = self.spawn(bundle);
+        self.set_parent(child, Some(parent));
+        Some(child)
+    }
+
+    /// Despawns `entity` and all its descendants, detaching it from its
+    /// parent. Returns false if it was already gone.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+        self.detach(entity);
+        let mut stack = vec![entity];
+        while let Some(next) = stack.pop() {
+            if let Some(children) = self.get::<Children>(next) {
+                stack.extend_from_slice(&children);
+            }
+            self.despawn(next);
+        }
+        true
+    }
+
+    /// World matrix computed from the local transforms up the parent chain,
+    /// current even before transforms are propagated.
+    pub fn world_matrix(&self, entity: Entity) -> Option<Mat4> {
+        let mut matrix = self.get::<Transform>(entity)?.matrix();
+        let mut current = entity;
+        while let Some(parent) = self.get::<Parent>(current).map(|p| p.0) {
+            if let Some(local) = self.get::<Transform>(parent) {
+                matrix = local.matrix() * matrix;
+            }
+            current = parent;
+        }
+        Some(matrix)
+    }
+
+    fn reparent(&mut self, child: Entity, parent: Option<Entity>, keep_global: bool) -> bool {
+        if !self.contains(child) || parent.is_some_and(|p| !self.contains(p) || self.is_descendant(p, child)) {
+            return false;
+        }
+        let world_matrix = if keep_global { self.world_matrix(child) } else { None };
+        self.detach(child);
+        if let Some(parent) = parent {
+            self.insert(child, (Parent(parent),));
+            let attached = self.get_mut::<Children>(parent).map(|mut children| children.0.push(child)).is_some();
+            if !attached {
+                self.insert(parent, (Children(SmallVec::from_slice(&[child])),));
+            }
+        }
+        if let Some(world_matrix) = world_matrix {
+            let parent_matrix = parent.and_then(|p| self.world_matrix(p)).unwrap_or(Mat4::IDENTITY);
+            if let Some(mut local) = self.get_mut::<Transform>(child) {
+                *local = Transform::from_matrix(parent_matrix.inverse() * world_matrix);
+            }
+        }
+        if let Some(mut global) = self.get_mut::<GlobalTransform>(child) {
+            global.source = None;
+        }
+        true
+    }
+
+    /// Whether `entity` is `ancestor` or below it.
+    fn is_descendant(&self, entity: Entity, ancestor: Entity) -> bool {
+        let mut current = Some(entity);
+        while let Some(e) = current {
+            if e == ancestor {
+                return true;
+            }
+            current = self.get::<Parent>(e).map(|p| p.0);
+        }
+        false
+    }
+
+    /// Removes `child` from its parent's [`Children`] and drops its
+    /// [`Parent`].
+    fn detach(&mut self, child: Entity) {
+        let Some(Parent(parent)) = self.remove::<Parent>(child) else { return };
+        let empty = self.get_mut::<Children>(parent).is_some_and(|mut children| {
+            children.0.retain(|c| *c != child);
+            children.0.is_empty()
+        });
+        if empty {
+            self.remove::<Children>(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(world: &mut World, translation: Vec3) -> Entity {
+        world.spawn((Transform::from_translation(translation), GlobalTransform::default()))
+    }
+
+    fn child(world: &mut World, parent: Entity, translation: Vec3) -> Entity {
+        world.spawn_child(parent, (Transform::from_translation(translation), GlobalTransform::default())).unwrap()
+    }
+
+    fn global(world: &World, entity: Entity) -> Vec3 {
+        world.get::<GlobalTransform>(entity).unwrap().translation()
+    }
+
+    #[test]
+    fn propagation_follows_parent_changes() {
+        let mut world = World::new();
+        let root = spawn(&mut world, Vec3::X);
+        let middle = child(&mut world, root, Vec3::Y);
+        let leaf = child(&mut world, middle, Vec3::Z);
+        propagate_transforms(&world);
+        assert_eq!(global(&world, leaf), Vec3::ONE);
+
+        world.get_mut::<Transform>(root).unwrap().translation = Vec3::ZERO;
+        propagate_transforms(&world);
+        assert_eq!(global(&world, middle), Vec3::Y);
+        assert_eq!(global(&world, leaf), Vec3::new(0.0, 1.0, 1.0));
+        assert_eq!(world.world_matrix(leaf).unwrap().w_axis.truncate(), Vec3::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn reparenting_in_place_keeps_world_position() {
+        let mut world = World::new();
+        let a = spawn(&mut world, Vec3::new(1.0, 0.0, 0.0));
+        let scaled = Transform::from_translation(Vec3::new(0.0, 5.0, 0.0)).with_scale(Vec3::splat(2.0));
+        let b = world.spawn((scaled, GlobalTransform::default()));
+        let item = child(&mut world, a, Vec3::new(0.0, 0.0, 3.0));
+        propagate_transforms(&world);
+        let before = global(&world, item);
+
+        assert!(world.set_parent_in_place(item, Some(b)));
+        propagate_transforms(&world);
+        assert!(global(&world, item).abs_diff_eq(before, 1e-5));
+        assert!(world.get::<Transform>(item).unwrap().translation.abs_diff_eq(Vec3::new(0.5, -2.5, 1.5), 1e-5));
+        assert!(world.get::<Children>(a).is_none());
+        assert_eq!(world.get::<Children>(b).unwrap()[..], [item]);
+
+        // A plain reparent keeps the local transform instead.
+        assert!(world.set_parent(item, None));
+        propagate_transforms(&world);
+        assert!(global(&world, item).abs_diff_eq(Vec3::new(0.5, -2.5, 1.5), 1e-5));
+    }
+
+    #[test]
+    fn cycles_and_dead_entities_are_rejected() {
+        let mut world = World::new();
+        let root = spawn(&mut world, Vec3::ZERO);
+        let below = child(&mut world, root, Vec3::ZERO);
+        assert!(!world.set_parent(root, Some(below)));
+        assert!(!world.set_parent(root, Some(root)));
+
+        world.despawn(root);
+        let count = world.len();
+        assert_eq!(world.spawn_child(root, (Transform::IDENTITY,)), None);
+        assert_eq!(world.len(), count);
+    }
+
+    #[test]
+    fn despawn_recursive_removes_the_subtree() {
+        let mut world = World::new();
+        let root = spawn(&mut world, Vec3::ZERO);
+        let branch = child(&mut world, root, Vec3::ZERO);
+        let sibling = child(&mut world, root, Vec3::ZERO);
+        let leaves = [child(&mut world, branch, Vec3::ZERO), child(&mut world, branch, Vec3::ZERO)];
+
+        assert!(world.despawn_recursive(branch));
+        assert!(!world.despawn_recursive(branch));
+        assert!(leaves.iter().all(|&leaf| !world.contains(leaf)));
+        assert_eq!(world.get::<Children>(root).unwrap()[..], [sibling]);
+        assert_eq!(world.len(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+//! Typed GPU buffers and bind group helpers.
+//!
+//! Bind groups are built from a [`BindGroupBuilder`], which pairs every
+//! resource with its layout entry, and kept in a [`BindGroupCache`]. The
+//! cache reuses a bind group for as long as the same resources are bound, so
+//! when a texture or buffer is recreated (a resize, a storage buffer
+//! outgrowing its capacity) the next lookup simply builds a new one.
+
+use std::marker::PhantomData;
+use std::num::NonZeroU64;
+
+use bytemuck::Pod;
+use wgpu::util::DeviceExt;
+
+/// A float 2D texture that can be sampled with filtering.
+pub fn texture_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    texture_entry_with(binding, visibility, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::D2)
+}
+
+/// A texture read with `textureLoad` only; also accepts depth textures.
+pub fn unfilterable_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    texture_entry_with(binding, visibility, wgpu::TextureSampleType::Float { filterable: false }, wgpu::TextureViewDimension::D2)
+}
+
+pub fn texture_entry_with(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    sample_type: wgpu::TextureSampleType,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Texture { sample_type, view_dimension, multisampled: false },
+        count: None,
+    }
+}
+
+pub fn sampler_entry(binding: u32, visibility: wgpu::ShaderStages, ty: wgpu::SamplerBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry { binding, visibility, ty: wgpu::BindingType::Sampler(ty), count: None }
+}
+
+pub fn buffer_entry(binding: u32, visibility: wgpu::ShaderStages, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None },
+        count: None,
+    }
+}
+
+/// A uniform bound with a dynamic offset, one `T` at a time; see [`UniformRing`].
+pub fn dynamic_uniform_entry<T: Pod>(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+        },
+        count: None,
+    }
+}
+
+/// A uniform buffer holding one `T`.
+pub struct UniformBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    _value: PhantomData<T>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, label: &str, value: &T) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(value),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self { buffer, _value: PhantomData }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+/// A storage buffer holding an array of `T` that grows as needed. Growing
+/// replaces the buffer, which invalidates bind groups built from it.
+pub struct StorageBuffer<T: Pod> {
+    label: String,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    len: usize,
+    capacity: usize,
+    _value: PhantomData<T>,
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    /// `usage` is added to `STORAGE | COPY_DST`, e.g. `VERTEX` or `INDIRECT`.
+    pub fn new(device: &wgpu::Device, label: &str, capacity: usize, usage: wgpu::BufferUsages) -> Self {
+        let usage = usage | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let capacity = capacity.max(1);
+        Self {
+            buffer: Self::create(device, label, capacity, usage),
+            label: label.to_owned(),
+            usage,
+            len: 0,
+            capacity,
+            _value: PhantomData,
+        }
+    }
+
+    fn create(device: &wgpu::Device, label: &str, capacity: usize, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        // Storage bindings must not be smaller than 4 bytes.
+        let size = (capacity * std::mem::size_of::<T>()).max(4) as u64;
+        device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size, usage, mapped_at_creation: false })
+    }
+
+    /// Replaces the contents with `values`. Returns whether the buffer had to
+    /// be recreated to fit them.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, values: &[T]) -> bool {
+        let grown = values.len() > self.capacity;
+        if grown {
+            self.capacity = values.len().next_power_of_two();
+            self.buffer = Self::create(device, &self.label, self.capacity, self.usage);
+        }
+        self.len = values.len();
+        if !values.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(values));
+        }
+        grown
+    }
+
+    /// Sets the length without uploading anything, for contents written on
+    /// the GPU. Returns whether the buffer had to be recreated, losing its
+    /// contents.
+    pub fn resize(&mut self, device: &wgpu::Device, len: usize) -> bool {
+        let grown = len > self.capacity;
+        if grown {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create(device, &self.label, self.capacity, self.usage);
+        }
+        self.len = len;
+        grown
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+/// Per-frame allocator of uniform values bound with dynamic offsets, e.g. one
+/// per draw. Values are staged on the CPU with [`push`](Self::push) and
+/// uploaded together by [`upload`](Self::upload); build bind groups after
+/// the upload, since it may grow the buffer.
+pub struct UniformRing<T: Pod> {
+    label: String,
+    buffer: wgpu::Buffer,
+    /// Size of one slot, `T` rounded up to the device's offset alignment.
+    stride: u64,
+    capacity: u32,
+    staged: Vec<u8>,
+    _value: PhantomData<T>,
+}
+
+impl<T: Pod> UniformRing<T> {
+    pub fn new(device: &wgpu::Device, label: &str, capacity: u32) -> Self {
+        let align = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = (std::mem::size_of::<T>() as u64).max(16).div_ceil(align) * align;
+        let capacity = capacity.max(1);
+        Self {
+            buffer: Self::create(device, label, stride, capacity),
+            label: label.to_owned(),
+            stride,
+            capacity,
+            staged: Vec::new(),
+            _value: PhantomData,
+        }
+    }
+
+    fn create(device: &wgpu::Device, label: &str, stride: u64, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: stride * capacity as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Forgets the values of the previous frame.
+    pub fn reset(&mut self) {
+        self.staged.clear();
+    }
+
+    /// Stages `value` and returns the dynamic offset to bind it with.
+    pub fn push(&mut self, value: &T) -> u32 {
+        let offset = self.staged.len();
+        self.staged.extend_from_slice(bytemuck::bytes_of(value));
+        self.staged.resize(offset + self.stride as usize, 0);
+        offset as u32
+    }
+
+    /// Writes the staged values, growing the buffer if they don't fit.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let count = (self.staged.len() as u64 / self.stride) as u32;
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.buffer = Self::create(device, &self.label, self.stride, self.capacity);
+        }
+        if !self.staged.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.staged);
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        (self.staged.len() as u64 / self.stride) as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// One slot of the buffer, for a [`dynamic_uniform_entry`] binding.
+    pub fn bound(&self) -> BoundResource {
+        BoundResource::Buffer {
+            buffer: self.buffer.clone(),
+            offset: 0,
+            size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+        }
+    }
+}
+
+/// A resource bound in a bind group. Compares by resource identity, so a
+/// recreated texture or buffer never matches its predecessor.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BoundResource {
+    Buffer { buffer: wgpu::Buffer, offset: u64, size: Option<NonZeroU64> },
+    TextureView(wgpu::TextureView),
+    Sampler(wgpu::Sampler),
+}
+
+impl BoundResource {
+    pub fn buffer(buffer: &wgpu::Buffer) -> Self {
+        Self::Buffer { buffer: buffer.clone(), offset: 0, size: None }
+    }
+
+    /// The owned form of a borrowed binding; arrays of resources are not
+    /// supported.
+    pub fn from_binding(resource: &wgpu::BindingResource) -> Option<Self> {
+        Some(match resource {
+            wgpu::BindingResource::Buffer(b) => Self::Buffer { buffer: b.buffer.clone(), offset: b.offset, size: b.size },
+            wgpu::BindingResource::TextureView(view) => Self::TextureView((*view).clone()),
+            wgpu::BindingResource::Sampler(sampler) => Self::Sampler((*sampler).clone()),
+            _ => return None,
+        })
+    }
+
+    fn as_binding(&self) -> wgpu::BindingResource<'_> {
+        match self {
+            Self::Buffer { buffer, offset, size } => {
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding { buffer, offset: *offset, size: *size })
+            }
+            Self::TextureView(view) => wgpu::BindingResource::TextureView(view),
+            Self::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        }
+    }
+}
+
+/// Collects the layout entries and resources of one bind group.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BindGroupBuilder {
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+    resources: Vec<BoundResource>,
+}
+
+impl BindGroupBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entry(mut self, entry: wgpu::BindGroupLayoutEntry, resource: BoundResource) -> Self {
+        self.entries.push(entry);
+        self.resources.push(resource);
+        self
+    }
+
+    pub fn uniform(self, binding: u32, visibility: wgpu::ShaderStages, buffer: &wgpu::Buffer) -> Self {
+        self.entry(buffer_entry(binding, visibility, wgpu::BufferBindingType::Uniform), BoundResource::buffer(buffer))
+    }
+
+    pub fn dynamic_uniform<T: Pod>(self, binding: u32, visibility: wgpu::ShaderStages, ring: &UniformRing<T>) -> Self {
+        self.entry(dynamic_uniform_entry::<T>(binding, visibility), ring.bound())
+    }
+
+    pub fn storage(self, binding: u32, visibility: wgpu::ShaderStages, buffer: &wgpu::Buffer, read_only: bool) -> Self {
+        self.entry(buffer_entry(binding, visibility, wgpu::BufferBindingType::Storage { read_only }), BoundResource::buffer(buffer))
+    }
+
+    /// A filterable float 2D texture.
+    pub fn texture(self, binding: u32, visibility: wgpu::ShaderStages, view: &wgpu::TextureView) -> Self {
+        self.entry(texture_entry(binding, visibility), BoundResource::TextureView(view.clone()))
+    }
+
+    pub fn sampler(self, binding: u32, visibility: wgpu::ShaderStages, ty: wgpu::SamplerBindingType, sampler: &wgpu::Sampler) -> Self {
+        self.entry(sampler_entry(binding, visibility, ty), BoundResource::Sampler(sampler.clone()))
+    }
+
+    pub fn layout_entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
+    pub fn build(&self, device: &wgpu::Device, label: &str, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .zip(&self.resources)
+            .map(|(entry, resource)| wgpu::BindGroupEntry { binding: entry.binding, resource: resource.as_binding() })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some(label), layout, entries: &entries })
+    }
+}
+
+/// Keeps the bind groups most recently built by one user, keyed by what they
+/// bind, and the layout they share.
+///
+/// Holds on to at most `capacity` groups: enough for a pass that is drawn a
+/// few times per frame with different inputs, while groups of recreated
+/// resources age out.
+pub struct BindGroupCache {
+    label: String,
+    capacity: usize,
+    layout: Option<(Vec<wgpu::BindGroupLayoutEntry>, wgpu::BindGroupLayout)>,
+    /// Most recently used first.
+    groups: Vec<(Vec<BoundResource>, wgpu::BindGroup)>,
+}
+
+impl BindGroupCache {
+    pub fn new(label: &str, capacity: usize) -> Self {
+        Self { label: label.to_owned(), capacity: capacity.max(1), layout: None, groups: Vec::new() }
+    }
+
+    /// The layout for `entries`, created when they change.
+    pub fn layout(&mut self, device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry]) -> &wgpu::BindGroupLayout {
+        if self.layout.as_ref().is_none_or(|(cached, _)| cached != entries) {
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some(&self.label), entries });
+            self.layout = Some((entries.to_vec(), layout));
+            self.groups.clear();
+        }
+        &self.layout.as_ref().expect("created above").1
+    }
+
+    /// The bind group for `builder`, built if none of the cached groups binds
+    /// the same resources.
+    pub fn get(&mut self, device: &wgpu::Device, builder: BindGroupBuilder) -> &wgpu::BindGroup {
+        let layout = self.layout(device, &builder.entries).clone();
+        match self.groups.iter().position(|(resources, _)| *resources == builder.resources) {
+            Some(i) => {
+                let group = self.groups.remove(i);
+                self.groups.insert(0, group);
+            }
+            None => {
+                let group = builder.build(device, &self.label, &layout);
+                self.groups.insert(0, (builder.resources, group));
+                self.groups.truncate(self.capacity);
+            }
+        }
+        &self.groups[0].1
+    }
+
+    /// Drops every cached bind group.
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+}
@@ -0,0 +1,446 @@
+//! Draws the [`DebugDraw`] queue of mars-core.
+
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use mars_core::debug_draw::{DebugDraw, DebugItem, DebugShape, DebugSpace};
+
+use crate::binding::{self, BindGroupBuilder, BindGroupCache, StorageBuffer, UniformBuffer};
+use crate::graph::{NodeContext, RenderNode};
+use crate::output::needs_srgb_encode;
+use crate::pipeline::{ColorTarget, PipelinesHandle, RenderPipelineDesc, VertexLayout};
+use crate::shader::ShaderDefs;
+
+/// Graph buffer whose leading `mat4x4` is the view-projection for
+/// world-space items; the 3D prepass publishes its camera under this name.
+pub const CAMERA: &str = "camera";
+/// Graph texture used for depth testing; the 3D prepass depth.
+pub const DEPTH: &str = "depth";
+
+/// Pixel height glyphs are rasterized at; labels scale from it.
+const GLYPH_PX: f32 = 32.0;
+const ATLAS_SIZE: u32 = 512;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    screen: f32,
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct TextVertex {
+    anchor: [f32; 3],
+    screen: f32,
+    color: [f32; 4],
+    offset: [f32; 2],
+    uv: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct DebugParams {
+    viewport: [f32; 2],
+    _pad: [f32; 2],
+}
+
+#[derive(Clone, Copy)]
+struct GlyphSlot {
+    /// Top-left of the glyph box relative to the pen at the line's top, in
+    /// [`GLYPH_PX`] pixels.
+    offset: [f32; 2],
+    size: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+}
+
+/// Glyphs rasterized on demand into a single-channel texture, packed in
+/// shelves. When a frame's glyphs don't fit it is [reset](Self::reset) and
+/// the frame's text laid out again, so only the glyphs in use are kept.
+struct GlyphAtlas {
+    font: FontArc,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    glyphs: HashMap<char, Option<GlyphSlot>>,
+    cursor: (u32, u32),
+    shelf_height: u32,
+    /// A glyph was skipped for lack of space since the last reset.
+    full: bool,
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device, font: FontArc) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Debug Glyph Atlas"),
+            size: wgpu::Extent3d { width: ATLAS_SIZE, height: ATLAS_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { font, texture, view, glyphs: HashMap::new(), cursor: (0, 0), shelf_height: 0, full: false }
+    }
+
+    /// The atlas slot of `c`, or `None` for glyphs without coverage such as
+    /// spaces and for glyphs that don't fit.
+    fn glyph(&mut self, queue: &wgpu::Queue, c: char) -> Option<GlyphSlot> {
+        if let Some(slot) = self.glyphs.get(&c) {
+            return *slot;
+        }
+        let font = self.font.clone();
+        let scaled = font.as_scaled(PxScale::from(GLYPH_PX));
+        let glyph = scaled.scaled_glyph(c);
+        let slot = font.outline_glyph(glyph).and_then(|outlined| {
+            let bounds = outlined.px_bounds();
+            let (w, h) = (bounds.width().ceil() as u32, bounds.height().ceil() as u32);
+            if w == 0 || h == 0 {
+                return None;
+            }
+            // One texel of padding keeps filtering from bleeding between glyphs.
+            let (x, y) = self.allocate(w + 1, h + 1)?;
+            let mut coverage = vec![0u8; (w * h) as usize];
+            outlined.draw(|gx, gy, v| {
+                if gx < w && gy < h {
+                    coverage[(gy * w + gx) as usize] = (v.clamp(0.0, 1.0) * 255.0) as u8;
+                }
+            });
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo { texture: &self.texture, mip_level: 0, origin: wgpu::Origin3d { x, y, z: 0 }, aspect: wgpu::TextureAspect::All },
+                &coverage,
+                wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(w), rows_per_image: None },
+                wgpu::Extent3d { width: w, height: h, depth_or_array_layers: 1 },
+            );
+            let atlas = ATLAS_SIZE as f32;
+            Some(GlyphSlot {
+                offset: [bounds.min.x, bounds.min.y + scaled.ascent()],
+                size: [w as f32, h as f32],
+                uv_min: [x as f32 / atlas, y as f32 / atlas],
+                uv_max: [(x + w) as f32 / atlas, (y + h) as f32 / atlas],
+            })
+        });
+        // A glyph skipped for lack of space may fit after a reset.
+        if slot.is_some() || !self.full {
+            self.glyphs.insert(c, slot);
+        }
+        slot
+    }
+
+    /// Space for a `w` by `h` glyph. Never evicts: glyphs already handed
+    /// out this frame must keep their texels.
+    fn allocate(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        if w > ATLAS_SIZE || h > ATLAS_SIZE {
+            return None;
+        }
+        if self.cursor.0 + w > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.shelf_height);
+            self.shelf_height = 0;
+        }
+        if self.cursor.1 + h > ATLAS_SIZE {
+            self.full = true;
+            return None;
+        }
+        let at = self.cursor;
+        self.cursor.0 += w;
+        self.shelf_height = self.shelf_height.max(h);
+        Some(at)
+    }
+
+    /// Forgets every glyph, so the space is reused as glyphs are requested
+    /// again.
+    fn reset(&mut self) {
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.shelf_height = 0;
+        self.full = false;
+    }
+
+    fn h_advance(&self, c: char) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(GLYPH_PX));
+        scaled.h_advance(self.font.glyph_id(c))
+    }
+
+    fn line_height(&self) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(GLYPH_PX));
+        scaled.height() + scaled.line_gap()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    format: wgpu::TextureFormat,
+    depth: Option<wgpu::TextureFormat>,
+    depth_test: bool,
+    text: bool,
+}
+
+#[derive(Default)]
+struct Batch {
+    lines: Vec<LineVertex>,
+    text: Vec<TextVertex>,
+}
+
+/// Draws the global [`DebugDraw`] queue over the graph output.
+///
+/// World-space items use the [`CAMERA`] buffer and are skipped while the
+/// graph has none. Depth-tested items are tested against the [`DEPTH`]
+/// texture when it matches the output size, and drawn on top otherwise.
+/// Text needs a font, see [`DebugDrawNode::with_font`]. The node only reads
+/// the queue; call [`DebugDraw::tick`] once per frame to expire items.
+pub struct DebugDrawNode {
+    frame_groups: BindGroupCache,
+    atlas_groups: BindGroupCache,
+    /// Entries of the frame and glyph atlas bind groups.
+    group_entries: [Vec<wgpu::BindGroupLayoutEntry>; 2],
+    /// Valid for `pipelines`.
+    descs: HashMap<PipelineKey, RenderPipelineDesc>,
+    pipelines: Option<PipelinesHandle>,
+    params: UniformBuffer<DebugParams>,
+    no_camera: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    atlas: Option<GlyphAtlas>,
+    /// Depth-tested and overlay items.
+    batches: [Batch; 2],
+    line_buffers: [StorageBuffer<LineVertex>; 2],
+    text_buffers: [StorageBuffer<TextVertex>; 2],
+    items: Vec<DebugItem>,
+    segments: Vec<[Vec3; 2]>,
+}
+
+impl DebugDrawNode {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex = wgpu::ShaderStages::VERTEX;
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let frame_entries = vec![
+            binding::buffer_entry(0, vertex, wgpu::BufferBindingType::Uniform),
+            binding::buffer_entry(1, vertex, wgpu::BufferBindingType::Uniform),
+        ];
+        let atlas_entries = vec![binding::texture_entry(0, fragment), binding::sampler_entry(1, fragment, wgpu::SamplerBindingType::Filtering)];
+
+        let no_camera = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug No Camera"),
+            size: 64,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let vertices = |label| StorageBuffer::new(device, label, 256, wgpu::BufferUsages::VERTEX);
+        Self {
+            frame_groups: BindGroupCache::new("Debug Draw", 4),
+            atlas_groups: BindGroupCache::new("Debug Glyphs", 1),
+            group_entries: [frame_entries, atlas_entries],
+            descs: HashMap::new(),
+            pipelines: None,
+            params: UniformBuffer::new(device, "Debug Draw Params", &DebugParams { viewport: [1.0; 2], _pad: [0.0; 2] }),
+            no_camera,
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Debug Glyph Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            atlas: None,
+            batches: Default::default(),
+            line_buffers: [vertices("Debug Lines"), vertices("Debug Overlay Lines")],
+            text_buffers: [
+                StorageBuffer::new(device, "Debug Text", 256, wgpu::BufferUsages::VERTEX),
+                StorageBuffer::new(device, "Debug Overlay Text", 256, wgpu::BufferUsages::VERTEX),
+            ],
+            items: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Enables text labels, rasterized with `font`.
+    pub fn with_font(mut self, device: &wgpu::Device, font: FontArc) -> Self {
+        self.atlas = Some(GlyphAtlas::new(device, font));
+        self
+    }
+
+    fn pipeline(&mut self, pipelines: &PipelinesHandle, device: &wgpu::Device, key: PipelineKey) -> wgpu::RenderPipeline {
+        // Shader ids belong to one library.
+        if !self.pipelines.as_ref().is_some_and(|p| p.ptr_eq(pipelines)) {
+            self.pipelines = Some(pipelines.clone());
+            self.descs.clear();
+        }
+        let mut pipelines = pipelines.lock();
+        let desc = self.descs.entry(key).or_insert_with(|| {
+            let line_attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32, 2 => Float32x4];
+            let text_attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32, 2 => Float32x4, 3 => Float32x2, 4 => Float32x2];
+            let (groups, entry, stride, attributes, topology) = if key.text {
+                (&self.group_entries[..], "text", size_of::<TextVertex>(), &text_attributes[..], wgpu::PrimitiveTopology::TriangleList)
+            } else {
+                (&self.group_entries[..1], "line", size_of::<LineVertex>(), &line_attributes[..], wgpu::PrimitiveTopology::LineList)
+            };
+            let mut defs = ShaderDefs::new();
+            if needs_srgb_encode(key.format) {
+                defs.set("ENCODE_SRGB", 1);
+            }
+            let shader = pipelines.builtin(device, "mars/debug_draw.wgsl", &defs);
+            let mut desc = RenderPipelineDesc::new("Debug Draw Pipeline", shader)
+                .with_entry_points(&format!("vs_{entry}"), Some(&format!("fs_{entry}")))
+                .with_vertex_buffer(VertexLayout::new(stride as u64, attributes))
+                .with_target(ColorTarget::new(key.format).with_blend(wgpu::BlendState::ALPHA_BLENDING))
+                .with_primitive(wgpu::PrimitiveState { topology, ..Default::default() });
+            for entries in groups {
+                desc = desc.with_bind_group(entries);
+            }
+            if let Some(format) = key.depth {
+                desc = desc.with_depth(wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled: false,
+                    depth_compare: if key.depth_test { wgpu::CompareFunction::LessEqual } else { wgpu::CompareFunction::Always },
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                });
+            }
+            desc
+        });
+        pipelines.render_pipeline(device, desc)
+    }
+
+    /// Turns this frame's items into vertices, split by depth testing.
+    fn build_batches(&mut self, queue: &wgpu::Queue, has_camera: bool) {
+        self.fill_batches(queue, has_camera);
+        // Out of atlas space: start over with only this frame's glyphs. Any
+        // that still don't fit are left out.
+        if let Some(atlas) = self.atlas.as_mut().filter(|a| a.full) {
+            atlas.reset();
+            self.fill_batches(queue, has_camera);
+        }
+    }
+
+    fn fill_batches(&mut self, queue: &wgpu::Queue, has_camera: bool) {
+        for batch in &mut self.batches {
+            batch.lines.clear();
+            batch.text.clear();
+        }
+        for item in &self.items {
+            if item.space == DebugSpace::World && !has_camera {
+                continue;
+            }
+            let screen = if item.space == DebugSpace::Screen { 1.0 } else { 0.0 };
+            let color = item.style.color.to_array();
+            let batch = &mut self.batches[usize::from(!item.style.depth_test)];
+            if let DebugShape::Text { position, text, size } = &item.shape {
+                let Some(atlas) = self.atlas.as_mut() else { continue };
+                let scale = size / GLYPH_PX;
+                let mut pen = [0.0f32, 0.0];
+                for c in text.chars() {
+                    if c == '\n' {
+                        pen = [0.0, pen[1] + atlas.line_height() * scale];
+                        continue;
+                    }
+                    if let Some(glyph) = atlas.glyph(queue, c) {
+                        let x0 = pen[0] + glyph.offset[0] * scale;
+                        let y0 = pen[1] + glyph.offset[1] * scale;
+                        let (x1, y1) = (x0 + glyph.size[0] * scale, y0 + glyph.size[1] * scale);
+                        let vertex = |x: f32, y: f32, u: f32, v: f32| TextVertex { anchor: position.to_array(), screen, color, offset: [x, y], uv: [u, v] };
+                        let (u0, v0, u1, v1) = (glyph.uv_min[0], glyph.uv_min[1], glyph.uv_max[0], glyph.uv_max[1]);
+                        batch.text.extend_from_slice(&[
+                            vertex(x0, y0, u0, v0),
+                            vertex(x1, y0, u1, v0),
+                            vertex(x0, y1, u0, v1),
+                            vertex(x0, y1, u0, v1),
+                            vertex(x1, y0, u1, v0),
+                            vertex(x1, y1, u1, v1),
+                        ]);
+                    }
+                    pen[0] += atlas.h_advance(c) * scale;
+                }
+                continue;
+            }
+            self.segments.clear();
+            item.shape.segments(item.space, &mut self.segments);
+            for segment in &self.segments {
+                batch.lines.extend(segment.iter().map(|p| LineVertex { position: p.to_array(), screen, color }));
+            }
+        }
+    }
+}
+
+impl RenderNode for DebugDrawNode {
+    fn name(&self) -> &'static str {
+        "debug_draw"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        self.items.clear();
+        DebugDraw::global().snapshot(&mut self.items);
+        if self.items.is_empty() {
+            return Ok(());
+        }
+
+        let camera = ctx.resources.buffer(CAMERA).cloned();
+        self.build_batches(ctx.queue, camera.is_some());
+        self.params.write(ctx.queue, &DebugParams { viewport: [ctx.size.0 as f32, ctx.size.1 as f32], _pad: [0.0; 2] });
+        for (i, batch) in self.batches.iter().enumerate() {
+            self.line_buffers[i].write(ctx.device, ctx.queue, &batch.lines);
+            self.text_buffers[i].write(ctx.device, ctx.queue, &batch.text);
+        }
+
+        let depth = ctx.resources.texture(DEPTH).filter(|t| t.desc.size == ctx.size).map(|t| (t.view.clone(), t.desc.format));
+        let vertex = wgpu::ShaderStages::VERTEX;
+        let frame_group = self
+            .frame_groups
+            .get(ctx.device, BindGroupBuilder::new().uniform(0, vertex, camera.as_ref().unwrap_or(&self.no_camera)).uniform(1, vertex, self.params.buffer()))
+            .clone();
+        let atlas_group = self.atlas.as_ref().map(|atlas| {
+            let fragment = wgpu::ShaderStages::FRAGMENT;
+            self.atlas_groups
+                .get(ctx.device, BindGroupBuilder::new().texture(0, fragment, &atlas.view).sampler(1, fragment, wgpu::SamplerBindingType::Filtering, &self.sampler))
+                .clone()
+        });
+
+        // Depth-tested first, so overlay items end up on top.
+        let mut draws = Vec::new();
+        for (i, batch) in self.batches.iter().enumerate() {
+            let depth_test = i == 0;
+            let key = |text| PipelineKey { format: ctx.format, depth: depth.as_ref().map(|d| d.1), depth_test, text };
+            if !batch.lines.is_empty() {
+                draws.push((key(false), self.line_buffers[i].buffer().clone(), batch.lines.len() as u32));
+            }
+            if !batch.text.is_empty() && atlas_group.is_some() {
+                draws.push((key(true), self.text_buffers[i].buffer().clone(), batch.text.len() as u32));
+            }
+        }
+        let pipelines: Vec<_> = draws.iter().map(|(key, ..)| self.pipeline(ctx.pipelines, ctx.device, *key)).collect();
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Debug Draw") });
+        {
+            let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug Draw Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: ctx.view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: depth.as_ref().map(|(view, _)| wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rp.set_bind_group(0, &frame_group, &[]);
+            for ((key, buffer, count), pipeline) in draws.iter().zip(&pipelines) {
+                rp.set_pipeline(pipeline);
+                if let (true, Some(group)) = (key.text, &atlas_group) {
+                    rp.set_bind_group(1, group, &[]);
+                }
+                rp.set_vertex_buffer(0, buffer.slice(..));
+                rp.draw(0..*count, 0..1);
+            }
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+// Debug lines and text labels; see debug_draw.rs.
+
+struct Camera {
+  view_proj: mat4x4<f32>,
+};
+
+struct Params {
+  viewport: vec2<f32>,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> params: Params;
+@group(1) @binding(0) var atlas: texture_2d<f32>;
+@group(1) @binding(1) var atlas_sampler: sampler;
+
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) uv: vec2<f32>,
+};
+
+// Screen-space points are pixels from the top-left and sit at the near plane.
+fn project(p: vec3<f32>, screen: f32) -> vec4<f32> {
+  if (screen > 0.5) {
+    return vec4<f32>(p.xy / params.viewport * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+  }
+  return camera.view_proj * vec4<f32>(p, 1.0);
+}
+
+// ENCODE_SRGB is defined for non-sRGB 8-bit targets, whose encoding has to
+// happen here.
+fn encode(c: vec4<f32>) -> vec4<f32> {
+#ifdef ENCODE_SRGB
+  let rgb = clamp(c.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+  let lo = rgb * 12.92;
+  let hi = 1.055 * pow(rgb, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return vec4<f32>(select(hi, lo, rgb <= vec3<f32>(0.0031308)), c.a);
+#else
+  return c;
+#endif
+}
+
+@vertex
+fn vs_line(@location(0) position: vec3<f32>, @location(1) screen: f32, @location(2) color: vec4<f32>) -> VsOut {
+  var out: VsOut;
+  out.clip = project(position, screen);
+  out.color = color;
+  out.uv = vec2<f32>(0.0);
+  return out;
+}
+
+@fragment
+fn fs_line(in: VsOut) -> @location(0) vec4<f32> {
+  return encode(in.color);
+}
+
+// Glyph quads keep their pixel size wherever the anchor projects to.
+@vertex
+fn vs_text(
+  @location(0) anchor: vec3<f32>,
+  @location(1) screen: f32,
+  @location(2) color: vec4<f32>,
+  @location(3) offset: vec2<f32>,
+  @location(4) uv: vec2<f32>,
+) -> VsOut {
+  var out: VsOut;
+  var clip = project(anchor, screen);
+  clip = vec4<f32>(clip.xy + offset / params.viewport * vec2<f32>(2.0, -2.0) * clip.w, clip.zw);
+  out.clip = clip;
+  out.color = color;
+  out.uv = uv;
+  return out;
+}
+
+@fragment
+fn fs_text(in: VsOut) -> @location(0) vec4<f32> {
+  let coverage = textureSampleLevel(atlas, atlas_sampler, in.uv, 0.0).r;
+  return encode(vec4<f32>(in.color.rgb, in.color.a * coverage));
+}
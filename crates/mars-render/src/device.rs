@@ -1,23 +1,110 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
 use std::sync::Arc;
 use wgpu::*;
-use winit::window::Window;
+use winit::window::{Window, WindowId};
 
+/// Name of the surface created by [`RenderDevice::new`].
+pub const MAIN_SURFACE: &str = "main";
+
+/// Picks a surface format from the supported `formats`, best first.
+///
+/// With `hdr`, Rgba16Float is preferred: wgpu presents it as extended-range
+/// linear sRGB (scRGB), where 1.0 is 80 nits and brighter values reach HDR
+/// displays. Rgb10a2Unorm comes next; wgpu presents it in the standard sRGB
+/// color space, so it only buys precision (less banding), not range. Without
+/// HDR, or when neither is offered, the first sRGB format is used, then the
+/// first format of any kind. Returns `None` only if `formats` is empty.
+pub fn choose_format(formats: &[TextureFormat], hdr: bool) -> Option<TextureFormat> {
+    let hdr_formats: &[TextureFormat] = if hdr { &[TextureFormat::Rgba16Float, TextureFormat::Rgb10a2Unorm] } else { &[] };
+    hdr_formats
+        .iter()
+        .copied()
+        .find(|f| formats.contains(f))
+        .or_else(|| formats.iter().copied().find(|f| f.is_srgb()))
+        .or_else(|| formats.first().copied())
+}
+
+/// A window's swapchain with its own configuration.
 pub struct SurfaceBundle {
     pub surface: Surface<'static>,
     pub config: SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub window_id: WindowId,
+}
+
+impl SurfaceBundle {
+    fn new(device: &Device, adapter: &Adapter, surface: Surface<'static>, window: &Window) -> Result<Self> {
+        let size = window.inner_size();
+        let caps = surface.get_capabilities(adapter);
+        let format = choose_format(&caps.formats, false).context("surface offers no formats")?;
+
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: PresentMode::Fifo,
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(device, &config);
+        Ok(Self { surface, config, size, window_id: window.id() })
+    }
+
+    pub fn resize(&mut self, device: &Device, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 { return; }
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.surface.configure(device, &self.config);
+    }
+
+    /// Applies changes made to `config`, e.g. a new present mode.
+    pub fn reconfigure(&self, device: &Device) {
+        self.surface.configure(device, &self.config);
+    }
+
+    /// Switches between the best HDR format the surface offers and SDR; see
+    /// [`choose_format`]. Returns whether the surface now has an HDR format.
+    /// Pipelines that target the surface must follow its new format.
+    pub fn set_hdr(&mut self, device: &Device, adapter: &Adapter, hdr: bool) -> bool {
+        let caps = self.surface.get_capabilities(adapter);
+        // Keep the current format if the surface no longer reports any.
+        let format = choose_format(&caps.formats, hdr).unwrap_or(self.config.format);
+        if format != self.config.format {
+            self.config.format = format;
+            self.surface.configure(device, &self.config);
+        }
+        self.is_hdr()
+    }
+
+    /// Whether the surface has an extended-range format.
+    pub fn is_hdr(&self) -> bool {
+        crate::output::is_hdr_format(self.config.format)
+    }
+
+    /// Acquires the next frame; present it with [`SurfaceTexture::present`].
+    pub fn begin_frame(&self) -> std::result::Result<(SurfaceTexture, TextureView), wgpu::SurfaceError> {
+        let frame = self.surface.get_current_texture()?;
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok((frame, view))
+    }
 }
 
+/// The GPU device shared by any number of named surfaces, one per window.
 pub struct RenderDevice {
     pub instance: Instance,
     pub adapter: Adapter,
     pub device: Device,
     pub queue: Queue,
-    pub surface_bundle: SurfaceBundle,
+    surfaces: HashMap<String, SurfaceBundle>,
 }
 
 impl RenderDevice {
+    /// Creates a device able to present to `window`, whose surface is
+    /// registered as [`MAIN_SURFACE`].
     pub async fn new(window: Arc<Window>) -> Result<Self> {
         let instance = Instance::default();
 
@@ -35,7 +122,17 @@ impl RenderDevice {
             .request_device(
                 &DeviceDescriptor {
                     label: Some("Mars Device"),
-                    required_features: Features::empty(),
+                    // Opt into the driver pipeline cache, texture compression,
+                    // GPU timestamps and indirect multi-draw where the adapter
+                    // has them.
+                    required_features: adapter.features()
+                        & (Features::PIPELINE_CACHE
+                            | Features::TIMESTAMP_QUERY
+                            | Features::MULTI_DRAW_INDIRECT
+                            | Features::INDIRECT_FIRST_INSTANCE
+                            | Features::TEXTURE_COMPRESSION_BC
+                            | Features::TEXTURE_COMPRESSION_ETC2
+                            | Features::TEXTURE_COMPRESSION_ASTC),
                     required_limits: Limits::default(),
                     memory_hints: MemoryHints::default(), // NEW in v26
                     trace: Trace::Off,                    // NEW in v26
@@ -43,43 +140,102 @@ impl RenderDevice {
             )
             .await?;
 
-        let size = window.inner_size();
-        let caps = surface.get_capabilities(&adapter);
-        let format = caps.formats.iter().copied().find(|f| f.is_srgb()).unwrap_or(caps.formats[0]);
-
-        let config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            format,
-            width: size.width,
-            height: size.height,
-            present_mode: PresentMode::Fifo,
-            alpha_mode: caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
-
-        surface.configure(&device, &config);
-
+        let main = SurfaceBundle::new(&device, &adapter, surface, &window)?;
         Ok(Self {
             instance,
             adapter,
             device,
             queue,
-            surface_bundle: SurfaceBundle { surface, config, size },
+            surfaces: HashMap::from([(MAIN_SURFACE.to_owned(), main)]),
         })
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width == 0 || new_size.height == 0 { return; }
-        self.surface_bundle.size = new_size;
-        self.surface_bundle.config.width = new_size.width;
-        self.surface_bundle.config.height = new_size.height;
-        self.surface_bundle.surface.configure(&self.device, &self.surface_bundle.config);
+    /// Creates a surface for another window, e.g. a tool panel, under `name`.
+    /// Fails if the name is taken or the adapter cannot present to it.
+    pub fn add_surface(&mut self, name: &str, window: Arc<Window>) -> Result<&mut SurfaceBundle> {
+        if self.surfaces.contains_key(name) {
+            bail!("surface `{name}` already exists");
+        }
+        let surface = self.instance.create_surface(window.clone()).with_context(|| format!("create surface `{name}`"))?;
+        if !self.adapter.is_surface_supported(&surface) {
+            bail!("adapter cannot present to surface `{name}`");
+        }
+        let bundle = SurfaceBundle::new(&self.device, &self.adapter, surface, &window)
+            .with_context(|| format!("configure surface `{name}`"))?;
+        Ok(self.surfaces.entry(name.to_owned()).or_insert(bundle))
     }
 
-    pub fn begin_frame(&self) -> std::result::Result<(SurfaceTexture, TextureView), wgpu::SurfaceError> {
-        let frame = self.surface_bundle.surface.get_current_texture()?;
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        Ok((frame, view))
+    /// Drops a surface, e.g. when its window is closed.
+    pub fn remove_surface(&mut self, name: &str) -> Option<SurfaceBundle> {
+        self.surfaces.remove(name)
+    }
+
+    pub fn surface(&self, name: &str) -> Option<&SurfaceBundle> {
+        self.surfaces.get(name)
+    }
+
+    pub fn surface_mut(&mut self, name: &str) -> Option<&mut SurfaceBundle> {
+        self.surfaces.get_mut(name)
+    }
+
+    /// The main surface. Panics if it was removed.
+    pub fn main_surface(&self) -> &SurfaceBundle {
+        self.surfaces.get(MAIN_SURFACE).expect("main surface was removed")
+    }
+
+    pub fn surface_names(&self) -> impl Iterator<Item = &str> {
+        self.surfaces.keys().map(String::as_str)
     }
-}
\ No newline at end of file
+
+    /// Name of the surface presenting to `window`, for routing window events.
+    pub fn surface_for_window(&self, window: WindowId) -> Option<&str> {
+        self.surfaces.iter().find(|(_, s)| s.window_id == window).map(|(name, _)| name.as_str())
+    }
+
+    /// Requests an HDR format for the surface called `name`, falling back to
+    /// SDR; see [`SurfaceBundle::set_hdr`]. Returns whether HDR is active.
+    pub fn set_hdr(&mut self, name: &str, hdr: bool) -> bool {
+        let Some(surface) = self.surfaces.get_mut(name) else { return false };
+        surface.set_hdr(&self.device, &self.adapter, hdr)
+    }
+
+    /// Resizes the surface called `name`; unknown names are ignored.
+    pub fn resize(&mut self, name: &str, new_size: winit::dpi::PhysicalSize<u32>) {
+        if let Some(surface) = self.surfaces.get_mut(name) {
+            surface.resize(&self.device, new_size);
+        }
+    }
+
+    /// Acquires the next frame of the surface called `name`. An unknown name
+    /// is reported as [`SurfaceError::Other`].
+    pub fn begin_frame(&self, name: &str) -> std::result::Result<(SurfaceTexture, TextureView), wgpu::SurfaceError> {
+        self.surfaces.get(name).ok_or(wgpu::SurfaceError::Other)?.begin_frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdr_prefers_float_then_10_bit() {
+        let formats = [TextureFormat::Bgra8UnormSrgb, TextureFormat::Rgb10a2Unorm, TextureFormat::Rgba16Float];
+        assert_eq!(choose_format(&formats, true), Some(TextureFormat::Rgba16Float));
+        assert_eq!(choose_format(&formats[..2], true), Some(TextureFormat::Rgb10a2Unorm));
+    }
+
+    #[test]
+    fn sdr_picks_first_srgb() {
+        let formats = [TextureFormat::Rgba16Float, TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb];
+        assert_eq!(choose_format(&formats, false), Some(TextureFormat::Bgra8UnormSrgb));
+        // Requesting HDR without an HDR format falls back the same way.
+        assert_eq!(choose_format(&formats[1..], true), Some(TextureFormat::Bgra8UnormSrgb));
+    }
+
+    #[test]
+    fn falls_back_to_first_format() {
+        let formats = [TextureFormat::Bgra8Unorm, TextureFormat::Rgba8Unorm];
+        assert_eq!(choose_format(&formats, false), Some(TextureFormat::Bgra8Unorm));
+        assert_eq!(choose_format(&[], true), None);
+    }
+}
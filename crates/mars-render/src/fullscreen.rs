@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use bytemuck::Pod;
+
+use crate::binding::{self, BindGroupBuilder, BindGroupCache, BoundResource};
+use crate::output::needs_srgb_encode;
+use crate::pipeline::{ColorTarget, PipelinesHandle, RenderPipelineDesc};
+use crate::shader::ShaderDefs;
+
+/// A fragment-shader pass over a fullscreen triangle.
+///
+/// The shader is a built-in library source that does
+/// `#import mars::fullscreen`. Bindings of group 0: the input texture (0), a
+/// linear clamp sampler (1), the pass's uniform parameters (2), then any
+/// `extra` entries from 3 on. Pipelines come from the [`PipelinesHandle`]
+/// passed to each draw, per output format, so they follow shader reloads.
+/// Output to 8-bit non-sRGB formats is sRGB-encoded in the shader unless
+/// disabled.
+pub(crate) struct FullscreenPass {
+    label: &'static str,
+    shader: &'static str,
+    entry: &'static str,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+    bind_groups: BindGroupCache,
+    blend: Option<wgpu::BlendState>,
+    /// Pipeline descriptions per output format, valid for `pipelines`.
+    descs: HashMap<wgpu::TextureFormat, RenderPipelineDesc>,
+    pipelines: Option<PipelinesHandle>,
+    sampler: wgpu::Sampler,
+    params: wgpu::Buffer,
+    encode_srgb: bool,
+}
+
+pub(crate) fn linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("PostFx Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+impl FullscreenPass {
+    pub fn new<P: Pod>(
+        device: &wgpu::Device,
+        label: &'static str,
+        shader: &'static str,
+        entry: &'static str,
+        extra: &[wgpu::BindGroupLayoutEntry],
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let mut entries = vec![
+            binding::texture_entry(0, fragment),
+            binding::sampler_entry(1, fragment, wgpu::SamplerBindingType::Filtering),
+            binding::buffer_entry(2, fragment, wgpu::BufferBindingType::Uniform),
+        ];
+        entries.extend_from_slice(extra);
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (std::mem::size_of::<P>() as u64).max(16),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            label,
+            shader,
+            entry,
+            entries,
+            // Enough for a pass drawn once per mip of a bloom chain.
+            bind_groups: BindGroupCache::new(label, 16),
+            blend,
+            descs: HashMap::new(),
+            pipelines: None,
+            sampler: linear_sampler(device),
+            params,
+            encode_srgb: true,
+        }
+    }
+
+    /// Writes linear values regardless of the output format, for passes
+    /// whose output is not displayed directly.
+    pub fn without_srgb_encode(mut self) -> Self {
+        self.encode_srgb = false;
+        self
+    }
+
+    pub fn write_params<P: Pod>(&self, queue: &wgpu::Queue, params: &P) {
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(params));
+    }
+
+    fn pipeline(&mut self, pipelines: &PipelinesHandle, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        // Shader ids belong to one library.
+        if !self.pipelines.as_ref().is_some_and(|p| p.ptr_eq(pipelines)) {
+            self.pipelines = Some(pipelines.clone());
+            self.descs.clear();
+        }
+        let mut pipelines = pipelines.lock();
+        let desc = self.descs.entry(format).or_insert_with(|| {
+            let mut defs = ShaderDefs::new();
+            if self.encode_srgb && needs_srgb_encode(format) {
+                defs.set("ENCODE_SRGB", 1);
+            }
+            let shader = pipelines.builtin(device, self.shader, &defs);
+            RenderPipelineDesc::new(self.label, shader)
+                .with_entry_points("vs_main", Some(self.entry))
+                .with_bind_group(&self.entries)
+                .with_target(ColorTarget { blend: self.blend, ..ColorTarget::new(format) })
+        });
+        pipelines.render_pipeline(device, desc)
+    }
+
+    /// Records the pass reading `input` and writing `target`. With `load`
+    /// the previous contents are kept (used for additive blending).
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        pipelines: &PipelinesHandle,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        extra: &[wgpu::BindGroupEntry],
+        load: bool,
+    ) {
+        self.draw_in(pipelines, device, encoder, input, target, format, extra, load, None);
+    }
+
+    /// [`FullscreenPass::draw`] restricted to `viewport`, as x, y, width and
+    /// height in pixels. Clearing still affects the whole target.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_in(
+        &mut self,
+        pipelines: &PipelinesHandle,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        extra: &[wgpu::BindGroupEntry],
+        load: bool,
+        viewport: Option<[f32; 4]>,
+    ) {
+        let resource = |binding| match binding {
+            0 => BoundResource::TextureView(input.clone()),
+            1 => BoundResource::Sampler(self.sampler.clone()),
+            2 => BoundResource::buffer(&self.params),
+            _ => extra
+                .iter()
+                .find(|e| e.binding == binding)
+                .and_then(|e| BoundResource::from_binding(&e.resource))
+                .unwrap_or_else(|| panic!("{}: no resource for binding {binding}", self.label)),
+        };
+        let builder = self.entries.iter().fold(BindGroupBuilder::new(), |b, entry| b.entry(*entry, resource(entry.binding)));
+        let bind_group = self.bind_groups.get(device, builder).clone();
+
+        let label = self.label;
+        let pipeline = self.pipeline(pipelines, device, format);
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if load { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(wgpu::Color::BLACK) },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if let Some([x, y, w, h]) = viewport {
+            rp.set_viewport(x, y, w, h, 0.0, 1.0);
+        }
+        rp.set_pipeline(&pipeline);
+        rp.set_bind_group(0, &bind_group, &[]);
+        rp.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,38 @@
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+// Fullscreen triangle; uv (0, 0) is the top-left corner.
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VsOut {
+  let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+  var out: VsOut;
+  out.clip = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var src_sampler: sampler;
+
+fn luminance(c: vec3<f32>) -> f32 {
+  return dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+  let lo = c * 12.92;
+  let hi = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, c <= vec3<f32>(0.0031308));
+}
+
+// ENCODE_SRGB is defined when the pass writes a non-sRGB 8-bit target that
+// is displayed directly, so the encoding has to happen in the shader.
+fn finish(c: vec3<f32>) -> vec4<f32> {
+#ifdef ENCODE_SRGB
+  return vec4<f32>(linear_to_srgb(clamp(c, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+#else
+  return vec4<f32>(c, 1.0);
+#endif
+}
@@ -1,18 +1,180 @@
-use anyhow::Result;
-use crate::device::RenderDevice;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use crate::device::{RenderDevice, MAIN_SURFACE};
+use crate::pipeline::PipelinesHandle;
+use crate::view::SubView;
+
+/// Conventional name of the HDR texture the scene is rendered into, read by
+/// screen-space and post-processing nodes.
+pub const SCENE_COLOR: &str = "scene_color";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphTextureDesc {
+    pub size: (u32, u32),
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    pub mip_levels: u32,
+}
+
+impl GraphTextureDesc {
+    /// Single-mip texture that can be rendered to and sampled.
+    pub fn target(size: (u32, u32), format: wgpu::TextureFormat) -> Self {
+        Self {
+            size,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            mip_levels: 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GraphTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub desc: GraphTextureDesc,
+}
+
+impl GraphTexture {
+    pub fn new(device: &wgpu::Device, label: &str, desc: GraphTextureDesc) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: desc.size.0.max(1), height: desc.size.1.max(1), depth_or_array_layers: 1 },
+            mip_level_count: desc.mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, desc }
+    }
+}
+
+/// Named textures and buffers shared between the nodes of a graph.
+#[derive(Default)]
+pub struct GraphResources {
+    textures: HashMap<String, GraphTexture>,
+    buffers: HashMap<String, wgpu::Buffer>,
+}
+
+impl GraphResources {
+    /// Returns the texture called `name`, (re)creating it if it is missing or
+    /// does not match `desc`. Usage flags accumulate across callers.
+    pub fn ensure_texture(&mut self, device: &wgpu::Device, name: &str, desc: GraphTextureDesc) -> &GraphTexture {
+        let usage = match self.textures.get(name) {
+            Some(t) if t.desc.size == desc.size
+                && t.desc.format == desc.format
+                && t.desc.mip_levels == desc.mip_levels
+                && t.desc.usage.contains(desc.usage) => return &self.textures[name],
+            Some(t) => t.desc.usage | desc.usage,
+            None => desc.usage,
+        };
+
+        let texture = GraphTexture::new(device, name, GraphTextureDesc { usage, ..desc });
+        self.textures.insert(name.to_owned(), texture);
+        &self.textures[name]
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&GraphTexture> {
+        self.textures.get(name)
+    }
+
+    /// Publishes a texture created elsewhere, replacing any of that name.
+    pub fn insert_texture(&mut self, name: &str, texture: GraphTexture) -> Option<GraphTexture> {
+        self.textures.insert(name.to_owned(), texture)
+    }
+
+    pub fn remove_texture(&mut self, name: &str) -> Option<GraphTexture> {
+        self.textures.remove(name)
+    }
+
+    pub fn insert_buffer(&mut self, name: &str, buffer: wgpu::Buffer) {
+        self.buffers.insert(name.to_owned(), buffer);
+    }
+
+    pub fn buffer(&self, name: &str) -> Option<&wgpu::Buffer> {
+        self.buffers.get(name)
+    }
+}
+
+pub struct NodeContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Final output of the graph, usually the swapchain image.
+    pub view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: (u32, u32),
+    pub resources: &'a mut GraphResources,
+    /// Shaders and pipelines shared by the graph's nodes.
+    pub pipelines: &'a PipelinesHandle,
+    pub frame: u64,
+    /// Seconds since the previous run of the graph.
+    pub dt: f32,
+}
 
 pub trait RenderNode {
     fn name(&self) -> &'static str;
-    fn execute(&mut self, rd: &RenderDevice, view: &wgpu::TextureView) -> Result<()>;
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()>;
 }
 
+/// Sub-views nested deeper than this are not rendered by default; see
+/// [`RenderGraph::set_max_view_depth`].
+pub const DEFAULT_MAX_VIEW_DEPTH: u32 = 2;
+
 pub struct RenderGraph {
     nodes: Vec<Box<dyn RenderNode>>,
+    views: Vec<SubView>,
+    /// Sub-view outputs of enclosing graphs, shared into this one.
+    imported: Vec<String>,
+    max_view_depth: u32,
+    resources: GraphResources,
+    pipelines: PipelinesHandle,
+    frame: u64,
+    last_run: Option<Instant>,
+    time_step: Option<f32>,
+    target: String,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RenderGraph {
     pub fn new() -> Self {
-        Self { nodes: Vec::new() }
+        Self {
+            nodes: Vec::new(),
+            views: Vec::new(),
+            imported: Vec::new(),
+            max_view_depth: DEFAULT_MAX_VIEW_DEPTH,
+            resources: GraphResources::default(),
+            // Runs set the actual surface format.
+            pipelines: PipelinesHandle::new(wgpu::TextureFormat::Bgra8UnormSrgb),
+            frame: 0,
+            last_run: None,
+            time_step: None,
+            target: MAIN_SURFACE.to_owned(),
+        }
+    }
+
+    /// Renders to the surface called `name` instead of the main one.
+    pub fn with_target(mut self, name: &str) -> Self {
+        self.set_target(name);
+        self
+    }
+
+    pub fn set_target(&mut self, name: &str) {
+        self.target = name.to_owned();
+    }
+
+    /// Name of the surface the graph renders to.
+    pub fn target(&self) -> &str {
+        &self.target
     }
 
     pub fn add_node<N: RenderNode + 'static>(mut self, node: N) -> Self {
@@ -20,10 +182,215 @@ impl RenderGraph {
         self
     }
 
+    pub fn push_node<N: RenderNode + 'static>(&mut self, node: N) {
+        self.nodes.push(Box::new(node));
+    }
+
+    /// Adds a view rendered into a texture before this graph's nodes run.
+    pub fn add_view(mut self, view: SubView) -> Self {
+        self.views.push(view);
+        self
+    }
+
+    pub fn push_view(&mut self, view: SubView) {
+        self.views.push(view);
+    }
+
+    pub fn view(&self, name: &str) -> Option<&SubView> {
+        self.views.iter().find(|v| v.name() == name)
+    }
+
+    pub fn view_mut(&mut self, name: &str) -> Option<&mut SubView> {
+        self.views.iter_mut().find(|v| v.name() == name)
+    }
+
+    pub fn remove_view(&mut self, name: &str) -> Option<SubView> {
+        let index = self.views.iter().position(|v| v.name() == name)?;
+        self.resources.remove_texture(name);
+        Some(self.views.remove(index))
+    }
+
+    /// Levels of sub-views inside sub-views that are rendered, counting
+    /// this graph's own views as the first. Deeper views keep their last
+    /// contents, which ends mutually visible views, e.g. facing mirrors.
+    /// Only the outermost graph's limit applies.
+    pub fn set_max_view_depth(&mut self, depth: u32) {
+        self.max_view_depth = depth;
+    }
+
+    pub fn max_view_depth(&self) -> u32 {
+        self.max_view_depth
+    }
+
+    /// Reports `step` seconds as every run's `dt` instead of the measured
+    /// time, e.g. while recording; `None` measures again. Applies to the
+    /// graph's sub-views too.
+    pub fn set_time_step(&mut self, step: Option<f32>) {
+        self.time_step = step;
+    }
+
+    pub fn time_step(&self) -> Option<f32> {
+        self.time_step
+    }
+
+    pub fn node_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.nodes.iter().map(|n| n.name())
+    }
+
+    pub fn resources(&self) -> &GraphResources {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut GraphResources {
+        &mut self.resources
+    }
+
+    /// Shaders and pipelines of the graph's nodes, e.g. to
+    /// [`watch`](PipelinesHandle::watch) them for hot reload.
+    pub fn pipelines(&self) -> &PipelinesHandle {
+        &self.pipelines
+    }
+
+    /// Shares `pipelines` with this graph, e.g. the ones of a
+    /// `MeshRenderer`. Sub-views use their enclosing graph's.
+    pub fn set_pipelines(&mut self, pipelines: PipelinesHandle) {
+        self.pipelines = pipelines;
+    }
+
+    /// Runs the graph into `view`, a frame of the target surface.
     pub fn run(&mut self, rd: &RenderDevice, view: &wgpu::TextureView) -> Result<()> {
+        let surface = rd.surface(&self.target).with_context(|| format!("no surface `{}`", self.target))?;
+        let config = &surface.config;
+        self.run_on(&rd.device, &rd.queue, view, config.format, (config.width, config.height))
+    }
+
+    /// Acquires a frame of the target surface, runs the graph into it and
+    /// presents it. A lost or outdated surface is reconfigured and the frame
+    /// skipped; a timeout skips the frame.
+    pub fn render(&mut self, rd: &mut RenderDevice) -> Result<()> {
+        let surface = rd.surface_mut(&self.target).with_context(|| format!("no surface `{}`", self.target))?;
+        let (frame, view) = match surface.begin_frame() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                let size = surface.size;
+                rd.resize(&self.target, size);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("acquire frame of `{}`", self.target)),
+        };
+        self.run(rd, &view)?;
+        frame.present();
+        Ok(())
+    }
+
+    /// Runs the graph into an arbitrary `view`, e.g. an offscreen texture.
+    /// Sub-views run first. Shaders changed on disk are reloaded before
+    /// anything runs.
+    pub fn run_on(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) -> Result<()> {
+        self.pipelines.reload_changed(device);
+        self.pipelines.lock().cache.set_surface_format(format);
+        let max_depth = self.max_view_depth;
+        self.run_nested(device, queue, view, format, size, 0, max_depth)
+    }
+
+    /// [`RenderGraph::run_on`] for a graph whose sub-views are `depth`
+    /// levels deep.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn run_nested(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<()> {
+        self.run_views(device, queue, format, size, depth, max_depth)?;
+
+        let now = Instant::now();
+        let measured = self.last_run.map_or(0.0, |t| now.duration_since(t).as_secs_f32());
+        let dt = self.time_step.unwrap_or(measured);
+        self.last_run = Some(now);
+
+        let mut ctx = NodeContext {
+            device,
+            queue,
+            view,
+            format,
+            size,
+            resources: &mut self.resources,
+            pipelines: &self.pipelines,
+            frame: self.frame,
+            dt,
+        };
         for n in &mut self.nodes {
-            n.execute(rd, view)?;
+            n.execute(&mut ctx)?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn run_views(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<()> {
+        if self.views.is_empty() {
+            return Ok(());
+        }
+        // Every view sees the outputs of its siblings and of the enclosing
+        // graphs' views, including its own previous result.
+        let mut shared = self.imported.clone();
+        shared.extend(self.views.iter().map(|v| v.name().to_owned()));
+        for view in &mut self.views {
+            view.graph_mut().pipelines = self.pipelines.clone();
+            // Unconditional, so clearing the step here clears it below too.
+            let interval = view.interval.max(1) as f32;
+            view.graph_mut().set_time_step(self.time_step.map(|s| s * interval));
+            view.render(device, queue, &mut self.resources, &shared, format, size, depth, max_depth)?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Makes the enclosing graph's textures called `names` visible to this
+    /// graph's nodes and views.
+    pub(crate) fn import(&mut self, from: &GraphResources, names: &[String]) {
+        for name in names {
+            match from.texture(name) {
+                Some(texture) => self.resources.insert_texture(name, texture.clone()),
+                None => self.resources.remove_texture(name),
+            };
+        }
+        self.imported.clear();
+        self.imported.extend_from_slice(names);
+    }
+}
+
+/// A graph can be nested inside another; its nodes then share the outer
+/// graph's resources and output. Sub-views belong on the outer graph and
+/// are ignored here.
+impl RenderNode for RenderGraph {
+    fn name(&self) -> &'static str {
+        "subgraph"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        for n in &mut self.nodes {
+            n.execute(ctx)?;
+        }
+        Ok(())
+    }
+}
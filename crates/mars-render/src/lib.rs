@@ -1,9 +1,28 @@
+pub mod binding;
+pub mod debug_draw;
 pub mod device;
 pub mod graph;
+pub mod mipmap;
+pub mod output;
+pub mod pipeline;
+pub mod record;
+pub mod shader;
+pub mod texture;
+pub mod timing;
+pub mod view;
+
+#[cfg(any(feature = "3d", feature = "postfx"))]
+pub(crate) mod fullscreen;
+#[cfg(any(feature = "3d", feature = "postfx"))]
+pub mod upscale;
+#[cfg(any(feature = "3d", feature = "postfx"))]
+pub mod pip;
 
 // #[cfg(feature = "2d")]
 // pub mod two_d;
-// #[cfg(feature = "3d")]
-// pub mod three_d;
+#[cfg(feature = "3d")]
+pub mod three_d;
+#[cfg(feature = "postfx")]
+pub mod postfx;
 // #[cfg(feature = "voxel")]
 // pub mod voxel;
\ No newline at end of file
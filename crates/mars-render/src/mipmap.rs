@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use crate::binding::{self, BindGroupBuilder, BoundResource};
+use crate::pipeline::{ColorTarget, Pipelines, RenderPipelineDesc};
+use crate::shader::ShaderDefs;
+
+/// Number of mips in a full chain down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Fills the mip chain of a texture from its first level on the GPU, one
+/// render pass per level and layer.
+pub struct MipGenerator {
+    /// Texture loads happen outside any render graph, so the generator
+    /// keeps pipelines of its own.
+    pipelines: Pipelines,
+    layout: wgpu::BindGroupLayout,
+    descs: HashMap<wgpu::TextureFormat, RenderPipelineDesc>,
+}
+
+impl MipGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let mut pipelines = Pipelines::new(wgpu::TextureFormat::Rgba8UnormSrgb);
+        let layout = pipelines.bind_group_layout(device, &Self::layout_entries());
+        Self { pipelines, layout, descs: HashMap::new() }
+    }
+
+    fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+        [binding::unfilterable_entry(0, wgpu::ShaderStages::FRAGMENT)]
+    }
+
+    /// Whether mips of `format` can be generated: it must be a renderable
+    /// float format.
+    pub fn supports(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+        let features = format.guaranteed_format_features(device.features());
+        features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            && matches!(format.sample_type(None, None), Some(wgpu::TextureSampleType::Float { .. }))
+    }
+
+    fn pipeline(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        let desc = self.descs.entry(format).or_insert_with(|| {
+            let shader = self.pipelines.builtin(device, "mars/mipmap.wgsl", &ShaderDefs::new());
+            RenderPipelineDesc::new("Mipmap Pipeline", shader)
+                .with_bind_group(&Self::layout_entries())
+                .with_target(ColorTarget::new(format))
+        });
+        self.pipelines.render_pipeline(device, desc)
+    }
+
+    /// Records the passes that fill mips 1.. of every layer of `texture`
+    /// from mip 0. The texture needs `TEXTURE_BINDING | RENDER_ATTACHMENT`
+    /// usage and a format for which [`MipGenerator::supports`] holds.
+    pub fn generate(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        let format = texture.format();
+        let pipeline = self.pipeline(device, format);
+        let view = |mip, layer| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mipmap View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        for layer in 0..texture.depth_or_array_layers() {
+            for mip in 1..texture.mip_level_count() {
+                let bind_group = BindGroupBuilder::new()
+                    .entry(binding::unfilterable_entry(0, wgpu::ShaderStages::FRAGMENT), BoundResource::TextureView(view(mip - 1, layer)))
+                    .build(device, "Mipmap BG", &self.layout);
+                let target = view(mip, layer);
+                let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Mipmap Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                rp.set_pipeline(&pipeline);
+                rp.set_bind_group(0, &bind_group, &[]);
+                rp.draw(0..3, 0..1);
+            }
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Box-filters one mip level from the level above it. Texels are read with
+// textureLoad, so any float format works, including unfilterable ones; sRGB
+// views decode on load and encode on store, keeping the average linear.
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+  let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+  return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+  let size = vec2<i32>(textureDimensions(src));
+  let dst_size = max(size / 2, vec2<i32>(1));
+  let dst = vec2<i32>(pos.xy);
+  // An odd source dimension leaves a row/column over; the last texel of the
+  // destination takes three source texels instead of two.
+  let odd = (size & vec2<i32>(1)) == vec2<i32>(1);
+  let last = dst == dst_size - 1;
+  let extent = vec2<i32>(2) + vec2<i32>(select(0, 1, odd.x && last.x), select(0, 1, odd.y && last.y));
+
+  var sum = vec4<f32>(0.0);
+  for (var y = 0; y < extent.y; y++) {
+    for (var x = 0; x < extent.x; x++) {
+      sum += textureLoad(src, min(dst * 2 + vec2<i32>(x, y), size - 1), 0);
+    }
+  }
+  return sum / f32(extent.x * extent.y);
+}
@@ -0,0 +1,89 @@
+//! Encoding of linear scene light for the display format.
+//!
+//! SDR targets receive tonemapped values in 0..1, sRGB-encoded by the
+//! format or, for `Unorm` formats such as Rgb10a2Unorm, in the shader.
+//! Rgba16Float targets are treated as scRGB: linear sRGB primaries where 1.0
+//! is 80 nits, so highlights can exceed 1 up to the display's peak.
+
+/// Brightness of reference white on scRGB outputs, in nits.
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// Whether `format` carries extended-range (HDR) output.
+pub fn is_hdr_format(format: wgpu::TextureFormat) -> bool {
+    format == wgpu::TextureFormat::Rgba16Float
+}
+
+/// Whether writing linear color to `format` needs an explicit sRGB encode.
+/// Rgb10a2Unorm surfaces are presented as sRGB too; see
+/// [`choose_format`](crate::device::choose_format).
+pub(crate) fn needs_srgb_encode(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Rgb10a2Unorm)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputTransform {
+    /// Tonemapped to 0..1.
+    Sdr,
+    /// Tonemapped so that scene white sits at `paper_white_nits` and the
+    /// curve's shoulder ends at `peak_nits`, in scRGB units.
+    ScRgb { paper_white_nits: f32, peak_nits: f32 },
+}
+
+impl OutputTransform {
+    /// The transform for a target of `format`, with the given HDR brightness
+    /// levels used only if the format is HDR.
+    pub fn for_format(format: wgpu::TextureFormat, paper_white_nits: f32, peak_nits: f32) -> Self {
+        if is_hdr_format(format) {
+            OutputTransform::ScRgb { paper_white_nits, peak_nits: peak_nits.max(paper_white_nits) }
+        } else {
+            OutputTransform::Sdr
+        }
+    }
+
+    /// Display peak relative to paper white: the value tonemapping curves are
+    /// stretched to instead of 1.
+    pub fn headroom(self) -> f32 {
+        match self {
+            OutputTransform::Sdr => 1.0,
+            OutputTransform::ScRgb { paper_white_nits, peak_nits } => peak_nits / paper_white_nits,
+        }
+    }
+
+    /// Factor from paper-white-relative values to the output encoding.
+    pub fn output_scale(self) -> f32 {
+        match self {
+            OutputTransform::Sdr => 1.0,
+            OutputTransform::ScRgb { paper_white_nits, .. } => paper_white_nits / SCRGB_WHITE_NITS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn transform_follows_format() {
+        assert_eq!(OutputTransform::for_format(TextureFormat::Bgra8UnormSrgb, 200.0, 1000.0), OutputTransform::Sdr);
+        assert_eq!(OutputTransform::for_format(TextureFormat::Rgb10a2Unorm, 200.0, 1000.0), OutputTransform::Sdr);
+        assert_eq!(
+            OutputTransform::for_format(TextureFormat::Rgba16Float, 200.0, 1000.0),
+            OutputTransform::ScRgb { paper_white_nits: 200.0, peak_nits: 1000.0 }
+        );
+        // A peak below paper white is raised to it.
+        assert_eq!(
+            OutputTransform::for_format(TextureFormat::Rgba16Float, 200.0, 100.0),
+            OutputTransform::ScRgb { paper_white_nits: 200.0, peak_nits: 200.0 }
+        );
+    }
+
+    #[test]
+    fn headroom_and_scale() {
+        assert_eq!(OutputTransform::Sdr.headroom(), 1.0);
+        assert_eq!(OutputTransform::Sdr.output_scale(), 1.0);
+        let hdr = OutputTransform::ScRgb { paper_white_nits: 160.0, peak_nits: 800.0 };
+        assert_eq!(hdr.headroom(), 5.0);
+        assert_eq!(hdr.output_scale(), 2.0);
+    }
+}
@@ -0,0 +1,120 @@
+//! Picture-in-picture overlays of render-to-texture views.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{NodeContext, RenderNode};
+use crate::output::needs_srgb_encode;
+
+/// Placement of an overlay as fractions of the output, origin top-left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewRect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// The rectangle in pixels of an output of `size`, clipped to it, or
+    /// `None` if nothing is left.
+    pub fn pixels(&self, size: (u32, u32)) -> Option<[f32; 4]> {
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        let x0 = (self.x * w).round().clamp(0.0, w);
+        let y0 = (self.y * h).round().clamp(0.0, h);
+        let x1 = ((self.x + self.width) * w).round().clamp(0.0, w);
+        let y1 = ((self.y + self.height) * h).round().clamp(0.0, h);
+        (x1 > x0 && y1 > y0).then_some([x0, y0, x1 - x0, y1 - y0])
+    }
+}
+
+struct PipControl {
+    rect: ViewRect,
+    visible: bool,
+}
+
+/// Runtime control of a [`PictureInPicture`] node.
+#[derive(Clone)]
+pub struct PictureInPictureHandle(Arc<Mutex<PipControl>>);
+
+impl PictureInPictureHandle {
+    fn control(&self) -> std::sync::MutexGuard<'_, PipControl> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_rect(&self, rect: ViewRect) {
+        self.control().rect = rect;
+    }
+
+    pub fn rect(&self) -> ViewRect {
+        self.control().rect
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.control().visible = visible;
+    }
+
+    pub fn visible(&self) -> bool {
+        self.control().visible
+    }
+}
+
+/// Draws a graph texture, typically a [`SubView`](crate::view::SubView)'s
+/// output, into a rectangle of the graph's output. Does nothing while the
+/// texture does not exist, e.g. before the view first rendered.
+pub struct PictureInPicture {
+    texture: String,
+    handle: PictureInPictureHandle,
+    /// For linear inputs; encodes for 8-bit non-sRGB outputs.
+    blit: FullscreenPass,
+    /// For inputs that already hold sRGB-encoded values.
+    copy: FullscreenPass,
+}
+
+impl PictureInPicture {
+    pub fn new(device: &wgpu::Device, texture: &str, rect: ViewRect) -> Self {
+        let pass = |label| FullscreenPass::new::<[f32; 4]>(device, label, "mars/pip.wgsl", "fs_main", &[], None);
+        Self {
+            texture: texture.to_owned(),
+            handle: PictureInPictureHandle(Arc::new(Mutex::new(PipControl { rect, visible: true }))),
+            blit: pass("Picture-in-Picture"),
+            copy: pass("Picture-in-Picture Copy").without_srgb_encode(),
+        }
+    }
+
+    pub fn handle(&self) -> &PictureInPictureHandle {
+        &self.handle
+    }
+}
+
+impl RenderNode for PictureInPicture {
+    fn name(&self) -> &'static str {
+        "picture_in_picture"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let (rect, visible) = {
+            let control = self.handle.control();
+            (control.rect, control.visible)
+        };
+        let Some(viewport) = rect.pixels(ctx.size).filter(|_| visible) else {
+            return Ok(());
+        };
+        let Some(input) = ctx.resources.texture(&self.texture) else {
+            return Ok(());
+        };
+        let (view, format) = (input.view.clone(), input.desc.format);
+
+        let pass = if needs_srgb_encode(format) { &mut self.copy } else { &mut self.blit };
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Picture-in-Picture") });
+        pass.draw_in(ctx.pipelines, ctx.device, &mut encoder, &view, ctx.view, ctx.format, &[], true, Some(viewport));
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+// Picture-in-picture: a view's texture stretched over the viewport.
+
+#import mars::fullscreen
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  return finish(textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb);
+}
@@ -0,0 +1,442 @@
+//! Declarative pipeline descriptions and a cache that builds each distinct
+//! description once.
+//!
+//! A [`RenderPipelineDesc`] names everything that makes a pipeline unique:
+//! the shader (a [`ShaderId`] already stands for a source plus its defines),
+//! bind group layouts, vertex layouts, color targets with their blending,
+//! depth state and sample count. Targets may use [`TargetFormat::Surface`]
+//! and are specialized on the cache's current surface format when looked up,
+//! so the same description serves any swapchain. Pipelines are rebuilt when
+//! their shader is hot reloaded.
+//!
+//! The engine's render nodes build their pipelines through a shared
+//! [`PipelinesHandle`], which a [`RenderGraph`](crate::graph::RenderGraph)
+//! hands to its nodes and reloads once per run.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+
+use crate::shader::{ShaderDefs, ShaderId, ShaderLibrary};
+
+/// Format of a color target, either fixed or whatever the surface uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TargetFormat {
+    Surface,
+    Format(wgpu::TextureFormat),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ColorTarget {
+    pub format: TargetFormat,
+    pub blend: Option<wgpu::BlendState>,
+    pub write_mask: wgpu::ColorWrites,
+}
+
+impl ColorTarget {
+    /// An opaque target in the surface format.
+    pub fn surface() -> Self {
+        Self { format: TargetFormat::Surface, blend: None, write_mask: wgpu::ColorWrites::ALL }
+    }
+
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self { format: TargetFormat::Format(format), ..Self::surface() }
+    }
+
+    pub fn with_blend(mut self, blend: wgpu::BlendState) -> Self {
+        self.blend = Some(blend);
+        self
+    }
+}
+
+/// An owned [`wgpu::VertexBufferLayout`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    pub stride: wgpu::BufferAddress,
+    pub step_mode: wgpu::VertexStepMode,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(stride: wgpu::BufferAddress, attributes: &[wgpu::VertexAttribute]) -> Self {
+        Self { stride, step_mode: wgpu::VertexStepMode::Vertex, attributes: attributes.to_vec() }
+    }
+
+    /// Advances once per instance instead of per vertex.
+    pub fn per_instance(mut self) -> Self {
+        self.step_mode = wgpu::VertexStepMode::Instance;
+        self
+    }
+}
+
+impl From<wgpu::VertexBufferLayout<'_>> for VertexLayout {
+    fn from(layout: wgpu::VertexBufferLayout<'_>) -> Self {
+        Self { stride: layout.array_stride, step_mode: layout.step_mode, attributes: layout.attributes.to_vec() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderPipelineDesc {
+    pub label: String,
+    pub shader: ShaderId,
+    pub vertex_entry: String,
+    /// `None` for depth-only pipelines.
+    pub fragment_entry: Option<String>,
+    /// Entries of each bind group, in group order.
+    pub bind_groups: Vec<Vec<wgpu::BindGroupLayoutEntry>>,
+    pub vertex_buffers: Vec<VertexLayout>,
+    pub targets: Vec<ColorTarget>,
+    pub primitive: wgpu::PrimitiveState,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub sample_count: u32,
+}
+
+impl RenderPipelineDesc {
+    /// A single-sampled pipeline with `vs_main`/`fs_main` entry points and no
+    /// bindings, buffers or targets yet.
+    pub fn new(label: &str, shader: ShaderId) -> Self {
+        Self {
+            label: label.to_owned(),
+            shader,
+            vertex_entry: "vs_main".to_owned(),
+            fragment_entry: Some("fs_main".to_owned()),
+            bind_groups: Vec::new(),
+            vertex_buffers: Vec::new(),
+            targets: Vec::new(),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            sample_count: 1,
+        }
+    }
+
+    pub fn with_entry_points(mut self, vertex: &str, fragment: Option<&str>) -> Self {
+        self.vertex_entry = vertex.to_owned();
+        self.fragment_entry = fragment.map(str::to_owned);
+        self
+    }
+
+    /// Appends the next bind group.
+    pub fn with_bind_group(mut self, entries: &[wgpu::BindGroupLayoutEntry]) -> Self {
+        self.bind_groups.push(entries.to_vec());
+        self
+    }
+
+    pub fn with_vertex_buffer(mut self, layout: VertexLayout) -> Self {
+        self.vertex_buffers.push(layout);
+        self
+    }
+
+    pub fn with_target(mut self, target: ColorTarget) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    pub fn with_primitive(mut self, primitive: wgpu::PrimitiveState) -> Self {
+        self.primitive = primitive;
+        self
+    }
+
+    pub fn with_depth(mut self, depth_stencil: wgpu::DepthStencilState) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// The description with surface targets replaced by `surface_format`.
+    fn specialize(&self, surface_format: wgpu::TextureFormat) -> Self {
+        let mut desc = self.clone();
+        for target in &mut desc.targets {
+            if target.format == TargetFormat::Surface {
+                target.format = TargetFormat::Format(surface_format);
+            }
+        }
+        desc
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComputePipelineDesc {
+    pub label: String,
+    pub shader: ShaderId,
+    pub entry: String,
+    pub bind_groups: Vec<Vec<wgpu::BindGroupLayoutEntry>>,
+}
+
+impl ComputePipelineDesc {
+    pub fn new(label: &str, shader: ShaderId, entry: &str) -> Self {
+        Self { label: label.to_owned(), shader, entry: entry.to_owned(), bind_groups: Vec::new() }
+    }
+
+    pub fn with_bind_group(mut self, entries: &[wgpu::BindGroupLayoutEntry]) -> Self {
+        self.bind_groups.push(entries.to_vec());
+        self
+    }
+}
+
+struct Cached<P> {
+    pipeline: P,
+    /// Shader generation the pipeline was built from.
+    generation: u32,
+}
+
+/// Builds pipelines from descriptions, sharing identical bind group layouts,
+/// pipeline layouts and pipelines.
+///
+/// With [`PipelineCache::load_from_disk`] the driver's compiled pipelines are
+/// also kept in a wgpu [`wgpu::PipelineCache`] and written back by
+/// [`PipelineCache::save`], which shortens startup on backends that support
+/// it (currently Vulkan with [`wgpu::Features::PIPELINE_CACHE`]).
+pub struct PipelineCache {
+    surface_format: wgpu::TextureFormat,
+    bind_group_layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, wgpu::BindGroupLayout>,
+    layouts: HashMap<Vec<Vec<wgpu::BindGroupLayoutEntry>>, wgpu::PipelineLayout>,
+    render: HashMap<RenderPipelineDesc, Cached<wgpu::RenderPipeline>>,
+    compute: HashMap<ComputePipelineDesc, Cached<wgpu::ComputePipeline>>,
+    driver_cache: Option<(wgpu::PipelineCache, PathBuf)>,
+}
+
+impl PipelineCache {
+    pub fn new(surface_format: wgpu::TextureFormat) -> Self {
+        Self {
+            surface_format,
+            bind_group_layouts: HashMap::new(),
+            layouts: HashMap::new(),
+            render: HashMap::new(),
+            compute: HashMap::new(),
+            driver_cache: None,
+        }
+    }
+
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.surface_format
+    }
+
+    /// Pipelines with surface targets are specialized on `format` from now
+    /// on; the ones built for the old format stay cached.
+    pub fn set_surface_format(&mut self, format: wgpu::TextureFormat) {
+        self.surface_format = format;
+    }
+
+    /// Loads the driver pipeline cache for `adapter` from `dir`, if the
+    /// device supports one, and remembers where to [`save`](Self::save) it.
+    /// Call before building pipelines. Returns whether a cache is in use.
+    pub fn load_from_disk(&mut self, device: &wgpu::Device, adapter: &wgpu::Adapter, dir: impl AsRef<Path>) -> Result<bool> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return Ok(false);
+        }
+        let Some(key) = wgpu::util::pipeline_cache_key(&adapter.get_info()) else { return Ok(false) };
+        let path = dir.as_ref().join(key);
+        let data = match std::fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+        };
+        // SAFETY: the file is named by the adapter's cache key and only ever
+        // written by `save` from `PipelineCache::get_data`. wgpu validates the
+        // header and falls back to an empty cache if the data doesn't match.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Mars Pipeline Cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        self.driver_cache = Some((cache, path));
+        Ok(true)
+    }
+
+    /// Writes the driver pipeline cache back to disk. Does nothing unless
+    /// [`load_from_disk`](Self::load_from_disk) found a supported device.
+    pub fn save(&self) -> Result<()> {
+        let Some((cache, path)) = &self.driver_cache else { return Ok(()) };
+        let Some(data) = cache.get_data() else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        // Write then rename, so a crash never leaves a truncated cache behind.
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, data).with_context(|| format!("write {}", temp.display()))?;
+        std::fs::rename(&temp, path).with_context(|| format!("write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The shared layout for a bind group with `entries`, for creating bind
+    /// groups that match a cached pipeline.
+    pub fn bind_group_layout(&mut self, device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry]) -> wgpu::BindGroupLayout {
+        if let Some(layout) = self.bind_group_layouts.get(entries) {
+            return layout.clone();
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some("Cached BGL"), entries });
+        self.bind_group_layouts.insert(entries.to_vec(), layout.clone());
+        layout
+    }
+
+    fn pipeline_layout(&mut self, device: &wgpu::Device, bind_groups: &[Vec<wgpu::BindGroupLayoutEntry>]) -> wgpu::PipelineLayout {
+        if let Some(layout) = self.layouts.get(bind_groups) {
+            return layout.clone();
+        }
+        let groups: Vec<_> = bind_groups.iter().map(|entries| self.bind_group_layout(device, entries)).collect();
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cached Pipeline Layout"),
+            bind_group_layouts: &groups.iter().collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
+        self.layouts.insert(bind_groups.to_vec(), layout.clone());
+        layout
+    }
+
+    /// Returns the pipeline for `desc`, building it on first use or after its
+    /// shader was reloaded.
+    pub fn render_pipeline(&mut self, device: &wgpu::Device, shaders: &ShaderLibrary, desc: &RenderPipelineDesc) -> wgpu::RenderPipeline {
+        let desc = desc.specialize(self.surface_format);
+        let generation = shaders.generation(desc.shader);
+        if let Some(cached) = self.render.get(&desc).filter(|c| c.generation == generation) {
+            return cached.pipeline.clone();
+        }
+
+        let layout = self.pipeline_layout(device, &desc.bind_groups);
+        let module = shaders.module(desc.shader);
+        let buffers: Vec<_> = desc
+            .vertex_buffers
+            .iter()
+            .map(|b| wgpu::VertexBufferLayout { array_stride: b.stride, step_mode: b.step_mode, attributes: &b.attributes })
+            .collect();
+        let targets: Vec<_> = desc
+            .targets
+            .iter()
+            .map(|t| {
+                let TargetFormat::Format(format) = t.format else { unreachable!("specialized above") };
+                Some(wgpu::ColorTargetState { format, blend: t.blend, write_mask: t.write_mask })
+            })
+            .collect();
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&desc.label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some(&desc.vertex_entry),
+                buffers: &buffers,
+                compilation_options: Default::default(),
+            },
+            fragment: desc.fragment_entry.as_deref().map(|entry| wgpu::FragmentState {
+                module,
+                entry_point: Some(entry),
+                targets: &targets,
+                compilation_options: Default::default(),
+            }),
+            primitive: desc.primitive,
+            depth_stencil: desc.depth_stencil.clone(),
+            multisample: wgpu::MultisampleState { count: desc.sample_count, ..Default::default() },
+            multiview: None,
+            cache: self.driver_cache.as_ref().map(|(cache, _)| cache),
+        });
+        self.render.insert(desc, Cached { pipeline: pipeline.clone(), generation });
+        pipeline
+    }
+
+    /// Returns the compute pipeline for `desc`, building it on first use or
+    /// after its shader was reloaded.
+    pub fn compute_pipeline(&mut self, device: &wgpu::Device, shaders: &ShaderLibrary, desc: &ComputePipelineDesc) -> wgpu::ComputePipeline {
+        let generation = shaders.generation(desc.shader);
+        if let Some(cached) = self.compute.get(desc).filter(|c| c.generation == generation) {
+            return cached.pipeline.clone();
+        }
+
+        let layout = self.pipeline_layout(device, &desc.bind_groups);
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&desc.label),
+            layout: Some(&layout),
+            module: shaders.module(desc.shader),
+            entry_point: Some(&desc.entry),
+            compilation_options: Default::default(),
+            cache: self.driver_cache.as_ref().map(|(cache, _)| cache),
+        });
+        self.compute.insert(desc.clone(), Cached { pipeline: pipeline.clone(), generation });
+        pipeline
+    }
+
+    /// Number of distinct render and compute pipelines built so far.
+    pub fn len(&self) -> usize {
+        self.render.len() + self.compute.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A shader library and the pipelines built from it.
+pub struct Pipelines {
+    pub shaders: ShaderLibrary,
+    pub cache: PipelineCache,
+}
+
+impl Pipelines {
+    /// A library with the engine's [built-in sources](ShaderLibrary::add_builtin_sources).
+    pub fn new(surface_format: wgpu::TextureFormat) -> Self {
+        let mut shaders = ShaderLibrary::new();
+        shaders.add_builtin_sources();
+        Self { shaders, cache: PipelineCache::new(surface_format) }
+    }
+
+    /// Loads one of the engine's shaders.
+    ///
+    /// # Panics
+    ///
+    /// If it doesn't compile, which for the embedded copies is a bug and
+    /// with hot reload a bad edit made before the shader was first needed.
+    pub fn builtin(&mut self, device: &wgpu::Device, name: &str, defs: &ShaderDefs) -> ShaderId {
+        self.shaders.load(device, name, defs).unwrap_or_else(|e| panic!("built-in shader {name}: {e:#}"))
+    }
+
+    pub fn render_pipeline(&mut self, device: &wgpu::Device, desc: &RenderPipelineDesc) -> wgpu::RenderPipeline {
+        self.cache.render_pipeline(device, &self.shaders, desc)
+    }
+
+    pub fn compute_pipeline(&mut self, device: &wgpu::Device, desc: &ComputePipelineDesc) -> wgpu::ComputePipeline {
+        self.cache.compute_pipeline(device, &self.shaders, desc)
+    }
+
+    pub fn bind_group_layout(&mut self, device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry]) -> wgpu::BindGroupLayout {
+        self.cache.bind_group_layout(device, entries)
+    }
+}
+
+/// [`Pipelines`] shared between render nodes and renderers. Cheap to clone;
+/// share one between a graph and a renderer so both pick up the same
+/// reloads.
+#[derive(Clone)]
+pub struct PipelinesHandle(Arc<Mutex<Pipelines>>);
+
+impl PipelinesHandle {
+    pub fn new(surface_format: wgpu::TextureFormat) -> Self {
+        Self(Arc::new(Mutex::new(Pipelines::new(surface_format))))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Pipelines> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts watching shader files for [`reload_changed`](Self::reload_changed).
+    pub fn watch(&self) -> Result<()> {
+        self.lock().shaders.watch()
+    }
+
+    /// Recompiles shaders whose files changed; pipelines using them are
+    /// rebuilt the next time they are looked up. Returns whether any shader
+    /// was reloaded.
+    pub fn reload_changed(&self, device: &wgpu::Device) -> bool {
+        !self.lock().shaders.reload_changed(device).is_empty()
+    }
+
+    /// Whether both handles share the same pipelines.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+
+use crate::binding;
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{GraphTextureDesc, NodeContext, RenderNode};
+use super::{BloomDesc, EffectIo, HDR_FORMAT};
+
+const BLOOM_TEXTURE: &str = "postfx.bloom";
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct BloomParams {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _pad: f32,
+}
+
+/// Thresholded bloom over a half-resolution mip chain: downsample with a
+/// 13-tap filter, accumulate back up with a tent filter, then add to the image.
+pub struct Bloom {
+    pub desc: BloomDesc,
+    io: EffectIo,
+    prefilter: FullscreenPass,
+    downsample: FullscreenPass,
+    upsample: FullscreenPass,
+    composite: FullscreenPass,
+}
+
+impl Bloom {
+    pub(crate) fn new(device: &wgpu::Device, desc: BloomDesc, io: EffectIo) -> Self {
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let bloom_entry = binding::texture_entry(3, wgpu::ShaderStages::FRAGMENT);
+        Self {
+            desc,
+            io,
+            prefilter: FullscreenPass::new::<BloomParams>(device, "Bloom Prefilter", "mars/postfx/bloom.wgsl", "fs_prefilter", &[], None),
+            downsample: FullscreenPass::new::<BloomParams>(device, "Bloom Downsample", "mars/postfx/bloom.wgsl", "fs_downsample", &[], None),
+            upsample: FullscreenPass::new::<BloomParams>(device, "Bloom Upsample", "mars/postfx/bloom.wgsl", "fs_upsample", &[], Some(additive)),
+            composite: FullscreenPass::new::<BloomParams>(device, "Bloom Composite", "mars/postfx/bloom.wgsl", "fs_composite", &[bloom_entry], None),
+        }
+    }
+}
+
+impl RenderNode for Bloom {
+    fn name(&self) -> &'static str {
+        "bloom"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let params = BloomParams { threshold: self.desc.threshold, knee: self.desc.knee.max(1e-4), intensity: self.desc.intensity, _pad: 0.0 };
+        for pass in [&self.prefilter, &self.downsample, &self.upsample, &self.composite] {
+            pass.write_params(ctx.queue, &params);
+        }
+
+        let size = ((ctx.size.0 / 2).max(1), (ctx.size.1 / 2).max(1));
+        let max_mips = 32 - size.0.min(size.1).leading_zeros();
+        let mips = self.desc.mips.clamp(1, max_mips);
+        let desc = GraphTextureDesc { mip_levels: mips, ..GraphTextureDesc::target(size, HDR_FORMAT) };
+        let bloom = ctx.resources.ensure_texture(ctx.device, BLOOM_TEXTURE, desc).texture.clone();
+        let mip_views: Vec<_> = (0..mips)
+            .map(|mip| {
+                bloom.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let input = self.io.input(ctx)?;
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Bloom") });
+        self.prefilter.draw(ctx.pipelines, ctx.device, &mut encoder, &input, &mip_views[0], HDR_FORMAT, &[], false);
+        for mip in 1..mip_views.len() {
+            self.downsample.draw(ctx.pipelines, ctx.device, &mut encoder, &mip_views[mip - 1], &mip_views[mip], HDR_FORMAT, &[], false);
+        }
+        for mip in (1..mip_views.len()).rev() {
+            self.upsample.draw(ctx.pipelines, ctx.device, &mut encoder, &mip_views[mip], &mip_views[mip - 1], HDR_FORMAT, &[], true);
+        }
+
+        let (output, format) = self.io.output(ctx);
+        let bloom_entry = wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&mip_views[0]) };
+        self.composite.draw(ctx.pipelines, ctx.device, &mut encoder, &input, &output, format, &[bloom_entry], false);
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
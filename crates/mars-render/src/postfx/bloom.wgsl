@@ -0,0 +1,66 @@
+#import mars::fullscreen
+
+struct BloomParams {
+  threshold: f32,
+  knee: f32,
+  intensity: f32,
+  _pad: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: BloomParams;
+@group(0) @binding(3) var bloom_tex: texture_2d<f32>;
+
+fn tap(uv: vec2<f32>) -> vec3<f32> {
+  return textureSampleLevel(src, src_sampler, uv, 0.0).rgb;
+}
+
+// 13-tap downsample from "Next Generation Post Processing in Call of Duty".
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+  let t = 1.0 / vec2<f32>(textureDimensions(src));
+  let a = tap(uv + t * vec2<f32>(-2.0, -2.0));
+  let b = tap(uv + t * vec2<f32>(0.0, -2.0));
+  let c = tap(uv + t * vec2<f32>(2.0, -2.0));
+  let d = tap(uv + t * vec2<f32>(-2.0, 0.0));
+  let e = tap(uv);
+  let f = tap(uv + t * vec2<f32>(2.0, 0.0));
+  let g = tap(uv + t * vec2<f32>(-2.0, 2.0));
+  let h = tap(uv + t * vec2<f32>(0.0, 2.0));
+  let i = tap(uv + t * vec2<f32>(2.0, 2.0));
+  let j = tap(uv + t * vec2<f32>(-1.0, -1.0));
+  let k = tap(uv + t * vec2<f32>(1.0, -1.0));
+  let l = tap(uv + t * vec2<f32>(-1.0, 1.0));
+  let m = tap(uv + t * vec2<f32>(1.0, 1.0));
+  return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625 + (j + k + l + m) * 0.125;
+}
+
+@fragment
+fn fs_prefilter(in: VsOut) -> @location(0) vec4<f32> {
+  let c = downsample(in.uv);
+  // Quadratic soft knee around the threshold.
+  let brightness = max(c.r, max(c.g, c.b));
+  var soft = clamp(brightness - params.threshold + params.knee, 0.0, 2.0 * params.knee);
+  soft = soft * soft / (4.0 * params.knee + 1e-4);
+  let weight = max(soft, brightness - params.threshold) / max(brightness, 1e-4);
+  return vec4<f32>(c * weight, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter; blended additively onto the next larger mip.
+@fragment
+fn fs_upsample(in: VsOut) -> @location(0) vec4<f32> {
+  let t = 1.0 / vec2<f32>(textureDimensions(src));
+  var c = tap(in.uv) * 4.0;
+  c += (tap(in.uv + vec2<f32>(-t.x, 0.0)) + tap(in.uv + vec2<f32>(t.x, 0.0)) + tap(in.uv + vec2<f32>(0.0, -t.y)) + tap(in.uv + vec2<f32>(0.0, t.y))) * 2.0;
+  c += tap(in.uv - t) + tap(in.uv + t) + tap(in.uv + vec2<f32>(t.x, -t.y)) + tap(in.uv + vec2<f32>(-t.x, t.y));
+  return vec4<f32>(c / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: VsOut) -> @location(0) vec4<f32> {
+  let bloom = textureSampleLevel(bloom_tex, src_sampler, in.uv, 0.0).rgb;
+  return finish(tap(in.uv) + bloom * params.intensity);
+}
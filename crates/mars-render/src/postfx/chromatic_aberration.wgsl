@@ -0,0 +1,21 @@
+#import mars::fullscreen
+
+struct AberrationParams {
+  intensity: f32,
+  _pad0: f32,
+  _pad1: f32,
+  _pad2: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: AberrationParams;
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  // Offset grows towards the edges; red and blue shift in opposite directions.
+  let dir = (in.uv - 0.5) * 2.0;
+  let offset = dir * dot(dir, dir) * params.intensity;
+  let r = textureSampleLevel(src, src_sampler, in.uv - offset, 0.0).r;
+  let g = textureSampleLevel(src, src_sampler, in.uv, 0.0).g;
+  let b = textureSampleLevel(src, src_sampler, in.uv + offset, 0.0).b;
+  return finish(vec3<f32>(r, g, b));
+}
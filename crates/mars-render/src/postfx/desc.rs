@@ -0,0 +1,246 @@
+//! RON description of a post-processing chain.
+//!
+//! ```ron
+//! (
+//!     effects: [
+//!         Bloom((threshold: 1.0, intensity: 0.05)),
+//!         Exposure(Auto((min_ev: -4.0, max_ev: 12.0))),
+//!         Tonemap((operator: AgX)),
+//!         Vignette((intensity: 0.3)),
+//!         FilmGrain((intensity: 0.04)),
+//!         ColorGrading((lut: Some("assets/luts/warm.cube"), strength: 0.8)),
+//!     ],
+//! )
+//! ```
+//!
+//! Effects run in list order. Omitted fields take their defaults.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostFxDesc {
+    pub effects: Vec<EffectDesc>,
+}
+
+impl PostFxDesc {
+    pub fn from_ron(src: &str) -> Result<Self> {
+        ron::from_str(src).context("parse post-fx description")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        Self::from_ron(&src).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).context("serialize post-fx description")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EffectDesc {
+    Bloom(BloomDesc),
+    Exposure(ExposureDesc),
+    Tonemap(TonemapDesc),
+    Vignette(VignetteDesc),
+    ChromaticAberration(ChromaticAberrationDesc),
+    FilmGrain(FilmGrainDesc),
+    ColorGrading(ColorGradingDesc),
+}
+
+impl EffectDesc {
+    /// Whether the effect produces a new color image (exposure only
+    /// computes a value for the tonemapper).
+    pub fn writes_color(&self) -> bool {
+        !matches!(self, EffectDesc::Exposure(_))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomDesc {
+    /// Luminance above which pixels start to bloom.
+    pub threshold: f32,
+    /// Width of the soft transition below `threshold`.
+    pub knee: f32,
+    pub intensity: f32,
+    /// Number of downsample steps.
+    pub mips: u32,
+}
+
+impl Default for BloomDesc {
+    fn default() -> Self {
+        Self { threshold: 1.0, knee: 0.5, intensity: 0.05, mips: 6 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExposureDesc {
+    /// Fixed exposure compensation in stops.
+    Manual { ev: f32 },
+    Auto(AutoExposureDesc),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposureDesc {
+    /// Range of log2 scene luminance the histogram covers.
+    pub min_ev: f32,
+    pub max_ev: f32,
+    /// Adaptation rate; higher adapts faster.
+    pub speed: f32,
+    /// Fraction of darkest and brightest pixels ignored when averaging.
+    pub low_percent: f32,
+    pub high_percent: f32,
+    /// Extra compensation in stops on top of the metered exposure.
+    pub compensation: f32,
+}
+
+impl Default for AutoExposureDesc {
+    fn default() -> Self {
+        Self { min_ev: -8.0, max_ev: 8.0, speed: 1.5, low_percent: 0.1, high_percent: 0.95, compensation: 0.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TonemapOperator {
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TonemapDesc {
+    pub operator: TonemapOperator,
+    /// On HDR outputs: brightness of scene white, in nits.
+    pub paper_white_nits: f32,
+    /// On HDR outputs: brightness the curve rolls off to, ideally the
+    /// display's peak, in nits.
+    pub peak_nits: f32,
+}
+
+impl Default for TonemapDesc {
+    fn default() -> Self {
+        Self { operator: TonemapOperator::default(), paper_white_nits: 200.0, peak_nits: 1000.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VignetteDesc {
+    pub intensity: f32,
+    /// Distance from the center (1 = corner) where darkening starts.
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for VignetteDesc {
+    fn default() -> Self {
+        Self { intensity: 0.3, radius: 0.75, smoothness: 0.45 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChromaticAberrationDesc {
+    /// Channel offset at the screen edge, in UV units.
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberrationDesc {
+    fn default() -> Self {
+        Self { intensity: 0.004 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilmGrainDesc {
+    pub intensity: f32,
+    /// How much grain fades out in bright areas (0 = uniform).
+    pub response: f32,
+}
+
+impl Default for FilmGrainDesc {
+    fn default() -> Self {
+        Self { intensity: 0.04, response: 0.8 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorGradingDesc {
+    /// Path of a `.cube` LUT; `None` starts ungraded until a LUT is set at
+    /// runtime through [`PostFxChain::grading`](super::PostFxChain::grading).
+    pub lut: Option<String>,
+    /// Mix between the ungraded (0) and graded (1) image.
+    pub strength: f32,
+}
+
+impl Default for ColorGradingDesc {
+    fn default() -> Self {
+        Self { lut: None, strength: 1.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example from the module documentation.
+    const EXAMPLE: &str = r#"(
+        effects: [
+            Bloom((threshold: 1.0, intensity: 0.05)),
+            Exposure(Auto((min_ev: -4.0, max_ev: 12.0))),
+            Tonemap((operator: AgX)),
+            Vignette((intensity: 0.3)),
+            FilmGrain((intensity: 0.04)),
+            ColorGrading((lut: Some("assets/luts/warm.cube"), strength: 0.8)),
+        ],
+    )"#;
+
+    #[test]
+    fn parses_the_documented_example() {
+        let desc = PostFxDesc::from_ron(EXAMPLE).unwrap();
+        let expected = vec![
+            EffectDesc::Bloom(BloomDesc { threshold: 1.0, intensity: 0.05, ..Default::default() }),
+            EffectDesc::Exposure(ExposureDesc::Auto(AutoExposureDesc { min_ev: -4.0, max_ev: 12.0, ..Default::default() })),
+            EffectDesc::Tonemap(TonemapDesc { operator: TonemapOperator::AgX, ..Default::default() }),
+            EffectDesc::Vignette(VignetteDesc { intensity: 0.3, ..Default::default() }),
+            EffectDesc::FilmGrain(FilmGrainDesc { intensity: 0.04, ..Default::default() }),
+            EffectDesc::ColorGrading(ColorGradingDesc { lut: Some("assets/luts/warm.cube".into()), strength: 0.8 }),
+        ];
+        assert_eq!(desc.effects, expected);
+    }
+
+    #[test]
+    fn omitted_fields_take_their_defaults() {
+        assert_eq!(PostFxDesc::from_ron("()").unwrap(), PostFxDesc::default());
+        let desc = PostFxDesc::from_ron("(effects: [Bloom(()), Tonemap((peak_nits: 600.0)), ChromaticAberration(())])").unwrap();
+        let expected = vec![
+            EffectDesc::Bloom(BloomDesc::default()),
+            EffectDesc::Tonemap(TonemapDesc { peak_nits: 600.0, ..Default::default() }),
+            EffectDesc::ChromaticAberration(ChromaticAberrationDesc::default()),
+        ];
+        assert_eq!(desc.effects, expected);
+        // Manual exposure has no defaults.
+        assert!(PostFxDesc::from_ron("(effects: [Exposure(Manual())])").is_err());
+        assert!(PostFxDesc::from_ron("(effects: [Sharpen(())])").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let mut desc = PostFxDesc::from_ron(EXAMPLE).unwrap();
+        desc.effects.insert(1, EffectDesc::Exposure(ExposureDesc::Manual { ev: -1.5 }));
+        desc.effects.push(EffectDesc::ChromaticAberration(ChromaticAberrationDesc { intensity: 0.01 }));
+        assert_eq!(PostFxDesc::from_ron(&desc.to_ron().unwrap()).unwrap(), desc);
+    }
+}
@@ -0,0 +1,117 @@
+//! Single-pass screen effects.
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{NodeContext, RenderNode};
+use super::{ChromaticAberrationDesc, EffectIo, FilmGrainDesc, VignetteDesc};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct VignetteParams {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    aspect: f32,
+}
+
+/// Darkens the image towards the corners.
+pub struct Vignette {
+    pub desc: VignetteDesc,
+    io: EffectIo,
+    pass: FullscreenPass,
+}
+
+impl Vignette {
+    pub(crate) fn new(device: &wgpu::Device, desc: VignetteDesc, io: EffectIo) -> Self {
+        Self { desc, io, pass: FullscreenPass::new::<VignetteParams>(device, "Vignette", "mars/postfx/vignette.wgsl", "fs_main", &[], None) }
+    }
+}
+
+impl RenderNode for Vignette {
+    fn name(&self) -> &'static str {
+        "vignette"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let params = VignetteParams {
+            intensity: self.desc.intensity,
+            radius: self.desc.radius,
+            smoothness: self.desc.smoothness,
+            aspect: ctx.size.0.max(1) as f32 / ctx.size.1.max(1) as f32,
+        };
+        self.pass.write_params(ctx.queue, &params);
+        self.io.run(ctx, "Vignette", &mut self.pass, &[])
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct AberrationParams {
+    intensity: f32,
+    _pad: [f32; 3],
+}
+
+/// Radially offsets the red and blue channels.
+pub struct ChromaticAberration {
+    pub desc: ChromaticAberrationDesc,
+    io: EffectIo,
+    pass: FullscreenPass,
+}
+
+impl ChromaticAberration {
+    pub(crate) fn new(device: &wgpu::Device, desc: ChromaticAberrationDesc, io: EffectIo) -> Self {
+        Self { desc, io, pass: FullscreenPass::new::<AberrationParams>(device, "Chromatic Aberration", "mars/postfx/chromatic_aberration.wgsl", "fs_main", &[], None) }
+    }
+}
+
+impl RenderNode for ChromaticAberration {
+    fn name(&self) -> &'static str {
+        "chromatic_aberration"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        self.pass.write_params(ctx.queue, &AberrationParams { intensity: self.desc.intensity, _pad: [0.0; 3] });
+        self.io.run(ctx, "Chromatic Aberration", &mut self.pass, &[])
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GrainParams {
+    intensity: f32,
+    response: f32,
+    seed: u32,
+    _pad: u32,
+}
+
+/// Per-pixel noise, re-seeded every frame.
+pub struct FilmGrain {
+    pub desc: FilmGrainDesc,
+    io: EffectIo,
+    pass: FullscreenPass,
+}
+
+impl FilmGrain {
+    pub(crate) fn new(device: &wgpu::Device, desc: FilmGrainDesc, io: EffectIo) -> Self {
+        Self { desc, io, pass: FullscreenPass::new::<GrainParams>(device, "Film Grain", "mars/postfx/film_grain.wgsl", "fs_main", &[], None) }
+    }
+}
+
+impl RenderNode for FilmGrain {
+    fn name(&self) -> &'static str {
+        "film_grain"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let params = GrainParams {
+            intensity: self.desc.intensity,
+            response: self.desc.response,
+            seed: ctx.frame as u32,
+            _pad: 0,
+        };
+        self.pass.write_params(ctx.queue, &params);
+        self.io.run(ctx, "Film Grain", &mut self.pass, &[])
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+
+use crate::binding::{buffer_entry, texture_entry};
+use crate::graph::{NodeContext, RenderNode};
+use crate::pipeline::{ComputePipelineDesc, PipelinesHandle};
+use crate::shader::ShaderDefs;
+use super::{AutoExposureDesc, ExposureDesc, EXPOSURE};
+
+const BINS: u64 = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ExposureParams {
+    min_log: f32,
+    log_range: f32,
+    dt: f32,
+    speed: f32,
+    low_percent: f32,
+    high_percent: f32,
+    compensation: f32,
+    snap: u32,
+}
+
+struct AutoExposure {
+    entries: [wgpu::BindGroupLayoutEntry; 4],
+    histogram_pipeline: ComputePipelineDesc,
+    average_pipeline: ComputePipelineDesc,
+    params: wgpu::Buffer,
+    histogram: wgpu::Buffer,
+    snap: bool,
+}
+
+impl AutoExposure {
+    fn new(device: &wgpu::Device, pipelines: &PipelinesHandle) -> Self {
+        let compute = wgpu::ShaderStages::COMPUTE;
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        let entries = [
+            texture_entry(0, compute),
+            buffer_entry(1, compute, wgpu::BufferBindingType::Uniform),
+            buffer_entry(2, compute, storage),
+            buffer_entry(3, compute, storage),
+        ];
+        let shader = pipelines.lock().builtin(device, "mars/postfx/exposure.wgsl", &ShaderDefs::new());
+        let pipeline = |entry: &str| ComputePipelineDesc::new(entry, shader, entry).with_bind_group(&entries);
+        let histogram_pipeline = pipeline("build_histogram");
+        let average_pipeline = pipeline("average");
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure Params"),
+            size: std::mem::size_of::<ExposureParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Luminance Histogram"),
+            size: BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        Self { entries, histogram_pipeline, average_pipeline, params, histogram, snap: true }
+    }
+
+    fn meter(&mut self, ctx: &mut NodeContext, input: &str, desc: &AutoExposureDesc, exposure: &wgpu::Buffer) -> Result<()> {
+        let texture = ctx.resources.texture(input).with_context(|| format!("post-fx input `{input}` missing"))?;
+        let (width, height) = texture.desc.size;
+        let params = ExposureParams {
+            min_log: desc.min_ev,
+            log_range: (desc.max_ev - desc.min_ev).max(1e-3),
+            dt: ctx.dt,
+            speed: desc.speed,
+            low_percent: desc.low_percent,
+            high_percent: desc.high_percent.max(desc.low_percent),
+            compensation: desc.compensation,
+            snap: std::mem::take(&mut self.snap) as u32,
+        };
+        ctx.queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+
+        let (layout, histogram_pipeline, average_pipeline) = {
+            let mut pipelines = ctx.pipelines.lock();
+            (
+                pipelines.bind_group_layout(ctx.device, &self.entries),
+                pipelines.compute_pipeline(ctx.device, &self.histogram_pipeline),
+                pipelines.compute_pipeline(ctx.device, &self.average_pipeline),
+            )
+        };
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Exposure BG"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: self.params.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.histogram.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: exposure.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Auto Exposure") });
+        {
+            let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Luminance Histogram"), timestamp_writes: None });
+            cp.set_bind_group(0, &bind_group, &[]);
+            cp.set_pipeline(&histogram_pipeline);
+            cp.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+            cp.set_pipeline(&average_pipeline);
+            cp.dispatch_workgroups(1, 1, 1);
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+/// Computes the exposure the tonemapper applies, either fixed or metered
+/// from a luminance histogram of its input with smooth adaptation.
+///
+/// Writes no color; the result is published as the [`EXPOSURE`] buffer.
+pub struct Exposure {
+    pub desc: ExposureDesc,
+    input: &'static str,
+    buffer: wgpu::Buffer,
+    auto: Option<AutoExposure>,
+}
+
+impl Exposure {
+    pub(crate) fn new(device: &wgpu::Device, desc: ExposureDesc, input: &'static str) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure"),
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { desc, input, buffer, auto: None }
+    }
+}
+
+impl RenderNode for Exposure {
+    fn name(&self) -> &'static str {
+        "exposure"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        match &self.desc {
+            ExposureDesc::Manual { ev } => {
+                ctx.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[ev.exp2(), 0.0, 0.0, 0.0f32]));
+            }
+            ExposureDesc::Auto(desc) => {
+                let auto = self.auto.get_or_insert_with(|| AutoExposure::new(ctx.device, ctx.pipelines));
+                auto.meter(ctx, self.input, desc, &self.buffer)?;
+            }
+        }
+        ctx.resources.insert_buffer(EXPOSURE, self.buffer.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+struct ExposureParams {
+  min_log: f32,
+  log_range: f32,
+  dt: f32,
+  speed: f32,
+  low_percent: f32,
+  high_percent: f32,
+  compensation: f32,
+  // Jump straight to the metered value instead of adapting.
+  snap: u32,
+};
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: ExposureParams;
+@group(0) @binding(2) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+// x: exposure multiplier, y: adapted log2 luminance.
+@group(0) @binding(3) var<storage, read_write> exposure: vec4<f32>;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> counts: array<u32, 256>;
+
+// Bin 0 holds black pixels; 1..255 cover [min_log, min_log + log_range].
+fn luminance_bin(c: vec3<f32>) -> u32 {
+  let lum = dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+  if (lum < 1e-5) {
+    return 0u;
+  }
+  let t = clamp((log2(lum) - params.min_log) / params.log_range, 0.0, 1.0);
+  return u32(t * 254.0 + 1.0);
+}
+
+fn bin_log(bin: u32) -> f32 {
+  return params.min_log + (f32(bin) - 0.5) / 254.0 * params.log_range;
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(local_invocation_index) li: u32) {
+  atomicStore(&local_bins[li], 0u);
+  workgroupBarrier();
+  let dims = textureDimensions(src);
+  if (gid.x < dims.x && gid.y < dims.y) {
+    atomicAdd(&local_bins[luminance_bin(textureLoad(src, gid.xy, 0).rgb)], 1u);
+  }
+  workgroupBarrier();
+  atomicAdd(&histogram[li], atomicLoad(&local_bins[li]));
+}
+
+@compute @workgroup_size(256)
+fn average(@builtin(local_invocation_index) li: u32) {
+  counts[li] = atomicLoad(&histogram[li]);
+  atomicStore(&histogram[li], 0u);
+  workgroupBarrier();
+  if (li != 0u) {
+    return;
+  }
+
+  var total = 0u;
+  for (var i = 1u; i < 256u; i++) {
+    total += counts[i];
+  }
+  // Average only the part of the distribution between the two percentiles.
+  let low = f32(total) * params.low_percent;
+  let high = f32(total) * params.high_percent;
+  var seen = 0.0;
+  var sum = 0.0;
+  var weight = 0.0;
+  for (var i = 1u; i < 256u; i++) {
+    let start = seen;
+    seen += f32(counts[i]);
+    let w = max(min(seen, high) - max(start, low), 0.0);
+    sum += w * bin_log(i);
+    weight += w;
+  }
+
+  var adapted = exposure.y;
+  if (weight > 0.0) {
+    let metered = sum / weight;
+    if (params.snap != 0u) {
+      adapted = metered;
+    } else {
+      adapted += (metered - adapted) * (1.0 - exp(-params.dt * params.speed));
+    }
+  }
+  // Map the adapted luminance to middle grey.
+  exposure = vec4<f32>(0.18 / exp2(adapted) * exp2(params.compensation), adapted, 0.0, 0.0);
+}
@@ -0,0 +1,29 @@
+#import mars::fullscreen
+
+struct GrainParams {
+  intensity: f32,
+  response: f32,
+  seed: u32,
+  _pad: u32,
+};
+
+@group(0) @binding(2) var<uniform> params: GrainParams;
+
+fn hash(p: vec3<u32>) -> f32 {
+  var h = p.x * 1664525u + p.y * 1013904223u + p.z * 2654435761u;
+  h ^= h >> 16u;
+  h *= 2246822519u;
+  h ^= h >> 13u;
+  h *= 3266489917u;
+  h ^= h >> 16u;
+  return f32(h) / 4294967295.0;
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let c = textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb;
+  let noise = hash(vec3<u32>(vec2<u32>(in.clip.xy), params.seed)) - 0.5;
+  // Grain is strongest in the shadows and mid-tones.
+  let amount = params.intensity * mix(1.0, 1.0 - saturate(luminance(c)), params.response);
+  return finish(max(c + noise * amount, vec3<f32>(0.0)));
+}
@@ -0,0 +1,271 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use mars_asset::lut::CubeLut;
+
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{NodeContext, RenderNode};
+use super::{ColorGradingDesc, EffectIo};
+
+/// A [`CubeLut`] uploaded as an `Rgba32Float` 3D texture.
+pub struct LutTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+}
+
+impl LutTexture {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, lut: &CubeLut) -> Self {
+        let size = wgpu::Extent3d { width: lut.size, height: lut.size, depth_or_array_layers: lut.size };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(lut.title.as_deref().unwrap_or("LUT")),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texels: Vec<[f32; 4]> = lut.data.iter().map(|&[r, g, b]| [r, g, b, 1.0]).collect();
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(lut.size * 16), rows_per_image: Some(lut.size) },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, size: lut.size, domain_min: lut.domain_min, domain_max: lut.domain_max }
+    }
+}
+
+struct LutChange {
+    lut: CubeLut,
+    seconds: f32,
+}
+
+struct GradingControl {
+    strength: f32,
+    pending: Option<LutChange>,
+    blend: f32,
+}
+
+/// Runtime control over the color grading of a [`PostFxChain`](super::PostFxChain),
+/// e.g. to fade to a different grade when entering a cave. Cheap to clone;
+/// every `ColorGrading` effect of the chain follows the same handle.
+#[derive(Clone)]
+pub struct GradingHandle(Arc<Mutex<GradingControl>>);
+
+impl Default for GradingHandle {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(GradingControl { strength: 1.0, pending: None, blend: 0.0 })))
+    }
+}
+
+impl GradingHandle {
+    fn control(&self) -> std::sync::MutexGuard<'_, GradingControl> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Swaps the LUT on the next frame.
+    pub fn set_lut(&self, lut: CubeLut) {
+        self.transition_to(lut, 0.0);
+    }
+
+    /// Blends from the current LUT to `lut` over `seconds`. A transition
+    /// still in progress finishes immediately.
+    pub fn transition_to(&self, lut: CubeLut, seconds: f32) {
+        self.control().pending = Some(LutChange { lut, seconds });
+    }
+
+    /// Mix between the ungraded (0) and graded (1) image. Overrides the
+    /// description's strength until the chain is rebuilt.
+    pub fn set_strength(&self, strength: f32) {
+        self.control().strength = strength.clamp(0.0, 1.0);
+    }
+
+    pub fn strength(&self) -> f32 {
+        self.control().strength
+    }
+
+    /// Progress of the current transition, 0 when none is running.
+    pub fn transition_progress(&self) -> f32 {
+        self.control().blend
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GradingParams {
+    domain_min_a: [f32; 4],
+    domain_max_a: [f32; 4],
+    domain_min_b: [f32; 4],
+    domain_max_b: [f32; 4],
+    strength: f32,
+    blend: f32,
+    _pad: [f32; 2],
+}
+
+fn extend([x, y, z]: [f32; 3]) -> [f32; 4] {
+    [x, y, z, 0.0]
+}
+
+/// Grades the image through a 3D LUT, optionally cross-fading to a second
+/// one. Expects display-range input, so it belongs after the tonemapper.
+pub struct ColorGrading {
+    pub desc: ColorGradingDesc,
+    io: EffectIo,
+    pass: FullscreenPass,
+    handle: GradingHandle,
+    current: Option<LutTexture>,
+    next: Option<(LutTexture, f32)>,
+    blend: f32,
+}
+
+impl ColorGrading {
+    pub(crate) fn new(device: &wgpu::Device, desc: ColorGradingDesc, io: EffectIo, handle: GradingHandle) -> Self {
+        let lut_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D3,
+                multisampled: false,
+            },
+            count: None,
+        };
+        handle.set_strength(desc.strength);
+        Self {
+            desc,
+            io,
+            pass: FullscreenPass::new::<GradingParams>(device, "Color Grading", "mars/postfx/grading.wgsl", "fs_main", &[lut_entry(3), lut_entry(4)], None),
+            handle,
+            current: None,
+            next: None,
+            blend: 0.0,
+        }
+    }
+
+    /// Applies queued LUT changes and advances a running transition.
+    fn update(&mut self, ctx: &NodeContext) -> Result<()> {
+        if self.current.is_none() {
+            let lut = match &self.desc.lut {
+                Some(path) => CubeLut::load(path)?,
+                None => CubeLut::identity(2),
+            };
+            self.current = Some(LutTexture::new(ctx.device, ctx.queue, &lut));
+        }
+
+        let mut control = self.handle.control();
+        if let Some(change) = control.pending.take() {
+            let texture = LutTexture::new(ctx.device, ctx.queue, &change.lut);
+            if let Some((next, _)) = self.next.take() {
+                self.current = Some(next);
+            }
+            if change.seconds > 0.0 {
+                self.next = Some((texture, change.seconds));
+            } else {
+                self.current = Some(texture);
+            }
+            self.blend = 0.0;
+        }
+        if let Some((_, seconds)) = &self.next {
+            self.blend += ctx.dt / seconds;
+            if self.blend >= 1.0 {
+                self.current = self.next.take().map(|(texture, _)| texture);
+                self.blend = 0.0;
+            }
+        }
+        control.blend = self.blend;
+        Ok(())
+    }
+}
+
+impl RenderNode for ColorGrading {
+    fn name(&self) -> &'static str {
+        "color_grading"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        self.update(ctx)?;
+        let a = self.current.as_ref().expect("loaded by update");
+        let b = self.next.as_ref().map_or(a, |(texture, _)| texture);
+        let params = GradingParams {
+            domain_min_a: extend(a.domain_min),
+            domain_max_a: extend(a.domain_max),
+            domain_min_b: extend(b.domain_min),
+            domain_max_b: extend(b.domain_max),
+            strength: self.handle.strength(),
+            blend: self.blend,
+            _pad: [0.0; 2],
+        };
+        self.pass.write_params(ctx.queue, &params);
+
+        let (a, b) = (a.view.clone(), b.view.clone());
+        let entries = [
+            wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&a) },
+            wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&b) },
+        ];
+        self.io.run(ctx, "Color Grading", &mut self.pass, &entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postfx::testing;
+    use crate::postfx::{EffectDesc, PostFxChain, PostFxDesc};
+
+    /// Linear scene colors, one per pixel.
+    const PIXELS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.5, 0.25, 0.0], [0.05, 0.75, 0.2]];
+
+    fn linear_to_srgb(c: f32) -> f32 {
+        if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+    }
+
+    /// Grades [`PIXELS`] through a chain holding one `ColorGrading` effect
+    /// and returns the sRGB output bytes.
+    fn grade(device: &wgpu::Device, queue: &wgpu::Queue, lut: Option<CubeLut>, strength: f32) -> Vec<[u8; 4]> {
+        let mut chain =
+            PostFxChain::new(PostFxDesc { effects: vec![EffectDesc::ColorGrading(ColorGradingDesc { lut: None, strength })] });
+        if let Some(lut) = lut {
+            chain.grading().set_lut(lut);
+        }
+        let bytes = testing::run(device, queue, &mut chain, &PIXELS, wgpu::TextureFormat::Rgba8UnormSrgb);
+        bytes.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+    }
+
+    /// Compares against the CPU lookup of the display-encoded input.
+    fn assert_graded(output: &[[u8; 4]], lut: &CubeLut, strength: f32) {
+        for (pixel, linear) in output.iter().zip(PIXELS) {
+            let encoded = linear.map(linear_to_srgb);
+            let graded = lut.sample(encoded);
+            for i in 0..3 {
+                let expected = (encoded[i] + (graded[i] - encoded[i]) * strength) * 255.0;
+                assert!((pixel[i] as f32 - expected).abs() <= 2.0, "{linear:?}: got {pixel:?}, expected {expected} in channel {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn grades_offscreen_image() {
+        let Some((device, queue)) = testing::device() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let identity = CubeLut::identity(2);
+        assert_graded(&grade(&device, &queue, None, 1.0), &identity, 1.0);
+        assert_graded(&grade(&device, &queue, Some(CubeLut::identity(17)), 1.0), &identity, 1.0);
+
+        let mut invert = CubeLut::identity(5);
+        for entry in &mut invert.data {
+            *entry = entry.map(|c| 1.0 - c);
+        }
+        assert_graded(&grade(&device, &queue, Some(invert.clone()), 1.0), &invert, 1.0);
+        assert_graded(&grade(&device, &queue, Some(invert.clone()), 0.5), &invert, 0.5);
+        assert_graded(&grade(&device, &queue, Some(invert), 0.0), &identity, 1.0);
+    }
+}
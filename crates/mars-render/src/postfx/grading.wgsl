@@ -0,0 +1,50 @@
+#import mars::fullscreen
+
+struct GradingParams {
+  domain_min_a: vec4<f32>,
+  domain_max_a: vec4<f32>,
+  domain_min_b: vec4<f32>,
+  domain_max_b: vec4<f32>,
+  strength: f32,
+  // 0 = only lut_a, 1 = only lut_b.
+  blend: f32,
+  _pad0: f32,
+  _pad1: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: GradingParams;
+@group(0) @binding(3) var lut_a: texture_3d<f32>;
+@group(0) @binding(4) var lut_b: texture_3d<f32>;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+  let lo = c / 12.92;
+  let hi = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(hi, lo, c <= vec3<f32>(0.04045));
+}
+
+// Trilinear lookup by hand: Rgba32Float is not filterable everywhere.
+fn lookup(lut: texture_3d<f32>, c: vec3<f32>, domain_min: vec3<f32>, domain_max: vec3<f32>) -> vec3<f32> {
+  let n = vec3<i32>(textureDimensions(lut));
+  let p = clamp((c - domain_min) / (domain_max - domain_min), vec3<f32>(0.0), vec3<f32>(1.0)) * vec3<f32>(n - 1);
+  let i0 = vec3<i32>(floor(p));
+  let i1 = min(i0 + 1, n - 1);
+  let f = p - floor(p);
+  let c00 = mix(textureLoad(lut, i0, 0).rgb, textureLoad(lut, vec3<i32>(i1.x, i0.y, i0.z), 0).rgb, f.x);
+  let c10 = mix(textureLoad(lut, vec3<i32>(i0.x, i1.y, i0.z), 0).rgb, textureLoad(lut, vec3<i32>(i1.x, i1.y, i0.z), 0).rgb, f.x);
+  let c01 = mix(textureLoad(lut, vec3<i32>(i0.x, i0.y, i1.z), 0).rgb, textureLoad(lut, vec3<i32>(i1.x, i0.y, i1.z), 0).rgb, f.x);
+  let c11 = mix(textureLoad(lut, vec3<i32>(i0.x, i1.y, i1.z), 0).rgb, textureLoad(lut, i1, 0).rgb, f.x);
+  return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+// LUTs are authored against display-encoded color, so the lookup happens on
+// sRGB values and the result is decoded back to linear.
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let color = textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb;
+  let encoded = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+  var graded = lookup(lut_a, encoded, params.domain_min_a.xyz, params.domain_max_a.xyz);
+  if (params.blend > 0.0) {
+    graded = mix(graded, lookup(lut_b, encoded, params.domain_min_b.xyz, params.domain_max_b.xyz), params.blend);
+  }
+  return finish(srgb_to_linear(mix(encoded, graded, params.strength)));
+}
@@ -0,0 +1,165 @@
+//! Post-processing on an HDR scene target.
+//!
+//! The scene is rendered into the [`SCENE_COLOR`] graph texture (see
+//! [`scene_color`]); a [`PostFxChain`] node then runs the effects of a
+//! [`PostFxDesc`] in order, ping-ponging between intermediate HDR textures,
+//! and the last effect writes the graph's output view.
+
+mod bloom;
+mod desc;
+mod effects;
+mod exposure;
+mod grading;
+#[cfg(test)]
+mod testing;
+mod tonemap;
+
+use anyhow::{Context, Result};
+
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{GraphTexture, GraphTextureDesc, NodeContext, RenderGraph, RenderNode};
+
+pub use crate::graph::SCENE_COLOR;
+pub use bloom::Bloom;
+pub use desc::*;
+pub use effects::{ChromaticAberration, FilmGrain, Vignette};
+pub use exposure::Exposure;
+pub use grading::{ColorGrading, GradingHandle, LutTexture};
+pub use tonemap::Tonemap;
+
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Graph buffer holding the current exposure (`vec4`, x = multiplier).
+pub const EXPOSURE: &str = "postfx.exposure";
+
+const PING_PONG: [&str; 2] = ["postfx.ping", "postfx.pong"];
+
+/// Returns the HDR scene target for a `size` output, creating or resizing it
+/// as needed.
+pub fn scene_color<'a>(resources: &'a mut crate::graph::GraphResources, device: &wgpu::Device, size: (u32, u32)) -> &'a GraphTexture {
+    resources.ensure_texture(device, SCENE_COLOR, GraphTextureDesc::target(size, HDR_FORMAT))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Target {
+    Texture(&'static str),
+    /// The graph's output view.
+    View,
+}
+
+/// Where an effect reads from and writes to.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EffectIo {
+    pub input: &'static str,
+    pub output: Target,
+}
+
+impl EffectIo {
+    pub fn input(&self, ctx: &NodeContext) -> Result<wgpu::TextureView> {
+        let texture = ctx.resources.texture(self.input).with_context(|| format!("post-fx input `{}` missing", self.input))?;
+        Ok(texture.view.clone())
+    }
+
+    /// Output view and format, allocating the intermediate texture at the
+    /// graph's output size.
+    pub fn output(&self, ctx: &mut NodeContext) -> (wgpu::TextureView, wgpu::TextureFormat) {
+        match self.output {
+            Target::Texture(name) => {
+                let texture = ctx.resources.ensure_texture(ctx.device, name, GraphTextureDesc::target(ctx.size, HDR_FORMAT));
+                (texture.view.clone(), HDR_FORMAT)
+            }
+            Target::View => (ctx.view.clone(), ctx.format),
+        }
+    }
+
+    /// Runs a single fullscreen pass from input to output.
+    pub fn run(&self, ctx: &mut NodeContext, label: &str, pass: &mut FullscreenPass, extra: &[wgpu::BindGroupEntry]) -> Result<()> {
+        let input = self.input(ctx)?;
+        let (output, format) = self.output(ctx);
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) });
+        pass.draw(ctx.pipelines, ctx.device, &mut encoder, &input, &output, format, extra, false);
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+/// Runs a [`PostFxDesc`] as a nested graph, one node per effect.
+///
+/// Reads [`SCENE_COLOR`] and writes the graph output. A chain without any
+/// color effect still copies the scene to the output.
+pub struct PostFxChain {
+    desc: PostFxDesc,
+    graph: Option<RenderGraph>,
+    grading: GradingHandle,
+}
+
+impl PostFxChain {
+    pub fn new(desc: PostFxDesc) -> Self {
+        Self { desc, graph: None, grading: GradingHandle::default() }
+    }
+
+    pub fn from_ron(src: &str) -> Result<Self> {
+        Ok(Self::new(PostFxDesc::from_ron(src)?))
+    }
+
+    pub fn desc(&self) -> &PostFxDesc {
+        &self.desc
+    }
+
+    /// Runtime control of the chain's `ColorGrading` effects.
+    pub fn grading(&self) -> &GradingHandle {
+        &self.grading
+    }
+
+    /// Replaces the effect list; nodes are rebuilt on the next run.
+    pub fn set_desc(&mut self, desc: PostFxDesc) {
+        self.desc = desc;
+        self.graph = None;
+    }
+
+    fn build(&self, device: &wgpu::Device) -> RenderGraph {
+        let mut effects = self.desc.effects.clone();
+        if !effects.iter().any(EffectDesc::writes_color) {
+            effects.push(EffectDesc::Tonemap(TonemapDesc { operator: TonemapOperator::None, ..Default::default() }));
+        }
+        let last = effects.iter().rposition(EffectDesc::writes_color).unwrap_or_default();
+
+        let mut graph = RenderGraph::new();
+        let mut input = SCENE_COLOR;
+        let mut ping = 0;
+        for (i, effect) in effects.iter().enumerate() {
+            let output = if i == last { Target::View } else { Target::Texture(PING_PONG[ping]) };
+            let io = EffectIo { input, output };
+            match effect {
+                EffectDesc::Exposure(d) => {
+                    graph.push_node(Exposure::new(device, d.clone(), input));
+                    continue;
+                }
+                EffectDesc::Bloom(d) => graph.push_node(Bloom::new(device, d.clone(), io)),
+                EffectDesc::Tonemap(d) => graph.push_node(Tonemap::new(device, d.clone(), io)),
+                EffectDesc::Vignette(d) => graph.push_node(Vignette::new(device, d.clone(), io)),
+                EffectDesc::ChromaticAberration(d) => graph.push_node(ChromaticAberration::new(device, d.clone(), io)),
+                EffectDesc::FilmGrain(d) => graph.push_node(FilmGrain::new(device, d.clone(), io)),
+                EffectDesc::ColorGrading(d) => graph.push_node(ColorGrading::new(device, d.clone(), io, self.grading.clone())),
+            }
+            if let Target::Texture(name) = output {
+                input = name;
+                ping ^= 1;
+            }
+        }
+        graph
+    }
+}
+
+impl RenderNode for PostFxChain {
+    fn name(&self) -> &'static str {
+        "postfx"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        if self.graph.is_none() {
+            self.graph = Some(self.build(ctx.device));
+        }
+        self.graph.as_mut().unwrap().execute(ctx)
+    }
+}
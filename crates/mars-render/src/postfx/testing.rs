@@ -0,0 +1,101 @@
+//! Offscreen harness for effect tests: uploads one row of linear scene
+//! colors, runs a chain into a target of a chosen format and reads the row
+//! back.
+
+use crate::graph::{GraphResources, GraphTexture, GraphTextureDesc, NodeContext, RenderNode, SCENE_COLOR};
+use crate::pipeline::PipelinesHandle;
+use super::{PostFxChain, HDR_FORMAT};
+
+pub(crate) fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()
+}
+
+/// Exact for zero and for positive values representable as normal f16.
+pub(crate) fn f16_bits(v: f32) -> u16 {
+    if v == 0.0 {
+        return 0;
+    }
+    let bits = v.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    ((exponent as u16) << 10) | ((bits >> 13) & 0x3ff) as u16
+}
+
+pub(crate) fn f16_value(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    let magnitude = match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f => f32::INFINITY,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    };
+    sign * magnitude
+}
+
+/// Runs `chain` over `pixels` as the scene color and returns the output
+/// row's texels, `format.block_copy_size` bytes each.
+pub(crate) fn run(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    chain: &mut PostFxChain,
+    pixels: &[[f32; 3]],
+    format: wgpu::TextureFormat,
+) -> Vec<u8> {
+    let size = (pixels.len() as u32, 1);
+    let mut resources = GraphResources::default();
+    let mut desc = GraphTextureDesc::target(size, HDR_FORMAT);
+    desc.usage |= wgpu::TextureUsages::COPY_DST;
+    let scene = GraphTexture::new(device, SCENE_COLOR, desc);
+    let texels: Vec<u16> = pixels.iter().flat_map(|&[r, g, b]| [r, g, b, 1.0].map(f16_bits)).collect();
+    queue.write_texture(
+        scene.texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(size.0 * 8), rows_per_image: None },
+        scene.texture.size(),
+    );
+    resources.insert_texture(SCENE_COLOR, scene);
+
+    let mut out = GraphTextureDesc::target(size, format);
+    out.usage |= wgpu::TextureUsages::COPY_SRC;
+    let out = GraphTexture::new(device, "output", out);
+
+    let pipelines = PipelinesHandle::new(format);
+    let mut ctx = NodeContext {
+        device,
+        queue,
+        view: &out.view,
+        format,
+        size,
+        resources: &mut resources,
+        pipelines: &pipelines,
+        frame: 0,
+        dt: 0.0,
+    };
+    chain.execute(&mut ctx).unwrap();
+
+    let row = size.0 * format.block_copy_size(None).expect("color format");
+    // Rows of a buffer copy are padded to 256 bytes.
+    let padded = row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: padded as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        out.texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &readback,
+            layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded), rows_per_image: None },
+        },
+        out.texture.size(),
+    );
+    queue.submit(Some(encoder.finish()));
+    readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+    device.poll(wgpu::PollType::Wait).unwrap();
+    let bytes = readback.slice(..).get_mapped_range();
+    bytes[..row as usize].to_vec()
+}
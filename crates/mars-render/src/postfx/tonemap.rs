@@ -0,0 +1,119 @@
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::binding;
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{NodeContext, RenderNode};
+use crate::output::OutputTransform;
+use super::{EffectIo, TonemapDesc, TonemapOperator, EXPOSURE};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct TonemapParams {
+    mode: u32,
+    headroom: f32,
+    output_scale: f32,
+    _pad: u32,
+}
+
+/// Applies exposure and maps HDR to display range.
+///
+/// Uses the [`EXPOSURE`] buffer when an exposure node ran earlier in the
+/// graph, otherwise an exposure of 1. The display range follows the graph's
+/// output format through [`OutputTransform`]: on HDR outputs the curve
+/// extends to the desc's peak and scene white lands on its paper white, so
+/// effects after this one see values above 1.
+pub struct Tonemap {
+    pub desc: TonemapDesc,
+    io: EffectIo,
+    pass: FullscreenPass,
+    unit_exposure: wgpu::Buffer,
+}
+
+impl Tonemap {
+    pub(crate) fn new(device: &wgpu::Device, desc: TonemapDesc, io: EffectIo) -> Self {
+        let exposure_entry = binding::buffer_entry(3, wgpu::ShaderStages::FRAGMENT, wgpu::BufferBindingType::Storage { read_only: true });
+        let unit_exposure = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Unit Exposure"),
+            contents: bytemuck::cast_slice(&[1.0f32, 0.0, 0.0, 0.0]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        Self {
+            desc,
+            io,
+            pass: FullscreenPass::new::<TonemapParams>(device, "Tonemap", "mars/postfx/tonemap.wgsl", "fs_main", &[exposure_entry], None),
+            unit_exposure,
+        }
+    }
+}
+
+impl RenderNode for Tonemap {
+    fn name(&self) -> &'static str {
+        "tonemap"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let mode = match self.desc.operator {
+            TonemapOperator::None => 0,
+            TonemapOperator::Reinhard => 1,
+            TonemapOperator::Aces => 2,
+            TonemapOperator::AgX => 3,
+        };
+        let transform = OutputTransform::for_format(ctx.format, self.desc.paper_white_nits, self.desc.peak_nits);
+        let params = TonemapParams { mode, headroom: transform.headroom(), output_scale: transform.output_scale(), _pad: 0 };
+        self.pass.write_params(ctx.queue, &params);
+
+        let exposure = ctx.resources.buffer(EXPOSURE).unwrap_or(&self.unit_exposure).clone();
+        let entry = wgpu::BindGroupEntry { binding: 3, resource: exposure.as_entire_binding() };
+        self.io.run(ctx, "Tonemap", &mut self.pass, &[entry])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postfx::testing;
+    use crate::postfx::{EffectDesc, PostFxChain, PostFxDesc};
+
+    /// Scene gray levels, one per pixel.
+    const GRAYS: [f32; 4] = [0.0, 1.0, 2.0, 8.0];
+
+    /// Tonemaps [`GRAYS`] into an scRGB target and returns each pixel's red
+    /// channel.
+    fn tonemap(device: &wgpu::Device, queue: &wgpu::Queue, desc: TonemapDesc) -> Vec<f32> {
+        let mut chain = PostFxChain::new(PostFxDesc { effects: vec![EffectDesc::Tonemap(desc)] });
+        let bytes = testing::run(device, queue, &mut chain, &GRAYS.map(|v| [v; 3]), wgpu::TextureFormat::Rgba16Float);
+        bytes.chunks(8).map(|texel| testing::f16_value(u16::from_le_bytes([texel[0], texel[1]]))).collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: impl IntoIterator<Item = f32>) {
+        for (&a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() <= e * 2e-3 + 1e-4, "got {actual:?}, expected {e} for {a}");
+        }
+    }
+
+    #[test]
+    fn scrgb_output_keeps_highlights() {
+        let Some((device, queue)) = testing::device() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let desc = TonemapDesc { operator: TonemapOperator::None, paper_white_nits: 160.0, peak_nits: 800.0 };
+        let transform = OutputTransform::for_format(wgpu::TextureFormat::Rgba16Float, desc.paper_white_nits, desc.peak_nits);
+        let (headroom, scale) = (transform.headroom(), transform.output_scale());
+        assert_eq!((headroom, scale), (5.0, 2.0));
+
+        // Without a curve, scene white lands on paper white and everything
+        // above it scales along.
+        let linear = tonemap(&device, &queue, desc.clone());
+        assert_close(&linear, GRAYS.map(|v| v * scale));
+        assert!(linear[3] > 1.0);
+
+        // A curve rolls off towards the display's peak instead of 1.
+        let reinhard = tonemap(&device, &queue, TonemapDesc { operator: TonemapOperator::Reinhard, ..desc });
+        assert_close(&reinhard, GRAYS.map(|v| v / headroom / (1.0 + v / headroom) * headroom * scale));
+        assert!(reinhard[2] > 1.0);
+        assert!(reinhard.iter().all(|&v| v < headroom * scale));
+    }
+}
@@ -0,0 +1,60 @@
+#import mars::fullscreen
+
+struct TonemapParams {
+  mode: u32,
+  // Display peak relative to paper white; 1 for SDR.
+  headroom: f32,
+  // Paper white in output units (scRGB: nits / 80); 1 for SDR.
+  output_scale: f32,
+  _pad: u32,
+};
+
+@group(0) @binding(2) var<uniform> params: TonemapParams;
+@group(0) @binding(3) var<storage, read> exposure: vec4<f32>;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(x: vec3<f32>) -> vec3<f32> {
+  return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+  let x2 = x * x;
+  let x4 = x2 * x2;
+  return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+// Minimal AgX (Benjamin Wrensch's polynomial fit), returned in linear.
+fn agx(c: vec3<f32>) -> vec3<f32> {
+  let inset = mat3x3<f32>(
+    vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+    vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+    vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+  );
+  let outset = mat3x3<f32>(
+    vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+    vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+    vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+  );
+  let min_ev = -12.47393;
+  let max_ev = 4.026069;
+  var x = inset * max(c, vec3<f32>(1e-10));
+  x = clamp((log2(x) - min_ev) / (max_ev - min_ev), vec3<f32>(0.0), vec3<f32>(1.0));
+  x = outset * agx_contrast(x);
+  return pow(max(x, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  // The curves map to 0..1; scale around them so they roll off at the
+  // display's peak instead.
+  let h = params.headroom;
+  let c = textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb * exposure.x / h;
+  var mapped: vec3<f32>;
+  switch params.mode {
+    case 1u: { mapped = c / (1.0 + luminance(c)); }
+    case 2u: { mapped = aces(c); }
+    case 3u: { mapped = agx(c); }
+    default: { mapped = c; }
+  }
+  return finish(mapped * h * params.output_scale);
+}
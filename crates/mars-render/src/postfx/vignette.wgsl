@@ -0,0 +1,19 @@
+#import mars::fullscreen
+
+struct VignetteParams {
+  intensity: f32,
+  radius: f32,
+  smoothness: f32,
+  aspect: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: VignetteParams;
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let c = textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb;
+  // Normalized so the corners are at distance 1.
+  let d = (in.uv - 0.5) * vec2<f32>(params.aspect, 1.0) / length(vec2<f32>(params.aspect, 1.0) * 0.5);
+  let v = smoothstep(params.radius, params.radius - params.smoothness, length(d));
+  return finish(c * mix(1.0 - params.intensity, 1.0, v));
+}
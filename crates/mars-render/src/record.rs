@@ -0,0 +1,249 @@
+//! Deterministic offline recording of a graph's output.
+//!
+//! A [`FrameRecorder`] owns an offscreen target of a fixed size, runs the
+//! graph into it once per simulated frame with a fixed `dt`, and hands
+//! every `every`th frame to a [`FrameSink`], such as a numbered PNG
+//! sequence or a Y4M stream. Nothing depends on wall-clock time, so a
+//! headless run reproduces the same frames as long as the game advances its
+//! simulation by [`FrameRecorder::time_step`] per frame. Nodes that adapt
+//! to measured GPU time, like an automatic render scale, break this.
+
+use anyhow::{Context, Result};
+use mars_asset::video::FrameSink;
+
+use crate::graph::{GraphTexture, GraphTextureDesc, RenderGraph};
+
+/// Format of the recording target. Graphs encode for it like for an sRGB
+/// surface.
+pub const RECORD_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecorderConfig {
+    pub size: (u32, u32),
+    /// Simulated frames per second; the fixed `dt` is its inverse.
+    pub fps: u32,
+    /// Writes every `every`th frame only, starting with the first; 0 and 1
+    /// write all of them. The recorder sets the sink's frame rate to
+    /// `fps / every`.
+    pub every: u32,
+    /// Frames to simulate before [`FrameRecorder::is_finished`] is true.
+    pub frames: Option<u64>,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self { size: (1920, 1080), fps: 60, every: 1, frames: None }
+    }
+}
+
+pub struct FrameRecorder {
+    config: RecorderConfig,
+    sink: Box<dyn FrameSink>,
+    target: GraphTexture,
+    readback: wgpu::Buffer,
+    padded_row: u32,
+    pixels: Vec<u8>,
+    frame: u64,
+    written: u64,
+}
+
+impl FrameRecorder {
+    pub fn new(device: &wgpu::Device, config: RecorderConfig, mut sink: impl FrameSink + 'static) -> Self {
+        sink.set_frame_rate(config.fps.max(1), config.every.max(1));
+        let (width, height) = (config.size.0.max(1), config.size.1.max(1));
+        let desc = GraphTextureDesc {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            ..GraphTextureDesc::target((width, height), RECORD_FORMAT)
+        };
+        let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Recorder Readback"),
+            size: u64::from(padded_row) * u64::from(height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            config: RecorderConfig { size: (width, height), ..config },
+            sink: Box::new(sink),
+            target: GraphTexture::new(device, "Recorder Target", desc),
+            readback,
+            padded_row,
+            pixels: Vec::with_capacity((width * height * 4) as usize),
+            frame: 0,
+            written: 0,
+        }
+    }
+
+    pub fn config(&self) -> &RecorderConfig {
+        &self.config
+    }
+
+    /// Seconds of simulated time per frame.
+    pub fn time_step(&self) -> f32 {
+        1.0 / self.config.fps.max(1) as f32
+    }
+
+    /// Frames simulated so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Simulated seconds since the first frame.
+    pub fn time(&self) -> f64 {
+        self.frame as f64 / f64::from(self.config.fps.max(1))
+    }
+
+    /// Frames handed to the sink so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.config.frames.is_some_and(|n| self.frame >= n)
+    }
+
+    /// The recording target, e.g. to show the last frame on screen.
+    pub fn target(&self) -> &GraphTexture {
+        &self.target
+    }
+
+    /// Simulates one frame: runs `graph` into the target with the fixed
+    /// time step and writes the result if it is one of the recorded frames.
+    /// Waits for the GPU when writing.
+    pub fn record(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, graph: &mut RenderGraph) -> Result<()> {
+        let measured = graph.time_step();
+        graph.set_time_step(Some(self.time_step()));
+        let result = graph.run_on(device, queue, &self.target.view, RECORD_FORMAT, self.config.size);
+        graph.set_time_step(measured);
+        result?;
+
+        let frame = self.frame;
+        self.frame += 1;
+        if frame.is_multiple_of(u64::from(self.config.every.max(1))) {
+            self.read_target(device, queue)?;
+            let (width, height) = self.config.size;
+            self.sink.write_frame(width, height, &self.pixels).with_context(|| format!("write frame {frame}"))?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    /// Flushes the sink.
+    pub fn finish(mut self) -> Result<()> {
+        self.sink.finish()
+    }
+
+    fn read_target(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()> {
+        let (width, height) = self.config.size;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Recorder Readback") });
+        encoder.copy_texture_to_buffer(
+            self.target.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback,
+                layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(self.padded_row), rows_per_image: None },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.readback.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        device.poll(wgpu::PollType::Wait).context("wait for recorded frame")?;
+        rx.recv().context("readback callback dropped")?.context("map recorded frame")?;
+        {
+            let data = slice.get_mapped_range();
+            self.pixels.clear();
+            for row in data.chunks_exact(self.padded_row as usize) {
+                self.pixels.extend_from_slice(&row[..(width * 4) as usize]);
+            }
+        }
+        self.readback.unmap();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::graph::{NodeContext, RenderNode};
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()
+    }
+
+    /// Clears the output to a color whose red, green and blue bits spell
+    /// the graph's frame number.
+    struct FrameColor;
+
+    impl RenderNode for FrameColor {
+        fn name(&self) -> &'static str {
+            "frame_color"
+        }
+
+        fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+            let bit = |i: u32| ((ctx.frame >> i) & 1) as f64;
+            let color = wgpu::Color { r: bit(0), g: bit(1), b: bit(2), a: 1.0 };
+            let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: ctx.view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(color), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            ctx.queue.submit(Some(encoder.finish()));
+            Ok(())
+        }
+    }
+
+    /// Decodes the frame number of each written frame.
+    #[derive(Clone, Default)]
+    struct Frames {
+        written: Arc<Mutex<Vec<u64>>>,
+        rate: Arc<Mutex<Option<(u32, u32)>>>,
+    }
+
+    impl FrameSink for Frames {
+        fn write_frame(&mut self, _width: u32, _height: u32, rgba: &[u8]) -> Result<()> {
+            let frame = (0..3).map(|i| u64::from(rgba[i] > 127) << i).sum();
+            self.written.lock().unwrap().push(frame);
+            Ok(())
+        }
+
+        fn set_frame_rate(&mut self, num: u32, den: u32) {
+            *self.rate.lock().unwrap() = Some((num, den));
+        }
+    }
+
+    #[test]
+    fn records_every_nth_frame_at_the_reduced_rate() {
+        let Some((device, queue)) = device() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let sink = Frames::default();
+        let config = RecorderConfig { size: (2, 2), fps: 60, every: 3, frames: Some(8) };
+        let mut recorder = FrameRecorder::new(&device, config, sink.clone());
+        let mut graph = RenderGraph::new().add_node(FrameColor);
+        while !recorder.is_finished() {
+            recorder.record(&device, &queue, &mut graph).unwrap();
+        }
+
+        assert_eq!(*sink.written.lock().unwrap(), [0, 3, 6]);
+        assert_eq!(recorder.written(), 3);
+        assert_eq!(*sink.rate.lock().unwrap(), Some((60, 3)));
+        assert_eq!(recorder.time(), 8.0 / 60.0);
+    }
+}
@@ -0,0 +1,557 @@
+//! WGSL shader library with a small preprocessor and hot reload.
+//!
+//! Directives, each on its own line:
+//!
+//! ```text
+//! #include "common/lighting.wgsl"   // path relative to this file or a search path
+//! #import mars::noise               // same as #include "mars/noise.wgsl"
+//! #define SHADOWS                   // or `#define TAPS 8`
+//! #undef SHADOWS
+//! #ifdef SHADOWS / #ifndef SHADOWS / #if TAPS == 8 / #if TAPS != 8
+//! #else
+//! #endif
+//! ```
+//!
+//! `#{NAME}` in code is replaced by the value of a define. Each file is
+//! included at most once per shader. Composed shaders are validated with
+//! naga, and errors point at the original file and line.
+//!
+//! The engine's own shaders are available as `mars::…` imports once
+//! [`ShaderLibrary::add_builtin_sources`] has run, e.g.
+//! `#import mars::fullscreen` for the fullscreen-triangle vertex stage.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use mars_asset::watch::FileWatcher;
+
+/// Preprocessor defines selecting a shader permutation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderDefs(BTreeMap<String, String>);
+
+impl ShaderDefs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines `name` with the value `1`.
+    pub fn with(self, name: &str) -> Self {
+        self.with_value(name, 1)
+    }
+
+    pub fn with_value(mut self, name: &str, value: impl ToString) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: impl ToString) {
+        self.0.insert(name.to_owned(), value.to_string());
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+}
+
+/// Output of the preprocessor, with a line map back to the sources.
+#[derive(Clone, Debug)]
+pub struct ComposedShader {
+    pub source: String,
+    files: Vec<String>,
+    /// (index into `files`, 1-based line) for every output line.
+    lines: Vec<(usize, u32)>,
+    /// Files read from disk, for hot reload.
+    pub dependencies: Vec<PathBuf>,
+}
+
+impl ComposedShader {
+    /// Original file and line of a 1-based line in [`ComposedShader::source`].
+    pub fn locate(&self, line: u32) -> Option<(&str, u32)> {
+        let &(file, line) = self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], line))
+    }
+
+    fn describe(&self, location: Option<naga::SourceLocation>) -> String {
+        match location.and_then(|l| Some((self.locate(l.line_number)?, l.line_position))) {
+            Some(((file, line), column)) => format!("{file}:{line}:{column}"),
+            None => self.files.first().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Parses and validates the source with naga.
+    pub fn validate(&self) -> Result<naga::Module> {
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|e| {
+            let mut msg = format!("{}: {}", self.describe(e.location(&self.source)), e.message());
+            for (span, label) in e.labels() {
+                if !label.is_empty() && !msg.contains(label) {
+                    let _ = write!(msg, "\n  {}: {label}", self.describe(Some(span.location(&self.source))));
+                }
+            }
+            anyhow!(msg)
+        })?;
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|e| {
+                let mut msg = format!("{}: {}", self.describe(e.location(&self.source)), e.as_inner());
+                let mut source = std::error::Error::source(e.as_inner());
+                while let Some(inner) = source {
+                    let _ = write!(msg, ": {inner}");
+                    source = inner.source();
+                }
+                for (span, label) in e.spans().skip(1) {
+                    let _ = write!(msg, "\n  {}: {label}", self.describe(Some(span.location(&self.source))));
+                }
+                anyhow!(msg)
+            })?;
+        Ok(module)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderId(u32);
+
+struct ShaderEntry {
+    name: String,
+    defs: ShaderDefs,
+    module: wgpu::ShaderModule,
+    dependencies: Vec<PathBuf>,
+    generation: u32,
+}
+
+/// One open `#if` while composing a file.
+struct Branch {
+    /// Lines in the current branch are emitted.
+    taken: bool,
+    /// Some branch of this `#if` was taken, or the whole block is skipped,
+    /// so a later `#else` isn't.
+    any_taken: bool,
+    seen_else: bool,
+    /// Line of the `#if`.
+    line: u32,
+}
+
+/// A source registered in memory.
+struct Source {
+    text: String,
+    /// File the text was embedded from, read instead while watching.
+    path: Option<PathBuf>,
+}
+
+/// Where an included file came from.
+enum Origin {
+    Virtual(String),
+    Disk(PathBuf),
+}
+
+/// Loads, composes and caches shader modules; see the module docs for the
+/// preprocessor syntax.
+///
+/// Sources are looked up among the sources added with
+/// [`ShaderLibrary::add_source`] and friends, then relative to the including
+/// file, then in each search path. With [`ShaderLibrary::watch`] enabled,
+/// [`ShaderLibrary::reload_changed`] recompiles shaders whose files changed on
+/// disk; owners rebuild their pipelines when a shader's
+/// [`generation`](ShaderLibrary::generation) moves.
+#[derive(Default)]
+pub struct ShaderLibrary {
+    sources: HashMap<String, Source>,
+    search_paths: Vec<PathBuf>,
+    shaders: Vec<ShaderEntry>,
+    loaded: HashMap<(String, ShaderDefs), ShaderId>,
+    watcher: Option<FileWatcher>,
+}
+
+impl ShaderLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.search_paths.push(dir.into());
+    }
+
+    /// Registers an in-memory source under `name`, e.g. an `include_str!`
+    /// snippet shared by several shaders.
+    pub fn add_source(&mut self, name: &str, source: impl Into<String>) {
+        self.sources.insert(name.to_owned(), Source { text: source.into(), path: None });
+    }
+
+    /// Registers an in-memory source embedded from the file at `path`. While
+    /// the library [watches](Self::watch) for changes the file is read
+    /// instead, when it exists, so edits to it reload.
+    pub fn add_embedded_source(&mut self, name: &str, source: impl Into<String>, path: impl Into<PathBuf>) {
+        self.sources.insert(name.to_owned(), Source { text: source.into(), path: Some(path.into()) });
+    }
+
+    /// Registers the engine's shaders as `mars/<path>.wgsl`, importable as
+    /// `mars::<path>`. Edits to them in this crate's source tree reload like
+    /// any other watched file.
+    pub fn add_builtin_sources(&mut self) {
+        for &(name, source, path) in builtin_sources() {
+            self.add_embedded_source(name, source, path);
+        }
+    }
+
+    fn resolve(&self, name: &str, from: Option<&Path>) -> Result<Origin> {
+        if self.sources.contains_key(name) {
+            return Ok(Origin::Virtual(name.to_owned()));
+        }
+        let relative = from.and_then(Path::parent).map(|dir| dir.join(name));
+        let candidates = relative.into_iter().chain(self.search_paths.iter().map(|dir| dir.join(name)));
+        for path in candidates.chain(std::iter::once(PathBuf::from(name))) {
+            if path.is_file() {
+                return Ok(Origin::Disk(path.canonicalize().unwrap_or(path)));
+            }
+        }
+        bail!("shader source `{name}` not found")
+    }
+
+    /// Runs the preprocessor on `name` without compiling it.
+    pub fn compose(&self, name: &str, defs: &ShaderDefs) -> Result<ComposedShader> {
+        let mut out = ComposedShader { source: String::new(), files: Vec::new(), lines: Vec::new(), dependencies: Vec::new() };
+        let mut defs = defs.clone();
+        let origin = self.resolve(name, None)?;
+        self.compose_file(origin, &mut defs, &mut out)?;
+        Ok(out)
+    }
+
+    fn compose_file(&self, origin: Origin, defs: &mut ShaderDefs, out: &mut ComposedShader) -> Result<()> {
+        let (display, text, path) = match origin {
+            Origin::Virtual(name) => {
+                let source = &self.sources[&name];
+                let path = source.path.clone().filter(|path| path.is_file());
+                let text = match &path {
+                    Some(path) if self.watcher.is_some() => {
+                        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?
+                    }
+                    _ => source.text.clone(),
+                };
+                (name, text, path)
+            }
+            Origin::Disk(path) => {
+                let text = std::fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+                (path.display().to_string(), text, Some(path))
+            }
+        };
+        if out.files.contains(&display) {
+            return Ok(());
+        }
+        let file = out.files.len();
+        out.files.push(display.clone());
+        if let Some(path) = &path {
+            out.dependencies.push(path.clone());
+        }
+
+        let mut stack: Vec<Branch> = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let n = n as u32 + 1;
+            let at = || format!("{display}:{n}");
+            let active = stack.iter().all(|b| b.taken);
+            let Some(directive) = line.trim_start().strip_prefix('#').filter(|d| !d.starts_with('{')) else {
+                if active {
+                    out.source.push_str(&substitute(line, defs).with_context(at)?);
+                    out.source.push('\n');
+                    out.lines.push((file, n));
+                }
+                continue;
+            };
+            let directive = directive.split("//").next().unwrap_or_default().trim();
+            let (keyword, arg) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let arg = arg.trim();
+            match keyword {
+                "ifdef" | "ifndef" | "if" => {
+                    // Inside a skipped block the condition may refer to
+                    // defines that only exist in the other branch.
+                    let taken = active
+                        && match keyword {
+                            "ifdef" => defs.contains(arg),
+                            "ifndef" => !defs.contains(arg),
+                            _ => condition(arg, defs).with_context(at)?,
+                        };
+                    stack.push(Branch { taken, any_taken: taken || !active, seen_else: false, line: n });
+                }
+                "else" => {
+                    let top = stack.last_mut().with_context(|| format!("{}: #else without #if", at()))?;
+                    if top.seen_else {
+                        bail!("{}: second #else for the #if at line {}", at(), top.line);
+                    }
+                    top.taken = !top.any_taken;
+                    top.any_taken = true;
+                    top.seen_else = true;
+                }
+                "endif" => {
+                    stack.pop().with_context(|| format!("{}: #endif without #if", at()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let (name, value) = arg.split_once(char::is_whitespace).unwrap_or((arg, "1"));
+                    defs.set(name, value.trim());
+                }
+                "undef" => defs.remove(arg),
+                "include" | "import" => {
+                    let target = if keyword == "include" {
+                        arg.trim_matches('"').to_owned()
+                    } else {
+                        format!("{}.wgsl", arg.replace("::", "/"))
+                    };
+                    let origin = self.resolve(&target, path.as_deref()).with_context(at)?;
+                    self.compose_file(origin, defs, out)?;
+                }
+                _ => bail!("{}: unknown directive #{keyword}", at()),
+            }
+        }
+        if let Some(branch) = stack.last() {
+            bail!("{display}:{}: unterminated #if", branch.line);
+        }
+        Ok(())
+    }
+
+    /// Composes, validates and compiles `name` with `defs`, or returns the
+    /// already loaded permutation.
+    pub fn load(&mut self, device: &wgpu::Device, name: &str, defs: &ShaderDefs) -> Result<ShaderId> {
+        let key = (name.to_owned(), defs.clone());
+        if let Some(&id) = self.loaded.get(&key) {
+            return Ok(id);
+        }
+        let (module, dependencies) = self.build(device, name, defs)?;
+        if let Some(watcher) = &mut self.watcher {
+            for path in &dependencies {
+                watcher.watch(path)?;
+            }
+        }
+        let id = ShaderId(self.shaders.len() as u32);
+        self.shaders.push(ShaderEntry { name: name.to_owned(), defs: defs.clone(), module, dependencies, generation: 0 });
+        self.loaded.insert(key, id);
+        Ok(id)
+    }
+
+    fn build(&self, device: &wgpu::Device, name: &str, defs: &ShaderDefs) -> Result<(wgpu::ShaderModule, Vec<PathBuf>)> {
+        let composed = self.compose(name, defs)?;
+        composed.validate()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+        });
+        Ok((module, composed.dependencies))
+    }
+
+    pub fn module(&self, id: ShaderId) -> &wgpu::ShaderModule {
+        &self.shaders[id.0 as usize].module
+    }
+
+    /// Incremented every time the shader is recompiled by a reload.
+    pub fn generation(&self, id: ShaderId) -> u32 {
+        self.shaders[id.0 as usize].generation
+    }
+
+    /// Starts watching the files of loaded (and later loaded) shaders.
+    pub fn watch(&mut self) -> Result<()> {
+        if self.watcher.is_some() {
+            return Ok(());
+        }
+        let mut watcher = FileWatcher::new()?;
+        for path in self.shaders.iter().flat_map(|s| &s.dependencies) {
+            watcher.watch(path)?;
+        }
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// Recompiles shaders whose files changed since the last call and
+    /// returns them. A shader that fails to compile keeps its previous
+    /// module; the error is logged.
+    pub fn reload_changed(&mut self, device: &wgpu::Device) -> Vec<ShaderId> {
+        let Some(watcher) = &mut self.watcher else { return Vec::new() };
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            return Vec::new();
+        }
+
+        let mut reloaded = Vec::new();
+        for i in 0..self.shaders.len() {
+            if !self.shaders[i].dependencies.iter().any(|d| changed.contains(d)) {
+                continue;
+            }
+            let (name, defs) = (self.shaders[i].name.clone(), self.shaders[i].defs.clone());
+            match self.build(device, &name, &defs) {
+                Ok((module, dependencies)) => {
+                    if let Some(watcher) = &mut self.watcher {
+                        for path in &dependencies {
+                            if let Err(e) = watcher.watch(path) {
+                                tracing::warn!("{e:#}");
+                            }
+                        }
+                    }
+                    let shader = &mut self.shaders[i];
+                    shader.module = module;
+                    shader.dependencies = dependencies;
+                    shader.generation += 1;
+                    tracing::info!("reloaded shader {name}");
+                    reloaded.push(ShaderId(i as u32));
+                }
+                Err(e) => tracing::error!("shader {name}: {e:#}"),
+            }
+        }
+        reloaded
+    }
+}
+
+/// Registers engine shaders as (library name, source, file in this crate).
+macro_rules! builtin {
+    ($($file:literal),* $(,)?) => {
+        &[$((concat!("mars/", $file), include_str!($file), concat!(env!("CARGO_MANIFEST_DIR"), "/src/", $file))),*]
+    };
+}
+
+type Builtin = (&'static str, &'static str, &'static str);
+
+const BUILTIN: &[Builtin] = builtin!["fullscreen.wgsl", "debug_draw.wgsl", "mipmap.wgsl", "pip.wgsl", "upscale.wgsl"];
+
+#[cfg(feature = "postfx")]
+const BUILTIN_POSTFX: &[Builtin] = builtin![
+    "postfx/bloom.wgsl",
+    "postfx/chromatic_aberration.wgsl",
+    "postfx/exposure.wgsl",
+    "postfx/film_grain.wgsl",
+    "postfx/grading.wgsl",
+    "postfx/tonemap.wgsl",
+    "postfx/vignette.wgsl",
+];
+
+#[cfg(feature = "3d")]
+const BUILTIN_3D: &[Builtin] = builtin![
+    "three_d/cluster_common.wgsl",
+    "three_d/cluster_cull.wgsl",
+    "three_d/decal.wgsl",
+    "three_d/fxaa.wgsl",
+    "three_d/gpu_cull.wgsl",
+    "three_d/hiz.wgsl",
+    "three_d/ibl_brdf.wgsl",
+    "three_d/ibl_common.wgsl",
+    "three_d/ibl_equirect.wgsl",
+    "three_d/ibl_irradiance.wgsl",
+    "three_d/ibl_prefilter.wgsl",
+    "three_d/mesh.wgsl",
+    "three_d/prepass.wgsl",
+    "three_d/screen_common.wgsl",
+    "three_d/skybox.wgsl",
+    "three_d/ssao.wgsl",
+    "three_d/ssr.wgsl",
+    "three_d/taa.wgsl",
+];
+
+fn builtin_sources() -> impl Iterator<Item = &'static Builtin> {
+    let sources = BUILTIN.iter();
+    #[cfg(feature = "postfx")]
+    let sources = sources.chain(BUILTIN_POSTFX);
+    #[cfg(feature = "3d")]
+    let sources = sources.chain(BUILTIN_3D);
+    sources
+}
+
+/// Evaluates `NAME`, `NAME == value` or `NAME != value`.
+fn condition(expr: &str, defs: &ShaderDefs) -> Result<bool> {
+    for (op, equal) in [("==", true), ("!=", false)] {
+        if let Some((name, value)) = expr.split_once(op) {
+            let name = name.trim();
+            let actual = defs.get(name).with_context(|| format!("`{name}` is not defined"))?;
+            return Ok((actual == value.trim()) == equal);
+        }
+    }
+    Ok(defs.get(expr).is_some_and(|v| v != "0" && v != "false"))
+}
+
+/// Replaces `#{NAME}` with the define's value.
+fn substitute(line: &str, defs: &ShaderDefs) -> Result<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("#{") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..].find('}').with_context(|| format!("unterminated `#{{` in `{line}`"))? + start;
+        let name = &rest[start + 2..end];
+        out.push_str(defs.get(name).with_context(|| format!("`{name}` is not defined"))?);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(src: &str) -> ShaderLibrary {
+        let mut library = ShaderLibrary::new();
+        library.add_source("main.wgsl", src);
+        library.add_source("common.wgsl", "const COMMON: f32 = 1.0;");
+        library
+    }
+
+    fn compose(src: &str, defs: &ShaderDefs) -> Result<String> {
+        Ok(library(src).compose("main.wgsl", defs)?.source)
+    }
+
+    #[test]
+    fn selects_branches() {
+        let src = "#ifdef A\na\n#else\nnot a\n#endif\n#if TAPS == 8\neight\n#endif\n#ifndef B\nnot b\n#endif";
+        let defs = ShaderDefs::new().with("A").with_value("TAPS", 8);
+        assert_eq!(compose(src, &defs).unwrap(), "a\neight\nnot b\n");
+        let defs = ShaderDefs::new().with_value("TAPS", 4).with("B");
+        assert_eq!(compose(src, &defs).unwrap(), "not a\n");
+    }
+
+    #[test]
+    fn skipped_blocks_do_not_evaluate_conditions() {
+        let src = "#ifdef TAPS\n#if TAPS == 8\neight\n#else\nother\n#endif\n#else\nnone\n#endif";
+        assert_eq!(compose(src, &ShaderDefs::new()).unwrap(), "none\n");
+        assert_eq!(compose(src, &ShaderDefs::new().with_value("TAPS", 8)).unwrap(), "eight\n");
+        assert!(compose("#if TAPS == 8\n#endif", &ShaderDefs::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_unbalanced_directives() {
+        let err = compose("#ifdef A\n#else\n#else\n#endif", &ShaderDefs::new()).unwrap_err();
+        assert!(err.to_string().contains("main.wgsl:3: second #else"), "{err}");
+        assert!(compose("#else", &ShaderDefs::new()).is_err());
+        assert!(compose("#endif", &ShaderDefs::new()).is_err());
+        assert!(compose("#ifdef A", &ShaderDefs::new()).is_err());
+    }
+
+    #[test]
+    fn defines_and_includes() {
+        let src = "#include \"common.wgsl\"\n#import common\n#define N 3\nvar<private> x: array<f32, #{N}>;";
+        let composed = library(src).compose("main.wgsl", &ShaderDefs::new()).unwrap();
+        assert_eq!(composed.source, "const COMMON: f32 = 1.0;\nvar<private> x: array<f32, 3>;\n");
+        assert_eq!(composed.locate(1), Some(("common.wgsl", 1)));
+        assert_eq!(composed.locate(2), Some(("main.wgsl", 4)));
+        composed.validate().unwrap();
+    }
+
+    #[test]
+    fn builtin_shaders_validate() {
+        let mut library = ShaderLibrary::new();
+        library.add_builtin_sources();
+        for &(name, _, _) in builtin_sources() {
+            let composed = library.compose(name, &ShaderDefs::new()).unwrap_or_else(|e| panic!("{e:#}"));
+            composed.validate().unwrap_or_else(|e| panic!("{e:#}"));
+        }
+        let mut variants = vec![("mars/fullscreen.wgsl", "ENCODE_SRGB"), ("mars/debug_draw.wgsl", "ENCODE_SRGB")];
+        if cfg!(feature = "3d") {
+            variants.extend([("mars/three_d/decal.wgsl", "MULTIPLY"), ("mars/three_d/hiz.wgsl", "FARTHEST")]);
+        }
+        for (name, define) in variants {
+            let composed = library.compose(name, &ShaderDefs::new().with(define)).unwrap_or_else(|e| panic!("{e:#}"));
+            composed.validate().unwrap_or_else(|e| panic!("{name} with {define}: {e:#}"));
+        }
+    }
+}
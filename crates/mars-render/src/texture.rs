@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+use mars_asset::compressed::{BlockFormat, CompressedTexture, Compression};
+use mars_asset::texture::{ColorSpace, TexelFormat, TextureData, TextureUsage};
+
+use crate::mipmap::{self, MipGenerator};
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: (u32, u32),
+    pub mip_levels: u32,
+}
+
+impl Texture {
+    pub fn format_for(data: &TextureData) -> wgpu::TextureFormat {
+        match (data.format, data.color_space) {
+            (TexelFormat::Rgba8, ColorSpace::Srgb) => wgpu::TextureFormat::Rgba8UnormSrgb,
+            (TexelFormat::Rgba8, ColorSpace::Linear) => wgpu::TextureFormat::Rgba8Unorm,
+            (TexelFormat::Rgba32Float, _) => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    pub fn from_data(device: &wgpu::Device, queue: &wgpu::Queue, data: &TextureData, label: &str) -> Self {
+        let format = Self::format_for(data);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: data.width, height: data.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        write_layer(queue, &texture, data, 0);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, format, size: (data.width, data.height), mip_levels: 1 }
+    }
+
+    /// The wgpu format of a KTX2 payload.
+    pub fn block_format_for(format: BlockFormat, color_space: ColorSpace) -> wgpu::TextureFormat {
+        use wgpu::TextureFormat as F;
+        let srgb = color_space == ColorSpace::Srgb;
+        match format {
+            BlockFormat::Rgba8 if srgb => F::Rgba8UnormSrgb,
+            BlockFormat::Rgba8 => F::Rgba8Unorm,
+            BlockFormat::Rgba16Float => F::Rgba16Float,
+            BlockFormat::Rgba32Float => F::Rgba32Float,
+            BlockFormat::Bc1 if srgb => F::Bc1RgbaUnormSrgb,
+            BlockFormat::Bc1 => F::Bc1RgbaUnorm,
+            BlockFormat::Bc3 if srgb => F::Bc3RgbaUnormSrgb,
+            BlockFormat::Bc3 => F::Bc3RgbaUnorm,
+            BlockFormat::Bc4 => F::Bc4RUnorm,
+            BlockFormat::Bc5 => F::Bc5RgUnorm,
+            BlockFormat::Bc6hUfloat => F::Bc6hRgbUfloat,
+            BlockFormat::Bc7 if srgb => F::Bc7RgbaUnormSrgb,
+            BlockFormat::Bc7 => F::Bc7RgbaUnorm,
+            BlockFormat::Etc2Rgb8 if srgb => F::Etc2Rgb8UnormSrgb,
+            BlockFormat::Etc2Rgb8 => F::Etc2Rgb8Unorm,
+            BlockFormat::Etc2Rgb8A1 if srgb => F::Etc2Rgb8A1UnormSrgb,
+            BlockFormat::Etc2Rgb8A1 => F::Etc2Rgb8A1Unorm,
+            BlockFormat::Etc2Rgba8 if srgb => F::Etc2Rgba8UnormSrgb,
+            BlockFormat::Etc2Rgba8 => F::Etc2Rgba8Unorm,
+            BlockFormat::EacR11 => F::EacR11Unorm,
+            BlockFormat::EacRg11 => F::EacRg11Unorm,
+            BlockFormat::Astc { block_width, block_height } => F::Astc {
+                block: astc_block(block_width, block_height),
+                channel: if srgb { wgpu::AstcChannel::UnormSrgb } else { wgpu::AstcChannel::Unorm },
+            },
+        }
+    }
+}
+
+fn astc_block(width: u8, height: u8) -> wgpu::AstcBlock {
+    use wgpu::AstcBlock as B;
+    match (width, height) {
+        (4, 4) => B::B4x4,
+        (5, 5) => B::B5x5,
+        (6, 6) => B::B6x6,
+        (8, 8) => B::B8x8,
+        (10, 10) => B::B10x10,
+        (12, 12) => B::B12x12,
+        _ => unreachable!("ASTC {width}x{height} is not produced by the KTX2 loader"),
+    }
+}
+
+/// The device feature needed to sample textures of `compression`.
+pub fn compression_feature(compression: Compression) -> wgpu::Features {
+    match compression {
+        Compression::None => wgpu::Features::empty(),
+        Compression::Bc => wgpu::Features::TEXTURE_COMPRESSION_BC,
+        Compression::Etc2 => wgpu::Features::TEXTURE_COMPRESSION_ETC2,
+        Compression::Astc => wgpu::Features::TEXTURE_COMPRESSION_ASTC,
+    }
+}
+
+/// Loads image files into textures: PNG, JPEG, TGA and HDR get a mip chain
+/// generated on the GPU, KTX2 files are uploaded as stored, picking the
+/// block-compressed variant the device supports.
+pub struct TextureLoader {
+    mips: MipGenerator,
+    compression: Vec<Compression>,
+}
+
+impl TextureLoader {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // BC7 and ASTC beat ETC2 on quality; desktop devices only have BC.
+        let compression = [Compression::Bc, Compression::Astc, Compression::Etc2]
+            .into_iter()
+            .filter(|&c| device.features().contains(compression_feature(c)))
+            .collect();
+        Self { mips: MipGenerator::new(device), compression }
+    }
+
+    /// Compressed families the device can sample, preferred first.
+    pub fn supported_compression(&self) -> &[Compression] {
+        &self.compression
+    }
+
+    /// Loads `path`, treating 8-bit images according to `usage`. For
+    /// `name.ktx2`, a `name.bc.ktx2`, `name.astc.ktx2` or `name.etc2.ktx2`
+    /// next to it is preferred if the device supports its compression; the
+    /// color space of KTX2 files comes from their format.
+    pub fn load(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<Path>, usage: TextureUsage) -> Result<Texture> {
+        let path = path.as_ref();
+        let label = path.display().to_string();
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2")) {
+            let data = CompressedTexture::load(CompressedTexture::variant_path(path, &self.compression))?;
+            return self.from_compressed(device, queue, &data, &label);
+        }
+        let data = TextureData::load(path)?.with_usage(usage);
+        Ok(self.from_data(device, queue, &data, &label))
+    }
+
+    /// Uploads `data` with a full mip chain, or a single level if the
+    /// format is not renderable on this device.
+    pub fn from_data(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &TextureData, label: &str) -> Texture {
+        let format = Texture::format_for(data);
+        if !MipGenerator::supports(device, format) {
+            tracing::debug!("{label}: {format:?} is not renderable, skipping mips");
+            return Texture::from_data(device, queue, data, label);
+        }
+
+        let mip_levels = mipmap::mip_level_count(data.width, data.height);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: data.width, height: data.height, depth_or_array_layers: 1 },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        write_layer(queue, &texture, data, 0);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Mipmap Encoder") });
+        self.mips.generate(device, &mut encoder, &texture);
+        queue.submit(Some(encoder.finish()));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Texture { texture, view, format, size: (data.width, data.height), mip_levels }
+    }
+
+    /// Uploads a KTX2 texture with the mips it contains. Cube maps get a cube
+    /// view, other multi-layer textures a 2D array view.
+    pub fn from_compressed(&self, device: &wgpu::Device, queue: &wgpu::Queue, data: &CompressedTexture, label: &str) -> Result<Texture> {
+        let compression = data.format.compression();
+        ensure!(
+            device.features().contains(compression_feature(compression)),
+            "{label}: the device cannot sample {compression:?}-compressed textures"
+        );
+        let format = Texture::block_format_for(data.format, data.color_space);
+        let (block_w, block_h) = format.block_dimensions();
+        ensure!(
+            data.width.is_multiple_of(block_w) && data.height.is_multiple_of(block_h),
+            "{label}: {}x{} is not a multiple of the {block_w}x{block_h} block size",
+            data.width,
+            data.height
+        );
+
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: data.width, height: data.height, depth_or_array_layers: data.layers },
+            mip_level_count: data.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&desc);
+        let block_bytes = format.block_copy_size(None).expect("color formats have a block size");
+        for (mip, level) in data.levels.iter().enumerate() {
+            let size = desc.mip_level_size(mip as u32).expect("level within the chain").physical_size(format);
+            let (rows, row_bytes) = (size.height / block_h, size.width / block_w * block_bytes);
+            let expected = u64::from(row_bytes) * u64::from(rows) * u64::from(data.layers);
+            ensure!(level.len() as u64 == expected, "{label}: level {mip} has {} bytes, expected {expected}", level.len());
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo { texture: &texture, mip_level: mip as u32, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                level,
+                wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(row_bytes), rows_per_image: Some(rows) },
+                size,
+            );
+        }
+
+        let dimension = match (data.cube, data.layers) {
+            (true, 6) => wgpu::TextureViewDimension::Cube,
+            (true, _) => wgpu::TextureViewDimension::CubeArray,
+            (false, 1) => wgpu::TextureViewDimension::D2,
+            (false, _) => wgpu::TextureViewDimension::D2Array,
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor { dimension: Some(dimension), ..Default::default() });
+        Ok(Texture { texture, view, format, size: (data.width, data.height), mip_levels: desc.mip_level_count })
+    }
+}
+
+/// Uploads `data` into mip 0 of array layer `layer`.
+pub fn write_layer(queue: &wgpu::Queue, texture: &wgpu::Texture, data: &TextureData, layer: u32) {
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+            aspect: wgpu::TextureAspect::All,
+        },
+        &data.data,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(data.bytes_per_row()),
+            rows_per_image: Some(data.height),
+        },
+        wgpu::Extent3d { width: data.width, height: data.height, depth_or_array_layers: 1 },
+    );
+}
@@ -0,0 +1,213 @@
+//! Anti-aliasing for the mesh renderer.
+//!
+//! MSAA renders into a multisampled target resolved into the output; FXAA and
+//! TAA render into an intermediate target and filter it into the output.
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2};
+
+use crate::binding;
+use crate::fullscreen::FullscreenPass;
+use crate::pipeline::PipelinesHandle;
+
+/// History precision for TAA, independent of the output format.
+const HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Length of the Halton(2, 3) jitter sequence.
+const JITTER_PHASES: u32 = 8;
+/// Weight of the current frame in the TAA blend.
+const TAA_BLEND: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    /// Multisampling; `samples` must be supported for the color format, see
+    /// [`supported_sample_counts`]. One sample or fewer means no AA.
+    Msaa { samples: u32 },
+    Fxaa,
+    /// Temporal AA with a jittered projection and depth reprojection of the
+    /// history. Static geometry only: there are no per-object motion vectors.
+    Taa,
+}
+
+impl AntiAliasing {
+    /// The mode that actually takes effect: MSAA with fewer than two samples
+    /// is no anti-aliasing.
+    pub fn normalized(self) -> Self {
+        match self {
+            AntiAliasing::Msaa { samples } if samples <= 1 => AntiAliasing::None,
+            mode => mode,
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        match *self {
+            AntiAliasing::Msaa { samples } => samples.max(1),
+            _ => 1,
+        }
+    }
+}
+
+/// MSAA sample counts usable for render targets of `format` on `adapter`.
+pub fn supported_sample_counts(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Vec<u32> {
+    let flags = adapter.get_texture_format_features(format).flags;
+    [1, 2, 4, 8, 16].into_iter().filter(|&n| flags.sample_count_supported(n)).collect()
+}
+
+pub(super) fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+    r
+}
+
+fn color_target(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, size: (u32, u32), samples: u32) -> wgpu::TextureView {
+    let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+    if samples == 1 {
+        usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+    }
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size.0.max(1), height: size.1.max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: samples,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct FxaaParams {
+    span_max: f32,
+    reduce_mul: f32,
+    reduce_min: f32,
+    _pad: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct TaaParams {
+    inv_view_proj: [[f32; 4]; 4],
+    prev_view_proj: [[f32; 4]; 4],
+    blend: f32,
+    reset: u32,
+    _pad: [f32; 2],
+}
+
+struct Temporal {
+    resolve: FullscreenPass,
+    blit: FullscreenPass,
+    history: [wgpu::TextureView; 2],
+    current: usize,
+    phase: u32,
+    prev_view_proj: Option<Mat4>,
+}
+
+/// Targets and passes for the active [`AntiAliasing`] mode. Rebuilt whenever
+/// the mode, format or size changes.
+pub(crate) struct AntiAliasState {
+    mode: AntiAliasing,
+    format: wgpu::TextureFormat,
+    /// Multisampled color for MSAA, or the single-sampled scene for FXAA/TAA.
+    color: Option<wgpu::TextureView>,
+    fxaa: Option<FullscreenPass>,
+    taa: Option<Temporal>,
+}
+
+impl AntiAliasState {
+    pub fn new(device: &wgpu::Device, mode: AntiAliasing, format: wgpu::TextureFormat, size: (u32, u32)) -> Self {
+        let mode = mode.normalized();
+        let color = match mode {
+            AntiAliasing::None => None,
+            AntiAliasing::Msaa { samples } => Some(color_target(device, "MSAA Color", format, size, samples)),
+            AntiAliasing::Fxaa | AntiAliasing::Taa => Some(color_target(device, "AA Scene Color", format, size, 1)),
+        };
+        let fxaa = (mode == AntiAliasing::Fxaa).then(|| {
+            FullscreenPass::new::<FxaaParams>(device, "FXAA", "mars/three_d/fxaa.wgsl", "fs_main", &[], None).without_srgb_encode()
+        });
+        let taa = (mode == AntiAliasing::Taa).then(|| {
+            let fragment = wgpu::ShaderStages::FRAGMENT;
+            let extra = [binding::unfilterable_entry(3, fragment), binding::texture_entry(4, fragment)];
+            Temporal {
+                resolve: FullscreenPass::new::<TaaParams>(device, "TAA Resolve", "mars/three_d/taa.wgsl", "fs_resolve", &extra, None),
+                blit: FullscreenPass::new::<TaaParams>(device, "TAA Blit", "mars/three_d/taa.wgsl", "fs_blit", &[], None).without_srgb_encode(),
+                history: [0, 1].map(|_| color_target(device, "TAA History", HISTORY_FORMAT, size, 1)),
+                current: 0,
+                phase: 0,
+                prev_view_proj: None,
+            }
+        });
+        Self { mode, format, color, fxaa, taa }
+    }
+
+    pub fn mode(&self) -> AntiAliasing {
+        self.mode
+    }
+
+    /// Color attachment and resolve target the mesh pass should use to end up
+    /// in `output`.
+    pub fn attachment<'a>(&'a self, output: &'a wgpu::TextureView) -> (&'a wgpu::TextureView, Option<&'a wgpu::TextureView>) {
+        match (&self.mode, &self.color) {
+            (AntiAliasing::Msaa { samples }, Some(color)) if *samples > 1 => (color, Some(output)),
+            (_, Some(color)) => (color, None),
+            (_, None) => (output, None),
+        }
+    }
+
+    /// Sub-pixel projection offset for this frame, in NDC units.
+    pub fn jitter(&mut self, size: (u32, u32)) -> Vec2 {
+        let Some(taa) = &mut self.taa else { return Vec2::ZERO };
+        taa.phase = taa.phase % JITTER_PHASES + 1;
+        let offset = Vec2::new(halton(taa.phase, 2), halton(taa.phase, 3)) - 0.5;
+        offset * 2.0 / Vec2::new(size.0.max(1) as f32, size.1.max(1) as f32)
+    }
+
+    /// Filters the intermediate target into `output`. `view_proj` is the
+    /// unjittered camera matrix of this frame.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resolve(
+        &mut self,
+        pipelines: &PipelinesHandle,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        view_proj: Mat4,
+    ) {
+        let Some(color) = &self.color else { return };
+        let format = self.format;
+        if let Some(fxaa) = &mut self.fxaa {
+            fxaa.write_params(queue, &FxaaParams { span_max: 8.0, reduce_mul: 1.0 / 8.0, reduce_min: 1.0 / 128.0, _pad: 0.0 });
+            fxaa.draw(pipelines, device, encoder, color, output, format, &[], false);
+        }
+        if let Some(taa) = &mut self.taa {
+            let params = TaaParams {
+                inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+                prev_view_proj: taa.prev_view_proj.unwrap_or(view_proj).to_cols_array_2d(),
+                blend: TAA_BLEND,
+                reset: taa.prev_view_proj.is_none() as u32,
+                _pad: [0.0; 2],
+            };
+            taa.resolve.write_params(queue, &params);
+            let (prev, next) = (&taa.history[taa.current], &taa.history[1 - taa.current]);
+            let extra = [
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(depth) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(prev) },
+            ];
+            taa.resolve.draw(pipelines, device, encoder, color, next, HISTORY_FORMAT, &extra, false);
+            taa.blit.draw(pipelines, device, encoder, next, output, format, &[], false);
+            taa.current = 1 - taa.current;
+            taa.prev_view_proj = Some(view_proj);
+        }
+    }
+}
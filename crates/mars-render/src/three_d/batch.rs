@@ -0,0 +1,179 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use mars_core::bounds::{Frustum, Sphere};
+
+use super::cull::FrustumCuller;
+use super::material::{MaterialId, PipelineId};
+use super::mesh::MeshId;
+
+/// Per-instance vertex data, bound at vertex buffer slot 1.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+}
+
+impl InstanceData {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4
+    ];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Sort key for draw requests. Field order matters: sorting by pipeline first,
+/// then material, then mesh keeps state changes to a minimum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BatchKey {
+    pub pipeline: PipelineId,
+    pub material: MaterialId,
+    pub mesh: MeshId,
+}
+
+#[derive(Clone, Debug)]
+pub struct Batch {
+    pub key: BatchKey,
+    pub instances: Range<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchStats {
+    /// Draw requests submitted this frame.
+    pub requests: u32,
+    /// Requests dropped by frustum culling.
+    pub culled: u32,
+    /// Instanced draw calls issued.
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub pipeline_switches: u32,
+    pub material_switches: u32,
+}
+
+/// Collects draw requests for a frame and turns them into one instanced draw
+/// per (pipeline, material, mesh) group.
+pub struct Batcher {
+    requests: Vec<(BatchKey, Mat4)>,
+    instances: Vec<InstanceData>,
+    batches: Vec<Batch>,
+    buffer: wgpu::Buffer,
+    capacity: u64,
+    culled: u32,
+    stats: BatchStats,
+}
+
+impl Batcher {
+    const INITIAL_CAPACITY: u64 = 1024;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            requests: Vec::new(),
+            instances: Vec::new(),
+            batches: Vec::new(),
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            culled: 0,
+            stats: BatchStats::default(),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: capacity * std::mem::size_of::<InstanceData>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn push(&mut self, key: BatchKey, transform: Mat4) {
+        self.requests.push((key, transform));
+    }
+
+    /// Drops requests whose mesh bounds (indexed by [`MeshId`]) fall outside
+    /// `frustum`. Call before [`Batcher::prepare`].
+    pub fn cull(&mut self, culler: &mut FrustumCuller, frustum: &Frustum, mesh_bounds: &[Sphere]) {
+        let before = self.requests.len();
+        let mask = culler.cull(frustum, &self.requests, |(key, transform)| {
+            mesh_bounds[key.mesh.0 as usize].transform(transform)
+        });
+        let mut visible = mask.iter();
+        self.requests.retain(|_| *visible.next().unwrap());
+        self.culled += (before - self.requests.len()) as u32;
+    }
+
+    /// Sorts this frame's requests into batches and uploads the instance data,
+    /// growing the instance buffer if needed. Clears the request list.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.sort();
+        if self.instances.is_empty() {
+            return;
+        }
+        let needed = self.instances.len() as u64;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.instances));
+    }
+
+    /// Sorts this frame's requests into batches without uploading them, for
+    /// callers that build their own instance buffer from
+    /// [`Batcher::instances`]. Clears the request list.
+    pub fn sort(&mut self) {
+        self.requests.sort_by_key(|(key, _)| *key);
+
+        self.instances.clear();
+        self.batches.clear();
+        let culled = std::mem::take(&mut self.culled);
+        let mut stats = BatchStats { requests: self.requests.len() as u32 + culled, culled, ..Default::default() };
+
+        let mut prev: Option<BatchKey> = None;
+        for (key, transform) in self.requests.drain(..) {
+            let idx = self.instances.len() as u32;
+            self.instances.push(InstanceData { model: transform.to_cols_array_2d() });
+
+            match (prev, self.batches.last_mut()) {
+                (Some(p), Some(batch)) if p == key => batch.instances.end = idx + 1,
+                _ => {
+                    if prev.is_none_or(|p| p.pipeline != key.pipeline) {
+                        stats.pipeline_switches += 1;
+                    }
+                    if prev.is_none_or(|p| p.material != key.material) {
+                        stats.material_switches += 1;
+                    }
+                    self.batches.push(Batch { key, instances: idx..idx + 1 });
+                }
+            }
+            prev = Some(key);
+        }
+
+        stats.draw_calls = self.batches.len() as u32;
+        stats.instances = self.instances.len() as u32;
+        self.stats = stats;
+    }
+
+    pub fn batches(&self) -> &[Batch] {
+        &self.batches
+    }
+
+    /// This frame's instances in batch order.
+    pub fn instances(&self) -> &[InstanceData] {
+        &self.instances
+    }
+
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn stats(&self) -> BatchStats {
+        self.stats
+    }
+}
@@ -0,0 +1,85 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3};
+use mars_core::bounds::Frustum;
+
+use crate::binding::buffer_entry;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera3d {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub fov_y: f32,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera3d {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 2.0, 6.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 60f32.to_radians(),
+            aspect: 16.0 / 9.0,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+impl Camera3d {
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.target, self.up)
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fov_y, self.aspect, self.near, self.far)
+    }
+
+    pub fn view_proj(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(&self.view_proj())
+    }
+
+    pub fn uniform(&self) -> CameraUniform {
+        self.jittered_uniform(Vec2::ZERO)
+    }
+
+    /// Uniform with the projection offset by `jitter` NDC units, for
+    /// temporal anti-aliasing.
+    pub fn jittered_uniform(&self, jitter: Vec2) -> CameraUniform {
+        let view = self.view();
+        let proj = Mat4::from_translation(jitter.extend(0.0)) * self.projection();
+        CameraUniform {
+            view_proj: (proj * view).to_cols_array_2d(),
+            view: view.to_cols_array_2d(),
+            proj: proj.to_cols_array_2d(),
+            inv_view_proj: (proj * view).inverse().to_cols_array_2d(),
+            position: self.position.extend(1.0).to_array(),
+        }
+    }
+}
+
+/// GPU layout of the camera, bound at group 0 of every 3D pipeline.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub position: [f32; 4],
+}
+
+impl CameraUniform {
+    /// Layout entry of the camera bind group (group 0).
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+        let visibility = wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE;
+        [buffer_entry(0, visibility, wgpu::BufferBindingType::Uniform)]
+    }
+}
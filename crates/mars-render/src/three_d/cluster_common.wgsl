@@ -0,0 +1,38 @@
+struct Light {
+  position_range: vec4<f32>,
+  color_intensity: vec4<f32>,
+  // xyz: spot direction, w: 0 = point, 1 = spot
+  direction_kind: vec4<f32>,
+  // x: cos(inner), y: cos(outer)
+  spot: vec4<f32>,
+};
+
+struct ClusterParams {
+  inv_proj: mat4x4<f32>,
+  // xyz: cluster grid, w: max lights per cluster
+  grid: vec4<u32>,
+  light_count: u32,
+  debug: u32,
+  near: f32,
+  far: f32,
+  screen: vec2<f32>,
+  _pad: vec2<f32>,
+};
+
+// Exponential depth slices: each slice covers the same ratio of depth.
+fn cluster_slice(view_depth: f32, p: ClusterParams) -> u32 {
+  let s = log(max(view_depth, p.near) / p.near) / log(p.far / p.near) * f32(p.grid.z);
+  return min(u32(max(s, 0.0)), p.grid.z - 1u);
+}
+
+fn cluster_index(frag_xy: vec2<f32>, view_depth: f32, p: ClusterParams) -> u32 {
+  let tile = min(vec2<u32>(frag_xy / p.screen * vec2<f32>(p.grid.xy)), p.grid.xy - 1u);
+  let slice = cluster_slice(view_depth, p);
+  return tile.x + p.grid.x * (tile.y + p.grid.y * slice);
+}
+
+fn light_attenuation(dist: f32, range: f32) -> f32 {
+  let r = dist / range;
+  let window = clamp(1.0 - r * r * r * r, 0.0, 1.0);
+  return window * window / (dist * dist + 1.0);
+}
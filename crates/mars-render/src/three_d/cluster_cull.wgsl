@@ -0,0 +1,66 @@
+#import mars::three_d::cluster_common
+
+struct Camera {
+  view_proj: mat4x4<f32>,
+  view: mat4x4<f32>,
+  proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> params: ClusterParams;
+@group(1) @binding(1) var<storage, read> lights: array<Light>;
+@group(1) @binding(2) var<storage, read_write> cluster_counts: array<u32>;
+@group(1) @binding(3) var<storage, read_write> cluster_lights: array<u32>;
+
+// View-space point on the ray through `ndc` at depth `depth` (positive).
+fn view_point(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+  let p = params.inv_proj * vec4<f32>(ndc, 1.0, 1.0);
+  let dir = p.xyz / p.w;
+  return dir * (depth / -dir.z);
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let g = params.grid;
+  let total = g.x * g.y * g.z;
+  let index = gid.x;
+  if (index >= total) { return; }
+
+  let x = index % g.x;
+  let y = (index / g.x) % g.y;
+  let z = index / (g.x * g.y);
+
+  // Tile rows run top to bottom in screen space, NDC y runs bottom to top.
+  let ndc_min = vec2<f32>(f32(x) / f32(g.x) * 2.0 - 1.0, 1.0 - f32(y + 1u) / f32(g.y) * 2.0);
+  let ndc_max = vec2<f32>(f32(x + 1u) / f32(g.x) * 2.0 - 1.0, 1.0 - f32(y) / f32(g.y) * 2.0);
+  let ratio = params.far / params.near;
+  let z_near = params.near * pow(ratio, f32(z) / f32(g.z));
+  let z_far = params.near * pow(ratio, f32(z + 1u) / f32(g.z));
+
+  var bmin = vec3<f32>(1e30);
+  var bmax = vec3<f32>(-1e30);
+  for (var c = 0u; c < 4u; c++) {
+    let ndc = vec2<f32>(select(ndc_min.x, ndc_max.x, (c & 1u) != 0u), select(ndc_min.y, ndc_max.y, (c & 2u) != 0u));
+    let pn = view_point(ndc, z_near);
+    let pf = view_point(ndc, z_far);
+    bmin = min(bmin, min(pn, pf));
+    bmax = max(bmax, max(pn, pf));
+  }
+
+  var count = 0u;
+  let base = index * g.w;
+  for (var i = 0u; i < params.light_count && count < g.w; i++) {
+    let light = lights[i];
+    let center = (camera.view * vec4<f32>(light.position_range.xyz, 1.0)).xyz;
+    let closest = clamp(center, bmin, bmax);
+    let d = closest - center;
+    let range = light.position_range.w;
+    if (dot(d, d) <= range * range) {
+      cluster_lights[base + count] = i;
+      count += 1u;
+    }
+  }
+  cluster_counts[index] = count;
+}
@@ -0,0 +1,276 @@
+//! Clustered forward lighting.
+//!
+//! The view frustum is split into a grid of clusters (screen tiles times
+//! exponential depth slices). Each frame a compute pass tests every light's
+//! bounding sphere against every cluster's view-space AABB and writes the
+//! visible light indices per cluster; the mesh shader then only evaluates the
+//! lights of the cluster its fragment falls into.
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+use crate::binding::buffer_entry;
+use crate::pipeline::{ComputePipelineDesc, PipelinesHandle};
+use crate::shader::ShaderDefs;
+
+use super::camera::{Camera3d, CameraUniform};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClusterConfig {
+    /// Tiles across, tiles down, depth slices.
+    pub grid: [u32; 3],
+    /// Lights beyond this per cluster are dropped.
+    pub max_lights_per_cluster: u32,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self { grid: [16, 9, 24], max_lights_per_cluster: 128 }
+    }
+}
+
+impl ClusterConfig {
+    pub fn cluster_count(&self) -> u32 {
+        self.grid[0] * self.grid[1] * self.grid[2]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// Angles are half-angles in radians.
+    Spot { direction: Vec3, inner_angle: f32, outer_angle: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light's contribution reaches zero.
+    pub range: f32,
+    pub kind: LightKind,
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Self { position, color, intensity, range, kind: LightKind::Point }
+    }
+
+    pub fn spot(position: Vec3, direction: Vec3, color: Vec3, intensity: f32, range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range,
+            kind: LightKind::Spot { direction: direction.normalize_or_zero(), inner_angle, outer_angle },
+        }
+    }
+
+    fn gpu(&self) -> GpuLight {
+        let (direction_kind, spot) = match self.kind {
+            LightKind::Point => ([0.0; 4], [0.0; 4]),
+            LightKind::Spot { direction, inner_angle, outer_angle } => {
+                (direction.extend(1.0).to_array(), [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0])
+            }
+        };
+        GpuLight {
+            position_range: self.position.extend(self.range).to_array(),
+            color_intensity: self.color.extend(self.intensity).to_array(),
+            direction_kind,
+            spot,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct GpuLight {
+    pub position_range: [f32; 4],
+    pub color_intensity: [f32; 4],
+    pub direction_kind: [f32; 4],
+    pub spot: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct ClusterParams {
+    pub inv_proj: [[f32; 4]; 4],
+    pub grid: [u32; 4],
+    pub light_count: u32,
+    pub debug: u32,
+    pub near: f32,
+    pub far: f32,
+    pub screen: [f32; 2],
+    pub _pad: [f32; 2],
+}
+
+fn layout_entries(visibility: wgpu::ShaderStages, cluster_access_read_only: bool) -> [wgpu::BindGroupLayoutEntry; 4] {
+    let ro = wgpu::BufferBindingType::Storage { read_only: true };
+    let cluster = wgpu::BufferBindingType::Storage { read_only: cluster_access_read_only };
+    [
+        buffer_entry(0, visibility, wgpu::BufferBindingType::Uniform),
+        buffer_entry(1, visibility, ro),
+        buffer_entry(2, visibility, cluster),
+        buffer_entry(3, visibility, cluster),
+    ]
+}
+
+fn layout(device: &wgpu::Device, label: &str, entries: &[wgpu::BindGroupLayoutEntry]) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some(label), entries })
+}
+
+fn bind_buffers(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, label: &str, buffers: [&wgpu::Buffer; 4]) -> wgpu::BindGroup {
+    let entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(i, b)| wgpu::BindGroupEntry { binding: i as u32, resource: b.as_entire_binding() })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some(label), layout, entries: &entries })
+}
+
+pub struct ClusteredLighting {
+    pub config: ClusterConfig,
+    /// Lights are kept between frames; edit the list directly.
+    pub lights: Vec<Light>,
+    /// Shade meshes with a per-cluster light-count heatmap instead of lighting.
+    pub debug_view: bool,
+
+    gpu_lights: Vec<GpuLight>,
+    params: wgpu::Buffer,
+    light_buffer: wgpu::Buffer,
+    light_capacity: u64,
+    counts: wgpu::Buffer,
+    indices: wgpu::Buffer,
+
+    shade_layout: wgpu::BindGroupLayout,
+    shade_bind_group: wgpu::BindGroup,
+    cull_layout: wgpu::BindGroupLayout,
+    cull_bind_group: wgpu::BindGroup,
+    cull_pipeline: ComputePipelineDesc,
+}
+
+impl ClusteredLighting {
+    const INITIAL_LIGHTS: u64 = 64;
+
+    pub fn new(device: &wgpu::Device, pipelines: &PipelinesHandle, config: ClusterConfig) -> Self {
+        let shade_layout = layout(device, "Cluster Shade BGL", &Self::shade_layout_entries());
+        let cull_entries = layout_entries(wgpu::ShaderStages::COMPUTE, false);
+        let cull_layout = layout(device, "Cluster Cull BGL", &cull_entries);
+
+        let shader = pipelines.lock().builtin(device, "mars/three_d/cluster_cull.wgsl", &ShaderDefs::new());
+        let cull_pipeline = ComputePipelineDesc::new("Cluster Cull Pipeline", shader, "main")
+            .with_bind_group(&CameraUniform::layout_entries())
+            .with_bind_group(&cull_entries);
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Params"),
+            size: std::mem::size_of::<ClusterParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let light_buffer = Self::create_light_buffer(device, Self::INITIAL_LIGHTS);
+        let clusters = config.cluster_count() as u64;
+        let counts = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Counts"),
+            size: clusters * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let indices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Indices"),
+            size: clusters * config.max_lights_per_cluster as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let buffers = [&params, &light_buffer, &counts, &indices];
+        let shade_bind_group = bind_buffers(device, &shade_layout, "Cluster Shade BG", buffers);
+        let cull_bind_group = bind_buffers(device, &cull_layout, "Cluster Cull BG", buffers);
+
+        Self {
+            config,
+            lights: Vec::new(),
+            debug_view: false,
+            gpu_lights: Vec::new(),
+            params,
+            light_buffer,
+            light_capacity: Self::INITIAL_LIGHTS,
+            counts,
+            indices,
+            shade_layout,
+            shade_bind_group,
+            cull_layout,
+            cull_bind_group,
+            cull_pipeline,
+        }
+    }
+
+    fn create_light_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: capacity * std::mem::size_of::<GpuLight>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Layout entries of [`ClusteredLighting::shade_layout`].
+    pub fn shade_layout_entries() -> [wgpu::BindGroupLayoutEntry; 4] {
+        layout_entries(wgpu::ShaderStages::FRAGMENT, true)
+    }
+
+    /// Layout of the bind group the shading pass reads (group 3 of mesh pipelines).
+    pub fn shade_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.shade_layout
+    }
+
+    pub fn shade_bind_group(&self) -> &wgpu::BindGroup {
+        &self.shade_bind_group
+    }
+
+    /// Uploads lights and cluster parameters for a `width`×`height` target.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera3d, width: u32, height: u32) {
+        self.gpu_lights.clear();
+        self.gpu_lights.extend(self.lights.iter().map(Light::gpu));
+
+        let needed = self.gpu_lights.len() as u64;
+        if needed > self.light_capacity {
+            self.light_capacity = needed.next_power_of_two();
+            self.light_buffer = Self::create_light_buffer(device, self.light_capacity);
+            self.rebuild_bind_groups(device);
+        }
+        if !self.gpu_lights.is_empty() {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&self.gpu_lights));
+        }
+
+        let [gx, gy, gz] = self.config.grid;
+        let params = ClusterParams {
+            inv_proj: camera.projection().inverse().to_cols_array_2d(),
+            grid: [gx, gy, gz, self.config.max_lights_per_cluster],
+            light_count: self.gpu_lights.len() as u32,
+            debug: self.debug_view as u32,
+            near: camera.near,
+            far: camera.far,
+            screen: [width.max(1) as f32, height.max(1) as f32],
+            _pad: [0.0; 2],
+        };
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+    }
+
+    fn rebuild_bind_groups(&mut self, device: &wgpu::Device) {
+        let buffers = [&self.params, &self.light_buffer, &self.counts, &self.indices];
+        self.shade_bind_group = bind_buffers(device, &self.shade_layout, "Cluster Shade BG", buffers);
+        self.cull_bind_group = bind_buffers(device, &self.cull_layout, "Cluster Cull BG", buffers);
+    }
+
+    /// Records the light-binning compute pass. Must run before the mesh pass.
+    pub fn cull(&self, pipelines: &PipelinesHandle, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, camera_bind_group: &wgpu::BindGroup) {
+        let pipeline = pipelines.lock().compute_pipeline(device, &self.cull_pipeline);
+        let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Cluster Cull Pass"), timestamp_writes: None });
+        cp.set_pipeline(&pipeline);
+        cp.set_bind_group(0, camera_bind_group, &[]);
+        cp.set_bind_group(1, &self.cull_bind_group, &[]);
+        cp.dispatch_workgroups(self.config.cluster_count().div_ceil(64), 1, 1);
+    }
+}
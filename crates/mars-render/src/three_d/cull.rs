@@ -0,0 +1,49 @@
+use mars_core::bounds::{Frustum, Sphere};
+use rayon::prelude::*;
+
+/// Below this many objects the rayon fan-out costs more than it saves.
+const PAR_MIN_LEN: usize = 256;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+    pub tested: u32,
+    pub visible: u32,
+}
+
+/// CPU frustum culler. Tests world-space bounding spheres in parallel and
+/// keeps the visibility mask around to avoid reallocating every frame.
+#[derive(Default)]
+pub struct FrustumCuller {
+    mask: Vec<bool>,
+    stats: CullStats,
+}
+
+impl FrustumCuller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns one visibility flag per item, in order.
+    pub fn cull<T: Sync>(
+        &mut self,
+        frustum: &Frustum,
+        items: &[T],
+        bounds: impl Fn(&T) -> Sphere + Sync,
+    ) -> &[bool] {
+        items
+            .par_iter()
+            .with_min_len(PAR_MIN_LEN)
+            .map(|item| frustum.intersects_sphere(&bounds(item)))
+            .collect_into_vec(&mut self.mask);
+
+        self.stats = CullStats {
+            tested: items.len() as u32,
+            visible: self.mask.iter().filter(|v| **v).count() as u32,
+        };
+        &self.mask
+    }
+
+    pub fn stats(&self) -> CullStats {
+        self.stats
+    }
+}
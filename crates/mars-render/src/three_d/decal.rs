@@ -0,0 +1,666 @@
+//! Box-projected decals.
+//!
+//! A decal is an oriented box. Every scene pixel whose prepass depth
+//! reconstructs to a position inside the box takes the decal's texture,
+//! projected along the box's local Y axis onto whatever surface is there:
+//! bullet holes on walls, blood on floors, signage on props. Decals are
+//! blended over the shaded scene after the mesh pass, so they are not lit;
+//! [`DecalBlend::Multiply`] darkens the lit surface instead, which suits
+//! dirt and damage.
+//!
+//! Game code spawns decals through a [`Decals`] handle, which owns a fixed
+//! pool: when it is full the oldest decal makes room, and decals with a
+//! lifetime fade out and free their slot on their own.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Quat, Vec3};
+use mars_asset::texture::{TextureData, TextureUsage};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::binding::{self, BindGroupBuilder, BindGroupCache};
+use crate::graph::{NodeContext, RenderNode, SCENE_COLOR};
+use crate::pipeline::{ColorTarget, PipelinesHandle, RenderPipelineDesc, VertexLayout};
+use crate::shader::ShaderDefs;
+use crate::texture::{Texture, TextureLoader};
+use super::mesh::{MeshData, Vertex3d};
+use super::prepass;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecalBlend {
+    /// Covers the surface by the texture's alpha.
+    #[default]
+    Alpha,
+    /// Multiplies the shaded surface by the texture's color, faded by alpha.
+    Multiply,
+}
+
+/// A kind of decal, as stored in scene files.
+///
+/// ```ron
+/// (
+///     texture: "assets/decals/bullet_hole.png",
+///     size: (0.2, 0.1, 0.2),
+///     blend: Multiply,
+///     lifetime: Some(30.0),
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecalDesc {
+    /// Image projected onto surfaces, authored in sRGB.
+    pub texture: String,
+    /// Linear color and alpha multiplied with the texture.
+    pub tint: [f32; 4],
+    /// Width (local X), projection depth (local Y) and height (local Z) of
+    /// the box, before the instance's own transform.
+    pub size: Vec3,
+    pub blend: DecalBlend,
+    /// Angles in degrees between a surface's normal and the decal's Y axis
+    /// over which the decal fades out, so it does not smear across
+    /// surfaces it meets edge-on.
+    pub angle_fade: [f32; 2],
+    /// Order among overlapping decals; higher layers are drawn on top, and
+    /// newer decals on top within a layer.
+    pub layer: i32,
+    /// Seconds until the decal expires; `None` keeps it until despawned or
+    /// evicted from a full pool.
+    pub lifetime: Option<f32>,
+    /// Seconds before expiry over which the decal fades out.
+    pub fade_out: f32,
+}
+
+impl Default for DecalDesc {
+    fn default() -> Self {
+        Self {
+            texture: String::new(),
+            tint: [1.0; 4],
+            size: Vec3::ONE,
+            blend: DecalBlend::Alpha,
+            angle_fade: [60.0, 85.0],
+            layer: 0,
+            lifetime: None,
+            fade_out: 1.0,
+        }
+    }
+}
+
+impl DecalDesc {
+    pub fn from_ron(src: &str) -> Result<Self> {
+        ron::from_str(src).context("parse decal description")
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).context("serialize decal description")
+    }
+
+    /// Loads [`DecalDesc::texture`], relative to `base`.
+    pub fn load_texture(&self, base: impl AsRef<Path>) -> Result<TextureData> {
+        let path = base.as_ref().join(&self.texture);
+        Ok(TextureData::load(&path).with_context(|| format!("decal texture {}", path.display()))?.with_usage(TextureUsage::Color))
+    }
+
+    /// Opacity at `age` seconds from the lifetime fade.
+    fn opacity(&self, age: f32) -> f32 {
+        match self.lifetime {
+            Some(lifetime) if self.fade_out > 0.0 => ((lifetime - age) / self.fade_out).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// A transform placing a decal at `position` on a surface with `normal`,
+/// turned by `angle` radians about it. The texture's top edge points along
+/// the projection of -Z (or -X for surfaces facing up or down).
+pub fn facing(position: Vec3, normal: Vec3, angle: f32) -> Mat4 {
+    let normal = normal.normalize_or(Vec3::Y);
+    let align = Quat::from_rotation_arc(Vec3::Y, normal);
+    Mat4::from_rotation_translation(align * Quat::from_rotation_y(angle), position)
+}
+
+/// A kind registered with one [`Decals`] pool; other pools reject it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecalKind {
+    pool: u32,
+    index: u32,
+}
+
+/// A spawned decal; stale once it expired, was despawned or was evicted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecalId {
+    index: u32,
+    generation: u32,
+}
+
+struct LiveDecal {
+    kind: DecalKind,
+    transform: Mat4,
+    age: f32,
+    sequence: u64,
+}
+
+struct Slot {
+    generation: u32,
+    decal: Option<LiveDecal>,
+}
+
+struct KindEntry {
+    desc: DecalDesc,
+    /// Taken by the node when it uploads the texture.
+    pending: Option<TextureData>,
+    texel_size: (u32, u32),
+}
+
+/// Source of [`DecalPool::id`].
+static NEXT_POOL: AtomicU32 = AtomicU32::new(0);
+
+struct DecalPool {
+    /// Tags the pool's [`DecalKind`]s.
+    id: u32,
+    kinds: Vec<KindEntry>,
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    live: usize,
+    next_sequence: u64,
+}
+
+impl DecalPool {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            id: NEXT_POOL.fetch_add(1, Ordering::Relaxed),
+            kinds: Vec::new(),
+            slots: (0..capacity).map(|_| Slot { generation: 0, decal: None }).collect(),
+            free: (0..capacity as u32).rev().collect(),
+            live: 0,
+            next_sequence: 0,
+        }
+    }
+
+    fn kind(&self, kind: DecalKind) -> Option<&KindEntry> {
+        (kind.pool == self.id).then(|| self.kinds.get(kind.index as usize)).flatten()
+    }
+
+    fn get_mut(&mut self, id: DecalId) -> Option<&mut LiveDecal> {
+        self.slots.get_mut(id.index as usize).filter(|s| s.generation == id.generation)?.decal.as_mut()
+    }
+
+    fn release(&mut self, index: u32) {
+        let slot = &mut self.slots[index as usize];
+        if slot.decal.take().is_some() {
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(index);
+            self.live -= 1;
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        for index in 0..self.slots.len() as u32 {
+            let Some(decal) = self.slots[index as usize].decal.as_mut() else { continue };
+            decal.age += dt;
+            let lifetime = self.kinds[decal.kind.index as usize].desc.lifetime;
+            if lifetime.is_some_and(|l| decal.age >= l) {
+                self.release(index);
+            }
+        }
+    }
+}
+
+/// Spawns and tracks decals for a [`DecalNode`]; clones share the pool.
+#[derive(Clone)]
+pub struct Decals(Arc<Mutex<DecalPool>>);
+
+impl Decals {
+    fn pool(&self) -> std::sync::MutexGuard<'_, DecalPool> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers a kind of decal; `texture` is uploaded by the node on its
+    /// next run.
+    pub fn add_kind(&self, desc: DecalDesc, texture: TextureData) -> DecalKind {
+        let mut pool = self.pool();
+        let texel_size = (texture.width, texture.height);
+        pool.kinds.push(KindEntry { desc, pending: Some(texture), texel_size });
+        DecalKind { pool: pool.id, index: pool.kinds.len() as u32 - 1 }
+    }
+
+    /// The description of `kind`, or `None` if it belongs to another pool.
+    pub fn desc(&self, kind: DecalKind) -> Option<DecalDesc> {
+        self.pool().kind(kind).map(|k| k.desc.clone())
+    }
+
+    /// Places a decal of `kind`; `transform` positions, orients and scales
+    /// its box of [`DecalDesc::size`], see [`facing`]. Evicts the oldest
+    /// decal if the pool is full. Returns `None` if `kind` belongs to
+    /// another pool.
+    pub fn spawn(&self, kind: DecalKind, transform: Mat4) -> Option<DecalId> {
+        let mut pool = self.pool();
+        pool.kind(kind)?;
+        let index = match pool.free.pop() {
+            Some(index) => index,
+            None => {
+                let oldest = pool
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, s)| Some((i, s.decal.as_ref()?.sequence)))
+                    .min_by_key(|&(_, sequence)| sequence)
+                    .map(|(i, _)| i as u32)
+                    .expect("a full pool has live decals");
+                pool.release(oldest);
+                pool.free.pop().expect("released above")
+            }
+        };
+        let sequence = pool.next_sequence;
+        pool.next_sequence += 1;
+        pool.live += 1;
+        let slot = &mut pool.slots[index as usize];
+        slot.decal = Some(LiveDecal { kind, transform, age: 0.0, sequence });
+        Some(DecalId { index, generation: slot.generation })
+    }
+
+    /// Removes a decal; returns whether it was still alive.
+    pub fn despawn(&self, id: DecalId) -> bool {
+        let mut pool = self.pool();
+        if pool.get_mut(id).is_none() {
+            return false;
+        }
+        pool.release(id.index);
+        true
+    }
+
+    pub fn is_alive(&self, id: DecalId) -> bool {
+        self.pool().get_mut(id).is_some()
+    }
+
+    /// Moves a live decal, e.g. one stuck to a moving object.
+    pub fn set_transform(&self, id: DecalId, transform: Mat4) -> bool {
+        self.pool().get_mut(id).map(|d| d.transform = transform).is_some()
+    }
+
+    pub fn clear(&self) {
+        let mut pool = self.pool();
+        for index in 0..pool.slots.len() as u32 {
+            pool.release(index);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool().live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.pool().slots.len()
+    }
+}
+
+/// Per-decal vertex data, after the box's [`Vertex3d`] attributes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct DecalInstance {
+    model: [[f32; 4]; 4],
+    inv_model: [[f32; 4]; 4],
+    tint: [f32; 4],
+    /// World-space projection axis, and texels per world unit in w.
+    axis: [f32; 4],
+    /// Cosines where the angle fade starts and ends, lifetime opacity.
+    fade: [f32; 4],
+}
+
+impl DecalInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 11] = wgpu::vertex_attr_array![
+        3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4,
+        7 => Float32x4, 8 => Float32x4, 9 => Float32x4, 10 => Float32x4,
+        11 => Float32x4, 12 => Float32x4, 13 => Float32x4
+    ];
+}
+
+/// Draws the decals of a [`Decals`] pool onto [`SCENE_COLOR`] (unless
+/// retargeted) and ages them by the graph's `dt`.
+///
+/// Needs [`MeshRenderer::prepass`](super::MeshRenderer::prepass) to have run
+/// earlier in the frame; the decal boxes are drawn back faces only, so the
+/// camera may be inside one.
+pub struct DecalNode {
+    pub enabled: bool,
+    decals: Decals,
+    target: &'static str,
+    frame_groups: BindGroupCache,
+    texture_groups: BindGroupCache,
+    /// Entries of the frame and texture bind groups.
+    group_entries: [Vec<wgpu::BindGroupLayoutEntry>; 2],
+    /// Per target format, for alpha and multiply blending; valid for
+    /// `pipelines`.
+    descs: Vec<(wgpu::TextureFormat, [RenderPipelineDesc; 2])>,
+    pipelines: Option<PipelinesHandle>,
+    loader: TextureLoader,
+    textures: Vec<Option<Texture>>,
+    sampler: wgpu::Sampler,
+    cube: (wgpu::Buffer, wgpu::Buffer, u32),
+    /// Sized for the whole pool.
+    instances: wgpu::Buffer,
+    /// Sort keys and instance data of this frame's decals.
+    draw_list: Vec<((i32, u64), DecalKind, DecalInstance)>,
+}
+
+impl DecalNode {
+    /// A node drawing a pool of `capacity` decals.
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let frame_entries = vec![
+            binding::buffer_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT, wgpu::BufferBindingType::Uniform),
+            binding::unfilterable_entry(1, fragment),
+            binding::unfilterable_entry(2, fragment),
+        ];
+        let texture_entries = vec![binding::texture_entry(0, fragment), binding::sampler_entry(1, fragment, wgpu::SamplerBindingType::Filtering)];
+
+        let cube = MeshData::cube(0.5);
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Decal Box Vertices"),
+            contents: bytemuck::cast_slice(&cube.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Decal Box Indices"),
+            contents: bytemuck::cast_slice(&cube.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let capacity = capacity.max(1);
+        let decals = Decals(Arc::new(Mutex::new(DecalPool::new(capacity))));
+        Self {
+            enabled: true,
+            decals,
+            target: SCENE_COLOR,
+            frame_groups: BindGroupCache::new("Decal Frame", 4),
+            texture_groups: BindGroupCache::new("Decal Texture", 64),
+            group_entries: [frame_entries, texture_entries],
+            descs: Vec::new(),
+            pipelines: None,
+            loader: TextureLoader::new(device),
+            textures: Vec::new(),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Decal Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            cube: (vertices, indices, cube.indices.len() as u32),
+            instances: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Decal Instances"),
+                size: (capacity * size_of::<DecalInstance>()) as u64,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            draw_list: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Reads and writes the named graph texture instead.
+    pub fn with_target(mut self, target: &'static str) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn decals(&self) -> &Decals {
+        &self.decals
+    }
+
+    fn pipelines(&mut self, pipelines: &PipelinesHandle, device: &wgpu::Device, format: wgpu::TextureFormat) -> [wgpu::RenderPipeline; 2] {
+        // Shader ids belong to one library.
+        if !self.pipelines.as_ref().is_some_and(|p| p.ptr_eq(pipelines)) {
+            self.pipelines = Some(pipelines.clone());
+            self.descs.clear();
+        }
+        let mut pipelines = pipelines.lock();
+        let i = match self.descs.iter().position(|(f, _)| *f == format) {
+            Some(i) => i,
+            None => {
+                let multiply = wgpu::BlendState {
+                    color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Dst, dst_factor: wgpu::BlendFactor::Zero, operation: wgpu::BlendOperation::Add },
+                    alpha: wgpu::BlendComponent::OVER,
+                };
+                let mut desc = |label, defs: &ShaderDefs, blend| {
+                    let shader = pipelines.builtin(device, "mars/three_d/decal.wgsl", defs);
+                    RenderPipelineDesc::new(label, shader)
+                        .with_bind_group(&self.group_entries[0])
+                        .with_bind_group(&self.group_entries[1])
+                        .with_vertex_buffer(Vertex3d::layout().into())
+                        .with_vertex_buffer(VertexLayout::new(size_of::<DecalInstance>() as u64, &DecalInstance::ATTRIBS).per_instance())
+                        .with_target(ColorTarget { write_mask: wgpu::ColorWrites::COLOR, ..ColorTarget::new(format).with_blend(blend) })
+                        .with_primitive(wgpu::PrimitiveState { cull_mode: Some(wgpu::Face::Front), ..Default::default() })
+                };
+                let descs = [
+                    desc("Decal Pipeline", &ShaderDefs::new(), wgpu::BlendState::ALPHA_BLENDING),
+                    desc("Decal Multiply Pipeline", &ShaderDefs::new().with("MULTIPLY"), multiply),
+                ];
+                self.descs.push((format, descs));
+                self.descs.len() - 1
+            }
+        };
+        self.descs[i].1.each_ref().map(|desc| pipelines.render_pipeline(device, desc))
+    }
+
+    /// Uploads textures of newly added kinds, ages the pool and collects
+    /// the live decals in drawing order.
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, dt: f32) {
+        let mut pool = self.decals.pool();
+        for (i, kind) in pool.kinds.iter_mut().enumerate() {
+            if let Some(data) = kind.pending.take() {
+                self.textures.resize_with(self.textures.len().max(i + 1), || None);
+                self.textures[i] = Some(self.loader.from_data(device, queue, &data, &format!("Decal {}", kind.desc.texture)));
+            }
+        }
+        pool.advance(dt);
+
+        self.draw_list.clear();
+        for decal in pool.slots.iter().filter_map(|s| s.decal.as_ref()) {
+            let kind = &pool.kinds[decal.kind.index as usize];
+            let desc = &kind.desc;
+            let model = decal.transform * Mat4::from_scale(desc.size);
+            let axis = model.y_axis.truncate().normalize_or(Vec3::Y);
+            // Texels per world unit across the box, for picking a mip.
+            let extent = model.x_axis.truncate().length().min(model.z_axis.truncate().length()).max(1e-4);
+            let texels = kind.texel_size.0.max(kind.texel_size.1) as f32 / extent;
+            let [start, end] = desc.angle_fade.map(|deg| deg.to_radians().cos());
+            self.draw_list.push((
+                (desc.layer, decal.sequence),
+                decal.kind,
+                DecalInstance {
+                    model: model.to_cols_array_2d(),
+                    inv_model: model.inverse().to_cols_array_2d(),
+                    tint: desc.tint,
+                    axis: axis.extend(texels).to_array(),
+                    fade: [start, end, desc.opacity(decal.age), 0.0],
+                },
+            ));
+        }
+        drop(pool);
+        self.draw_list.sort_unstable_by_key(|(key, ..)| *key);
+    }
+}
+
+impl RenderNode for DecalNode {
+    fn name(&self) -> &'static str {
+        "decals"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        self.prepare(ctx.device, ctx.queue, ctx.dt);
+        if !self.enabled || self.draw_list.is_empty() {
+            return Ok(());
+        }
+        let depth = ctx.resources.texture(prepass::DEPTH).context(prepass::MISSING)?.view.clone();
+        let normals = ctx.resources.texture(prepass::NORMALS).context(prepass::MISSING)?.view.clone();
+        let camera = ctx.resources.buffer(prepass::CAMERA).context(prepass::MISSING)?.clone();
+        let target = ctx.resources.texture(self.target).with_context(|| format!("decal target `{}` missing", self.target))?;
+        let (target, format) = (target.view.clone(), target.desc.format);
+
+        let instances: Vec<DecalInstance> = self.draw_list.iter().map(|(_, _, instance)| *instance).collect();
+        ctx.queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(&instances));
+
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let frame_group = self
+            .frame_groups
+            .get(
+                ctx.device,
+                BindGroupBuilder::new()
+                    .uniform(0, wgpu::ShaderStages::VERTEX_FRAGMENT, &camera)
+                    .entry(binding::unfilterable_entry(1, fragment), binding::BoundResource::TextureView(depth))
+                    .entry(binding::unfilterable_entry(2, fragment), binding::BoundResource::TextureView(normals)),
+            )
+            .clone();
+        // Consecutive decals of one kind are drawn together.
+        let mut runs: Vec<(DecalKind, std::ops::Range<u32>)> = Vec::new();
+        for (i, (_, kind, _)) in self.draw_list.iter().enumerate() {
+            match runs.last_mut() {
+                Some((last, range)) if last == kind => range.end = i as u32 + 1,
+                _ => runs.push((*kind, i as u32..i as u32 + 1)),
+            }
+        }
+        let mut draws = Vec::with_capacity(runs.len());
+        for (kind, range) in runs {
+            let Some(texture) = self.textures.get(kind.index as usize).and_then(Option::as_ref) else { continue };
+            let group = self
+                .texture_groups
+                .get(ctx.device, BindGroupBuilder::new().texture(0, fragment, &texture.view).sampler(1, fragment, wgpu::SamplerBindingType::Filtering, &self.sampler))
+                .clone();
+            let multiply = self.decals.pool().kinds[kind.index as usize].desc.blend == DecalBlend::Multiply;
+            draws.push((group, usize::from(multiply), range));
+        }
+        let pipelines = self.pipelines(ctx.pipelines, ctx.device, format);
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Decals") });
+        {
+            let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Decal Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rp.set_bind_group(0, &frame_group, &[]);
+            rp.set_vertex_buffer(0, self.cube.0.slice(..));
+            rp.set_index_buffer(self.cube.1.slice(..), wgpu::IndexFormat::Uint32);
+            rp.set_vertex_buffer(1, self.instances.slice(..));
+            for (group, pipeline, range) in draws {
+                rp.set_pipeline(&pipelines[pipeline]);
+                rp.set_bind_group(1, &group, &[]);
+                rp.draw_indexed(0..self.cube.2, 0, range);
+            }
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mars_asset::texture::{ColorSpace, TexelFormat};
+
+    use super::*;
+
+    fn texture() -> TextureData {
+        TextureData { width: 1, height: 1, format: TexelFormat::Rgba8, color_space: ColorSpace::Srgb, data: vec![255; 4] }
+    }
+
+    fn decals(capacity: usize) -> Decals {
+        Decals(Arc::new(Mutex::new(DecalPool::new(capacity))))
+    }
+
+    #[test]
+    fn full_pool_evicts_the_oldest() {
+        let decals = decals(2);
+        let kind = decals.add_kind(DecalDesc::default(), texture());
+        let [a, b, c] = [0.0, 1.0, 2.0].map(|x| decals.spawn(kind, Mat4::from_translation(Vec3::X * x)).unwrap());
+        assert!(!decals.is_alive(a));
+        assert!(decals.is_alive(b) && decals.is_alive(c));
+        assert_eq!(decals.len(), 2);
+
+        // The evicted id stays stale even though its slot was reused.
+        assert!(!decals.despawn(a));
+        assert!(!decals.set_transform(a, Mat4::IDENTITY));
+        assert!(decals.despawn(b));
+        assert!(!decals.is_alive(b));
+        assert_eq!(decals.len(), 1);
+    }
+
+    #[test]
+    fn expired_decals_free_their_slot() {
+        let decals = decals(4);
+        let desc = DecalDesc { lifetime: Some(1.0), ..Default::default() };
+        let kind = decals.add_kind(desc, texture());
+        let id = decals.spawn(kind, Mat4::IDENTITY).unwrap();
+        decals.pool().advance(0.5);
+        assert!(decals.is_alive(id));
+        decals.pool().advance(0.5);
+        assert!(!decals.is_alive(id));
+        assert!(!decals.despawn(id));
+        assert!(decals.is_empty());
+
+        let next = decals.spawn(kind, Mat4::IDENTITY).unwrap();
+        assert_ne!(next, id);
+        assert!(!decals.is_alive(id));
+    }
+
+    #[test]
+    fn opacity_fades_before_expiry() {
+        let desc = DecalDesc { lifetime: Some(2.0), fade_out: 0.5, ..Default::default() };
+        assert_eq!(desc.opacity(0.0), 1.0);
+        assert_eq!(desc.opacity(1.5), 1.0);
+        assert_eq!(desc.opacity(1.75), 0.5);
+        assert_eq!(desc.opacity(2.0), 0.0);
+        assert_eq!(DecalDesc { fade_out: 0.0, ..desc.clone() }.opacity(1.9), 1.0);
+        assert_eq!(DecalDesc::default().opacity(1e6), 1.0);
+    }
+
+    #[test]
+    fn kinds_of_other_pools_are_rejected() {
+        let (a, b) = (decals(1), decals(1));
+        let kind = a.add_kind(DecalDesc::default(), texture());
+        b.add_kind(DecalDesc { layer: 1, ..Default::default() }, texture());
+        assert_eq!(a.desc(kind), Some(DecalDesc::default()));
+        assert_eq!(b.desc(kind), None);
+        assert_eq!(b.spawn(kind, Mat4::IDENTITY), None);
+        assert!(b.is_empty());
+    }
+
+    #[test]
+    fn ron_omits_defaults_and_round_trips() {
+        let desc = DecalDesc::from_ron(
+            r#"(
+                texture: "assets/decals/bullet_hole.png",
+                size: (0.2, 0.1, 0.2),
+                blend: Multiply,
+                lifetime: Some(30.0),
+            )"#,
+        )
+        .unwrap();
+        let expected = DecalDesc {
+            texture: "assets/decals/bullet_hole.png".into(),
+            size: Vec3::new(0.2, 0.1, 0.2),
+            blend: DecalBlend::Multiply,
+            lifetime: Some(30.0),
+            ..Default::default()
+        };
+        assert_eq!(desc, expected);
+        assert_eq!(DecalDesc::from_ron("()").unwrap(), DecalDesc::default());
+        assert_eq!(DecalDesc::from_ron(&desc.to_ron().unwrap()).unwrap(), desc);
+        assert!(DecalDesc::from_ron("(blend: Additive)").is_err());
+    }
+}
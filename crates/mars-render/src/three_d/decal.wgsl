@@ -0,0 +1,87 @@
+// Box-projected decals: each fragment of a decal box's back faces
+// reconstructs the scene position under it from the prepass depth and, if
+// that lies inside the box, projects the decal texture onto it.
+
+#import mars::three_d::screen_common
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var depth_tex: texture_2d<f32>;
+@group(0) @binding(2) var normal_tex: texture_2d<f32>;
+@group(1) @binding(0) var decal_tex: texture_2d<f32>;
+@group(1) @binding(1) var decal_sampler: sampler;
+
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) @interpolate(flat) inv0: vec4<f32>,
+  @location(1) @interpolate(flat) inv1: vec4<f32>,
+  @location(2) @interpolate(flat) inv2: vec4<f32>,
+  @location(3) @interpolate(flat) inv3: vec4<f32>,
+  @location(4) @interpolate(flat) tint: vec4<f32>,
+  @location(5) @interpolate(flat) axis: vec4<f32>,
+  @location(6) @interpolate(flat) fade: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+  @location(0) position: vec3<f32>,
+  @location(3) m0: vec4<f32>,
+  @location(4) m1: vec4<f32>,
+  @location(5) m2: vec4<f32>,
+  @location(6) m3: vec4<f32>,
+  @location(7) inv0: vec4<f32>,
+  @location(8) inv1: vec4<f32>,
+  @location(9) inv2: vec4<f32>,
+  @location(10) inv3: vec4<f32>,
+  @location(11) tint: vec4<f32>,
+  @location(12) axis: vec4<f32>,
+  @location(13) fade: vec4<f32>,
+) -> VsOut {
+  let model = mat4x4<f32>(m0, m1, m2, m3);
+  var out: VsOut;
+  out.clip = camera.view_proj * model * vec4<f32>(position, 1.0);
+  out.inv0 = inv0;
+  out.inv1 = inv1;
+  out.inv2 = inv2;
+  out.inv3 = inv3;
+  out.tint = tint;
+  out.axis = axis;
+  out.fade = fade;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let target_size = vec2<f32>(textureDimensions(depth_tex));
+  let pixel = vec2<i32>(in.clip.xy);
+  let depth = textureLoad(depth_tex, pixel, 0).r;
+  let uv = in.clip.xy / target_size;
+
+  let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+  let world = camera.inv_view_proj * ndc;
+  let inv_model = mat4x4<f32>(in.inv0, in.inv1, in.inv2, in.inv3);
+  let local = (inv_model * vec4<f32>(world.xyz / world.w, 1.0)).xyz;
+
+  // Fade out on surfaces turned away from the projection axis.
+  let n_view = oct_decode(textureLoad(normal_tex, pixel, 0).xy);
+  let n_world = transpose(mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz)) * n_view;
+  let facing = dot(normalize(n_world), in.axis.xyz);
+  let angle_fade = clamp((facing - in.fade.y) / max(in.fade.x - in.fade.y, 1e-4), 0.0, 1.0);
+
+  // The mip follows the size of a pixel at this depth rather than uv
+  // derivatives, which jump where the box crosses depth discontinuities.
+  let view_z = -view_position(uv, depth, camera.proj).z;
+  let pixel_world = 2.0 * view_z / (camera.proj[1][1] * target_size.y);
+  let lod = log2(max(pixel_world * in.axis.w, 1.0));
+  let color = textureSampleLevel(decal_tex, decal_sampler, vec2<f32>(local.x + 0.5, local.z + 0.5), lod) * in.tint;
+
+  if (depth >= 1.0 || any(abs(local) > vec3<f32>(0.5))) {
+    discard;
+  }
+  let alpha = color.a * angle_fade * in.fade.z;
+#ifdef MULTIPLY
+  // Multiply blending: the output is the factor applied to the scene color.
+  return vec4<f32>(mix(vec3<f32>(1.0), color.rgb, alpha), 1.0);
+#else
+  return vec4<f32>(color.rgb, alpha);
+#endif
+}
@@ -0,0 +1,508 @@
+//! Cubemaps, skybox environment and image-based lighting bakes.
+//!
+//! An [`Environment`] bundles the skybox cubemap with the maps the PBR shader
+//! needs for ambient light: a diffuse irradiance cube, a roughness-prefiltered
+//! specular cube (one roughness step per mip) and the split-sum BRDF LUT.
+//! [`IblBaker`] produces all of them on the GPU from a source cubemap.
+
+use anyhow::{bail, Result};
+use bytemuck::{Pod, Zeroable};
+use mars_asset::texture::{TexelFormat, TextureData};
+use wgpu::util::DeviceExt;
+
+use crate::pipeline::{ComputePipelineDesc, Pipelines, PipelinesHandle};
+use crate::shader::ShaderDefs;
+use crate::texture::{self, Texture};
+
+pub const IBL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub struct Cubemap {
+    pub texture: wgpu::Texture,
+    /// Cube view over all mips.
+    pub view: wgpu::TextureView,
+    pub size: u32,
+    pub mip_levels: u32,
+    pub format: wgpu::TextureFormat,
+}
+
+impl Cubemap {
+    pub fn new(
+        device: &wgpu::Device,
+        size: u32,
+        mip_levels: u32,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        Self { texture, view, size, mip_levels, format }
+    }
+
+    /// Builds a cubemap from six square faces in +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn from_faces(device: &wgpu::Device, queue: &wgpu::Queue, faces: &[TextureData; 6]) -> Result<Self> {
+        let first = &faces[0];
+        if first.width != first.height {
+            bail!("cubemap faces must be square, got {}x{}", first.width, first.height);
+        }
+        if faces.iter().any(|f| f.width != first.width || f.height != first.height || f.format != first.format) {
+            bail!("cubemap faces must share size and format");
+        }
+
+        let cube = Self::new(
+            device,
+            first.width,
+            1,
+            Texture::format_for(first),
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            "Cubemap",
+        );
+        for (layer, face) in faces.iter().enumerate() {
+            texture::write_layer(queue, &cube.texture, face, layer as u32);
+        }
+        Ok(cube)
+    }
+
+    /// 2D array view of one mip, for compute writes.
+    pub fn mip_view(&self, mip: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cubemap Mip View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct EnvParams {
+    pub intensity: f32,
+    pub max_lod: f32,
+    pub _pad: [f32; 2],
+}
+
+pub struct Environment {
+    pub skybox: Cubemap,
+    pub irradiance: Cubemap,
+    pub specular: Cubemap,
+    pub brdf_lut: Texture,
+    pub intensity: f32,
+}
+
+impl Environment {
+    /// Flat ambient environment with no directional detail; used until a real
+    /// environment is baked.
+    pub fn uniform(device: &wgpu::Device, queue: &wgpu::Queue, color: [f32; 3]) -> Self {
+        let texel = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let face = TextureData {
+            width: 1,
+            height: 1,
+            format: TexelFormat::Rgba8,
+            color_space: mars_asset::texture::ColorSpace::Linear,
+            data: vec![texel[0], texel[1], texel[2], 255],
+        };
+        let faces = std::array::from_fn(|_| face.clone());
+        let cube = || Cubemap::from_faces(device, queue, &faces).expect("1x1 faces are valid");
+
+        // Scale 1, bias 0: ambient specular reduces to prefiltered * F0.
+        let lut = TextureData { data: vec![255, 0, 0, 255], ..face.clone() };
+        Self {
+            skybox: cube(),
+            irradiance: cube(),
+            specular: cube(),
+            brdf_lut: Texture::from_data(device, queue, &lut, "BRDF LUT"),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn params(&self) -> EnvParams {
+        EnvParams {
+            intensity: self.intensity,
+            max_lod: self.specular.mip_levels.saturating_sub(1) as f32,
+            _pad: [0.0; 2],
+        }
+    }
+
+    /// Layout entries of the bind group made by [`Environment::create_bind_group`].
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 6] {
+        let tex = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        [
+            tex(0, wgpu::TextureViewDimension::Cube),
+            tex(1, wgpu::TextureViewDimension::Cube),
+            tex(2, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            tex(4, wgpu::TextureViewDimension::Cube),
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment BGL"),
+            entries: &Self::layout_entries(),
+        })
+    }
+
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        params: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment BG"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&self.irradiance.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&self.specular.view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&self.brdf_lut.view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::Sampler(sampler) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&self.skybox.view) },
+                wgpu::BindGroupEntry { binding: 5, resource: params.as_entire_binding() },
+            ],
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IblSettings {
+    pub irradiance_size: u32,
+    pub specular_size: u32,
+    /// Capped at the number of mips `specular_size` allows.
+    pub specular_mips: u32,
+    pub specular_samples: u32,
+    pub brdf_lut_size: u32,
+}
+
+impl Default for IblSettings {
+    fn default() -> Self {
+        Self { irradiance_size: 32, specular_size: 128, specular_mips: 6, specular_samples: 512, brdf_lut_size: 256 }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PrefilterParams {
+    roughness: f32,
+    sample_count: u32,
+    _pad: [f32; 2],
+}
+
+/// Compute pipelines for converting and convolving environment maps.
+pub struct IblBaker {
+    equirect_layout: wgpu::BindGroupLayout,
+    equirect: wgpu::ComputePipeline,
+    irradiance_layout: wgpu::BindGroupLayout,
+    irradiance: wgpu::ComputePipeline,
+    prefilter_layout: wgpu::BindGroupLayout,
+    prefilter: wgpu::ComputePipeline,
+    brdf_layout: wgpu::BindGroupLayout,
+    brdf: wgpu::ComputePipeline,
+    sampler: wgpu::Sampler,
+}
+
+fn storage_entry(binding: u32, view_dimension: wgpu::TextureViewDimension) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: IBL_FORMAT,
+            view_dimension,
+        },
+        count: None,
+    }
+}
+
+fn cube_source_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                multisampled: false,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+/// Builds one bake pipeline from the shader library, returning it with its
+/// bind group layout.
+fn compute_pipeline(
+    device: &wgpu::Device,
+    pipelines: &mut Pipelines,
+    label: &str,
+    shader: &str,
+    entries: &[wgpu::BindGroupLayoutEntry],
+) -> (wgpu::BindGroupLayout, wgpu::ComputePipeline) {
+    let shader = pipelines.builtin(device, shader, &ShaderDefs::new());
+    let desc = ComputePipelineDesc::new(label, shader, "main").with_bind_group(entries);
+    (pipelines.bind_group_layout(device, entries), pipelines.compute_pipeline(device, &desc))
+}
+
+fn groups(size: u32) -> u32 {
+    size.div_ceil(8)
+}
+
+impl IblBaker {
+    /// Builds the bake pipelines through `pipelines`, usually
+    /// [`MeshRenderer::pipelines`](super::MeshRenderer::pipelines).
+    pub fn new(device: &wgpu::Device, pipelines: &PipelinesHandle) -> Self {
+        let equirect_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            storage_entry(1, wgpu::TextureViewDimension::D2Array),
+        ];
+        let [src, smp] = cube_source_entries();
+        let irradiance_entries = [src, smp, storage_entry(2, wgpu::TextureViewDimension::D2Array)];
+        let prefilter_entries = [
+            src,
+            smp,
+            storage_entry(2, wgpu::TextureViewDimension::D2Array),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let brdf_entries = [storage_entry(0, wgpu::TextureViewDimension::D2)];
+
+        let mut pipelines = pipelines.lock();
+        let (equirect_layout, equirect) =
+            compute_pipeline(device, &mut pipelines, "Equirect To Cube", "mars/three_d/ibl_equirect.wgsl", &equirect_entries);
+        let (irradiance_layout, irradiance) =
+            compute_pipeline(device, &mut pipelines, "Irradiance Bake", "mars/three_d/ibl_irradiance.wgsl", &irradiance_entries);
+        let (prefilter_layout, prefilter) =
+            compute_pipeline(device, &mut pipelines, "Specular Prefilter", "mars/three_d/ibl_prefilter.wgsl", &prefilter_entries);
+        let (brdf_layout, brdf) = compute_pipeline(device, &mut pipelines, "BRDF LUT Bake", "mars/three_d/ibl_brdf.wgsl", &brdf_entries);
+
+        Self {
+            equirect_layout,
+            equirect,
+            irradiance_layout,
+            irradiance,
+            prefilter_layout,
+            prefilter,
+            brdf_layout,
+            brdf,
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("IBL Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Projects an equirectangular (latitude/longitude) image, usually a
+    /// decoded `.hdr`, onto a `size`² cubemap.
+    pub fn equirect_to_cubemap(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &TextureData,
+        size: u32,
+    ) -> Cubemap {
+        let src = Texture::from_data(device, queue, image, "Equirect Source");
+        let cube = Cubemap::new(
+            device,
+            size,
+            1,
+            IBL_FORMAT,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            "Environment Cubemap",
+        );
+        let dst = cube.mip_view(0);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Equirect BG"),
+            layout: &self.equirect_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&src.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&dst) },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Equirect Encoder") });
+        {
+            let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Equirect Pass"), timestamp_writes: None });
+            cp.set_pipeline(&self.equirect);
+            cp.set_bind_group(0, &bind_group, &[]);
+            cp.dispatch_workgroups(groups(size), groups(size), 6);
+        }
+        queue.submit(Some(encoder.finish()));
+        cube
+    }
+
+    /// Bakes irradiance, prefiltered specular and the BRDF LUT for `skybox`.
+    pub fn bake(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        skybox: Cubemap,
+        settings: &IblSettings,
+    ) -> Environment {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING;
+        let irradiance = Cubemap::new(device, settings.irradiance_size, 1, IBL_FORMAT, usage, "Irradiance Cubemap");
+        let max_mips = 32 - settings.specular_size.max(1).leading_zeros();
+        let mips = settings.specular_mips.clamp(1, max_mips);
+        let specular = Cubemap::new(device, settings.specular_size, mips, IBL_FORMAT, usage, "Specular Cubemap");
+
+        let lut_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF LUT"),
+            size: wgpu::Extent3d { width: settings.brdf_lut_size, height: settings.brdf_lut_size, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: IBL_FORMAT,
+            usage,
+            view_formats: &[],
+        });
+        let brdf_lut = Texture {
+            view: lut_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture: lut_texture,
+            format: IBL_FORMAT,
+            size: (settings.brdf_lut_size, settings.brdf_lut_size),
+            mip_levels: 1,
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("IBL Bake Encoder") });
+
+        let irradiance_dst = irradiance.mip_view(0);
+        let irradiance_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Irradiance BG"),
+            layout: &self.irradiance_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&skybox.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&irradiance_dst) },
+            ],
+        });
+
+        let prefilter_bgs: Vec<_> = (0..mips)
+            .map(|mip| {
+                let roughness = if mips > 1 { mip as f32 / (mips - 1) as f32 } else { 0.0 };
+                let params = PrefilterParams { roughness, sample_count: settings.specular_samples, _pad: [0.0; 2] };
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Prefilter Params"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let dst = specular.mip_view(mip);
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Prefilter BG"),
+                    layout: &self.prefilter_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&skybox.view) },
+                        wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                        wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&dst) },
+                        wgpu::BindGroupEntry { binding: 3, resource: buffer.as_entire_binding() },
+                    ],
+                })
+            })
+            .collect();
+
+        let brdf_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("BRDF LUT BG"),
+            layout: &self.brdf_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&brdf_lut.view) }],
+        });
+
+        {
+            let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("IBL Bake Pass"), timestamp_writes: None });
+
+            cp.set_pipeline(&self.irradiance);
+            cp.set_bind_group(0, &irradiance_bg, &[]);
+            cp.dispatch_workgroups(groups(settings.irradiance_size), groups(settings.irradiance_size), 6);
+
+            cp.set_pipeline(&self.prefilter);
+            for (mip, bg) in prefilter_bgs.iter().enumerate() {
+                let size = (settings.specular_size >> mip).max(1);
+                cp.set_bind_group(0, bg, &[]);
+                cp.dispatch_workgroups(groups(size), groups(size), 6);
+            }
+
+            cp.set_pipeline(&self.brdf);
+            cp.set_bind_group(0, &brdf_bg, &[]);
+            cp.dispatch_workgroups(groups(settings.brdf_lut_size), groups(settings.brdf_lut_size), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Environment { skybox, irradiance, specular, brdf_lut, intensity: 1.0 }
+    }
+
+    /// Loads an equirectangular `.hdr` and bakes a full environment from it.
+    pub fn load_hdr(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<std::path::Path>,
+        cube_size: u32,
+        settings: &IblSettings,
+    ) -> Result<Environment> {
+        let image = TextureData::load(path)?;
+        let skybox = self.equirect_to_cubemap(device, queue, &image, cube_size);
+        Ok(self.bake(device, queue, skybox, settings))
+    }
+}
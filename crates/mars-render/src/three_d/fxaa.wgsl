@@ -0,0 +1,48 @@
+#import mars::fullscreen
+
+struct FxaaParams {
+  span_max: f32,
+  reduce_mul: f32,
+  reduce_min: f32,
+  _pad: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: FxaaParams;
+
+fn fetch(uv: vec2<f32>) -> vec3<f32> {
+  return textureSampleLevel(src, src_sampler, uv, 0.0).rgb;
+}
+
+// Perceptual luma of HDR input: compress with a reversible tonemap first.
+fn fxaa_luma(c: vec3<f32>) -> f32 {
+  let l = luminance(c);
+  return sqrt(l / (1.0 + l));
+}
+
+// FXAA after Lottes, in its compact console form: blur along the edge
+// direction estimated from the four diagonal neighbours.
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let t = 1.0 / vec2<f32>(textureDimensions(src));
+  let center = fetch(in.uv);
+  let nw = fxaa_luma(fetch(in.uv + vec2<f32>(-1.0, -1.0) * t));
+  let ne = fxaa_luma(fetch(in.uv + vec2<f32>(1.0, -1.0) * t));
+  let sw = fxaa_luma(fetch(in.uv + vec2<f32>(-1.0, 1.0) * t));
+  let se = fxaa_luma(fetch(in.uv + vec2<f32>(1.0, 1.0) * t));
+  let m = fxaa_luma(center);
+  let luma_min = min(m, min(min(nw, ne), min(sw, se)));
+  let luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+  var dir = vec2<f32>(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+  let reduce = max((nw + ne + sw + se) * 0.25 * params.reduce_mul, params.reduce_min);
+  let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+  dir = clamp(dir * scale, vec2<f32>(-params.span_max), vec2<f32>(params.span_max)) * t;
+
+  let a = 0.5 * (fetch(in.uv + dir * (1.0 / 3.0 - 0.5)) + fetch(in.uv + dir * (2.0 / 3.0 - 0.5)));
+  let b = a * 0.5 + 0.25 * (fetch(in.uv - dir * 0.5) + fetch(in.uv + dir * 0.5));
+  let luma_b = fxaa_luma(b);
+  if (luma_b < luma_min || luma_b > luma_max) {
+    return finish(a);
+  }
+  return finish(b);
+}
@@ -0,0 +1,119 @@
+// GPU-driven culling: one invocation per object tests its bounding sphere
+// against the frustum and the previous frame's farthest-depth pyramid, then
+// appends the visible ones to their batch's instances and indirect draw.
+
+struct Object {
+  model: mat4x4<f32>,
+  // Local-space bounding sphere: center and radius.
+  sphere: vec4<f32>,
+  batch: u32,
+  first_instance: u32,
+  _pad0: u32,
+  _pad1: u32,
+};
+
+struct DrawArgs {
+  index_count: u32,
+  instance_count: atomic<u32>,
+  first_index: u32,
+  base_vertex: i32,
+  first_instance: u32,
+};
+
+struct Params {
+  // Left, right, bottom, top, near, far; normals point inward.
+  planes: array<vec4<f32>, 6>,
+  // The view-projection the pyramid was rendered with.
+  occlusion_view_proj: mat4x4<f32>,
+  object_count: u32,
+  // Levels of the pyramid; 0 disables the occlusion test.
+  hiz_mips: u32,
+  _pad0: u32,
+  _pad1: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> objects: array<Object>;
+@group(0) @binding(2) var<storage, read_write> args: array<DrawArgs>;
+@group(0) @binding(3) var<storage, read_write> instances: array<mat4x4<f32>>;
+@group(0) @binding(4) var hiz: texture_2d<f32>;
+
+fn in_frustum(center: vec3<f32>, radius: f32) -> bool {
+  for (var i = 0u; i < 6u; i++) {
+    let plane = params.planes[i];
+    if (dot(plane.xyz, center) + plane.w < -radius) {
+      return false;
+    }
+  }
+  return true;
+}
+
+fn hiz_depth(p: vec2<i32>, mip: u32, dims: vec2<i32>) -> f32 {
+  return textureLoad(hiz, clamp(p, vec2<i32>(0), dims - 1), i32(mip)).r;
+}
+
+// Conservative: anything crossing the camera plane or covering more than
+// the pyramid resolves to visible.
+fn occluded(center: vec3<f32>, radius: f32) -> bool {
+  if (params.hiz_mips == 0u) {
+    return false;
+  }
+  // Screen rectangle and nearest depth of the sphere's bounding box.
+  var lo = vec2<f32>(1.0e9);
+  var hi = vec2<f32>(-1.0e9);
+  var nearest = 1.0;
+  for (var i = 0u; i < 8u; i++) {
+    let corner = center + vec3<f32>(
+      select(-radius, radius, (i & 1u) != 0u),
+      select(-radius, radius, (i & 2u) != 0u),
+      select(-radius, radius, (i & 4u) != 0u),
+    );
+    let clip = params.occlusion_view_proj * vec4<f32>(corner, 1.0);
+    if (clip.w <= 1.0e-5) {
+      return false;
+    }
+    let ndc = clip.xyz / clip.w;
+    lo = min(lo, ndc.xy);
+    hi = max(hi, ndc.xy);
+    nearest = min(nearest, ndc.z);
+  }
+  if (nearest <= 0.0) {
+    return false;
+  }
+  let uv_min = clamp(vec2<f32>(lo.x, -hi.y) * 0.5 + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+  let uv_max = clamp(vec2<f32>(hi.x, -lo.y) * 0.5 + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+
+  // The level at which the rectangle spans at most two texels per axis.
+  let extent = (uv_max - uv_min) * vec2<f32>(textureDimensions(hiz, 0));
+  let mip = min(u32(ceil(log2(max(max(extent.x, extent.y), 1.0)))), params.hiz_mips - 1u);
+  let dims = vec2<i32>(textureDimensions(hiz, mip));
+  // Odd sizes make texel coordinates drift between levels by up to one, so
+  // the search widens by a texel on each side.
+  let p0 = vec2<i32>(uv_min * vec2<f32>(dims)) - 1;
+  let p1 = vec2<i32>(uv_max * vec2<f32>(dims)) + 1;
+  var farthest = 0.0;
+  for (var y = p0.y; y <= p1.y; y++) {
+    for (var x = p0.x; x <= p1.x; x++) {
+      farthest = max(farthest, hiz_depth(vec2<i32>(x, y), mip, dims));
+    }
+  }
+  return nearest > farthest;
+}
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let index = gid.x;
+  if (index >= params.object_count) {
+    return;
+  }
+  let object = objects[index];
+  let m = object.model;
+  let center = (m * vec4<f32>(object.sphere.xyz, 1.0)).xyz;
+  let scale = sqrt(max(dot(m[0].xyz, m[0].xyz), max(dot(m[1].xyz, m[1].xyz), dot(m[2].xyz, m[2].xyz))));
+  let radius = object.sphere.w * scale;
+  if (!in_frustum(center, radius) || occluded(center, radius)) {
+    return;
+  }
+  let slot = atomicAdd(&args[object.batch].instance_count, 1u);
+  instances[object.first_instance + slot] = m;
+}
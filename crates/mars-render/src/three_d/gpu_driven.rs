@@ -0,0 +1,422 @@
+//! GPU-driven drawing for large scenes.
+//!
+//! With [`MeshRenderer::set_gpu_driven`](super::MeshRenderer::set_gpu_driven)
+//! the renderer keeps every mesh in one shared vertex and index buffer and
+//! uploads each frame's objects to a storage buffer. A compute pass tests
+//! them against the view frustum and, optionally, the farthest-depth pyramid
+//! of the previous frame, packs the survivors into the instance buffer and
+//! counts them into indirect draw arguments. Batches sharing a pipeline and
+//! material are then drawn with one `multi_draw_indexed_indirect`, or with
+//! one `draw_indexed_indirect` each where the adapter lacks
+//! `MULTI_DRAW_INDIRECT` or `INDIRECT_FIRST_INSTANCE`.
+//!
+//! Occlusion is tested against last frame's depth, so an object coming into
+//! view from behind an occluder can show up a frame late.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use mars_core::bounds::Frustum;
+
+use super::batch::{BatchKey, Batcher, InstanceData};
+use super::hiz::{HiZ, HIZ_FORMAT};
+use super::mesh::{GpuMesh, Vertex3d};
+use crate::binding::{self, BindGroupBuilder, BindGroupCache, BoundResource, StorageBuffer, UniformBuffer};
+use crate::pipeline::{ComputePipelineDesc, PipelinesHandle};
+use crate::shader::ShaderDefs;
+
+const WORKGROUP_SIZE: u32 = 64;
+const ARGS_SIZE: u64 = std::mem::size_of::<DrawArgs>() as u64;
+const INSTANCE_SIZE: u64 = std::mem::size_of::<InstanceData>() as u64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ObjectData {
+    model: [[f32; 4]; 4],
+    /// Local-space bounding sphere: center and radius.
+    sphere: [f32; 4],
+    batch: u32,
+    first_instance: u32,
+    _pad: [u32; 2],
+}
+
+/// Layout of `wgpu::util::DrawIndexedIndirectArgs`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    occlusion_view_proj: [[f32; 4]; 4],
+    object_count: u32,
+    hiz_mips: u32,
+    _pad: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuDrivenStats {
+    /// Objects submitted to the culling pass.
+    pub objects: u32,
+    /// Indirect draws, one per (pipeline, material, mesh) batch.
+    pub batches: u32,
+    /// Draw commands recorded per pass: one per pipeline and material with
+    /// multi-draw, one per batch without.
+    pub draw_calls: u32,
+    pub multi_draw: bool,
+    /// Whether this frame was tested against a depth pyramid.
+    pub occlusion: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PoolEntry {
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+}
+
+/// Every mesh's vertices and indices in one pair of buffers, so that draws
+/// of different meshes can share one multi-draw.
+struct MeshPool {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    vertex_len: u64,
+    index_len: u64,
+    entries: Vec<PoolEntry>,
+}
+
+impl MeshPool {
+    const INITIAL_VERTICES: u64 = 1 << 16;
+    const INITIAL_INDICES: u64 = 1 << 18;
+    const VERTEX_SIZE: u64 = std::mem::size_of::<Vertex3d>() as u64;
+    const INDEX_SIZE: u64 = std::mem::size_of::<u32>() as u64;
+
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            vertices: Self::create(device, "Mesh Pool VBuf", Self::INITIAL_VERTICES * Self::VERTEX_SIZE, wgpu::BufferUsages::VERTEX),
+            indices: Self::create(device, "Mesh Pool IBuf", Self::INITIAL_INDICES * Self::INDEX_SIZE, wgpu::BufferUsages::INDEX),
+            vertex_len: 0,
+            index_len: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn create(device: &wgpu::Device, label: &str, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Makes room for `needed` bytes, copying the `used` bytes so far into
+    /// the replacement buffer.
+    fn reserve(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, buffer: &mut wgpu::Buffer, used: u64, needed: u64) {
+        if needed <= buffer.size() {
+            return;
+        }
+        let usage = buffer.usage() & (wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::INDEX);
+        let label = if usage.contains(wgpu::BufferUsages::VERTEX) { "Mesh Pool VBuf" } else { "Mesh Pool IBuf" };
+        let grown = Self::create(device, label, needed.next_power_of_two(), usage);
+        if used > 0 {
+            encoder.copy_buffer_to_buffer(buffer, 0, &grown, 0, used);
+        }
+        *buffer = grown;
+    }
+
+    /// Appends the meshes added since the last call.
+    fn sync(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, meshes: &[GpuMesh]) {
+        for mesh in &meshes[self.entries.len()..] {
+            let vertex_bytes = mesh.vertex_buffer.size();
+            let index_bytes = mesh.index_count as u64 * Self::INDEX_SIZE;
+            let vertex_offset = self.vertex_len * Self::VERTEX_SIZE;
+            let index_offset = self.index_len * Self::INDEX_SIZE;
+            Self::reserve(device, encoder, &mut self.vertices, vertex_offset, vertex_offset + vertex_bytes);
+            Self::reserve(device, encoder, &mut self.indices, index_offset, index_offset + index_bytes);
+            encoder.copy_buffer_to_buffer(&mesh.vertex_buffer, 0, &self.vertices, vertex_offset, vertex_bytes);
+            encoder.copy_buffer_to_buffer(&mesh.index_buffer, 0, &self.indices, index_offset, index_bytes);
+            self.entries.push(PoolEntry {
+                first_index: self.index_len as u32,
+                index_count: mesh.index_count,
+                base_vertex: self.vertex_len as i32,
+            });
+            self.vertex_len += vertex_bytes / Self::VERTEX_SIZE;
+            self.index_len += mesh.index_count as u64;
+        }
+    }
+}
+
+/// Consecutive batches drawn with the same pipeline and material.
+struct DrawGroup {
+    key: BatchKey,
+    batches: Range<u32>,
+}
+
+/// Compute culling and indirect drawing state of a
+/// [`MeshRenderer`](super::MeshRenderer).
+pub struct GpuDriven {
+    /// Also tests objects against the previous frame's depth. Needs a
+    /// single-sampled depth buffer: the prepass's, or the mesh pass's without
+    /// MSAA.
+    pub occlusion_culling: bool,
+    multi_draw: bool,
+    pool: MeshPool,
+    objects: StorageBuffer<ObjectData>,
+    args: StorageBuffer<DrawArgs>,
+    instances: StorageBuffer<InstanceData>,
+    params: UniformBuffer<CullParams>,
+    bind_groups: BindGroupCache,
+    pipeline: ComputePipelineDesc,
+    hiz: HiZ,
+    hiz_texture: Option<wgpu::Texture>,
+    hiz_view: Option<wgpu::TextureView>,
+    /// Bound while there is no pyramid.
+    empty_hiz: wgpu::TextureView,
+    /// View-projection the pyramid's depth was rendered with.
+    hiz_view_proj: Option<Mat4>,
+    object_data: Vec<ObjectData>,
+    draw_args: Vec<DrawArgs>,
+    first_instances: Vec<u32>,
+    groups: Vec<DrawGroup>,
+    stats: GpuDrivenStats,
+}
+
+impl GpuDriven {
+    pub fn new(device: &wgpu::Device, pipelines: &PipelinesHandle) -> Self {
+        // Multi-draw packs all batches into one instance buffer, so every
+        // draw but the first needs a non-zero first instance.
+        let multi_draw = device
+            .features()
+            .contains(wgpu::Features::MULTI_DRAW_INDIRECT | wgpu::Features::INDIRECT_FIRST_INSTANCE);
+        let objects = StorageBuffer::new(device, "GPU Cull Objects", 1024, wgpu::BufferUsages::empty());
+        let args = StorageBuffer::new(device, "GPU Cull Draw Args", 64, wgpu::BufferUsages::INDIRECT);
+        let instances = StorageBuffer::new(device, "GPU Cull Instances", 1024, wgpu::BufferUsages::VERTEX);
+        let params = UniformBuffer::new(device, "GPU Cull Params", &CullParams::zeroed());
+
+        let empty_hiz = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Empty Hi-Z"),
+                size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HIZ_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_groups = BindGroupCache::new("GPU Cull BG", 4);
+        let builder = Self::bind_group_builder(&params, &objects, &args, &instances, &empty_hiz);
+        let shader = pipelines.lock().builtin(device, "mars/three_d/gpu_cull.wgsl", &ShaderDefs::new());
+        let pipeline = ComputePipelineDesc::new("GPU Cull Pipeline", shader, "cull").with_bind_group(builder.layout_entries());
+
+        Self {
+            occlusion_culling: true,
+            multi_draw,
+            pool: MeshPool::new(device),
+            objects,
+            args,
+            instances,
+            params,
+            bind_groups,
+            pipeline,
+            hiz: HiZ::new(true),
+            hiz_texture: None,
+            hiz_view: None,
+            empty_hiz,
+            hiz_view_proj: None,
+            object_data: Vec::new(),
+            draw_args: Vec::new(),
+            first_instances: Vec::new(),
+            groups: Vec::new(),
+            stats: GpuDrivenStats::default(),
+        }
+    }
+
+    fn bind_group_builder(
+        params: &UniformBuffer<CullParams>,
+        objects: &StorageBuffer<ObjectData>,
+        args: &StorageBuffer<DrawArgs>,
+        instances: &StorageBuffer<InstanceData>,
+        hiz: &wgpu::TextureView,
+    ) -> BindGroupBuilder {
+        let compute = wgpu::ShaderStages::COMPUTE;
+        BindGroupBuilder::new()
+            .uniform(0, compute, params.buffer())
+            .storage(1, compute, objects.buffer(), true)
+            .storage(2, compute, args.buffer(), false)
+            .storage(3, compute, instances.buffer(), false)
+            .entry(binding::unfilterable_entry(4, compute), BoundResource::TextureView(hiz.clone()))
+    }
+
+    /// Whether batches are drawn with `multi_draw_indexed_indirect`.
+    pub fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    pub fn stats(&self) -> GpuDrivenStats {
+        self.stats
+    }
+
+    /// Uploads this frame's objects and draw arguments from the sorted
+    /// `batcher` and records the culling pass.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare(
+        &mut self,
+        pipelines: &PipelinesHandle,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        batcher: &Batcher,
+        meshes: &[GpuMesh],
+        frustum: &Frustum,
+    ) {
+        self.pool.sync(device, encoder, meshes);
+
+        self.object_data.clear();
+        self.draw_args.clear();
+        self.first_instances.clear();
+        self.groups.clear();
+        let instances = batcher.instances();
+        for (i, batch) in batcher.batches().iter().enumerate() {
+            let key = batch.key;
+            let entry = self.pool.entries[key.mesh.0 as usize];
+            let first_instance = batch.instances.start;
+            self.draw_args.push(DrawArgs {
+                index_count: entry.index_count,
+                instance_count: 0,
+                first_index: entry.first_index,
+                base_vertex: entry.base_vertex,
+                first_instance: if self.multi_draw { first_instance } else { 0 },
+            });
+            self.first_instances.push(first_instance);
+
+            let sphere = meshes[key.mesh.0 as usize].sphere;
+            let sphere = sphere.center.extend(sphere.radius).to_array();
+            self.object_data.extend(batch.instances.clone().map(|j| ObjectData {
+                model: instances[j as usize].model,
+                sphere,
+                batch: i as u32,
+                first_instance,
+                _pad: [0; 2],
+            }));
+
+            let i = i as u32;
+            match self.groups.last_mut() {
+                Some(group) if group.key.pipeline == key.pipeline && group.key.material == key.material => group.batches.end = i + 1,
+                _ => self.groups.push(DrawGroup { key, batches: i..i + 1 }),
+            }
+        }
+
+        let hiz_mips = match (&self.hiz_texture, self.hiz_view_proj) {
+            (Some(texture), Some(_)) if self.occlusion_culling => texture.mip_level_count(),
+            _ => 0,
+        };
+        self.stats = GpuDrivenStats {
+            objects: self.object_data.len() as u32,
+            batches: self.draw_args.len() as u32,
+            draw_calls: if self.multi_draw { self.groups.len() } else { self.draw_args.len() } as u32,
+            multi_draw: self.multi_draw,
+            occlusion: hiz_mips > 0,
+        };
+        if self.object_data.is_empty() {
+            return;
+        }
+
+        self.objects.write(device, queue, &self.object_data);
+        self.args.write(device, queue, &self.draw_args);
+        self.instances.resize(device, instances.len());
+        let planes = frustum.planes.map(|p| p.normal.extend(p.d).to_array());
+        self.params.write(
+            queue,
+            &CullParams {
+                planes,
+                occlusion_view_proj: self.hiz_view_proj.unwrap_or_default().to_cols_array_2d(),
+                object_count: self.object_data.len() as u32,
+                hiz_mips,
+                _pad: [0; 2],
+            },
+        );
+
+        let hiz = self.hiz_view.as_ref().filter(|_| hiz_mips > 0).unwrap_or(&self.empty_hiz);
+        let builder = Self::bind_group_builder(&self.params, &self.objects, &self.args, &self.instances, hiz);
+        let bind_group = self.bind_groups.get(device, builder);
+        let pipeline = pipelines.lock().compute_pipeline(device, &self.pipeline);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("GPU Cull"), timestamp_writes: None });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.dispatch_workgroups((self.object_data.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Builds the pyramid the next frame is tested against from `depth`,
+    /// rendered with `view_proj`. Without a depth buffer the next frame only
+    /// gets frustum culling.
+    pub(crate) fn update_hiz(
+        &mut self,
+        pipelines: &PipelinesHandle,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: Option<&wgpu::TextureView>,
+        size: (u32, u32),
+        view_proj: Mat4,
+    ) {
+        let Some(depth) = depth.filter(|_| self.occlusion_culling) else {
+            self.hiz_view_proj = None;
+            return;
+        };
+        if self.hiz_texture.as_ref().is_none_or(|t| (t.width(), t.height()) != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Occlusion Hi-Z"),
+                size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+                mip_level_count: HiZ::mip_count(size),
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HIZ_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            self.hiz_view = Some(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+            self.hiz_texture = Some(texture);
+        }
+        self.hiz.build(pipelines, device, encoder, depth, self.hiz_texture.as_ref().expect("created above"));
+        self.hiz_view_proj = Some(view_proj);
+    }
+
+    /// Records this frame's draws. `bind` sets the pipeline and material
+    /// state for a group's key, given the previously bound key.
+    pub(crate) fn draw(&self, rp: &mut wgpu::RenderPass<'_>, mut bind: impl FnMut(&mut wgpu::RenderPass<'_>, BatchKey, Option<BatchKey>)) {
+        if self.groups.is_empty() {
+            return;
+        }
+        rp.set_vertex_buffer(0, self.pool.vertices.slice(..));
+        rp.set_index_buffer(self.pool.indices.slice(..), wgpu::IndexFormat::Uint32);
+        rp.set_vertex_buffer(1, self.instances.buffer().slice(..));
+        let mut bound = None;
+        for group in &self.groups {
+            bind(rp, group.key, bound);
+            bound = Some(group.key);
+            let batches = group.batches.clone();
+            if self.multi_draw {
+                rp.multi_draw_indexed_indirect(self.args.buffer(), batches.start as u64 * ARGS_SIZE, batches.len() as u32);
+                continue;
+            }
+            // Without first-instance support each draw starts at instance 0
+            // of a buffer slice instead.
+            for i in batches {
+                let first = self.first_instances[i as usize] as u64;
+                rp.set_vertex_buffer(1, self.instances.buffer().slice(first * INSTANCE_SIZE..));
+                rp.draw_indexed_indirect(self.args.buffer(), i as u64 * ARGS_SIZE);
+            }
+        }
+    }
+}
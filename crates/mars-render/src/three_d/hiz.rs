@@ -0,0 +1,76 @@
+//! Hierarchical depth pyramids built from a single-sampled depth buffer.
+
+use crate::binding;
+use crate::pipeline::{ComputePipelineDesc, PipelinesHandle};
+use crate::shader::ShaderDefs;
+
+pub(crate) const HIZ_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Builds a depth pyramid in which every texel holds the closest depth of
+/// the texels it covers one level down, or the farthest for occlusion tests.
+pub(crate) struct HiZ {
+    entries: [wgpu::BindGroupLayoutEntry; 2],
+    defs: ShaderDefs,
+}
+
+impl HiZ {
+    pub(crate) fn new(farthest: bool) -> Self {
+        let compute = wgpu::ShaderStages::COMPUTE;
+        let entries = [
+            binding::unfilterable_entry(0, compute),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: compute,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: HIZ_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ];
+        let defs = if farthest { ShaderDefs::new().with("FARTHEST") } else { ShaderDefs::new() };
+        Self { entries, defs }
+    }
+
+    /// Levels of a full pyramid over `size`.
+    pub(crate) fn mip_count(size: (u32, u32)) -> u32 {
+        32 - size.0.max(size.1).max(1).leading_zeros()
+    }
+
+    /// Fills every level of `hiz`, whose base level has the size of `depth`.
+    pub(crate) fn build(&self, pipelines: &PipelinesHandle, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, depth: &wgpu::TextureView, hiz: &wgpu::Texture) {
+        let mips: Vec<_> = (0..hiz.mip_level_count())
+            .map(|mip| {
+                hiz.create_view(&wgpu::TextureViewDescriptor { base_mip_level: mip, mip_level_count: Some(1), ..Default::default() })
+            })
+            .collect();
+        let (layout, copy, downsample) = {
+            let mut pipelines = pipelines.lock();
+            let shader = pipelines.builtin(device, "mars/three_d/hiz.wgsl", &self.defs);
+            let mut pipeline = |entry| {
+                let desc = ComputePipelineDesc::new("Hi-Z Pipeline", shader, entry).with_bind_group(&self.entries);
+                pipelines.compute_pipeline(device, &desc)
+            };
+            let (copy, downsample) = (pipeline("copy"), pipeline("downsample"));
+            (pipelines.bind_group_layout(device, &self.entries), copy, downsample)
+        };
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Hi-Z"), timestamp_writes: None });
+        for (mip, view) in mips.iter().enumerate() {
+            let src = if mip == 0 { depth } else { &mips[mip - 1] };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Hi-Z BG"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(src) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(view) },
+                ],
+            });
+            let width = (hiz.width() >> mip).max(1);
+            let height = (hiz.height() >> mip).max(1);
+            pass.set_pipeline(if mip == 0 { &copy } else { &downsample });
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Hierarchical depth: every mip holds the closest (minimum) depth of the
+// texels it covers in the level below, or the farthest (maximum) one when
+// FARTHEST is defined, as occlusion culling needs.
+
+@group(0) @binding(0) var src_depth: texture_2d<f32>;
+@group(0) @binding(1) var dst: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn copy(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let dims = textureDimensions(dst);
+  if (gid.x >= dims.x || gid.y >= dims.y) {
+    return;
+  }
+  textureStore(dst, gid.xy, vec4<f32>(textureLoad(src_depth, gid.xy, 0).r, 0.0, 0.0, 0.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn downsample(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let dims = textureDimensions(dst);
+  if (gid.x >= dims.x || gid.y >= dims.y) {
+    return;
+  }
+  let src_dims = vec2<i32>(textureDimensions(src_depth));
+  let base = vec2<i32>(gid.xy) * 2;
+  // Odd source sizes fold the last row/column into the last texel.
+  let last = vec2<i32>(gid.xy) == vec2<i32>(dims) - 1;
+  let odd = src_dims % 2 == vec2<i32>(1);
+  let extent = select(vec2<i32>(2), vec2<i32>(3), vec2<bool>(last.x && odd.x, last.y && odd.y));
+#ifdef FARTHEST
+  var m = 0.0;
+#else
+  var m = 1.0;
+#endif
+  for (var y = 0; y < extent.y; y++) {
+    for (var x = 0; x < extent.x; x++) {
+      let p = min(base + vec2<i32>(x, y), src_dims - 1);
+      let d = textureLoad(src_depth, p, 0).r;
+#ifdef FARTHEST
+      m = max(m, d);
+#else
+      m = min(m, d);
+#endif
+    }
+  }
+  textureStore(dst, gid.xy, vec4<f32>(m, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,40 @@
+#import mars::three_d::ibl_common
+
+@group(0) @binding(0) var dst: texture_storage_2d<rgba16float, write>;
+
+const SAMPLE_COUNT: u32 = 1024u;
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+  let k = roughness * roughness / 2.0;
+  return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let size = textureDimensions(dst);
+  if (gid.x >= size.x || gid.y >= size.y) { return; }
+  let uv = (vec2<f32>(gid.xy) + 0.5) / vec2<f32>(size);
+  let n_dot_v = uv.x;
+  let roughness = uv.y;
+
+  let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+  let n = vec3<f32>(0.0, 0.0, 1.0);
+  var a = 0.0;
+  var b = 0.0;
+  for (var i = 0u; i < SAMPLE_COUNT; i++) {
+    let h = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), n, roughness);
+    let l = normalize(2.0 * dot(v, h) * h - v);
+    let n_dot_l = max(l.z, 0.0);
+    let n_dot_h = max(h.z, 0.0);
+    let v_dot_h = max(dot(v, h), 0.0);
+    if (n_dot_l > 0.0) {
+      let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+      let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+      let fc = pow(1.0 - v_dot_h, 5.0);
+      a += (1.0 - fc) * g_vis;
+      b += fc * g_vis;
+    }
+  }
+  let n_samples = f32(SAMPLE_COUNT);
+  textureStore(dst, vec2<i32>(gid.xy), vec4<f32>(a / n_samples, b / n_samples, 0.0, 1.0));
+}
@@ -0,0 +1,47 @@
+const PI: f32 = 3.14159265359;
+
+// Direction through texel `uv` (in [-1, 1]) of cube face `face`, using the
+// +X, -X, +Y, -Y, +Z, -Z layer order of wgpu cube textures.
+fn cube_dir(face: u32, uv: vec2<f32>) -> vec3<f32> {
+  switch face {
+    case 0u: { return normalize(vec3<f32>(1.0, -uv.y, -uv.x)); }
+    case 1u: { return normalize(vec3<f32>(-1.0, -uv.y, uv.x)); }
+    case 2u: { return normalize(vec3<f32>(uv.x, 1.0, uv.y)); }
+    case 3u: { return normalize(vec3<f32>(uv.x, -1.0, -uv.y)); }
+    case 4u: { return normalize(vec3<f32>(uv.x, -uv.y, 1.0)); }
+    default: { return normalize(vec3<f32>(-uv.x, -uv.y, -1.0)); }
+  }
+}
+
+fn texel_dir(gid: vec3<u32>, size: vec2<u32>) -> vec3<f32> {
+  let uv = (vec2<f32>(gid.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+  return cube_dir(gid.z, uv);
+}
+
+fn radical_inverse(bits_in: u32) -> f32 {
+  var bits = bits_in;
+  bits = (bits << 16u) | (bits >> 16u);
+  bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+  bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+  bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+  bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+  return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(i: u32, n: u32) -> vec2<f32> {
+  return vec2<f32>(f32(i) / f32(n), radical_inverse(i));
+}
+
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+  let a = roughness * roughness;
+  let phi = 2.0 * PI * xi.x;
+  let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+  let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+  let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+  var up = vec3<f32>(1.0, 0.0, 0.0);
+  if (abs(n.z) < 0.999) { up = vec3<f32>(0.0, 0.0, 1.0); }
+  let tangent = normalize(cross(up, n));
+  let bitangent = cross(n, tangent);
+  return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
@@ -0,0 +1,28 @@
+#import mars::three_d::ibl_common
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var dst: texture_storage_2d_array<rgba16float, write>;
+
+// Bilinear fetch; the source is Rgba32Float, which is not filterable.
+fn sample_equirect(uv: vec2<f32>) -> vec4<f32> {
+  let dims = vec2<i32>(textureDimensions(src));
+  let p = uv * vec2<f32>(dims) - 0.5;
+  let f = fract(p);
+  let base = vec2<i32>(floor(p));
+  let x0 = (base.x % dims.x + dims.x) % dims.x;
+  let x1 = (x0 + 1) % dims.x;
+  let y0 = clamp(base.y, 0, dims.y - 1);
+  let y1 = clamp(base.y + 1, 0, dims.y - 1);
+  let top = mix(textureLoad(src, vec2<i32>(x0, y0), 0), textureLoad(src, vec2<i32>(x1, y0), 0), f.x);
+  let bottom = mix(textureLoad(src, vec2<i32>(x0, y1), 0), textureLoad(src, vec2<i32>(x1, y1), 0), f.x);
+  return mix(top, bottom, f.y);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let size = textureDimensions(dst);
+  if (gid.x >= size.x || gid.y >= size.y) { return; }
+  let d = texel_dir(gid, size);
+  let uv = vec2<f32>(atan2(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+  textureStore(dst, vec2<i32>(gid.xy), i32(gid.z), vec4<f32>(sample_equirect(uv).rgb, 1.0));
+}
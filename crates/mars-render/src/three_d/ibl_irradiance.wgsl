@@ -0,0 +1,31 @@
+#import mars::three_d::ibl_common
+
+@group(0) @binding(0) var env: texture_cube<f32>;
+@group(0) @binding(1) var env_sampler: sampler;
+@group(0) @binding(2) var dst: texture_storage_2d_array<rgba16float, write>;
+
+const SAMPLE_DELTA: f32 = 0.025;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let size = textureDimensions(dst);
+  if (gid.x >= size.x || gid.y >= size.y) { return; }
+  let n = texel_dir(gid, size);
+
+  var up = vec3<f32>(0.0, 1.0, 0.0);
+  if (abs(n.y) > 0.999) { up = vec3<f32>(0.0, 0.0, 1.0); }
+  let right = normalize(cross(up, n));
+  up = cross(n, right);
+
+  var irradiance = vec3<f32>(0.0);
+  var count = 0.0;
+  for (var phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+    for (var theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+      let t = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+      let dir = t.x * right + t.y * up + t.z * n;
+      irradiance += textureSampleLevel(env, env_sampler, dir, 0.0).rgb * cos(theta) * sin(theta);
+      count += 1.0;
+    }
+  }
+  textureStore(dst, vec2<i32>(gid.xy), i32(gid.z), vec4<f32>(PI * irradiance / count, 1.0));
+}
@@ -0,0 +1,33 @@
+#import mars::three_d::ibl_common
+
+struct Params {
+  roughness: f32,
+  sample_count: u32,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var env: texture_cube<f32>;
+@group(0) @binding(1) var env_sampler: sampler;
+@group(0) @binding(2) var dst: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let size = textureDimensions(dst);
+  if (gid.x >= size.x || gid.y >= size.y) { return; }
+  // Split-sum approximation: assume view = normal = reflection.
+  let n = texel_dir(gid, size);
+
+  var color = vec3<f32>(0.0);
+  var weight = 0.0;
+  for (var i = 0u; i < params.sample_count; i++) {
+    let h = importance_sample_ggx(hammersley(i, params.sample_count), n, params.roughness);
+    let l = normalize(2.0 * dot(n, h) * h - n);
+    let n_dot_l = dot(n, l);
+    if (n_dot_l > 0.0) {
+      color += textureSampleLevel(env, env_sampler, l, 0.0).rgb * n_dot_l;
+      weight += n_dot_l;
+    }
+  }
+  textureStore(dst, vec2<i32>(gid.xy), i32(gid.z), vec4<f32>(color / max(weight, 1e-4), 1.0));
+}
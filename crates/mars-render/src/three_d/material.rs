@@ -0,0 +1,76 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::binding::buffer_entry;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PipelineId(pub(crate) u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(pub(crate) u32);
+
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialDesc {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// `None` uses the renderer's built-in lit pipeline.
+    pub pipeline: Option<PipelineId>,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        Self { base_color: [1.0; 4], metallic: 0.0, roughness: 0.5, pipeline: None }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub _pad: [f32; 2],
+}
+
+pub struct Material {
+    pub pipeline: PipelineId,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    /// Layout entries of a material's bind group (group 1).
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+        [buffer_entry(0, wgpu::ShaderStages::FRAGMENT, wgpu::BufferBindingType::Uniform)]
+    }
+
+    pub fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some("Material BGL"), entries: &Self::layout_entries() })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        pipeline: PipelineId,
+        desc: &MaterialDesc,
+    ) -> Self {
+        let uniform = MaterialUniform {
+            base_color: desc.base_color,
+            metallic: desc.metallic,
+            roughness: desc.roughness,
+            _pad: [0.0; 2],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material UBO"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material BG"),
+            layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+        Self { pipeline, buffer, bind_group }
+    }
+}
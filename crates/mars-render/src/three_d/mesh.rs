@@ -0,0 +1,116 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use mars_core::bounds::{Aabb, Sphere};
+use wgpu::util::DeviceExt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(pub(crate) u32);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct Vertex3d {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl Vertex3d {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// CPU-side geometry, uploaded once with [`GpuMesh::upload`].
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex3d>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|v| Vec3::from(v.position)))
+    }
+
+    pub fn cube(half_extent: f32) -> Self {
+        let h = half_extent;
+        // (normal, tangent u, tangent v) per face
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (n, u, v) in faces {
+            let base = vertices.len() as u32;
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = [
+                    (n[0] + u[0] * su + v[0] * sv) * h,
+                    (n[1] + u[1] * su + v[1] * sv) * h,
+                    (n[2] + u[2] * su + v[2] * sv) * h,
+                ];
+                let uv = [(su + 1.0) * 0.5, 1.0 - (sv + 1.0) * 0.5];
+                vertices.push(Vertex3d { position, normal: n, uv });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        Self { vertices, indices }
+    }
+
+    pub fn plane(half_extent: f32) -> Self {
+        let h = half_extent;
+        let n = [0.0, 1.0, 0.0];
+        let vertices = vec![
+            Vertex3d { position: [-h, 0.0, h], normal: n, uv: [0.0, 1.0] },
+            Vertex3d { position: [h, 0.0, h], normal: n, uv: [1.0, 1.0] },
+            Vertex3d { position: [h, 0.0, -h], normal: n, uv: [1.0, 0.0] },
+            Vertex3d { position: [-h, 0.0, -h], normal: n, uv: [0.0, 0.0] },
+        ];
+        Self { vertices, indices: vec![0, 1, 2, 0, 2, 3] }
+    }
+}
+
+pub struct GpuMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    /// Local-space bounds, used for culling.
+    pub aabb: Aabb,
+    pub sphere: Sphere,
+}
+
+impl GpuMesh {
+    pub fn upload(device: &wgpu::Device, data: &MeshData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh VBuf"),
+            contents: bytemuck::cast_slice(&data.vertices),
+            // Copied into the shared GPU-driven mesh pool when that is in use.
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh IBuf"),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_SRC,
+        });
+        let aabb = data.bounds();
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+            aabb,
+            sphere: Sphere::from_aabb(&aabb),
+        }
+    }
+}
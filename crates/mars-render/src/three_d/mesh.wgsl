@@ -0,0 +1,148 @@
+#import mars::three_d::cluster_common
+
+struct Camera {
+  view_proj: mat4x4<f32>,
+  view: mat4x4<f32>,
+  proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+struct Material {
+  base_color: vec4<f32>,
+  metallic: f32,
+  roughness: f32,
+  _pad: vec2<f32>,
+};
+
+struct EnvParams {
+  intensity: f32,
+  max_lod: f32,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> material: Material;
+@group(2) @binding(0) var irradiance_map: texture_cube<f32>;
+@group(2) @binding(1) var specular_map: texture_cube<f32>;
+@group(2) @binding(2) var brdf_lut: texture_2d<f32>;
+@group(2) @binding(3) var env_sampler: sampler;
+@group(2) @binding(5) var<uniform> env: EnvParams;
+@group(3) @binding(0) var<uniform> clusters: ClusterParams;
+@group(3) @binding(1) var<storage, read> lights: array<Light>;
+@group(3) @binding(2) var<storage, read> cluster_counts: array<u32>;
+@group(3) @binding(3) var<storage, read> cluster_lights: array<u32>;
+
+const PI: f32 = 3.14159265359;
+
+struct VsIn {
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) uv: vec2<f32>,
+  @location(3) model_0: vec4<f32>,
+  @location(4) model_1: vec4<f32>,
+  @location(5) model_2: vec4<f32>,
+  @location(6) model_3: vec4<f32>,
+};
+
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) world_pos: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(in: VsIn) -> VsOut {
+  let model = mat4x4<f32>(in.model_0, in.model_1, in.model_2, in.model_3);
+  let world = model * vec4<f32>(in.position, 1.0);
+  var out: VsOut;
+  out.clip = camera.view_proj * world;
+  out.world_pos = world.xyz;
+  out.normal = normalize((model * vec4<f32>(in.normal, 0.0)).xyz);
+  out.uv = in.uv;
+  return out;
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+  let a2 = roughness * roughness * roughness * roughness;
+  let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+  let r = roughness + 1.0;
+  let k = r * r / 8.0;
+  return (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+  return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+  return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+fn brdf_direct(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, albedo: vec3<f32>, f0: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+  let h = normalize(v + l);
+  let n_dot_v = max(dot(n, v), 1e-4);
+  let n_dot_l = max(dot(n, l), 0.0);
+  let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+  let spec = distribution_ggx(max(dot(n, h), 0.0), roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * f
+    / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+  let kd = (1.0 - f) * (1.0 - metallic);
+  return (kd * albedo / PI + spec) * n_dot_l;
+}
+
+// Blue (few lights) to red (cluster full).
+fn heat(t: f32) -> vec3<f32> {
+  return clamp(vec3<f32>(2.0 * t, 2.0 - abs(4.0 * t - 2.0), 2.0 - 2.0 * t), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let albedo = material.base_color.rgb;
+  let metallic = material.metallic;
+  let roughness = clamp(material.roughness, 0.04, 1.0);
+
+  let n = normalize(in.normal);
+  let v = normalize(camera.position.xyz - in.world_pos);
+  let n_dot_v = max(dot(n, v), 1e-4);
+  let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+
+  // Directional sun
+  var direct = brdf_direct(n, v, normalize(vec3<f32>(0.4, 1.0, 0.3)), albedo, f0, metallic, roughness) * 3.0;
+
+  // Clustered point and spot lights
+  let view_depth = -(camera.view * vec4<f32>(in.world_pos, 1.0)).z;
+  let cluster = cluster_index(in.clip.xy, view_depth, clusters);
+  let count = cluster_counts[cluster];
+  if (clusters.debug != 0u) {
+    return vec4<f32>(heat(f32(count) / f32(clusters.grid.w)), 1.0);
+  }
+  let base = cluster * clusters.grid.w;
+  for (var i = 0u; i < count; i++) {
+    let light = lights[cluster_lights[base + i]];
+    let to_light = light.position_range.xyz - in.world_pos;
+    let dist = length(to_light);
+    let l = to_light / max(dist, 1e-4);
+    var atten = light_attenuation(dist, light.position_range.w);
+    if (light.direction_kind.w > 0.5) {
+      let cos_angle = dot(-l, light.direction_kind.xyz);
+      atten *= smoothstep(light.spot.y, light.spot.x, cos_angle);
+    }
+    direct += brdf_direct(n, v, l, albedo, f0, metallic, roughness) * light.color_intensity.rgb * light.color_intensity.w * atten;
+  }
+
+  // Image-based ambient (split-sum)
+  let f_amb = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+  let kd_amb = (1.0 - f_amb) * (1.0 - metallic);
+  let diffuse = textureSample(irradiance_map, env_sampler, n).rgb * albedo;
+  let r = reflect(-v, n);
+  let prefiltered = textureSampleLevel(specular_map, env_sampler, r, roughness * env.max_lod).rgb;
+  let brdf = textureSample(brdf_lut, env_sampler, vec2<f32>(n_dot_v, roughness)).rg;
+  let ambient = (kd_amb * diffuse + prefiltered * (f_amb * brdf.x + brdf.y)) * env.intensity;
+
+  return vec4<f32>(direct + ambient, material.base_color.a);
+}
@@ -0,0 +1,61 @@
+pub mod antialias;
+pub mod batch;
+pub mod camera;
+pub mod clustered;
+pub mod cull;
+pub mod decal;
+pub mod environment;
+pub mod gpu_driven;
+mod hiz;
+pub mod material;
+pub mod mesh;
+pub mod prepass;
+pub mod renderer;
+pub mod ssao;
+pub mod ssr;
+
+pub use antialias::{supported_sample_counts, AntiAliasing};
+pub use batch::{Batch, BatchKey, BatchStats, Batcher, InstanceData};
+pub use camera::{Camera3d, CameraUniform};
+pub use clustered::{ClusterConfig, ClusteredLighting, Light, LightKind};
+pub use cull::{CullStats, FrustumCuller};
+pub use decal::{DecalBlend, DecalDesc, DecalId, DecalKind, DecalNode, Decals};
+pub use environment::{Cubemap, EnvParams, Environment, IblBaker, IblSettings};
+pub use gpu_driven::{GpuDriven, GpuDrivenStats};
+pub use material::{Material, MaterialDesc, MaterialId, MaterialUniform, PipelineId};
+pub use mesh::{GpuMesh, MeshData, MeshId, Vertex3d};
+pub use renderer::MeshRenderer;
+pub use ssao::{Ssao, SsaoQuality, SsaoSettings};
+pub use ssr::{Ssr, SsrQuality, SsrSettings};
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+pub struct DepthTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: (u32, u32),
+}
+
+impl DepthTexture {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let size = (width.max(1), height.max(1));
+        // Only single-sampled depth is read back (e.g. by TAA); GL also fails
+        // MSAA resolves when multisampled depth is sampleable.
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        if sample_count == 1 {
+            usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, size }
+    }
+}
@@ -0,0 +1,43 @@
+//! Depth/normal prepass shared by the screen-space effects.
+//!
+//! [`MeshRenderer::prepass`](super::MeshRenderer::prepass) publishes its
+//! outputs as graph resources under the names below; the main mesh pass then
+//! reuses the depth buffer instead of clearing its own.
+
+use crate::pipeline::{ColorTarget, Pipelines, RenderPipelineDesc};
+use crate::shader::ShaderDefs;
+use super::mesh::Vertex3d;
+use super::batch::InstanceData;
+use super::camera::CameraUniform;
+use super::material::Material;
+use super::DEPTH_FORMAT;
+
+/// Single-sampled scene depth ([`DEPTH_FORMAT`]).
+pub const DEPTH: &str = "depth";
+/// View-space normal (octahedral xy), roughness and metallic.
+pub const NORMALS: &str = "normals";
+/// The camera uniform buffer ([`CameraUniform`](super::CameraUniform)) used
+/// for the frame, jitter included.
+pub const CAMERA: &str = "camera";
+
+pub(crate) const MISSING: &str = "prepass outputs missing; run MeshRenderer::prepass earlier in the frame";
+
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub(crate) fn pipeline_desc(device: &wgpu::Device, pipelines: &mut Pipelines) -> RenderPipelineDesc {
+    let shader = pipelines.builtin(device, "mars/three_d/prepass.wgsl", &ShaderDefs::new());
+    RenderPipelineDesc::new("Prepass Pipeline", shader)
+        .with_bind_group(&CameraUniform::layout_entries())
+        .with_bind_group(&Material::layout_entries())
+        .with_vertex_buffer(Vertex3d::layout().into())
+        .with_vertex_buffer(InstanceData::layout().into())
+        .with_target(ColorTarget::new(NORMAL_FORMAT))
+        .with_primitive(wgpu::PrimitiveState { cull_mode: Some(wgpu::Face::Back), ..Default::default() })
+        .with_depth(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        })
+}
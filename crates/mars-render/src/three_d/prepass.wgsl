@@ -0,0 +1,58 @@
+struct Camera {
+  view_proj: mat4x4<f32>,
+  view: mat4x4<f32>,
+  proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+struct Material {
+  base_color: vec4<f32>,
+  metallic: f32,
+  roughness: f32,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> material: Material;
+
+struct VsIn {
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) uv: vec2<f32>,
+  @location(3) model_0: vec4<f32>,
+  @location(4) model_1: vec4<f32>,
+  @location(5) model_2: vec4<f32>,
+  @location(6) model_3: vec4<f32>,
+};
+
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) view_normal: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VsIn) -> VsOut {
+  let model = mat4x4<f32>(in.model_0, in.model_1, in.model_2, in.model_3);
+  var out: VsOut;
+  // Same expression as mesh.wgsl so the main pass can depth-test LessEqual.
+  let world = model * vec4<f32>(in.position, 1.0);
+  out.clip = camera.view_proj * world;
+  out.view_normal = (camera.view * model * vec4<f32>(in.normal, 0.0)).xyz;
+  return out;
+}
+
+// Octahedral normal encoding into [-1, 1]^2.
+fn oct_encode(n: vec3<f32>) -> vec2<f32> {
+  let p = n.xy / (abs(n.x) + abs(n.y) + abs(n.z));
+  if (n.z < 0.0) {
+    return (1.0 - abs(p.yx)) * select(vec2<f32>(-1.0), vec2<f32>(1.0), p >= vec2<f32>(0.0));
+  }
+  return p;
+}
+
+// xy: view-space normal (octahedral), z: roughness, w: metallic.
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(oct_encode(normalize(in.view_normal)), clamp(material.roughness, 0.04, 1.0), material.metallic);
+}
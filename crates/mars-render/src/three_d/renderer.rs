@@ -0,0 +1,563 @@
+use glam::Mat4;
+use mars_core::bounds::Sphere;
+use wgpu::util::DeviceExt;
+
+use super::antialias::{AntiAliasState, AntiAliasing};
+use super::batch::{BatchKey, BatchStats, Batcher, InstanceData};
+use super::camera::{Camera3d, CameraUniform};
+use super::clustered::{ClusterConfig, ClusteredLighting};
+use super::cull::{CullStats, FrustumCuller};
+use super::environment::Environment;
+use super::gpu_driven::GpuDriven;
+use super::material::{Material, MaterialDesc, MaterialId, PipelineId};
+use super::mesh::{GpuMesh, MeshData, MeshId, Vertex3d};
+use super::prepass::{self, NORMAL_FORMAT};
+use super::{DepthTexture, DEPTH_FORMAT};
+use crate::graph::{GraphResources, GraphTextureDesc};
+use crate::pipeline::{ColorTarget, Pipelines, PipelinesHandle, RenderPipelineDesc};
+use crate::shader::{ShaderDefs, ShaderId};
+
+/// Forward mesh renderer. Game code calls [`MeshRenderer::draw`] once per
+/// object per frame; requests are batched and drawn instanced in
+/// [`MeshRenderer::render`].
+pub struct MeshRenderer {
+    meshes: Vec<GpuMesh>,
+    mesh_bounds: Vec<Sphere>,
+    materials: Vec<Material>,
+    pipelines: PipelinesHandle,
+    /// Indexed by [`PipelineId`]; built through `pipelines` when drawn.
+    mesh_pipelines: Vec<RenderPipelineDesc>,
+    default_pipeline: PipelineId,
+
+    camera: Camera3d,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    material_layout: wgpu::BindGroupLayout,
+
+    environment: Environment,
+    env_layout: wgpu::BindGroupLayout,
+    env_bind_group: wgpu::BindGroup,
+    env_params: wgpu::Buffer,
+    env_sampler: wgpu::Sampler,
+    skybox_pipeline: RenderPipelineDesc,
+    pub draw_skybox: bool,
+
+    lighting: ClusteredLighting,
+
+    batcher: Batcher,
+    culler: FrustumCuller,
+    pub frustum_culling: bool,
+    /// Set while culling and draw submission run on the GPU.
+    gpu_driven: Option<GpuDriven>,
+    depth: DepthTexture,
+    prepass_pipeline: RenderPipelineDesc,
+    /// Depth written by a frame's prepass, reused by that frame's mesh pass
+    /// without MSAA.
+    prepass_depth: Option<(u64, wgpu::TextureView)>,
+    /// Last frame whose camera and instances were uploaded.
+    prepared_frame: Option<u64>,
+    antialias: AntiAliasState,
+    color_format: wgpu::TextureFormat,
+    pub clear_color: Option<wgpu::Color>,
+}
+
+impl MeshRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let camera = Camera3d { aspect: width.max(1) as f32 / height.max(1) as f32, ..Default::default() };
+
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera BGL"),
+            entries: &CameraUniform::layout_entries(),
+        });
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera UBO"),
+            contents: bytemuck::bytes_of(&camera.uniform()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera BG"),
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+        let material_layout = Material::layout(device);
+
+        let environment = Environment::uniform(device, queue, [0.03; 3]);
+        let env_layout = Environment::bind_group_layout(device);
+        let env_params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment UBO"),
+            contents: bytemuck::bytes_of(&environment.params()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let env_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let env_bind_group = environment.create_bind_group(device, &env_layout, &env_sampler, &env_params);
+        let pipelines = PipelinesHandle::new(color_format);
+        let lighting = ClusteredLighting::new(device, &pipelines, ClusterConfig::default());
+        let (skybox_pipeline, prepass_pipeline, mesh_shader) = {
+            let mut pipelines = pipelines.lock();
+            (
+                Self::skybox_pipeline_desc(device, &mut pipelines, color_format),
+                prepass::pipeline_desc(device, &mut pipelines),
+                pipelines.builtin(device, "mars/three_d/mesh.wgsl", &ShaderDefs::new()),
+            )
+        };
+
+        let mut renderer = Self {
+            meshes: Vec::new(),
+            mesh_bounds: Vec::new(),
+            materials: Vec::new(),
+            pipelines,
+            mesh_pipelines: Vec::new(),
+            default_pipeline: PipelineId(0),
+            camera,
+            camera_buffer,
+            camera_bind_group,
+            material_layout,
+            environment,
+            env_layout,
+            env_bind_group,
+            env_params,
+            env_sampler,
+            skybox_pipeline,
+            draw_skybox: false,
+            lighting,
+            batcher: Batcher::new(device),
+            culler: FrustumCuller::new(),
+            frustum_culling: true,
+            gpu_driven: None,
+            depth: DepthTexture::new(device, width, height, 1),
+            prepass_pipeline,
+            prepass_depth: None,
+            prepared_frame: None,
+            antialias: AntiAliasState::new(device, AntiAliasing::None, color_format, (width, height)),
+            color_format,
+            clear_color: Some(wgpu::Color::BLACK),
+        };
+
+        renderer.default_pipeline = renderer.add_pipeline(mesh_shader, "Mesh Pipeline");
+        renderer
+    }
+
+    /// The shader library and pipeline cache the renderer builds its
+    /// pipelines with. Load custom mesh shaders from its library, and share
+    /// it with a [`RenderGraph`](crate::graph::RenderGraph) so both reload
+    /// together.
+    pub fn pipelines(&self) -> &PipelinesHandle {
+        &self.pipelines
+    }
+
+    /// Describes a pipeline compatible with the renderer's bind group and
+    /// vertex layouts. The shader must expose `vs_main` and `fs_main`, and
+    /// gets the clustered-lighting declarations with
+    /// `#import mars::three_d::cluster_common`.
+    pub fn pipeline_desc(&self, shader: ShaderId, label: &str) -> RenderPipelineDesc {
+        RenderPipelineDesc::new(label, shader)
+            .with_bind_group(&CameraUniform::layout_entries())
+            .with_bind_group(&Material::layout_entries())
+            .with_bind_group(&Environment::layout_entries())
+            .with_bind_group(&ClusteredLighting::shade_layout_entries())
+            .with_vertex_buffer(Vertex3d::layout().into())
+            .with_vertex_buffer(InstanceData::layout().into())
+            .with_target(ColorTarget::new(self.color_format).with_blend(wgpu::BlendState::REPLACE))
+            .with_primitive(wgpu::PrimitiveState { cull_mode: Some(wgpu::Face::Back), ..Default::default() })
+            .with_depth(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                // Equal depths pass so the prepass depth buffer can be reused.
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            })
+            .with_sample_count(self.antialias.mode().sample_count())
+    }
+
+    fn skybox_pipeline_desc(device: &wgpu::Device, pipelines: &mut Pipelines, color_format: wgpu::TextureFormat) -> RenderPipelineDesc {
+        let shader = pipelines.builtin(device, "mars/three_d/skybox.wgsl", &ShaderDefs::new());
+        RenderPipelineDesc::new("Skybox Pipeline", shader)
+            .with_bind_group(&CameraUniform::layout_entries())
+            .with_bind_group(&Environment::layout_entries())
+            .with_target(ColorTarget::new(color_format).with_blend(wgpu::BlendState::REPLACE))
+            .with_depth(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            })
+    }
+
+    /// Registers a pipeline drawing with `shader` (see
+    /// [`MeshRenderer::pipeline_desc`]) for use by materials. `shader` must
+    /// come from [`MeshRenderer::pipelines`].
+    pub fn add_pipeline(&mut self, shader: ShaderId, label: &str) -> PipelineId {
+        let desc = self.pipeline_desc(shader, label);
+        self.mesh_pipelines.push(desc);
+        PipelineId(self.mesh_pipelines.len() as u32 - 1)
+    }
+
+    pub fn antialiasing(&self) -> AntiAliasing {
+        self.antialias.mode()
+    }
+
+    /// Switches the anti-aliasing mode, reallocating targets and rebuilding
+    /// pipelines when the sample count changes.
+    pub fn set_antialiasing(&mut self, device: &wgpu::Device, mode: AntiAliasing) {
+        let mode = mode.normalized();
+        if mode == self.antialias.mode() {
+            return;
+        }
+        let samples = mode.sample_count();
+        let rebuild = samples != self.antialias.mode().sample_count();
+        let (width, height) = self.depth.size;
+        self.antialias = AntiAliasState::new(device, mode, self.color_format, (width, height));
+        if rebuild {
+            self.depth = DepthTexture::new(device, width, height, samples);
+            self.skybox_pipeline.sample_count = samples;
+            for desc in &mut self.mesh_pipelines {
+                desc.sample_count = samples;
+            }
+        }
+    }
+
+    pub fn add_mesh(&mut self, device: &wgpu::Device, data: &MeshData) -> MeshId {
+        let mesh = GpuMesh::upload(device, data);
+        self.mesh_bounds.push(mesh.sphere);
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() as u32 - 1)
+    }
+
+    pub fn add_material(&mut self, device: &wgpu::Device, desc: MaterialDesc) -> MaterialId {
+        let pipeline = desc.pipeline.unwrap_or(self.default_pipeline);
+        self.materials.push(Material::new(device, &self.material_layout, pipeline, &desc));
+        MaterialId(self.materials.len() as u32 - 1)
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Replaces the skybox and IBL maps and enables the skybox pass.
+    pub fn set_environment(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, environment: Environment) {
+        queue.write_buffer(&self.env_params, 0, bytemuck::bytes_of(&environment.params()));
+        self.env_bind_group = environment.create_bind_group(device, &self.env_layout, &self.env_sampler, &self.env_params);
+        self.environment = environment;
+        self.draw_skybox = true;
+    }
+
+    pub fn lighting(&self) -> &ClusteredLighting {
+        &self.lighting
+    }
+
+    pub fn lighting_mut(&mut self) -> &mut ClusteredLighting {
+        &mut self.lighting
+    }
+
+    pub fn camera(&self) -> &Camera3d {
+        &self.camera
+    }
+
+    pub fn set_camera(&mut self, camera: Camera3d) {
+        self.camera = camera;
+    }
+
+    /// Queues one instance of `mesh` for this frame.
+    pub fn draw(&mut self, mesh: MeshId, material: MaterialId, transform: Mat4) {
+        let pipeline = self.materials[material.0 as usize].pipeline;
+        self.batcher.push(BatchKey { pipeline, material, mesh }, transform);
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if width == 0 || height == 0 { return; }
+        let mode = self.antialias.mode();
+        self.depth = DepthTexture::new(device, width, height, mode.sample_count());
+        self.antialias = AntiAliasState::new(device, mode, self.color_format, (width, height));
+        self.camera.aspect = width as f32 / height as f32;
+    }
+
+    /// Switches between CPU culling with direct draws and GPU culling with
+    /// indirect draws; see [`GpuDriven`]. [`MeshRenderer::frustum_culling`]
+    /// only applies to the CPU path.
+    pub fn set_gpu_driven(&mut self, device: &wgpu::Device, enabled: bool) {
+        if enabled != self.gpu_driven.is_some() {
+            self.gpu_driven = enabled.then(|| GpuDriven::new(device, &self.pipelines));
+        }
+    }
+
+    pub fn gpu_driven(&self) -> Option<&GpuDriven> {
+        self.gpu_driven.as_ref()
+    }
+
+    pub fn gpu_driven_mut(&mut self) -> Option<&mut GpuDriven> {
+        self.gpu_driven.as_mut()
+    }
+
+    pub fn stats(&self) -> BatchStats {
+        self.batcher.stats()
+    }
+
+    pub fn cull_stats(&self) -> CullStats {
+        self.culler.stats()
+    }
+
+    /// Writes the camera, culls and uploads this frame's instances. Runs once
+    /// per `frame`, from whichever of the prepass and the mesh pass comes
+    /// first; either may be skipped.
+    fn prepare_frame(&mut self, frame: u64, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) {
+        if self.prepared_frame == Some(frame) {
+            return;
+        }
+        self.prepared_frame = Some(frame);
+        self.pipelines.reload_changed(device);
+        let jitter = self.antialias.jitter(self.depth.size);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&self.camera.jittered_uniform(jitter)));
+        if let Some(gpu) = &mut self.gpu_driven {
+            self.batcher.sort();
+            gpu.prepare(&self.pipelines, device, queue, encoder, &self.batcher, &self.meshes, &self.camera.frustum());
+            return;
+        }
+        if self.frustum_culling {
+            self.batcher.cull(&mut self.culler, &self.camera.frustum(), &self.mesh_bounds);
+        }
+        self.batcher.prepare(device, queue);
+    }
+
+    /// Records the depth/normal prepass and publishes its targets and the
+    /// camera buffer as [`prepass::DEPTH`], [`prepass::NORMALS`] and
+    /// [`prepass::CAMERA`]. Call before [`MeshRenderer::render`] with the
+    /// same `frame`, usually [`NodeContext::frame`](crate::graph::NodeContext::frame);
+    /// without MSAA the mesh pass then reuses the prepass depth.
+    ///
+    /// Every material is drawn with the built-in prepass shader, so custom
+    /// pipelines must not displace vertices.
+    pub fn prepass(
+        &mut self,
+        frame: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GraphResources,
+    ) {
+        self.prepare_frame(frame, device, queue, encoder);
+
+        let size = self.depth.size;
+        let depth = resources.ensure_texture(device, prepass::DEPTH, GraphTextureDesc::target(size, DEPTH_FORMAT)).view.clone();
+        let normals = resources.ensure_texture(device, prepass::NORMALS, GraphTextureDesc::target(size, NORMAL_FORMAT)).view.clone();
+        resources.insert_buffer(prepass::CAMERA, self.camera_buffer.clone());
+        let pipeline = self.pipelines.lock().render_pipeline(device, &self.prepass_pipeline);
+
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Prepass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &normals,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rp.set_pipeline(&pipeline);
+        rp.set_bind_group(0, &self.camera_bind_group, &[]);
+        if let Some(gpu) = &self.gpu_driven {
+            gpu.draw(&mut rp, |rp, key, bound| {
+                if bound.is_none_or(|b| b.material != key.material) {
+                    rp.set_bind_group(1, &self.materials[key.material.0 as usize].bind_group, &[]);
+                }
+            });
+        } else {
+            rp.set_vertex_buffer(1, self.batcher.instance_buffer().slice(..));
+            let mut bound: Option<BatchKey> = None;
+            for batch in self.batcher.batches() {
+                let key = batch.key;
+                if bound.is_none_or(|b| b.material != key.material) {
+                    rp.set_bind_group(1, &self.materials[key.material.0 as usize].bind_group, &[]);
+                }
+                let mesh = &self.meshes[key.mesh.0 as usize];
+                if bound.is_none_or(|b| b.mesh != key.mesh) {
+                    rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                }
+                rp.draw_indexed(0..mesh.index_count, 0, batch.instances.clone());
+                bound = Some(key);
+            }
+        }
+        drop(rp);
+
+        self.prepass_depth = Some((frame, depth));
+    }
+
+    /// Uploads this frame's instances and records the mesh pass, plus any
+    /// anti-aliasing resolve, into `encoder`. `view` must have the renderer's
+    /// color format and size. `frame` identifies the frame, as in
+    /// [`MeshRenderer::prepass`].
+    pub fn render(
+        &mut self,
+        frame: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        self.prepare_frame(frame, device, queue, encoder);
+        // Depth from an earlier frame's prepass is stale.
+        let prepass_depth = self.prepass_depth.take().filter(|(f, _)| *f == frame).map(|(_, depth)| depth);
+        let single_sampled = self.antialias.mode().sample_count() == 1;
+        let reused_depth = prepass_depth.as_ref().filter(|_| single_sampled);
+
+        let (width, height) = self.depth.size;
+        self.lighting.prepare(device, queue, &self.camera, width, height);
+        self.lighting.cull(&self.pipelines, device, encoder, &self.camera_bind_group);
+        let (mesh_pipelines, skybox_pipeline) = {
+            let mut pipelines = self.pipelines.lock();
+            let mesh: Vec<_> = self.mesh_pipelines.iter().map(|desc| pipelines.render_pipeline(device, desc)).collect();
+            (mesh, self.draw_skybox.then(|| pipelines.render_pipeline(device, &self.skybox_pipeline)))
+        };
+
+        let load = match self.clear_color {
+            Some(c) => wgpu::LoadOp::Clear(c),
+            None => wgpu::LoadOp::Load,
+        };
+        let (color, resolve_target) = self.antialias.attachment(view);
+        let (depth, depth_load) = match reused_depth {
+            Some(depth) => (depth, wgpu::LoadOp::Load),
+            None => (&self.depth.view, wgpu::LoadOp::Clear(1.0)),
+        };
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mesh Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations { load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations { load: depth_load, store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rp.set_bind_group(0, &self.camera_bind_group, &[]);
+        rp.set_bind_group(2, &self.env_bind_group, &[]);
+        rp.set_bind_group(3, self.lighting.shade_bind_group(), &[]);
+        if let Some(gpu) = &self.gpu_driven {
+            gpu.draw(&mut rp, |rp, key, bound| {
+                if bound.is_none_or(|b| b.pipeline != key.pipeline) {
+                    rp.set_pipeline(&mesh_pipelines[key.pipeline.0 as usize]);
+                }
+                if bound.is_none_or(|b| b.material != key.material) {
+                    rp.set_bind_group(1, &self.materials[key.material.0 as usize].bind_group, &[]);
+                }
+            });
+        } else {
+            rp.set_vertex_buffer(1, self.batcher.instance_buffer().slice(..));
+
+            let mut bound: Option<BatchKey> = None;
+            for batch in self.batcher.batches() {
+                let key = batch.key;
+                if bound.is_none_or(|b| b.pipeline != key.pipeline) {
+                    rp.set_pipeline(&mesh_pipelines[key.pipeline.0 as usize]);
+                }
+                if bound.is_none_or(|b| b.material != key.material) {
+                    rp.set_bind_group(1, &self.materials[key.material.0 as usize].bind_group, &[]);
+                }
+                if bound.is_none_or(|b| b.mesh != key.mesh) {
+                    let mesh = &self.meshes[key.mesh.0 as usize];
+                    rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                }
+                let mesh = &self.meshes[key.mesh.0 as usize];
+                rp.draw_indexed(0..mesh.index_count, 0, batch.instances.clone());
+                bound = Some(key);
+            }
+        }
+
+        if let Some(skybox_pipeline) = &skybox_pipeline {
+            rp.set_pipeline(skybox_pipeline);
+            rp.set_bind_group(1, &self.env_bind_group, &[]);
+            rp.draw(0..3, 0..1);
+        }
+        drop(rp);
+
+        let depth = reused_depth.unwrap_or(&self.depth.view);
+        self.antialias.resolve(&self.pipelines, device, queue, encoder, view, depth, self.camera.view_proj());
+
+        if let Some(gpu) = &mut self.gpu_driven {
+            let depth = prepass_depth.as_ref().or(single_sampled.then_some(&self.depth.view));
+            gpu.update_hiz(&self.pipelines, device, encoder, depth, self.depth.size, self.camera.view_proj());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()
+    }
+
+    #[test]
+    fn prepass_only_frames_dont_skip_later_preparation() {
+        let Some((device, queue)) = device() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let mut renderer = MeshRenderer::new(&device, &queue, format, 4, 4);
+        renderer.frustum_culling = false;
+        let mesh = renderer.add_mesh(&device, &MeshData::cube(0.5));
+        let material = renderer.add_material(&device, MaterialDesc::default());
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = target.create_view(&Default::default());
+        let mut resources = GraphResources::default();
+        let mut encoder = device.create_command_encoder(&Default::default());
+
+        // A depth-only frame: the prepass runs, the mesh pass doesn't.
+        renderer.draw(mesh, material, Mat4::IDENTITY);
+        renderer.prepass(0, &device, &queue, &mut encoder, &mut resources);
+        assert_eq!(renderer.stats().requests, 1);
+
+        for _ in 0..2 {
+            renderer.draw(mesh, material, Mat4::IDENTITY);
+        }
+        renderer.prepass(1, &device, &queue, &mut encoder, &mut resources);
+        renderer.render(1, &device, &queue, &mut encoder, &view);
+        assert_eq!(renderer.stats().requests, 2);
+
+        // Without a prepass the mesh pass prepares on its own.
+        renderer.draw(mesh, material, Mat4::IDENTITY);
+        renderer.render(2, &device, &queue, &mut encoder, &view);
+        assert_eq!(renderer.stats().requests, 1);
+        queue.submit(Some(encoder.finish()));
+    }
+}
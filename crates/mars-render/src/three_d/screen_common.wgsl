@@ -0,0 +1,32 @@
+struct Camera {
+  view_proj: mat4x4<f32>,
+  view: mat4x4<f32>,
+  proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+fn oct_decode(e: vec2<f32>) -> vec3<f32> {
+  var n = vec3<f32>(e, 1.0 - abs(e.x) - abs(e.y));
+  if (n.z < 0.0) {
+    n = vec3<f32>((1.0 - abs(n.yx)) * select(vec2<f32>(-1.0), vec2<f32>(1.0), n.xy >= vec2<f32>(0.0)), n.z);
+  }
+  return normalize(n);
+}
+
+// View-space position from a uv and a 0..1 depth, for any perspective
+// projection (including a jittered one).
+fn view_position(uv: vec2<f32>, depth: f32, proj: mat4x4<f32>) -> vec3<f32> {
+  let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+  let z = -proj[3][2] / (proj[2][2] + depth);
+  let x = -z * (ndc.x + proj[2][0]) / proj[0][0];
+  let y = -z * (ndc.y + proj[2][1]) / proj[1][1];
+  return vec3<f32>(x, y, z);
+}
+
+// Screen uv and 0..1 depth of a view-space position.
+fn project(p: vec3<f32>, proj: mat4x4<f32>) -> vec3<f32> {
+  let clip = proj * vec4<f32>(p, 1.0);
+  let ndc = clip.xyz / clip.w;
+  return vec3<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, ndc.z);
+}
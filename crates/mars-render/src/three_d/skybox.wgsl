@@ -0,0 +1,42 @@
+struct Camera {
+  view_proj: mat4x4<f32>,
+  view: mat4x4<f32>,
+  proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+struct EnvParams {
+  intensity: f32,
+  max_lod: f32,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(3) var env_sampler: sampler;
+@group(1) @binding(4) var skybox: texture_cube<f32>;
+@group(1) @binding(5) var<uniform> env: EnvParams;
+
+struct VsOut {
+  @builtin(position) clip: vec4<f32>,
+  @location(0) ndc: vec2<f32>,
+};
+
+// Fullscreen triangle at the far plane; drawn after opaque geometry with a
+// LessEqual depth test so only uncovered pixels are shaded.
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VsOut {
+  let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+  let ndc = uv * 2.0 - 1.0;
+  var out: VsOut;
+  out.clip = vec4<f32>(ndc, 1.0, 1.0);
+  out.ndc = ndc;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4<f32> {
+  let far = camera.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+  let dir = normalize(far.xyz / far.w - camera.position.xyz);
+  return vec4<f32>(textureSample(skybox, env_sampler, dir).rgb * env.intensity, 1.0);
+}
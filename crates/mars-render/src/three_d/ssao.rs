@@ -0,0 +1,190 @@
+//! Screen-space ambient occlusion over the depth/normal prepass.
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+
+use crate::binding;
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{GraphTextureDesc, NodeContext, RenderNode, SCENE_COLOR};
+use super::antialias::halton;
+use super::prepass;
+
+const SSAO_TEXTURE: &str = "ssao";
+const SSAO_BLUR_TEXTURE: &str = "ssao.blur";
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+const MAX_SAMPLES: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SsaoQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl SsaoQuality {
+    fn sample_count(self) -> u32 {
+        match self {
+            SsaoQuality::Low => 8,
+            SsaoQuality::Medium => 16,
+            SsaoQuality::High => 32,
+        }
+    }
+
+    fn blur_radius(self) -> i32 {
+        match self {
+            SsaoQuality::Low => 2,
+            SsaoQuality::Medium => 4,
+            SsaoQuality::High => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsaoSettings {
+    pub quality: SsaoQuality,
+    /// Sampling radius in view-space units.
+    pub radius: f32,
+    /// Depth offset against self-occlusion on flat surfaces.
+    pub bias: f32,
+    /// Exponent applied to the ambient term.
+    pub intensity: f32,
+    /// How strongly the blur stops at depth discontinuities.
+    pub depth_sensitivity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self { quality: SsaoQuality::default(), radius: 0.5, bias: 0.025, intensity: 1.5, depth_sensitivity: 20.0 }
+    }
+}
+
+impl SsaoSettings {
+    pub fn preset(quality: SsaoQuality) -> Self {
+        Self { quality, ..Default::default() }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct SsaoParams {
+    kernel: [[f32; 4]; MAX_SAMPLES],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+    direction: [f32; 2],
+    depth_sigma: f32,
+    blur_radius: i32,
+}
+
+/// Hemisphere samples with z > 0, denser towards the origin.
+fn kernel(count: u32) -> [[f32; 4]; MAX_SAMPLES] {
+    let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+    for (i, sample) in kernel.iter_mut().enumerate().take(count as usize) {
+        let index = i as u32 + 1;
+        let phi = halton(index, 2) * std::f32::consts::TAU;
+        let cos_theta = (1.0 - halton(index, 3)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let t = i as f32 / count as f32;
+        let length = halton(index, 5).max(0.1) * (0.1 + 0.9 * t * t);
+        *sample = [phi.cos() * sin_theta * length, phi.sin() * sin_theta * length, cos_theta * length, 0.0];
+    }
+    kernel
+}
+
+/// Ambient occlusion from the prepass depth and normals, blurred with a
+/// depth-aware separable filter and multiplied into the target.
+///
+/// Needs [`MeshRenderer::prepass`](super::MeshRenderer::prepass) to have run
+/// earlier in the frame. Writes [`SCENE_COLOR`] unless retargeted.
+pub struct Ssao {
+    pub settings: SsaoSettings,
+    pub enabled: bool,
+    target: &'static str,
+    occlusion: FullscreenPass,
+    blur_x: FullscreenPass,
+    blur_y: FullscreenPass,
+    composite: FullscreenPass,
+}
+
+impl Ssao {
+    pub fn new(device: &wgpu::Device, settings: SsaoSettings) -> Self {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let extra = [
+            binding::unfilterable_entry(3, fragment),
+            binding::buffer_entry(4, fragment, wgpu::BufferBindingType::Uniform),
+        ];
+        let multiply = wgpu::BlendState {
+            color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::Src, operation: wgpu::BlendOperation::Add },
+            alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+        };
+        let pass = |label, entry, blend| FullscreenPass::new::<SsaoParams>(device, label, "mars/three_d/ssao.wgsl", entry, &extra, blend).without_srgb_encode();
+        Self {
+            settings,
+            enabled: true,
+            target: SCENE_COLOR,
+            occlusion: pass("SSAO", "fs_occlusion", None),
+            blur_x: pass("SSAO Blur X", "fs_blur", None),
+            blur_y: pass("SSAO Blur Y", "fs_blur", None),
+            composite: pass("SSAO Composite", "fs_composite", Some(multiply)),
+        }
+    }
+
+    /// Applies the occlusion to the named graph texture instead.
+    pub fn with_target(mut self, target: &'static str) -> Self {
+        self.target = target;
+        self
+    }
+}
+
+impl RenderNode for Ssao {
+    fn name(&self) -> &'static str {
+        "ssao"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let depth = ctx.resources.texture(prepass::DEPTH).context(prepass::MISSING)?;
+        let size = depth.desc.size;
+        let depth = depth.view.clone();
+        let normals = ctx.resources.texture(prepass::NORMALS).context(prepass::MISSING)?.view.clone();
+        let camera = ctx.resources.buffer(prepass::CAMERA).context(prepass::MISSING)?.clone();
+        let target = ctx.resources.texture(self.target).with_context(|| format!("SSAO target `{}` missing", self.target))?;
+        let (target, format) = (target.view.clone(), target.desc.format);
+
+        let s = self.settings;
+        let mut params = SsaoParams {
+            kernel: kernel(s.quality.sample_count()),
+            radius: s.radius,
+            bias: s.bias,
+            intensity: s.intensity,
+            sample_count: s.quality.sample_count(),
+            direction: [1.0, 0.0],
+            depth_sigma: s.depth_sensitivity,
+            blur_radius: s.quality.blur_radius(),
+        };
+        self.occlusion.write_params(ctx.queue, &params);
+        self.blur_x.write_params(ctx.queue, &params);
+        self.composite.write_params(ctx.queue, &params);
+        params.direction = [0.0, 1.0];
+        self.blur_y.write_params(ctx.queue, &params);
+
+        let ao = ctx.resources.ensure_texture(ctx.device, SSAO_TEXTURE, GraphTextureDesc::target(size, AO_FORMAT)).view.clone();
+        let blurred = ctx.resources.ensure_texture(ctx.device, SSAO_BLUR_TEXTURE, GraphTextureDesc::target(size, AO_FORMAT)).view.clone();
+        let extra = [
+            wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&depth) },
+            wgpu::BindGroupEntry { binding: 4, resource: camera.as_entire_binding() },
+        ];
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("SSAO") });
+        self.occlusion.draw(ctx.pipelines, ctx.device, &mut encoder, &normals, &ao, AO_FORMAT, &extra, false);
+        self.blur_x.draw(ctx.pipelines, ctx.device, &mut encoder, &ao, &blurred, AO_FORMAT, &extra, false);
+        self.blur_y.draw(ctx.pipelines, ctx.device, &mut encoder, &blurred, &ao, AO_FORMAT, &extra, false);
+        self.composite.draw(ctx.pipelines, ctx.device, &mut encoder, &ao, &target, format, &extra, true);
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
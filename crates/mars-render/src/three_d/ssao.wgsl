@@ -0,0 +1,96 @@
+#import mars::fullscreen
+#import mars::three_d::screen_common
+
+struct SsaoParams {
+  kernel: array<vec4<f32>, 32>,
+  radius: f32,
+  bias: f32,
+  intensity: f32,
+  sample_count: u32,
+  // Blur step in pixels; unused by the occlusion pass.
+  direction: vec2<f32>,
+  depth_sigma: f32,
+  blur_radius: i32,
+};
+
+@group(0) @binding(2) var<uniform> params: SsaoParams;
+@group(0) @binding(3) var depth_tex: texture_2d<f32>;
+@group(0) @binding(4) var<uniform> camera: Camera;
+
+fn noise(p: vec2<u32>) -> f32 {
+  var h = p.x * 1664525u + p.y * 1013904223u;
+  h ^= h >> 16u;
+  h *= 2246822519u;
+  h ^= h >> 13u;
+  return f32(h & 0xffffu) / 65535.0;
+}
+
+fn linear_depth(pixel: vec2<i32>) -> f32 {
+  let dims = vec2<f32>(textureDimensions(depth_tex));
+  let uv = (vec2<f32>(pixel) + 0.5) / dims;
+  return view_position(uv, textureLoad(depth_tex, pixel, 0).r, camera.proj).z;
+}
+
+// Hemisphere occlusion around the view-space normal (src holds the prepass
+// normals), with a per-pixel random rotation of the kernel.
+@fragment
+fn fs_occlusion(in: VsOut) -> @location(0) vec4<f32> {
+  let pixel = vec2<i32>(in.clip.xy);
+  let depth = textureLoad(depth_tex, pixel, 0).r;
+  if (depth >= 1.0) {
+    return vec4<f32>(1.0);
+  }
+  let dims = vec2<f32>(textureDimensions(depth_tex));
+  let p = view_position(in.uv, depth, camera.proj);
+  let n = oct_decode(textureLoad(src, pixel, 0).xy);
+
+  let angle = noise(vec2<u32>(pixel)) * 6.2831853;
+  let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+  var t = random - n * dot(random, n);
+  if (dot(t, t) < 1e-4) {
+    t = vec3<f32>(0.0, 0.0, 1.0) - n * n.z;
+  }
+  t = normalize(t);
+  let tbn = mat3x3<f32>(t, cross(n, t), n);
+
+  var occlusion = 0.0;
+  for (var i = 0u; i < params.sample_count; i++) {
+    let s = p + tbn * params.kernel[i].xyz * params.radius;
+    let q = project(s, camera.proj);
+    if (any(q.xy < vec2<f32>(0.0)) || any(q.xy > vec2<f32>(1.0))) {
+      continue;
+    }
+    let scene_z = linear_depth(vec2<i32>(q.xy * dims));
+    let range = smoothstep(0.0, 1.0, params.radius / max(abs(p.z - scene_z), 1e-4));
+    occlusion += select(0.0, 1.0, scene_z >= s.z + params.bias) * range;
+  }
+  let ao = pow(1.0 - occlusion / f32(max(params.sample_count, 1u)), params.intensity);
+  return vec4<f32>(ao, ao, ao, 1.0);
+}
+
+// Separable gaussian that does not blur across depth discontinuities.
+@fragment
+fn fs_blur(in: VsOut) -> @location(0) vec4<f32> {
+  let pixel = vec2<i32>(in.clip.xy);
+  let dims = vec2<i32>(textureDimensions(src));
+  let center_z = linear_depth(pixel);
+  let step = vec2<i32>(params.direction);
+  let sigma = f32(params.blur_radius) * 0.5 + 0.5;
+  var sum = 0.0;
+  var weight = 0.0;
+  for (var i = -params.blur_radius; i <= params.blur_radius; i++) {
+    let q = clamp(pixel + step * i, vec2<i32>(0), dims - 1);
+    let dz = abs(linear_depth(q) - center_z) / max(abs(center_z), 1e-4);
+    let w = exp(-f32(i * i) / (2.0 * sigma * sigma)) * exp(-dz * params.depth_sigma);
+    sum += textureLoad(src, q, 0).r * w;
+    weight += w;
+  }
+  let ao = sum / max(weight, 1e-4);
+  return vec4<f32>(ao, ao, ao, 1.0);
+}
+
+// Blended multiplicatively onto the scene color.
+@fragment
+fn fs_composite(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(textureSampleLevel(src, src_sampler, in.uv, 0.0).rrr, 1.0);
+}
@@ -0,0 +1,212 @@
+//! Screen-space reflections traced through a hierarchical depth buffer.
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+
+use crate::binding;
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{GraphTextureDesc, NodeContext, RenderNode, SCENE_COLOR};
+use super::environment::Environment;
+use super::hiz::{HiZ, HIZ_FORMAT};
+use super::prepass;
+
+const HIZ_TEXTURE: &str = "ssr.hiz";
+const SSR_TEXTURE: &str = "ssr";
+const SSR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SsrQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl SsrQuality {
+    fn max_steps(self) -> u32 {
+        match self {
+            SsrQuality::Low => 32,
+            SsrQuality::Medium => 64,
+            SsrQuality::High => 128,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsrSettings {
+    pub quality: SsrQuality,
+    /// Longest reflected ray in view-space units.
+    pub max_distance: f32,
+    /// View-space depth behind a surface that still counts as a hit.
+    pub thickness: f32,
+    /// Surfaces rougher than this get no reflections.
+    pub max_roughness: f32,
+    pub intensity: f32,
+}
+
+impl Default for SsrSettings {
+    fn default() -> Self {
+        Self { quality: SsrQuality::default(), max_distance: 50.0, thickness: 0.5, max_roughness: 0.6, intensity: 1.0 }
+    }
+}
+
+impl SsrSettings {
+    pub fn preset(quality: SsrQuality) -> Self {
+        Self { quality, ..Default::default() }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct SsrParams {
+    max_steps: u32,
+    max_level: u32,
+    thickness: f32,
+    max_distance: f32,
+    max_roughness: f32,
+    intensity: f32,
+    env_max_lod: f32,
+    env_intensity: f32,
+}
+
+/// Reflections found by marching the prepass depth pyramid, falling back to
+/// the environment's specular cube where the ray leaves the screen or misses,
+/// then alpha-blended over the target by Fresnel and roughness.
+///
+/// Needs [`MeshRenderer::prepass`](super::MeshRenderer::prepass) to have run
+/// earlier in the frame. Reads and writes [`SCENE_COLOR`] unless retargeted.
+pub struct Ssr {
+    pub settings: SsrSettings,
+    pub enabled: bool,
+    target: &'static str,
+    hiz: HiZ,
+    trace: FullscreenPass,
+    composite: FullscreenPass,
+    env_view: wgpu::TextureView,
+    env_max_lod: f32,
+    env_intensity: f32,
+}
+
+impl Ssr {
+    pub fn new(device: &wgpu::Device, settings: SsrSettings) -> Self {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let extra = [
+            binding::unfilterable_entry(3, fragment),
+            binding::unfilterable_entry(4, fragment),
+            binding::unfilterable_entry(5, fragment),
+            binding::buffer_entry(6, fragment, wgpu::BufferBindingType::Uniform),
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: fragment,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+
+        // Black until an environment is set.
+        let black = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("SSR Fallback Cube"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 6 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let env_view = black.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        Self {
+            settings,
+            enabled: true,
+            target: SCENE_COLOR,
+            hiz: HiZ::new(false),
+            trace: FullscreenPass::new::<SsrParams>(device, "SSR Trace", "mars/three_d/ssr.wgsl", "fs_trace", &extra, None).without_srgb_encode(),
+            composite: FullscreenPass::new::<SsrParams>(device, "SSR Composite", "mars/three_d/ssr.wgsl", "fs_composite", &extra, Some(wgpu::BlendState::ALPHA_BLENDING))
+                .without_srgb_encode(),
+            env_view,
+            env_max_lod: 0.0,
+            env_intensity: 0.0,
+        }
+    }
+
+    /// Reads and writes the named graph texture instead.
+    pub fn with_target(mut self, target: &'static str) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Uses the environment's prefiltered specular cube where the trace
+    /// finds no on-screen hit.
+    pub fn set_environment(&mut self, environment: &Environment) {
+        let params = environment.params();
+        self.env_view = environment.specular.view.clone();
+        self.env_max_lod = params.max_lod;
+        self.env_intensity = params.intensity;
+    }
+}
+
+impl RenderNode for Ssr {
+    fn name(&self) -> &'static str {
+        "ssr"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let depth = ctx.resources.texture(prepass::DEPTH).context(prepass::MISSING)?;
+        let size = depth.desc.size;
+        let depth = depth.view.clone();
+        let normals = ctx.resources.texture(prepass::NORMALS).context(prepass::MISSING)?.view.clone();
+        let camera = ctx.resources.buffer(prepass::CAMERA).context(prepass::MISSING)?.clone();
+        let target = ctx.resources.texture(self.target).with_context(|| format!("SSR target `{}` missing", self.target))?;
+        let (target, format) = (target.view.clone(), target.desc.format);
+
+        let mips = HiZ::mip_count(size);
+        let hiz_desc = GraphTextureDesc {
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            mip_levels: mips,
+            ..GraphTextureDesc::target(size, HIZ_FORMAT)
+        };
+        let hiz = ctx.resources.ensure_texture(ctx.device, HIZ_TEXTURE, hiz_desc);
+        let (hiz_texture, hiz_view) = (hiz.texture.clone(), hiz.view.clone());
+        let reflections = ctx.resources.ensure_texture(ctx.device, SSR_TEXTURE, GraphTextureDesc::target(size, SSR_FORMAT)).view.clone();
+
+        let s = self.settings;
+        let params = SsrParams {
+            max_steps: s.quality.max_steps(),
+            max_level: mips - 1,
+            thickness: s.thickness,
+            max_distance: s.max_distance,
+            max_roughness: s.max_roughness,
+            intensity: s.intensity,
+            env_max_lod: self.env_max_lod,
+            env_intensity: self.env_intensity,
+        };
+        self.trace.write_params(ctx.queue, &params);
+        self.composite.write_params(ctx.queue, &params);
+
+        let extra = [
+            wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&depth) },
+            wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&normals) },
+            wgpu::BindGroupEntry { binding: 5, resource: wgpu::BindingResource::TextureView(&hiz_view) },
+            wgpu::BindGroupEntry { binding: 6, resource: camera.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 7, resource: wgpu::BindingResource::TextureView(&self.env_view) },
+        ];
+
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("SSR") });
+        self.hiz.build(ctx.pipelines, ctx.device, &mut encoder, &depth, &hiz_texture);
+        self.trace.draw(ctx.pipelines, ctx.device, &mut encoder, &target, &reflections, SSR_FORMAT, &extra, false);
+        self.composite.draw(ctx.pipelines, ctx.device, &mut encoder, &reflections, &target, format, &extra, true);
+        ctx.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+#import mars::fullscreen
+#import mars::three_d::screen_common
+
+struct SsrParams {
+  max_steps: u32,
+  max_level: u32,
+  // View-space depth range behind a surface still counted as a hit.
+  thickness: f32,
+  max_distance: f32,
+  max_roughness: f32,
+  intensity: f32,
+  env_max_lod: f32,
+  env_intensity: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: SsrParams;
+@group(0) @binding(3) var depth_tex: texture_2d<f32>;
+@group(0) @binding(4) var normal_tex: texture_2d<f32>;
+@group(0) @binding(5) var hiz: texture_2d<f32>;
+@group(0) @binding(6) var<uniform> camera: Camera;
+@group(0) @binding(7) var env_map: texture_cube<f32>;
+
+struct Hit {
+  uv: vec2<f32>,
+  t: f32,
+  found: bool,
+};
+
+// Min-Z hierarchical traversal: the ray (uv, depth) = o + d * t, t in [0, 1],
+// climbs the Hi-Z pyramid while it passes in front of whole cells and
+// descends when it may intersect one.
+fn trace(o: vec3<f32>, d: vec3<f32>) -> Hit {
+  var hit = Hit(vec2<f32>(0.0), 0.0, false);
+  let dims0 = vec2<f32>(textureDimensions(hiz, 0));
+  // Start one pixel away to avoid self-intersection.
+  var t = 1.5 / max(length(d.xy * dims0), 1e-4);
+  var level = 0i;
+  let cross_step = select(vec2<f32>(0.0), vec2<f32>(1.0), d.xy > vec2<f32>(0.0));
+  for (var i = 0u; i < params.max_steps; i++) {
+    if (t > 1.0) {
+      break;
+    }
+    let p = o + d * t;
+    if (any(p.xy < vec2<f32>(0.0)) || any(p.xy > vec2<f32>(1.0))) {
+      break;
+    }
+    let dims = vec2<f32>(textureDimensions(hiz, level));
+    let cell = floor(p.xy * dims);
+    let z_min = textureLoad(hiz, vec2<i32>(cell), level).r;
+
+    // Parameter at which the ray leaves the cell in screen space.
+    let boundary = (cell + cross_step) / dims;
+    let t_xy = (boundary - o.xy) / select(d.xy, vec2<f32>(1e-6), abs(d.xy) < vec2<f32>(1e-6));
+    let t_exit = min(select(t_xy.x, 1e6, t_xy.x <= t), select(t_xy.y, 1e6, t_xy.y <= t)) + 1e-5;
+
+    if (p.z < z_min) {
+      let t_z = select(1e6, (z_min - o.z) / d.z, d.z > 0.0);
+      if (t_z < t_exit) {
+        t = max(t_z, t);
+        level = max(level - 1, 0);
+        if (level == 0 && t_z <= t) {
+          t += 1e-5;
+        }
+      } else {
+        t = t_exit;
+        level = min(level + 1, i32(params.max_level));
+      }
+    } else if (level > 0) {
+      level -= 1;
+    } else {
+      let surface_z = view_position(p.xy, z_min, camera.proj).z;
+      let ray_z = view_position(p.xy, p.z, camera.proj).z;
+      if (surface_z - ray_z < params.thickness) {
+        hit = Hit(p.xy, t, true);
+        break;
+      }
+      t = t_exit;
+    }
+  }
+  return hit;
+}
+
+@fragment
+fn fs_trace(in: VsOut) -> @location(0) vec4<f32> {
+  let pixel = vec2<i32>(in.clip.xy);
+  let depth = textureLoad(depth_tex, pixel, 0).r;
+  let surface = textureLoad(normal_tex, pixel, 0);
+  let roughness = surface.z;
+  if (depth >= 1.0 || roughness > params.max_roughness) {
+    return vec4<f32>(0.0);
+  }
+
+  let p = view_position(in.uv, depth, camera.proj);
+  let n = oct_decode(surface.xy);
+  let v = normalize(p);
+  let r = reflect(v, n);
+
+  // Keep the ray end in front of the near plane.
+  let near = camera.proj[3][2] / camera.proj[2][2];
+  var distance = params.max_distance;
+  if (r.z > 0.0) {
+    distance = min(distance, (-near * 1.01 - p.z) / r.z);
+  }
+  let o = project(p, camera.proj);
+  let e = project(p + r * distance, camera.proj);
+
+  let inv_view_rot = transpose(mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz));
+  let env_dir = inv_view_rot * r;
+  var color = textureSampleLevel(env_map, src_sampler, env_dir, roughness * params.env_max_lod).rgb * params.env_intensity;
+
+  let hit = trace(o, e - o);
+  if (hit.found) {
+    let edge = min(hit.uv, 1.0 - hit.uv);
+    let confidence = smoothstep(0.0, 0.1, min(edge.x, edge.y)) * (1.0 - hit.t * hit.t);
+    color = mix(color, textureSampleLevel(src, src_sampler, hit.uv, 0.0).rgb, confidence);
+  }
+
+  let f0 = mix(0.04, 1.0, surface.w);
+  let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(n, -v), 0.0), 5.0);
+  let gloss = 1.0 - smoothstep(params.max_roughness * 0.5, params.max_roughness, roughness);
+  return vec4<f32>(color, clamp(fresnel * gloss * params.intensity, 0.0, 1.0));
+}
+
+// Alpha-blended onto the scene color.
+@fragment
+fn fs_composite(in: VsOut) -> @location(0) vec4<f32> {
+  return textureSampleLevel(src, src_sampler, in.uv, 0.0);
+}
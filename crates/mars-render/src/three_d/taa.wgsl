@@ -0,0 +1,81 @@
+#import mars::fullscreen
+
+struct TaaParams {
+  // Unjittered current and previous camera matrices.
+  inv_view_proj: mat4x4<f32>,
+  prev_view_proj: mat4x4<f32>,
+  // Weight of the current frame.
+  blend: f32,
+  // Set on the first frame or after the history was invalidated.
+  reset: u32,
+  _pad: vec2<f32>,
+};
+
+@group(0) @binding(2) var<uniform> params: TaaParams;
+// Depth bound as unfilterable float so it can be loaded on every backend.
+@group(0) @binding(3) var depth_tex: texture_2d<f32>;
+@group(0) @binding(4) var history: texture_2d<f32>;
+
+// Reversible tonemap so bright samples don't dominate the blend.
+fn compress(c: vec3<f32>) -> vec3<f32> {
+  return c / (1.0 + luminance(c));
+}
+
+fn uncompress(c: vec3<f32>) -> vec3<f32> {
+  return c / max(1.0 - luminance(c), 1e-4);
+}
+
+fn to_ycocg(c: vec3<f32>) -> vec3<f32> {
+  return vec3<f32>(
+    0.25 * c.r + 0.5 * c.g + 0.25 * c.b,
+    0.5 * c.r - 0.5 * c.b,
+    -0.25 * c.r + 0.5 * c.g - 0.25 * c.b,
+  );
+}
+
+fn from_ycocg(c: vec3<f32>) -> vec3<f32> {
+  return vec3<f32>(c.x + c.y - c.z, c.x + c.z, c.x - c.y - c.z);
+}
+
+@fragment
+fn fs_resolve(in: VsOut) -> @location(0) vec4<f32> {
+  let dims = vec2<i32>(textureDimensions(src));
+  let pixel = vec2<i32>(in.clip.xy);
+
+  // Neighborhood bounds of the current frame, used to reject stale history.
+  var lo = vec3<f32>(1e9);
+  var hi = vec3<f32>(-1e9);
+  var current = vec3<f32>(0.0);
+  for (var y = -1; y <= 1; y++) {
+    for (var x = -1; x <= 1; x++) {
+      let p = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), dims - 1);
+      let c = to_ycocg(compress(textureLoad(src, p, 0).rgb));
+      lo = min(lo, c);
+      hi = max(hi, c);
+      if (x == 0 && y == 0) {
+        current = c;
+      }
+    }
+  }
+  if (params.reset != 0u) {
+    return vec4<f32>(uncompress(from_ycocg(current)), 1.0);
+  }
+
+  // Reproject using depth; only camera motion is accounted for.
+  let depth = textureLoad(depth_tex, pixel, 0).r;
+  let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+  let world = params.inv_view_proj * ndc;
+  let prev = params.prev_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+  let prev_uv = vec2<f32>(prev.x / prev.w * 0.5 + 0.5, 0.5 - prev.y / prev.w * 0.5);
+  if (any(prev_uv < vec2<f32>(0.0)) || any(prev_uv > vec2<f32>(1.0))) {
+    return vec4<f32>(uncompress(from_ycocg(current)), 1.0);
+  }
+
+  let hist = clamp(to_ycocg(compress(textureSampleLevel(history, src_sampler, prev_uv, 0.0).rgb)), lo, hi);
+  return vec4<f32>(uncompress(from_ycocg(mix(hist, current, params.blend))), 1.0);
+}
+
+@fragment
+fn fs_blit(in: VsOut) -> @location(0) vec4<f32> {
+  return finish(textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb);
+}
@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Frames that may be in flight before [`GpuTimer::begin`] skips one.
+const SLOTS: u32 = 4;
+
+/// [`Slot::mapped`] states, set by the `map_async` callback.
+const WAITING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+struct Slot {
+    readback: wgpu::Buffer,
+    pending: bool,
+    mapped: Arc<AtomicU8>,
+}
+
+/// Measures GPU time between [`begin`](GpuTimer::begin) and
+/// [`end`](GpuTimer::end) with timestamp queries.
+///
+/// Results arrive a few frames late and are read back without stalling;
+/// [`GpuTimer::poll`] returns the newest one that arrived since the last
+/// poll, and [`GpuTimer::latest_ms`] the newest overall.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    slots: Vec<Slot>,
+    current: Option<u32>,
+    period_ns: f32,
+    latest_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    /// Returns `None` if the device lacks [`wgpu::Features::TIMESTAMP_QUERY`].
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU Timer Queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: SLOTS * 2,
+        });
+        let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Timer Resolve"),
+            size: (SLOTS * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT as u32) as u64,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let slots = (0..SLOTS)
+            .map(|_| Slot {
+                readback: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("GPU Timer Readback"),
+                    size: 2 * wgpu::QUERY_SIZE as u64,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                pending: false,
+                mapped: Arc::new(AtomicU8::new(WAITING)),
+            })
+            .collect();
+        Some(Self { query_set, resolve, slots, current: None, period_ns: queue.get_timestamp_period(), latest_ms: None })
+    }
+
+    /// Submits the start timestamp. Returns false, and measures nothing this
+    /// frame, while every slot still waits for its readback.
+    pub fn begin(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        self.current = self.slots.iter().position(|s| !s.pending).map(|i| i as u32);
+        let Some(slot) = self.current else { return false };
+        self.submit_timestamp(device, queue, slot * 2, |_| {});
+        true
+    }
+
+    /// Submits the end timestamp and queues the readback of this frame's
+    /// measurement.
+    pub fn end(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(slot) = self.current.take() else { return };
+        let offset = (slot * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT as u32) as u64;
+        let s = &self.slots[slot as usize];
+        let readback = s.readback.clone();
+        self.submit_timestamp(device, queue, slot * 2 + 1, |encoder| {
+            encoder.resolve_query_set(&self.query_set, slot * 2..slot * 2 + 2, &self.resolve, offset);
+            encoder.copy_buffer_to_buffer(&self.resolve, offset, &readback, 0, 2 * wgpu::QUERY_SIZE as u64);
+        });
+
+        let s = &mut self.slots[slot as usize];
+        s.pending = true;
+        s.mapped.store(WAITING, Ordering::Relaxed);
+        let mapped = s.mapped.clone();
+        s.readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            mapped.store(if result.is_ok() { MAPPED } else { FAILED }, Ordering::Release);
+        });
+    }
+
+    fn submit_timestamp(&self, device: &wgpu::Device, queue: &wgpu::Queue, index: u32, extra: impl FnOnce(&mut wgpu::CommandEncoder)) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("GPU Timer") });
+        // An empty pass is the portable way to write a timestamp between
+        // submissions.
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("GPU Timestamp"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+        extra(&mut encoder);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Collects finished measurements without blocking and returns the GPU
+    /// time in milliseconds of the newest one, or `None` if none arrived
+    /// since the last poll. A readback that failed to map frees its slot
+    /// without a measurement.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<f32> {
+        let _ = device.poll(wgpu::PollType::Poll);
+        let mut fresh: Option<(u64, f32)> = None;
+        for slot in self.slots.iter_mut().filter(|s| s.pending) {
+            match slot.mapped.swap(WAITING, Ordering::Acquire) {
+                MAPPED => {
+                    {
+                        let data = slot.readback.slice(..).get_mapped_range();
+                        let stamps: &[u64] = bytemuck::cast_slice(&data);
+                        let ticks = stamps[1].saturating_sub(stamps[0]);
+                        // Slots finish in any order; keep the latest frame.
+                        if fresh.is_none_or(|(start, _)| stamps[0] > start) {
+                            fresh = Some((stamps[0], ticks as f32 * self.period_ns / 1_000_000.0));
+                        }
+                    }
+                    slot.readback.unmap();
+                    slot.pending = false;
+                }
+                FAILED => slot.pending = false,
+                _ => {}
+            }
+        }
+        let fresh = fresh.map(|(_, ms)| ms);
+        if fresh.is_some() {
+            self.latest_ms = fresh;
+        }
+        fresh
+    }
+
+    /// The latest measurement, without polling.
+    pub fn latest_ms(&self) -> Option<f32> {
+        self.latest_ms
+    }
+}
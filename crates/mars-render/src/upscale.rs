@@ -0,0 +1,357 @@
+//! Rendering at a fraction of the output resolution.
+//!
+//! A [`RenderScale`] node runs a nested graph, usually the scene and its
+//! post-processing, at a reduced size into an intermediate texture and
+//! upscales that into the outer graph's output. Nodes after it, such as the
+//! HUD, draw at native resolution.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+
+use crate::fullscreen::FullscreenPass;
+use crate::graph::{GraphTextureDesc, NodeContext, RenderGraph, RenderNode};
+use crate::output;
+use crate::timing::GpuTimer;
+
+/// Graph texture the nested graph renders into when scaled.
+pub const SCALED_COLOR: &str = "render_scale.color";
+/// Graph texture holding the EASU output before sharpening.
+const UPSCALED: &str = "render_scale.upscaled";
+
+/// Scales chosen automatically are multiples of this, so that small timing
+/// changes do not reallocate targets every frame.
+pub const SCALE_STEP: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Upscaler {
+    Bilinear,
+    /// FSR1-style edge-adaptive upsampling (EASU) followed by contrast
+    /// adaptive sharpening (RCAS). `sharpness` is in 0..=1; 0 skips RCAS.
+    /// HDR outputs skip RCAS too, as it assumes display range.
+    Fsr { sharpness: f32 },
+}
+
+impl Default for Upscaler {
+    fn default() -> Self {
+        Upscaler::Fsr { sharpness: 0.8 }
+    }
+}
+
+/// Adjusts the render scale to keep the measured frame time near a target.
+///
+/// GPU time is taken to grow with the pixel count, so the scale moves by the
+/// square root of the time ratio, damped, after the smoothed time leaves a
+/// tolerance band around the target. After a change it waits for the new
+/// resolution to show up in the (delayed) measurements.
+///
+/// Hand-set scales off the [`SCALE_STEP`] grid are snapped to it on the
+/// first change.
+#[derive(Clone, Debug)]
+pub struct ScaleController {
+    pub target_ms: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Relative deviation from the target that is tolerated, e.g. 0.1.
+    pub tolerance: f32,
+    /// Measurements to ignore after a change; GPU timings arrive a few
+    /// frames late and not every frame.
+    pub settle_frames: u32,
+    smoothed_ms: Option<f32>,
+    wait: u32,
+}
+
+impl ScaleController {
+    /// Panics unless `target_ms` is positive and finite.
+    pub fn new(target_ms: f32) -> Self {
+        assert!(target_ms > 0.0 && target_ms.is_finite(), "target frame time must be positive and finite, got {target_ms}");
+        Self { target_ms, min_scale: 0.5, max_scale: 1.0, tolerance: 0.1, settle_frames: 8, smoothed_ms: None, wait: 0 }
+    }
+
+    /// Feeds one GPU time measurement and returns the scale to use from now
+    /// on.
+    pub fn update(&mut self, scale: f32, frame_ms: f32) -> f32 {
+        if self.wait > 0 {
+            self.wait -= 1;
+            return scale;
+        }
+        let smoothed = self.smoothed_ms.map_or(frame_ms, |s| s + (frame_ms - s) * 0.2);
+        self.smoothed_ms = Some(smoothed);
+        if smoothed <= 0.0 || (smoothed / self.target_ms - 1.0).abs() <= self.tolerance {
+            return scale;
+        }
+
+        let ideal = scale * (self.target_ms / smoothed).sqrt();
+        let damped = scale + (ideal - scale) * 0.5;
+        // Move by at least one step in the needed direction.
+        let mut next = (damped / SCALE_STEP).round() * SCALE_STEP;
+        let on_grid = (scale / SCALE_STEP).round() * SCALE_STEP;
+        if smoothed > self.target_ms {
+            next = next.min(on_grid - SCALE_STEP);
+        } else {
+            next = next.max(on_grid + SCALE_STEP);
+        }
+        let next = next.clamp(self.min_scale, self.max_scale);
+        if next != scale {
+            self.smoothed_ms = None;
+            self.wait = self.settle_frames;
+        }
+        next
+    }
+}
+
+struct ScaleControl {
+    scale: f32,
+    upscaler: Upscaler,
+    auto: Option<ScaleController>,
+    frame_ms: Option<f32>,
+}
+
+/// Runtime control of a [`RenderScale`] node.
+#[derive(Clone)]
+pub struct RenderScaleHandle(Arc<Mutex<ScaleControl>>);
+
+impl Default for RenderScaleHandle {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(ScaleControl { scale: 1.0, upscaler: Upscaler::default(), auto: None, frame_ms: None })))
+    }
+}
+
+impl RenderScaleHandle {
+    fn control(&self) -> std::sync::MutexGuard<'_, ScaleControl> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fraction of the output resolution per axis, clamped to 0.25..=1.
+    /// Overridden by the automatic controller while one is set.
+    pub fn set_scale(&self, scale: f32) {
+        self.control().scale = scale.clamp(0.25, 1.0);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.control().scale
+    }
+
+    pub fn set_upscaler(&self, upscaler: Upscaler) {
+        self.control().upscaler = upscaler;
+    }
+
+    pub fn upscaler(&self) -> Upscaler {
+        self.control().upscaler
+    }
+
+    /// Enables or disables automatic scaling.
+    pub fn set_auto(&self, controller: Option<ScaleController>) {
+        self.control().auto = controller;
+    }
+
+    /// Latest measured time of the nested graph: GPU time where timestamp
+    /// queries are available, otherwise the whole frame's CPU time.
+    pub fn frame_ms(&self) -> Option<f32> {
+        self.control().frame_ms
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct UpscaleParams {
+    sharpness: f32,
+    _pad: [f32; 3],
+}
+
+/// Runs a nested graph at a reduced resolution and upscales the result.
+///
+/// The nested nodes see the scaled size and an intermediate view in
+/// [`NodeContext`]; resolution-dependent state, like a `MeshRenderer`'s
+/// depth buffer, must follow `ctx.size`. At a scale of 1 the nested graph
+/// writes the output directly.
+pub struct RenderScale {
+    graph: RenderGraph,
+    handle: RenderScaleHandle,
+    timer: Option<GpuTimer>,
+    timer_checked: bool,
+    bilinear: FullscreenPass,
+    easu: FullscreenPass,
+    rcas: FullscreenPass,
+}
+
+impl RenderScale {
+    pub fn new(device: &wgpu::Device, graph: RenderGraph) -> Self {
+        // The input is already in the output's encoding.
+        let pass = |label, entry| FullscreenPass::new::<UpscaleParams>(device, label, "mars/upscale.wgsl", entry, &[], None).without_srgb_encode();
+        Self {
+            graph,
+            handle: RenderScaleHandle::default(),
+            timer: None,
+            timer_checked: false,
+            bilinear: pass("Bilinear Upscale", "fs_bilinear"),
+            easu: pass("EASU", "fs_easu"),
+            rcas: pass("RCAS", "fs_rcas"),
+        }
+    }
+
+    pub fn handle(&self) -> &RenderScaleHandle {
+        &self.handle
+    }
+
+    /// The nested graph, e.g. to add nodes. Its nodes share the outer graph's
+    /// resources.
+    pub fn graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    /// Size the nested graph renders at for an output of `size`.
+    pub fn scaled_size(size: (u32, u32), scale: f32) -> (u32, u32) {
+        let axis = |v: u32| ((v as f32 * scale).round() as u32).clamp(1, v.max(1));
+        (axis(size.0), axis(size.1))
+    }
+
+    fn upscale(&mut self, ctx: &mut NodeContext, input: &wgpu::TextureView, upscaler: Upscaler) {
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Upscale") });
+        match upscaler {
+            Upscaler::Bilinear => {
+                self.bilinear.draw(ctx.pipelines, ctx.device, &mut encoder, input, ctx.view, ctx.format, &[], false);
+            }
+            Upscaler::Fsr { sharpness } if sharpness <= 0.0 || output::is_hdr_format(ctx.format) => {
+                self.easu.draw(ctx.pipelines, ctx.device, &mut encoder, input, ctx.view, ctx.format, &[], false);
+            }
+            Upscaler::Fsr { sharpness } => {
+                let upscaled = ctx.resources.ensure_texture(ctx.device, UPSCALED, GraphTextureDesc::target(ctx.size, ctx.format)).view.clone();
+                self.easu.draw(ctx.pipelines, ctx.device, &mut encoder, input, &upscaled, ctx.format, &[], false);
+                // FSR expresses sharpness as stops of reduction from the maximum.
+                let stops = (1.0 - sharpness.min(1.0)) * 2.0;
+                self.rcas.write_params(ctx.queue, &UpscaleParams { sharpness: (-stops).exp2(), _pad: [0.0; 3] });
+                self.rcas.draw(ctx.pipelines, ctx.device, &mut encoder, &upscaled, ctx.view, ctx.format, &[], false);
+            }
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl RenderNode for RenderScale {
+    fn name(&self) -> &'static str {
+        "render_scale"
+    }
+
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        if !self.timer_checked {
+            self.timer = GpuTimer::new(ctx.device, ctx.queue);
+            self.timer_checked = true;
+        }
+        let (scale, upscaler) = {
+            let control = self.handle.control();
+            (control.scale, control.upscaler)
+        };
+        let size = Self::scaled_size(ctx.size, scale);
+
+        let timing = self.timer.as_mut().is_some_and(|t| t.begin(ctx.device, ctx.queue));
+        if size == ctx.size {
+            self.graph.execute(ctx)?;
+        } else {
+            let view = ctx.resources.ensure_texture(ctx.device, SCALED_COLOR, GraphTextureDesc::target(size, ctx.format)).view.clone();
+            let mut inner = NodeContext {
+                pipelines: ctx.pipelines,
+                device: ctx.device,
+                queue: ctx.queue,
+                view: &view,
+                format: ctx.format,
+                size,
+                resources: &mut *ctx.resources,
+                frame: ctx.frame,
+                dt: ctx.dt,
+            };
+            self.graph.execute(&mut inner)?;
+        }
+        if timing {
+            self.timer.as_mut().unwrap().end(ctx.device, ctx.queue);
+        }
+        if size != ctx.size {
+            let input = ctx.resources.texture(SCALED_COLOR).map(|t| t.view.clone()).unwrap();
+            self.upscale(ctx, &input, upscaler);
+        }
+
+        // The controller only sees new measurements; GPU times arrive a few
+        // frames late and not every frame.
+        let (frame_ms, fresh_ms) = match &mut self.timer {
+            Some(timer) => {
+                let fresh = timer.poll(ctx.device);
+                (timer.latest_ms(), fresh)
+            }
+            None => {
+                let ms = (ctx.dt > 0.0).then_some(ctx.dt * 1000.0);
+                (ms, ms)
+            }
+        };
+        let mut guard = self.handle.control();
+        let control = &mut *guard;
+        control.frame_ms = frame_ms;
+        if let (Some(controller), Some(ms)) = (control.auto.as_mut(), fresh_ms) {
+            control.scale = controller.update(control.scale, ms);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_scale(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "scale {actual}, expected {expected}");
+    }
+
+    #[test]
+    fn times_within_tolerance_keep_the_scale() {
+        let mut controller = ScaleController::new(10.0);
+        for ms in [10.9, 9.2, 10.0, 10.5] {
+            assert_eq!(controller.update(0.75, ms), 0.75);
+        }
+    }
+
+    #[test]
+    fn moves_at_least_one_step() {
+        let mut controller = ScaleController { tolerance: 0.0, ..ScaleController::new(10.0) };
+        // Both ideal scales round back to the current one.
+        assert_scale(controller.update(1.0, 10.5), 0.95);
+        let mut controller = ScaleController { tolerance: 0.0, ..ScaleController::new(10.0) };
+        assert_scale(controller.update(0.7, 9.5), 0.75);
+    }
+
+    #[test]
+    fn stays_within_the_limits() {
+        let mut controller = ScaleController::new(10.0);
+        assert_eq!(controller.update(0.5, 40.0), 0.5);
+        let mut controller = ScaleController::new(10.0);
+        assert_eq!(controller.update(1.0, 1.0), 1.0);
+        let mut controller = ScaleController { min_scale: 0.6, ..ScaleController::new(10.0) };
+        assert_scale(controller.update(0.7, 40.0), 0.6);
+    }
+
+    #[test]
+    fn off_grid_scales_snap_to_the_grid() {
+        let mut controller = ScaleController { min_scale: 0.1, ..ScaleController::new(10.0) };
+        assert_scale(controller.update(0.33, 20.0), 0.3);
+        let mut controller = ScaleController { min_scale: 0.1, tolerance: 0.0, ..ScaleController::new(10.0) };
+        assert_scale(controller.update(0.33, 9.9), 0.4);
+    }
+
+    #[test]
+    fn waits_for_measurements_to_settle() {
+        let mut controller = ScaleController { settle_frames: 2, ..ScaleController::new(10.0) };
+        let scale = controller.update(1.0, 20.0);
+        assert!(scale < 1.0);
+        // Measurements still at the old resolution are ignored.
+        assert_eq!(controller.update(scale, 20.0), scale);
+        assert_eq!(controller.update(scale, 20.0), scale);
+        // The smoothing restarts from the first settled measurement.
+        assert_eq!(controller.update(scale, 10.0), scale);
+        assert!(controller.update(scale, 20.0) < scale);
+    }
+
+    #[test]
+    #[should_panic(expected = "target frame time must be positive")]
+    fn rejects_a_zero_target() {
+        ScaleController::new(0.0);
+    }
+}
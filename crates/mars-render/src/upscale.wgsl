@@ -0,0 +1,160 @@
+// Upscaling of a reduced-resolution image to the output size. The EASU and
+// RCAS passes follow AMD FidelityFX Super Resolution 1.
+
+#import mars::fullscreen
+
+struct UpscaleParams {
+  // RCAS sharpening amount, exp2(-stops); 1 is the sharpest.
+  sharpness: f32,
+  _pad0: f32,
+  _pad1: f32,
+  _pad2: f32,
+};
+
+@group(0) @binding(2) var<uniform> params: UpscaleParams;
+
+fn load_clamped(p: vec2<i32>) -> vec3<f32> {
+  let size = vec2<i32>(textureDimensions(src));
+  return textureLoad(src, clamp(p, vec2<i32>(0), size - 1), 0).rgb;
+}
+
+@fragment
+fn fs_bilinear(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(textureSampleLevel(src, src_sampler, in.uv, 0.0).rgb, 1.0);
+}
+
+// Cheap luma used for edge detection.
+fn easu_luma(c: vec3<f32>) -> f32 {
+  return c.b * 0.5 + (c.r * 0.5 + c.g);
+}
+
+// Accumulates gradient direction and edge length from one bilinear corner:
+// a is above, b left, c the center, d right and e below.
+fn easu_set(w: f32, a: f32, b: f32, c: f32, d: f32, e: f32, dir: ptr<function, vec2<f32>>, len: ptr<function, f32>) {
+  let dir_x = d - b;
+  let len_x = clamp(abs(dir_x) / max(max(abs(d - c), abs(c - b)), 1e-5), 0.0, 1.0);
+  let dir_y = e - a;
+  let len_y = clamp(abs(dir_y) / max(max(abs(e - c), abs(c - a)), 1e-5), 0.0, 1.0);
+  *dir += vec2<f32>(dir_x, dir_y) * w;
+  *len += (len_x * len_x + len_y * len_y) * w;
+}
+
+// One tap of the edge-aligned, approximated Lanczos-2 kernel.
+fn easu_tap(off: vec2<f32>, dir: vec2<f32>, len: vec2<f32>, lob: f32, clp: f32, c: vec3<f32>, acc: ptr<function, vec4<f32>>) {
+  var v = vec2<f32>(dot(off, dir), dot(off, vec2<f32>(-dir.y, dir.x)));
+  v *= len;
+  let d2 = min(dot(v, v), clp);
+  var wb = 2.0 / 5.0 * d2 - 1.0;
+  var wa = lob * d2 - 1.0;
+  wb *= wb;
+  wa *= wa;
+  wb = 25.0 / 16.0 * wb - (25.0 / 16.0 - 1.0);
+  let w = wb * wa;
+  *acc += vec4<f32>(c * w, w);
+}
+
+// Edge-adaptive spatial upsampling from the 12 texels around the sample:
+//     b c
+//   e f g h
+//   i j k l
+//     n o
+@fragment
+fn fs_easu(in: VsOut) -> @location(0) vec4<f32> {
+  let in_size = vec2<f32>(textureDimensions(src));
+  let pp_full = in.uv * in_size - 0.5;
+  let fp = floor(pp_full);
+  let pp = pp_full - fp;
+  let p = vec2<i32>(fp);
+
+  let b = load_clamped(p + vec2<i32>(0, -1));
+  let c = load_clamped(p + vec2<i32>(1, -1));
+  let e = load_clamped(p + vec2<i32>(-1, 0));
+  let f = load_clamped(p);
+  let g = load_clamped(p + vec2<i32>(1, 0));
+  let h = load_clamped(p + vec2<i32>(2, 0));
+  let i = load_clamped(p + vec2<i32>(-1, 1));
+  let j = load_clamped(p + vec2<i32>(0, 1));
+  let k = load_clamped(p + vec2<i32>(1, 1));
+  let l = load_clamped(p + vec2<i32>(2, 1));
+  let n = load_clamped(p + vec2<i32>(0, 2));
+  let o = load_clamped(p + vec2<i32>(1, 2));
+
+  let bl = easu_luma(b);
+  let cl = easu_luma(c);
+  let el = easu_luma(e);
+  let fl = easu_luma(f);
+  let gl = easu_luma(g);
+  let hl = easu_luma(h);
+  let il = easu_luma(i);
+  let jl = easu_luma(j);
+  let kl = easu_luma(k);
+  let ll = easu_luma(l);
+  let nl = easu_luma(n);
+  let ol = easu_luma(o);
+
+  var dir = vec2<f32>(0.0);
+  var len = 0.0;
+  easu_set((1.0 - pp.x) * (1.0 - pp.y), bl, el, fl, gl, jl, &dir, &len);
+  easu_set(pp.x * (1.0 - pp.y), cl, fl, gl, hl, kl, &dir, &len);
+  easu_set((1.0 - pp.x) * pp.y, fl, il, jl, kl, nl, &dir, &len);
+  easu_set(pp.x * pp.y, gl, jl, kl, ll, ol, &dir, &len);
+
+  // Normalize the direction; flat areas fall back to the x axis.
+  let dir_len2 = dot(dir, dir);
+  if (dir_len2 < 1.0 / 32768.0) {
+    dir = vec2<f32>(1.0, 0.0);
+  } else {
+    dir *= inverseSqrt(dir_len2);
+  }
+
+  // Strong edges stretch the kernel along the edge and shrink the lobe.
+  len = len * 0.5;
+  len *= len;
+  let stretch = dot(dir, dir) / max(abs(dir.x), abs(dir.y));
+  let len2 = vec2<f32>(1.0 + (stretch - 1.0) * len, 1.0 - 0.5 * len);
+  let lob = 0.5 + ((1.0 / 4.0 - 0.04) - 0.5) * len;
+  let clp = 1.0 / lob;
+
+  var acc = vec4<f32>(0.0);
+  easu_tap(vec2<f32>(0.0, -1.0) - pp, dir, len2, lob, clp, b, &acc);
+  easu_tap(vec2<f32>(1.0, -1.0) - pp, dir, len2, lob, clp, c, &acc);
+  easu_tap(vec2<f32>(-1.0, 1.0) - pp, dir, len2, lob, clp, i, &acc);
+  easu_tap(vec2<f32>(0.0, 1.0) - pp, dir, len2, lob, clp, j, &acc);
+  easu_tap(vec2<f32>(0.0, 0.0) - pp, dir, len2, lob, clp, f, &acc);
+  easu_tap(vec2<f32>(-1.0, 0.0) - pp, dir, len2, lob, clp, e, &acc);
+  easu_tap(vec2<f32>(1.0, 1.0) - pp, dir, len2, lob, clp, k, &acc);
+  easu_tap(vec2<f32>(2.0, 1.0) - pp, dir, len2, lob, clp, l, &acc);
+  easu_tap(vec2<f32>(2.0, 0.0) - pp, dir, len2, lob, clp, h, &acc);
+  easu_tap(vec2<f32>(1.0, 0.0) - pp, dir, len2, lob, clp, g, &acc);
+  easu_tap(vec2<f32>(1.0, 2.0) - pp, dir, len2, lob, clp, o, &acc);
+  easu_tap(vec2<f32>(0.0, 2.0) - pp, dir, len2, lob, clp, n, &acc);
+
+  // Clamp to the nearest four texels to remove ringing.
+  let lo = min(min(f, g), min(j, k));
+  let hi = max(max(f, g), max(j, k));
+  return vec4<f32>(clamp(acc.rgb / acc.w, lo, hi), 1.0);
+}
+
+// Maximum negative lobe, as in FSR: 0.25 - 1/16.
+const RCAS_LIMIT: f32 = 0.1875;
+
+// Contrast-adaptive sharpening from the cross around each pixel, limited so
+// the result never leaves the local range.
+@fragment
+fn fs_rcas(in: VsOut) -> @location(0) vec4<f32> {
+  let p = vec2<i32>(in.clip.xy);
+  let b = load_clamped(p + vec2<i32>(0, -1));
+  let d = load_clamped(p + vec2<i32>(-1, 0));
+  let e = load_clamped(p);
+  let f = load_clamped(p + vec2<i32>(1, 0));
+  let h = load_clamped(p + vec2<i32>(0, 1));
+
+  let mn = min(min(b, d), min(f, h));
+  let mx = max(max(b, d), max(f, h));
+  let hit_min = min(mn, e) / (4.0 * mx + 1e-5);
+  let hit_max = (1.0 - max(mx, e)) / min(4.0 * mn - 4.0, vec3<f32>(-1e-5));
+  let lobe_rgb = max(-hit_min, hit_max);
+  let lobe = max(-RCAS_LIMIT, min(max(lobe_rgb.r, max(lobe_rgb.g, lobe_rgb.b)), 0.0)) * params.sharpness;
+  let c = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+  return vec4<f32>(c, 1.0);
+}
@@ -0,0 +1,147 @@
+//! Render-to-texture views.
+//!
+//! A [`SubView`] runs its own graph, usually a scene node drawing from a
+//! different camera, into an offscreen texture that the enclosing graph
+//! publishes under the view's name. Views run before the enclosing graph's
+//! nodes, so anything later in the frame can sample the result: mirror and
+//! portal shaders, in-world monitors, or a picture-in-picture overlay such
+//! as a minimap.
+//!
+//! Each view renders into a private texture and then swaps it with the
+//! published one, so consumers must look the texture up by name every
+//! frame rather than keep its view.
+
+use anyhow::Result;
+
+use crate::graph::{GraphResources, GraphTexture, GraphTextureDesc, RenderGraph};
+
+/// Resolution of a view's texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewSize {
+    Fixed(u32, u32),
+    /// A fraction of the enclosing graph's output per axis, followed when
+    /// that resizes.
+    Relative(f32),
+}
+
+impl ViewSize {
+    pub fn resolve(self, parent: (u32, u32)) -> (u32, u32) {
+        match self {
+            ViewSize::Fixed(w, h) => (w.max(1), h.max(1)),
+            ViewSize::Relative(scale) => {
+                let axis = |v: u32| ((v as f32 * scale).round() as u32).max(1);
+                (axis(parent.0), axis(parent.1))
+            }
+        }
+    }
+}
+
+/// A graph rendered into a named texture of the graph it is added to.
+///
+/// The view's nodes see the texture's size and format in their
+/// [`NodeContext`](crate::graph::NodeContext); resolution-dependent state,
+/// like a `MeshRenderer`'s depth buffer, must follow `ctx.size`. The view's
+/// graph has resources of its own, so names like
+/// [`SCENE_COLOR`](crate::graph::SCENE_COLOR) do not collide with the main
+/// view's. The outputs of all views of the enclosing graphs are shared into
+/// it, including this view's own previous frame.
+pub struct SubView {
+    name: String,
+    graph: RenderGraph,
+    size: ViewSize,
+    format: Option<wgpu::TextureFormat>,
+    pub enabled: bool,
+    /// Renders on every `interval`th frame only, e.g. for distant monitors;
+    /// 0 and 1 render every frame.
+    pub interval: u32,
+    /// Times the view renders per frame, each pass seeing the previous
+    /// one's result. Above 1 this adds bounces to a view that sees itself,
+    /// like a mirror facing a mirror.
+    pub passes: u32,
+    frame: u64,
+    back: Option<GraphTexture>,
+}
+
+impl SubView {
+    /// A view publishing `graph`'s output as `name`, at the enclosing
+    /// graph's size and format.
+    pub fn new(name: &str, graph: RenderGraph) -> Self {
+        Self {
+            name: name.to_owned(),
+            graph,
+            size: ViewSize::Relative(1.0),
+            format: None,
+            enabled: true,
+            interval: 1,
+            passes: 1,
+            frame: 0,
+            back: None,
+        }
+    }
+
+    pub fn with_size(mut self, size: ViewSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Renders in `format` instead of the enclosing graph's, e.g.
+    /// `Rgba16Float` for an HDR view sampled by lit materials.
+    pub fn with_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> ViewSize {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: ViewSize) {
+        self.size = size;
+    }
+
+    pub fn graph(&self) -> &RenderGraph {
+        &self.graph
+    }
+
+    pub fn graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    /// Renders the view and publishes the result in `resources`, the
+    /// enclosing graph's. `depth` counts the views this one is nested in.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &mut GraphResources,
+        shared: &[String],
+        parent_format: wgpu::TextureFormat,
+        parent_size: (u32, u32),
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<()> {
+        let frame = self.frame;
+        self.frame += 1;
+        if !self.enabled || depth >= max_depth || !frame.is_multiple_of(u64::from(self.interval.max(1))) {
+            return Ok(());
+        }
+
+        let size = self.size.resolve(parent_size);
+        let desc = GraphTextureDesc::target(size, self.format.unwrap_or(parent_format));
+        for _ in 0..self.passes.max(1) {
+            let back = match self.back.take() {
+                Some(texture) if texture.desc == desc => texture,
+                _ => GraphTexture::new(device, &self.name, desc),
+            };
+            self.graph.import(resources, shared);
+            self.graph.run_nested(device, queue, &back.view, desc.format, size, depth + 1, max_depth)?;
+            self.back = resources.insert_texture(&self.name, back);
+        }
+        Ok(())
+    }
+}
@@ -1,19 +1,20 @@
-use std::borrow::Cow;
-use ab_glyph::{point, FontArc, Glyph, PxScale, Font as _};
+use ab_glyph::{point, FontArc, Glyph, PxScale};
+use mars_render::pipeline::{ColorTarget, PipelineCache, RenderPipelineDesc, VertexLayout};
+use mars_render::shader::{ShaderDefs, ShaderLibrary};
 
 pub struct Hud {
     pub tex_size: (u32, u32),
     pub texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
     pub bind_group: wgpu::BindGroup,
     pub pipeline: wgpu::RenderPipeline,
+    pipeline_desc: RenderPipelineDesc,
+    shaders: ShaderLibrary,
     pub vbuf: wgpu::Buffer,
     pub font: FontArc,
 }
 
 impl Hud {
-    pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> Self {
+    pub fn new(device: &wgpu::Device, pipelines: &mut PipelineCache) -> Self {
         let tex_size = (1024, 512);
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("HUD Texture"),
@@ -33,48 +34,55 @@ impl Hud {
             ..Default::default()
         });
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("HUD Shader"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("hud.wgsl"))),
+        // Loaded from disk so edits to hud.wgsl show up live; the embedded
+        // copy is used when the example runs outside the source tree.
+        let mut shaders = ShaderLibrary::new();
+        shaders.add_search_path(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/hello_shapes"));
+        let shader = shaders.load(device, "hud.wgsl", &ShaderDefs::new()).unwrap_or_else(|e| {
+            eprintln!("hud.wgsl: {e:#}; using the embedded copy");
+            shaders.add_source("hud.wgsl", include_str!("hud.wgsl"));
+            shaders.load(device, "hud.wgsl", &ShaderDefs::new()).expect("embedded HUD shader is valid")
         });
+        if let Err(e) = shaders.watch() {
+            eprintln!("shader hot reload disabled: {e:#}");
+        }
 
-        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("HUD BGL"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
+        let bind_group_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                    count: None,
-                },
-            ],
-        });
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                count: None,
+            },
+        ];
+        let pipeline_desc = RenderPipelineDesc::new("HUD Pipeline", shader)
+            .with_bind_group(&bind_group_entries)
+            .with_vertex_buffer(VertexLayout::new(
+                std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+            ))
+            .with_target(ColorTarget::surface().with_blend(wgpu::BlendState::ALPHA_BLENDING));
+        let pipeline = pipelines.render_pipeline(device, &shaders, &pipeline_desc);
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("HUD BG"),
-            layout: &bgl,
+            layout: &pipelines.bind_group_layout(device, &bind_group_entries),
             entries: &[
                 wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) },
                 wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
             ],
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("HUD Pipeline Layout"),
-            bind_group_layouts: &[&bgl],
-            push_constant_ranges: &[],
-        });
-
         let vbuf = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("HUD VBuf"),
             size: (std::mem::size_of::<[f32; 4]>() * 6) as u64,
@@ -82,40 +90,17 @@ impl Hud {
             mapped_at_creation: false,
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("HUD Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-            cache: None,
-        });
-
         let font = FontArc::try_from_slice(include_bytes!("DejaVuSansMono.ttf"))
             .expect("place DejaVuSansMono.ttf next to hud.rs");
 
-        Self { tex_size, texture, view, sampler, bind_group, pipeline, vbuf, font }
+        Self { tex_size, texture, bind_group, pipeline, pipeline_desc, shaders, vbuf, font }
+    }
+
+    /// Picks up edits to the HUD shader.
+    pub fn reload_shaders(&mut self, device: &wgpu::Device, pipelines: &mut PipelineCache) {
+        if !self.shaders.reload_changed(device).is_empty() {
+            self.pipeline = pipelines.render_pipeline(device, &self.shaders, &self.pipeline_desc);
+        }
     }
 
     pub fn update_vertices(
@@ -180,7 +165,7 @@ impl Hud {
                         if px < 0 || py < 0 || px >= w as i32 || py >= h as i32 { return; }
                         let idx = ((py as u32 * w + px as u32) * 4) as usize;
                         let a = (cov * 255.0) as u8;
-                        rgba[idx + 0] = 255;
+                        rgba[idx] = 255;
                         rgba[idx + 1] = 255;
                         rgba[idx + 2] = 255;
                         rgba[idx + 3] = a.max(rgba[idx + 3]);
@@ -1,7 +1,8 @@
 use anyhow::Result;
 use std::sync::Arc;
 
-use mars_render::{device::RenderDevice, graph::{RenderGraph, RenderNode}};
+use mars_core::{debug_draw::DebugDraw, time::Time};
+use mars_render::{device::{RenderDevice, MAIN_SURFACE}, graph::{NodeContext, RenderGraph, RenderNode}, pipeline::PipelineCache};
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
@@ -15,8 +16,9 @@ use hud::Hud;
 struct ClearNode;
 impl RenderNode for ClearNode {
     fn name(&self) -> &'static str { "clear" }
-    fn execute(&mut self, rd: &RenderDevice, view: &wgpu::TextureView) -> Result<()> {
-        let mut encoder = rd.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    fn execute(&mut self, ctx: &mut NodeContext) -> Result<()> {
+        let view = ctx.view;
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Clear Encoder"),
         });
         {
@@ -36,7 +38,7 @@ impl RenderNode for ClearNode {
                 occlusion_query_set: None,
             });
         }
-        rd.queue.submit(Some(encoder.finish()));
+        ctx.queue.submit(Some(encoder.finish()));
         Ok(())
     }
 }
@@ -48,10 +50,10 @@ struct App {
     minimized: bool,
 
     hud: Option<Hud>,
+    pipelines: Option<PipelineCache>,
     static_lines: String,
 
-    last_frame_t: std::time::Instant,
-    ema_fps: f32,
+    time: Time,
     dropped_timeouts: u32,
     dropped_lost: u32,
 }
@@ -68,9 +70,9 @@ impl ApplicationHandler for App {
 
         // ===== build static metrics text =====
         let info = rd.adapter.get_info();
-        let fmt  = rd.surface_bundle.config.format;
-        let pm   = rd.surface_bundle.config.present_mode;
-        let am   = rd.surface_bundle.config.alpha_mode;
+        let fmt  = rd.main_surface().config.format;
+        let pm   = rd.main_surface().config.present_mode;
+        let am   = rd.main_surface().config.alpha_mode;
         let lim  = rd.adapter.limits();
         let size = win.inner_size();
         let scale= win.scale_factor();
@@ -100,17 +102,24 @@ Window: {}×{} @ scale {:.2}",
             size.width, size.height, scale
         );
 
+        // Pipelines, with the driver's compiled code kept across runs where supported
+        let mut pipelines = PipelineCache::new(fmt);
+        if let Err(e) = pipelines.load_from_disk(&rd.device, &rd.adapter, std::env::temp_dir().join("mars-engine")) {
+            eprintln!("pipeline cache: {e:#}");
+        }
+
         // HUD
-        let mut hud = Hud::new(&rd.device, fmt);
-        hud.update_vertices(&rd.queue, rd.surface_bundle.config.width, rd.surface_bundle.config.height, 8);
+        let hud = Hud::new(&rd.device, &mut pipelines);
+        hud.update_vertices(&rd.queue, rd.main_surface().config.width, rd.main_surface().config.height, 8);
 
         // First frame (so we show with HUD already drawn)
         self.window = Some(win.clone());
         self.rd = Some(rd);
         self.hud = Some(hud);
+        self.pipelines = Some(pipelines);
         self.minimized = false;
-        self.last_frame_t = std::time::Instant::now();
-        self.ema_fps = 0.0;
+        self.time = Time::new();
+        self.time.update();
         self.dropped_timeouts = 0;
         self.dropped_lost = 0;
 
@@ -120,7 +129,7 @@ Window: {}×{} @ scale {:.2}",
             let text = format!("{}\n{}", self.static_lines, dyn_line);
             hud.upload_text(&rd.queue, &text);
 
-            if let Ok((frame, view)) = rd.begin_frame() {
+            if let Ok((frame, view)) = rd.begin_frame(MAIN_SURFACE) {
                 let mut encoder = rd.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("First Frame") });
                 {
                     let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -147,53 +156,58 @@ Window: {}×{} @ scale {:.2}",
     }
 
     fn window_event(&mut self, elwt: &ActiveEventLoop, _id: winit::window::WindowId, ev: WindowEvent) {
-        let (Some(win), Some(rd), Some(hud)) = (&self.window, &mut self.rd, &mut self.hud) else { return; };
+        let (Some(win), Some(rd), Some(hud), Some(pipelines)) = (&self.window, &mut self.rd, &mut self.hud, &mut self.pipelines) else { return; };
 
         match ev {
-            WindowEvent::CloseRequested => elwt.exit(),
+            WindowEvent::CloseRequested => {
+                if let Err(e) = pipelines.save() {
+                    eprintln!("pipeline cache: {e:#}");
+                }
+                elwt.exit();
+            }
 
             WindowEvent::Resized(size) => {
                 self.minimized = size.width == 0 || size.height == 0;
                 if !self.minimized {
-                    rd.resize(size);
-                    hud.update_vertices(&rd.queue, rd.surface_bundle.config.width, rd.surface_bundle.config.height, 8);
+                    rd.resize(MAIN_SURFACE, size);
+                    hud.update_vertices(&rd.queue, rd.main_surface().config.width, rd.main_surface().config.height, 8);
                 }
             }
 
             WindowEvent::ScaleFactorChanged { .. } => {
-                hud.update_vertices(&rd.queue, rd.surface_bundle.config.width, rd.surface_bundle.config.height, 8);
+                hud.update_vertices(&rd.queue, rd.main_surface().config.width, rd.main_surface().config.height, 8);
             }
 
             WindowEvent::RedrawRequested => {
                 if self.minimized { return; }
+                hud.reload_shaders(&rd.device, pipelines);
 
                 // timing
-                let now = std::time::Instant::now();
-                let dt  = now.duration_since(self.last_frame_t).as_secs_f32();
-                self.last_frame_t = now;
-                let frame_ms = (dt * 1000.0).clamp(0.0, 1000.0);
-                let fps_now  = if dt > 0.0 { 1.0 / dt } else { 0.0 };
-                self.ema_fps = if self.ema_fps == 0.0 { fps_now } else { 0.9*self.ema_fps + 0.1*fps_now };
-
-                match rd.begin_frame() {
+                self.time.update();
+                let frame_ms = (self.time.real_delta_secs() * 1000.0).clamp(0.0, 1000.0);
+
+                match rd.begin_frame(MAIN_SURFACE) {
                     Ok((frame, view)) => {
                         // Build live line and upload
                         let dyn_line = format!("Frame: {:>.2} ms FPS≈{:>.1} dropped={}/{}",
-                                               frame_ms, self.ema_fps, self.dropped_timeouts, self.dropped_lost);
+                                               frame_ms, self.time.fps(), self.dropped_timeouts, self.dropped_lost);
                         let text = format!("{}\n{}", self.static_lines, dyn_line);
                         hud.upload_text(&rd.queue, &text);
 
-                        // Clear + HUD
+                        // Graph (clear), then HUD on top
+                        if let Err(e) = self.graph.run(rd, &view) {
+                            eprintln!("render graph: {e:?}");
+                        }
                         let mut encoder = rd.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Frame Encoder") });
                         {
                             let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                                label: Some("Clear Pass"),
+                                label: Some("HUD Pass"),
                                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                                     view: &view,
                                     depth_slice: None,
                                     resolve_target: None,
                                     ops: wgpu::Operations {
-                                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                        load: wgpu::LoadOp::Load,
                                         store: wgpu::StoreOp::Store,
                                     },
                                 })],
@@ -205,14 +219,16 @@ Window: {}×{} @ scale {:.2}",
                         }
                         rd.queue.submit(Some(encoder.finish()));
                         frame.present();
+                        DebugDraw::global().tick(self.time.delta_secs());
                         win.request_redraw();
                     }
                     Err(err) => {
                         match err {
                             wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
                                 self.dropped_lost += 1;
-                                rd.resize(rd.surface_bundle.size);
-                                hud.update_vertices(&rd.queue, rd.surface_bundle.config.width, rd.surface_bundle.config.height, 8);
+                                let size = rd.main_surface().size;
+                                rd.resize(MAIN_SURFACE, size);
+                                hud.update_vertices(&rd.queue, rd.main_surface().config.width, rd.main_surface().config.height, 8);
                             }
                             wgpu::SurfaceError::Timeout => {
                                 self.dropped_timeouts += 1;
@@ -238,9 +254,9 @@ fn main() -> Result<()> {
         graph: RenderGraph::new().add_node(ClearNode), // your other nodes can follow later
         minimized: false,
         hud: None,
+        pipelines: None,
         static_lines: String::new(),
-        last_frame_t: std::time::Instant::now(),
-        ema_fps: 0.0,
+        time: Time::new(),
         dropped_timeouts: 0,
         dropped_lost: 0,
     };